@@ -240,6 +240,13 @@ fn on_new_packet<'a>(
                 return Action::Nop;
             };
 
+            if ip.get_mf() || ip.get_fragment_offset() != 0 {
+                // NOTE we don't have memory to spare for `ipv4::frag::Reassembler`
+                warning!("IPv4 fragment; ignoring");
+
+                return Action::Nop;
+            }
+
             let src_ip = ip.get_source();
 
             if !src_mac.is_broadcast() {
@@ -295,9 +302,18 @@ fn on_new_packet<'a>(
                 ipv4::Protocol::Udp => {
                     info!("IPv4 protocol: UDP");
 
-                    if let Ok(mut udp) = udp::Packet::parse(ip.payload_mut()) {
+                    let dest_ip = ip.get_destination();
+                    if let Ok(udp) = udp::Packet::parse(ip.payload_mut()) {
                         info!("valid UDP packet");
 
+                        let udp = if let Ok(udp) = udp.verify_ipv4_checksum(src_ip, dest_ip) {
+                            udp
+                        } else {
+                            error!("UDP: invalid checksum");
+
+                            return Action::Nop;
+                        };
+
                         let src_mac = if let Some(mac) = cache.get(&src_ip) {
                             mac
                         } else {
@@ -361,9 +377,9 @@ fn on_new_packet<'a>(
 
                             // we build the response in-place
                             // update the UDP header
-                            udp.set_source(dst_port);
+                            let mut udp = udp.set_source(dst_port);
                             udp.set_destination(src_port);
-                            udp.zero_checksum();
+                            udp.update_ipv4_checksum(IP, src_ip);
 
                             // update the IP header
                             let mut ip = ip.set_source(IP);
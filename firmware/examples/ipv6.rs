@@ -408,16 +408,16 @@ fn on_new_packet<'a>(
                 ipv6::NextHeader::Udp => {
                     info!("IPv6 next-header: UDP");
 
-                    let mut udp = if let Ok(udp) = udp::Packet::parse(ip.payload_mut()) {
+                    let udp = if let Ok(udp) = udp::Packet::parse(ip.payload_mut()) {
                         info!("valid UDP packet");
 
-                        if !udp.verify_ipv6_checksum(src_nl_addr, dest_nl_addr) {
+                        if let Ok(udp) = udp.verify_ipv6_checksum(src_nl_addr, dest_nl_addr) {
+                            udp
+                        } else {
                             error!("UDP: invalid checksum");
 
                             return Action::Nop;
                         }
-
-                        udp
                     } else {
                         error!("invalid UDP packet");
 
@@ -483,7 +483,7 @@ fn on_new_packet<'a>(
 
                         // we build the response in-place
                         // update the UDP header
-                        udp.set_source(dst_port);
+                        let mut udp = udp.set_source(dst_port);
                         udp.set_destination(src_port);
                         udp.update_ipv6_checksum(our_nl_addr, src_nl_addr);
 
@@ -228,10 +228,20 @@ fn on_new_frame<'a>(
     let src_nl_addr = match ip.get_source() {
         iphc::Addr::Complete(addr) => addr,
         iphc::Addr::Elided(ea) => ea.complete(src_ll_addr),
+        _ => {
+            warning!("context based compression is not supported; ignoring");
+
+            return Action::Nop;
+        }
     };
     let dest_nl_addr = match ip.get_destination() {
         iphc::Addr::Complete(addr) => addr,
         iphc::Addr::Elided(ea) => ea.complete(dest_ll_addr),
+        _ => {
+            warning!("context based compression is not supported; ignoring");
+
+            return Action::Nop;
+        }
     };
     let our_nl_addr = our_nl_addr();
 
@@ -23,6 +23,8 @@ unsafe fn SysTick() {
 #[exception]
 unsafe fn SVCall() {
     if let Some(p) = PACKET.take() {
+        force_eval!(p.get_traffic_class());
+        force_eval!(p.get_flow_label());
         force_eval!(p.get_next_header());
         force_eval!(p.get_hop_limit());
         force_eval!(p.get_source());
@@ -32,6 +34,8 @@ unsafe fn SVCall() {
         force_eval!(p.get_nh());
         force_eval!(p.get_hlim());
         force_eval!(p.get_cid());
+        force_eval!(p.get_sci());
+        force_eval!(p.get_dci());
         force_eval!(p.get_sac());
         force_eval!(p.get_sam());
         force_eval!(p.get_m());
@@ -0,0 +1,52 @@
+#![no_std]
+#![no_main]
+
+use cortex_m::asm;
+use cortex_m_rt::{entry, exception};
+use panic_never::force_eval;
+
+use jnet::{tcp, Unknown};
+
+const LEN: usize = 128;
+static mut BUFFER: [u8; LEN] = [0; LEN];
+static mut SEGMENT: Option<tcp::Segment<&'static mut [u8], Unknown>> = None;
+
+#[exception]
+unsafe fn SysTick() {
+    if let Ok(s) = tcp::Segment::parse(&mut BUFFER[..]) {
+        SEGMENT = Some(s);
+    } else {
+        asm::nop();
+    }
+}
+
+#[exception]
+unsafe fn SVCall() {
+    if let Some(s) = SEGMENT.take() {
+        force_eval!(s.get_source());
+        force_eval!(s.get_destination());
+        force_eval!(s.get_sequence_number());
+        force_eval!(s.get_acknowledgment_number());
+        force_eval!(s.get_data_offset());
+        force_eval!(s.get_ns());
+        force_eval!(s.get_cwr());
+        force_eval!(s.get_ece());
+        force_eval!(s.get_urg());
+        force_eval!(s.get_ack());
+        force_eval!(s.get_psh());
+        force_eval!(s.get_rst());
+        force_eval!(s.get_syn());
+        force_eval!(s.get_fin());
+        force_eval!(s.get_window());
+        force_eval!(s.get_urgent_pointer());
+        force_eval!(s.len());
+        force_eval!(s.options().count());
+        force_eval!(s.header());
+        force_eval!(s.payload());
+    }
+}
+
+#[entry]
+fn main() -> ! {
+    loop {}
+}
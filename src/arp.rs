@@ -21,6 +21,8 @@ use crate::{
     Unknown,
 };
 
+pub mod resolver;
+
 /* Packet structure */
 const HTYPE: Range<usize> = 0..2;
 const PTYPE: Range<usize> = 2..4;
@@ -0,0 +1,768 @@
+//! ARP cache, address resolution and Address Conflict Detection (ACD)
+//!
+//! # References
+//!
+//! - [RFC 826: An Ethernet Address Resolution Protocol][0]
+//!
+//! [0]: https://tools.ietf.org/html/rfc826
+//!
+//! - [RFC 1122: Requirements for Internet Hosts -- Communication Layers][1], section 2.3.2 "Address
+//!   Resolution Protocol -- ARP"
+//!
+//! [1]: https://tools.ietf.org/html/rfc1122
+//!
+//! - [RFC 5227: IPv4 Address Conflict Detection][2]
+//!
+//! [2]: https://tools.ietf.org/html/rfc5227
+
+use as_slice::{AsMutSlice, AsSlice};
+use heapless::{ArrayLength, Vec};
+
+use crate::{
+    arp::{Operation, Packet},
+    ipv4, mac,
+    time::{Duration, Instant},
+};
+
+/// Time between two ARP requests for the same address
+pub const REQUEST_INTERVAL: Duration = Duration::from_millis(1_000);
+
+/// Number of ARP requests sent before an address is deemed unreachable
+pub const MAX_REQUESTS: u8 = 3;
+
+/// Maximum random delay the caller should wait before starting to probe an address (`PROBE_WAIT`)
+pub const PROBE_WAIT: Duration = Duration::from_millis(1_000);
+
+/// Number of probe packets (`PROBE_NUM`)
+pub const PROBE_NUM: u8 = 3;
+
+/// Time between two probe packets (`PROBE_MIN`)
+pub const PROBE_MIN: Duration = Duration::from_millis(1_000);
+
+/// Time between the last probe packet and the first announcement (`ANNOUNCE_WAIT`)
+pub const ANNOUNCE_WAIT: Duration = Duration::from_millis(2_000);
+
+/// Number of announcement packets (`ANNOUNCE_NUM`)
+pub const ANNOUNCE_NUM: u8 = 2;
+
+/// Time between two announcement packets (`ANNOUNCE_INTERVAL`)
+pub const ANNOUNCE_INTERVAL: Duration = Duration::from_millis(2_000);
+
+/// Minimum time between two defensive ARP packets (`DEFEND_INTERVAL`)
+pub const DEFEND_INTERVAL: Duration = Duration::from_millis(10_000);
+
+/// Resolver error
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Error {
+    /// The datagram doesn't fit in the buffer of the cache entry
+    TooLarge,
+
+    /// All the entries are waiting for a reply; none of them can be evicted
+    Exhausted,
+}
+
+/// An action the caller must take on behalf of the resolver
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Action {
+    /// Send this ARP message
+    Send(Message),
+
+    /// No reply was received for this address; the datagram that was waiting for it has been
+    /// dropped
+    Unreachable(ipv4::Addr),
+
+    /// Another host is using this address; it has been released and must no longer be used
+    Conflict(ipv4::Addr),
+}
+
+/// An ARP message the resolver wants to send
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Message {
+    /// ARP request
+    Request {
+        /// Sender Protocol Address
+        spa: ipv4::Addr,
+        /// Target Protocol Address
+        tpa: ipv4::Addr,
+    },
+
+    /// ARP reply
+    Reply {
+        /// Sender Protocol Address
+        spa: ipv4::Addr,
+        /// Target Hardware Address
+        tha: mac::Addr,
+        /// Target Protocol Address
+        tpa: ipv4::Addr,
+    },
+
+    /// ARP probe for the given address
+    Probe(ipv4::Addr),
+
+    /// ARP announcement of the given address
+    Announce(ipv4::Addr),
+}
+
+impl Message {
+    /// Returns the address the Ethernet frame that carries this message must be sent to
+    pub fn destination(&self) -> mac::Addr {
+        match *self {
+            Message::Reply { tha, .. } => tha,
+            _ => mac::Addr::BROADCAST,
+        }
+    }
+
+    /// Writes this message into an ARP packet
+    ///
+    /// NOTE the SHA field is not modified; it must be set to the address of the interface (this is
+    /// done by `ether::Frame::arp`)
+    pub fn write<B>(&self, arp: &mut Packet<B>)
+    where
+        B: AsSlice<Element = u8> + AsMutSlice<Element = u8>,
+    {
+        match *self {
+            Message::Request { spa, tpa } => {
+                arp.set_oper(Operation::Request);
+                arp.set_spa(spa);
+                arp.set_tha(mac::Addr([0; 6]));
+                arp.set_tpa(tpa);
+            }
+
+            Message::Reply { spa, tha, tpa } => {
+                arp.set_oper(Operation::Reply);
+                arp.set_spa(spa);
+                arp.set_tha(tha);
+                arp.set_tpa(tpa);
+            }
+
+            Message::Probe(addr) => arp.probe(addr),
+
+            Message::Announce(addr) => arp.announce(addr),
+        }
+    }
+}
+
+/// An entry of the ARP cache; see `Resolver`
+pub struct Entry<M>
+where
+    M: ArrayLength<u8>,
+{
+    ip: ipv4::Addr,
+    mac: Option<mac::Addr>,
+    // when the entry was last updated or, if unresolved, when the last request was sent
+    timer: Instant,
+    // number of requests sent
+    requests: u8,
+    // datagram waiting for address resolution
+    pending: Vec<u8, M>,
+}
+
+// Address Conflict Detection state machine of our own address; also used by `ipv4::link_local`
+#[derive(Clone, Copy)]
+pub(crate) enum Acd {
+    Unconfigured,
+    Probing {
+        addr: ipv4::Addr,
+        probes: u8,
+        timer: Instant,
+    },
+    Bound {
+        addr: ipv4::Addr,
+        announcements: u8,
+        timer: Instant,
+        last_defense: Option<Instant>,
+        defend: bool,
+    },
+}
+
+/// ARP resolver
+///
+/// Owns a fixed capacity ARP cache of `N` entries and the IPv4 address of the interface. While an
+/// address is being resolved a single datagram of up to `M` bytes can be held for it; newer
+/// datagrams replace older ones.
+///
+/// The resolver doesn't send or receive packets on its own. The caller resolves addresses with
+/// `send`, feeds it the ARP packets it receives with `handle_packet` and calls `poll` periodically
+/// (e.g. every 100 ms) to learn which ARP messages to send.
+pub struct Resolver<N, M>
+where
+    N: ArrayLength<Entry<M>>,
+    M: ArrayLength<u8>,
+{
+    mac: mac::Addr,
+    max_age: Duration,
+    acd: Acd,
+    conflict: Option<ipv4::Addr>,
+    reply: Option<Message>,
+    entries: Vec<Entry<M>, N>,
+}
+
+impl<N, M> Resolver<N, M>
+where
+    N: ArrayLength<Entry<M>>,
+    M: ArrayLength<u8>,
+{
+    /// Creates a new resolver for the interface with address `mac`
+    ///
+    /// Resolved addresses are removed from the cache after `max_age`. RFC 1122 suggests a value
+    /// "on the order of a minute".
+    pub fn new(mac: mac::Addr, max_age: Duration) -> Self {
+        Resolver {
+            mac,
+            max_age,
+            acd: Acd::Unconfigured,
+            conflict: None,
+            reply: None,
+            entries: Vec::new(),
+        }
+    }
+
+    /* Own address */
+    /// Starts probing `addr` to check that no other host is using it
+    ///
+    /// RFC 5227 recommends waiting a random time between 0 and `PROBE_WAIT` before calling this
+    /// method. Once `PROBE_NUM` probes have gone unanswered the address is claimed: `address`
+    /// returns it and `ANNOUNCE_NUM` announcements are sent. If a conflict is detected `poll`
+    /// returns `Action::Conflict`.
+    pub fn claim(&mut self, addr: ipv4::Addr, now: Instant) {
+        self.acd = Acd::probing(addr, now);
+    }
+
+    /// Uses `addr` without probing for conflicts first
+    ///
+    /// The address is still announced and defended
+    pub fn set_address(&mut self, addr: ipv4::Addr, now: Instant) {
+        self.acd = Acd::bound(addr, now);
+    }
+
+    /// Stops using the current address, or stops probing it
+    pub fn release(&mut self) {
+        self.acd = Acd::Unconfigured;
+    }
+
+    /// Returns the address of the interface, if one has been claimed
+    pub fn address(&self) -> Option<ipv4::Addr> {
+        self.acd.address()
+    }
+
+    /* Address resolution */
+    /// Resolves the hardware address of `ip` in order to send it `datagram`
+    ///
+    /// Returns `Ok(Some(mac))` if the address is in the cache; the caller must then send
+    /// `datagram` itself. Otherwise `datagram` is held until a reply arrives and `Ok(None)` is
+    /// returned; see `handle_packet` and `poll`.
+    ///
+    /// NOTE broadcast and multicast addresses don't need to be resolved and must not be passed to
+    /// this method
+    pub fn send(
+        &mut self,
+        ip: ipv4::Addr,
+        datagram: &[u8],
+        now: Instant,
+    ) -> Result<Option<mac::Addr>, Error> {
+        if let Some(entry) = self.entries.iter_mut().find(|entry| entry.ip == ip) {
+            if entry.mac.is_none() {
+                entry.pending = hold(datagram)?;
+            }
+
+            return Ok(entry.mac);
+        }
+
+        let pending = hold(datagram)?;
+
+        if self.entries.len() == N::to_usize() {
+            // evict the resolved entry that was updated the longest time ago
+            let pos = self
+                .entries
+                .iter()
+                .enumerate()
+                .filter(|(_, entry)| entry.mac.is_some())
+                .max_by_key(|(_, entry)| now.duration_since(entry.timer))
+                .map(|(pos, _)| pos)
+                .ok_or(Error::Exhausted)?;
+
+            self.entries.swap_remove(pos);
+        }
+
+        self.entries
+            .push(Entry {
+                ip,
+                mac: None,
+                timer: now,
+                requests: 0,
+                pending,
+            })
+            .map_err(|_| Error::Exhausted)?;
+
+        Ok(None)
+    }
+
+    /// Processes an incoming ARP packet
+    ///
+    /// If this resolves an address that had a datagram waiting for it, the hardware address and the
+    /// datagram are returned; the caller must send the datagram.
+    pub fn handle_packet<B>(&mut self, arp: &Packet<B>, now: Instant) -> Option<(mac::Addr, &[u8])>
+    where
+        B: AsSlice<Element = u8>,
+    {
+        let sha = arp.get_sha();
+        let spa = arp.get_spa();
+        let tpa = arp.get_tpa();
+
+        if sha == self.mac {
+            // our own packet, looped back
+            return None;
+        }
+
+        let bound = self.address();
+        if let Some(addr) = self.acd.handle_packet(arp, now) {
+            self.conflict = Some(addr);
+        }
+
+        if let Some(addr) = bound {
+            if spa == addr {
+                return None;
+            }
+
+            if arp.get_oper() == Operation::Request && tpa == addr {
+                self.reply = Some(Message::Reply {
+                    spa: addr,
+                    tha: sha,
+                    tpa: spa,
+                });
+            }
+        }
+
+        if arp.is_a_probe() {
+            // the sender has no address yet
+            return None;
+        }
+
+        // RFC 826 - "Packet Reception"
+        if let Some(pos) = self.entries.iter().position(|entry| entry.ip == spa) {
+            let entry = &mut self.entries[pos];
+            let resolved = entry.mac.is_none();
+
+            entry.mac = Some(sha);
+            entry.timer = now;
+
+            return if resolved {
+                Some((sha, &entry.pending[..]))
+            } else {
+                None
+            };
+        }
+
+        if self.address() == Some(tpa) && self.entries.len() < N::to_usize() {
+            // the sender is likely to talk to us soon
+            self.entries
+                .push(Entry {
+                    ip: spa,
+                    mac: Some(sha),
+                    timer: now,
+                    requests: 0,
+                    pending: Vec::new(),
+                })
+                .ok();
+        }
+
+        None
+    }
+
+    /// Advances the timers of the resolver
+    ///
+    /// If this returns an `Action` the caller must carry it out *now*. Call this method again
+    /// until it returns `None` as several actions may be due at the same time.
+    pub fn poll(&mut self, now: Instant) -> Option<Action> {
+        if let Some(addr) = self.conflict.take() {
+            return Some(Action::Conflict(addr));
+        }
+
+        if let Some(reply) = self.reply.take() {
+            return Some(Action::Send(reply));
+        }
+
+        if let Some(message) = self.acd.poll(now, PROBE_MIN) {
+            return Some(Action::Send(message));
+        }
+
+        let spa = self.address().unwrap_or(ipv4::Addr::UNSPECIFIED);
+        let mut i = 0;
+        while i < self.entries.len() {
+            let entry = &mut self.entries[i];
+            let elapsed = now.duration_since(entry.timer);
+
+            if entry.mac.is_some() {
+                if elapsed >= self.max_age {
+                    self.entries.swap_remove(i);
+                    continue;
+                }
+            } else if entry.requests == 0 || elapsed >= REQUEST_INTERVAL {
+                if entry.requests < MAX_REQUESTS {
+                    entry.requests += 1;
+                    entry.timer = now;
+
+                    return Some(Action::Send(Message::Request { spa, tpa: entry.ip }));
+                } else {
+                    let ip = entry.ip;
+                    self.entries.swap_remove(i);
+
+                    return Some(Action::Unreachable(ip));
+                }
+            }
+
+            i += 1;
+        }
+
+        None
+    }
+
+    /// Returns the hardware address of `ip`, if it's in the cache
+    pub fn get(&self, ip: ipv4::Addr) -> Option<mac::Addr> {
+        self.entries
+            .iter()
+            .find(|entry| entry.ip == ip)
+            .and_then(|entry| entry.mac)
+    }
+
+    /// Returns the number of entries in the cache
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns `true` if the cache is empty
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl Acd {
+    pub(crate) fn probing(addr: ipv4::Addr, now: Instant) -> Self {
+        Acd::Probing {
+            addr,
+            probes: 0,
+            timer: now,
+        }
+    }
+
+    pub(crate) fn bound(addr: ipv4::Addr, now: Instant) -> Self {
+        Acd::Bound {
+            addr,
+            announcements: 0,
+            timer: now,
+            last_defense: None,
+            defend: false,
+        }
+    }
+
+    // Returns the address, if it has been claimed
+    pub(crate) fn address(&self) -> Option<ipv4::Addr> {
+        match *self {
+            Acd::Bound { addr, .. } => Some(addr),
+            _ => None,
+        }
+    }
+
+    // Processes an incoming ARP packet that was not sent by us
+    //
+    // Returns the address if another host is using it; the address is then released
+    pub(crate) fn handle_packet<B>(&mut self, arp: &Packet<B>, now: Instant) -> Option<ipv4::Addr>
+    where
+        B: AsSlice<Element = u8>,
+    {
+        let spa = arp.get_spa();
+
+        // RFC 5227 - Section 2.1.1 and 2.4
+        match *self {
+            Acd::Probing { addr, .. } => {
+                if spa == addr || (arp.is_a_probe() && arp.get_tpa() == addr) {
+                    *self = Acd::Unconfigured;
+                    return Some(addr);
+                }
+            }
+
+            Acd::Bound {
+                addr,
+                ref mut last_defense,
+                ref mut defend,
+                ..
+            } => {
+                if spa == addr {
+                    if last_defense.map(|t| now.duration_since(t) < DEFEND_INTERVAL) == Some(true) {
+                        *self = Acd::Unconfigured;
+                        return Some(addr);
+                    } else {
+                        *last_defense = Some(now);
+                        *defend = true;
+                    }
+                }
+            }
+
+            Acd::Unconfigured => {}
+        }
+
+        None
+    }
+
+    // Returns the next probe or announcement that must be sent
+    //
+    // `probe_interval` is the time between two probes; the first probe is sent right away
+    pub(crate) fn poll(&mut self, now: Instant, probe_interval: Duration) -> Option<Message> {
+        match *self {
+            Acd::Unconfigured => None,
+
+            Acd::Probing {
+                addr,
+                ref mut probes,
+                ref mut timer,
+            } => {
+                let elapsed = now.duration_since(*timer);
+
+                if *probes < PROBE_NUM {
+                    if *probes == 0 || elapsed >= probe_interval {
+                        *probes += 1;
+                        *timer = now;
+
+                        return Some(Message::Probe(addr));
+                    }
+                } else if elapsed >= ANNOUNCE_WAIT {
+                    *self = Acd::Bound {
+                        addr,
+                        announcements: 1,
+                        timer: now,
+                        last_defense: None,
+                        defend: false,
+                    };
+
+                    return Some(Message::Announce(addr));
+                }
+
+                None
+            }
+
+            Acd::Bound {
+                addr,
+                ref mut announcements,
+                ref mut timer,
+                ref mut defend,
+                ..
+            } => {
+                if *defend {
+                    *defend = false;
+
+                    return Some(Message::Announce(addr));
+                }
+
+                if *announcements < ANNOUNCE_NUM
+                    && (*announcements == 0 || now.duration_since(*timer) >= ANNOUNCE_INTERVAL)
+                {
+                    *announcements += 1;
+                    *timer = now;
+
+                    return Some(Message::Announce(addr));
+                }
+
+                None
+            }
+        }
+    }
+}
+
+fn hold<M>(datagram: &[u8]) -> Result<Vec<u8, M>, Error>
+where
+    M: ArrayLength<u8>,
+{
+    let mut pending = Vec::new();
+    pending
+        .extend_from_slice(datagram)
+        .map_err(|_| Error::TooLarge)?;
+    Ok(pending)
+}
+
+#[cfg(test)]
+mod tests {
+    use heapless::consts::{U2, U64};
+
+    use super::{Action, Error, Message, Resolver};
+    use crate::{
+        arp::{Operation, Packet},
+        ipv4, mac,
+        time::{Duration, Instant},
+    };
+
+    const MAC: mac::Addr = mac::Addr([0x02, 0, 0, 0, 0, 1]);
+    const IP: ipv4::Addr = ipv4::Addr([192, 168, 1, 1]);
+
+    const PEER_MAC: mac::Addr = mac::Addr([0x02, 0, 0, 0, 0, 2]);
+    const PEER_IP: ipv4::Addr = ipv4::Addr([192, 168, 1, 2]);
+
+    const MAX_AGE: Duration = Duration::from_millis(60_000);
+
+    fn at(millis: u32) -> Instant {
+        Instant::from_millis(millis)
+    }
+
+    fn packet(buffer: &mut [u8], sha: mac::Addr, message: Message) -> Packet<&mut [u8]> {
+        let mut arp = Packet::new(buffer);
+        arp.set_sha(sha);
+        message.write(&mut arp);
+        arp
+    }
+
+    // drains the actions that are due
+    fn drain(resolver: &mut Resolver<U2, U64>, now: Instant) {
+        while resolver.poll(now).is_some() {}
+    }
+
+    #[test]
+    fn resolution() {
+        let mut resolver = Resolver::<U2, U64>::new(MAC, MAX_AGE);
+        resolver.set_address(IP, at(0));
+        drain(&mut resolver, at(0));
+
+        assert_eq!(resolver.send(PEER_IP, b"hello", at(0)), Ok(None));
+        assert_eq!(
+            resolver.send(PEER_IP, &[0; 65], at(0)),
+            Err(Error::TooLarge)
+        );
+        assert_eq!(
+            resolver.poll(at(0)),
+            Some(Action::Send(Message::Request {
+                spa: IP,
+                tpa: PEER_IP
+            }))
+        );
+        assert_eq!(resolver.poll(at(500)), None);
+
+        let mut buffer = [0; 28];
+        let reply = packet(
+            &mut buffer,
+            PEER_MAC,
+            Message::Reply {
+                spa: PEER_IP,
+                tha: MAC,
+                tpa: IP,
+            },
+        );
+        assert_eq!(
+            resolver.handle_packet(&reply, at(600)),
+            Some((PEER_MAC, &b"hello"[..]))
+        );
+        assert_eq!(
+            resolver.send(PEER_IP, b"world", at(700)),
+            Ok(Some(PEER_MAC))
+        );
+
+        // aging
+        drain(&mut resolver, at(60_600));
+        assert_eq!(resolver.get(PEER_IP), None);
+        assert!(resolver.is_empty());
+    }
+
+    #[test]
+    fn unreachable() {
+        let mut resolver = Resolver::<U2, U64>::new(MAC, MAX_AGE);
+
+        assert_eq!(resolver.send(PEER_IP, b"hello", at(0)), Ok(None));
+        for i in 0..3 {
+            assert_eq!(
+                resolver.poll(at(i * 1_000)),
+                Some(Action::Send(Message::Request {
+                    spa: ipv4::Addr::UNSPECIFIED,
+                    tpa: PEER_IP
+                }))
+            );
+        }
+        assert_eq!(resolver.poll(at(3_000)), Some(Action::Unreachable(PEER_IP)));
+        assert!(resolver.is_empty());
+    }
+
+    #[test]
+    fn reply() {
+        let mut resolver = Resolver::<U2, U64>::new(MAC, MAX_AGE);
+        resolver.set_address(IP, at(0));
+        drain(&mut resolver, at(0));
+
+        let mut buffer = [0; 28];
+        let request = packet(
+            &mut buffer,
+            PEER_MAC,
+            Message::Request {
+                spa: PEER_IP,
+                tpa: IP,
+            },
+        );
+        assert_eq!(request.get_oper(), Operation::Request);
+        assert_eq!(resolver.handle_packet(&request, at(0)), None);
+
+        let reply = resolver.poll(at(0));
+        assert_eq!(
+            reply,
+            Some(Action::Send(Message::Reply {
+                spa: IP,
+                tha: PEER_MAC,
+                tpa: PEER_IP
+            }))
+        );
+        if let Some(Action::Send(message)) = reply {
+            assert_eq!(message.destination(), PEER_MAC);
+        }
+
+        // the sender was added to the cache
+        assert_eq!(resolver.get(PEER_IP), Some(PEER_MAC));
+    }
+
+    #[test]
+    fn acd() {
+        let mut resolver = Resolver::<U2, U64>::new(MAC, MAX_AGE);
+        resolver.claim(IP, at(0));
+
+        for i in 0..3 {
+            assert_eq!(
+                resolver.poll(at(i * 1_000)),
+                Some(Action::Send(Message::Probe(IP)))
+            );
+        }
+        assert_eq!(resolver.address(), None);
+
+        assert_eq!(
+            resolver.poll(at(4_000)),
+            Some(Action::Send(Message::Announce(IP)))
+        );
+        assert_eq!(resolver.address(), Some(IP));
+        assert_eq!(resolver.poll(at(5_000)), None);
+        assert_eq!(
+            resolver.poll(at(6_000)),
+            Some(Action::Send(Message::Announce(IP)))
+        );
+        assert_eq!(resolver.poll(at(8_000)), None);
+
+        // a conflicting announcement is defended against
+        let mut buffer = [0; 28];
+        let announcement = packet(&mut buffer, PEER_MAC, Message::Announce(IP));
+        assert_eq!(resolver.handle_packet(&announcement, at(10_000)), None);
+        assert_eq!(
+            resolver.poll(at(10_000)),
+            Some(Action::Send(Message::Announce(IP)))
+        );
+
+        // ... but only once per `DEFEND_INTERVAL`
+        assert_eq!(resolver.handle_packet(&announcement, at(12_000)), None);
+        assert_eq!(resolver.poll(at(12_000)), Some(Action::Conflict(IP)));
+        assert_eq!(resolver.address(), None);
+    }
+
+    #[test]
+    fn simultaneous_probe() {
+        let mut resolver = Resolver::<U2, U64>::new(MAC, MAX_AGE);
+        resolver.claim(IP, at(0));
+        assert_eq!(resolver.poll(at(0)), Some(Action::Send(Message::Probe(IP))));
+
+        let mut buffer = [0; 28];
+        let probe = packet(&mut buffer, PEER_MAC, Message::Probe(IP));
+        assert!(probe.is_a_probe());
+        assert_eq!(resolver.handle_packet(&probe, at(500)), None);
+        assert_eq!(resolver.poll(at(500)), Some(Action::Conflict(IP)));
+        assert_eq!(resolver.poll(at(5_000)), None);
+    }
+}
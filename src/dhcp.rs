@@ -0,0 +1,801 @@
+//! DHCP: Dynamic Host Configuration Protocol (IPv4)
+//!
+//! # References
+//!
+//! - [RFC 951: Bootstrap Protocol (BOOTP)][0]
+//!
+//! [0]: https://tools.ietf.org/html/rfc951
+//!
+//! - [RFC 2131: Dynamic Host Configuration Protocol][1]
+//!
+//! [1]: https://tools.ietf.org/html/rfc2131
+//!
+//! - [RFC 2132: DHCP Options and BOOTP Vendor Extensions][2]
+//!
+//! [2]: https://tools.ietf.org/html/rfc2132
+
+use core::{
+    cmp, fmt,
+    ops::{Range, RangeFrom},
+};
+
+use as_slice::{AsMutSlice, AsSlice};
+use byteorder::{ByteOrder, NetworkEndian as NE};
+use cast::{u16, usize};
+
+use crate::{ipv4, mac, traits::UncheckedIndex};
+
+pub mod client;
+pub mod server;
+
+/// UDP port of DHCP servers
+pub const SERVER_PORT: u16 = 67;
+
+/// UDP port of DHCP clients
+pub const CLIENT_PORT: u16 = 68;
+
+/// Magic cookie that precedes the options (RFC 2131 - Section 3)
+pub const MAGIC_COOKIE: [u8; 4] = [99, 130, 83, 99];
+
+/* Message format */
+const OP: usize = 0;
+const HTYPE: usize = 1;
+const HLEN: usize = 2;
+const HOPS: usize = 3;
+const XID: Range<usize> = 4..8;
+const SECS: Range<usize> = 8..10;
+const FLAGS: usize = 10;
+const CIADDR: Range<usize> = 12..16;
+const YIADDR: Range<usize> = 16..20;
+const SIADDR: Range<usize> = 20..24;
+const GIADDR: Range<usize> = 24..28;
+const CHADDR: Range<usize> = 28..44;
+const COOKIE: Range<usize> = 236..240;
+const OPTIONS: RangeFrom<usize> = 240..;
+
+// Flags (first byte)
+mod broadcast {
+    pub const MASK: u8 = (1 << SIZE) - 1;
+    pub const OFFSET: u8 = 7;
+    pub const SIZE: u8 = 1;
+}
+
+/// Size of the fixed-format part of a DHCP message, including the magic cookie
+pub const HEADER_SIZE: u8 = OPTIONS.start as u8;
+
+/// Minimum size of a BOOTP message; shorter messages are padded (RFC 1542 - Section 2.1)
+pub const MIN_SIZE: u16 = 300;
+
+// Hardware type: Ethernet
+const ETHERNET: u8 = 1;
+
+/// DHCP message
+// NOTE Invariants
+// - Options are always valid: they don't overrun the buffer
+// - `end` is the index of the End option or, if there's no End option, the end of the buffer
+pub struct Message<BUFFER>
+where
+    BUFFER: AsSlice<Element = u8>,
+{
+    buffer: BUFFER,
+    end: u16,
+    len: u16,
+}
+
+impl<B> Message<B>
+where
+    B: AsSlice<Element = u8>,
+{
+    /* Constructors */
+    /// Parses bytes into a DHCP message
+    ///
+    /// NOTE option overloading (options stored in the 'sname' and 'file' fields) is not supported
+    pub fn parse(bytes: B) -> Result<Self, B> {
+        let slice = bytes.as_slice();
+
+        if slice.len() < usize(HEADER_SIZE) || slice.len() > usize::from(u16::MAX) {
+            return Err(bytes);
+        }
+
+        if unsafe { slice.r(COOKIE) } != &MAGIC_COOKIE[..] {
+            return Err(bytes);
+        }
+
+        if let Ok(end) = scan(unsafe { slice.rf(OPTIONS) }) {
+            // NOTE(cast) see the length check above
+            let len = slice.len() as u16;
+
+            Ok(Message {
+                buffer: bytes,
+                end: u16(HEADER_SIZE) + end,
+                len,
+            })
+        } else {
+            Err(bytes)
+        }
+    }
+
+    /* Getters */
+    /// Returns the 'op' field of the header
+    pub fn get_op(&self) -> Op {
+        Op::from(self.as_slice()[OP])
+    }
+
+    /// Returns the 'htype' (hardware address type) field of the header
+    pub fn get_htype(&self) -> u8 {
+        self.as_slice()[HTYPE]
+    }
+
+    /// Returns the 'hlen' (hardware address length) field of the header
+    pub fn get_hlen(&self) -> u8 {
+        self.as_slice()[HLEN]
+    }
+
+    /// Returns the 'hops' field of the header
+    pub fn get_hops(&self) -> u8 {
+        self.as_slice()[HOPS]
+    }
+
+    /// Returns the 'xid' (transaction ID) field of the header
+    pub fn get_xid(&self) -> u32 {
+        NE::read_u32(unsafe { self.as_slice().r(XID) })
+    }
+
+    /// Returns the 'secs' field of the header
+    pub fn get_secs(&self) -> u16 {
+        NE::read_u16(unsafe { self.as_slice().r(SECS) })
+    }
+
+    /// Returns the BROADCAST flag
+    pub fn get_broadcast(&self) -> bool {
+        get!(self.as_slice()[FLAGS], broadcast) == 1
+    }
+
+    /// Returns the 'ciaddr' (client IP address) field of the header
+    pub fn get_ciaddr(&self) -> ipv4::Addr {
+        self.get_addr(CIADDR)
+    }
+
+    /// Returns the 'yiaddr' ('your' IP address) field of the header
+    pub fn get_yiaddr(&self) -> ipv4::Addr {
+        self.get_addr(YIADDR)
+    }
+
+    /// Returns the 'siaddr' (next server IP address) field of the header
+    pub fn get_siaddr(&self) -> ipv4::Addr {
+        self.get_addr(SIADDR)
+    }
+
+    /// Returns the 'giaddr' (relay agent IP address) field of the header
+    pub fn get_giaddr(&self) -> ipv4::Addr {
+        self.get_addr(GIADDR)
+    }
+
+    /// Returns the 'chaddr' (client hardware address) field of the header as a MAC address
+    ///
+    /// NOTE this is only meaningful if 'htype' is Ethernet (`1`)
+    pub fn get_chaddr(&self) -> mac::Addr {
+        let mut addr = mac::Addr([0; 6]);
+        addr.0
+            .copy_from_slice(unsafe { self.as_slice().r(CHADDR.start..CHADDR.start + 6) });
+        addr
+    }
+
+    /// Returns the value of the DHCP Message Type option, if present
+    pub fn get_message_type(&self) -> Option<MessageType> {
+        self.options().find_map(|opt| match opt {
+            DhcpOption::MessageType(ty) => Some(ty),
+            _ => None,
+        })
+    }
+
+    /// Returns an iterator over the options of this message
+    pub fn options(&self) -> Options<'_> {
+        Options {
+            opts: unsafe { self.as_slice().r(OPTIONS.start..usize(self.end)) },
+        }
+    }
+
+    /// Returns the byte representation of this message
+    pub fn as_bytes(&self) -> &[u8] {
+        unsafe { self.as_slice().rt(..usize(self.len)) }
+    }
+
+    /// Returns the length of this message
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> u16 {
+        self.len
+    }
+
+    /* Miscellaneous */
+    /// Frees the underlying buffer
+    pub fn free(self) -> B {
+        self.buffer
+    }
+
+    /* Private */
+    fn as_slice(&self) -> &[u8] {
+        self.buffer.as_slice()
+    }
+
+    fn get_addr(&self, range: Range<usize>) -> ipv4::Addr {
+        let mut addr = ipv4::Addr::UNSPECIFIED;
+        addr.0.copy_from_slice(unsafe { self.as_slice().r(range) });
+        addr
+    }
+}
+
+impl<B> Message<B>
+where
+    B: AsSlice<Element = u8> + AsMutSlice<Element = u8>,
+{
+    /* Constructors */
+    /// Transforms the given buffer into a DHCP message
+    ///
+    /// This constructor zeroes the buffer and then sets these fields:
+    ///
+    /// - op = Request
+    /// - htype = Ethernet
+    /// - hlen = 6
+    /// - magic cookie
+    ///
+    /// The message has no options and is padded to `MIN_SIZE` bytes.
+    ///
+    /// # Panics
+    ///
+    /// This constructor panics if the given `buffer` is smaller than `MIN_SIZE`
+    pub fn new(mut buffer: B) -> Self {
+        let blen = buffer.as_slice().len();
+        assert!(blen >= usize(MIN_SIZE));
+
+        let slice = buffer.as_mut_slice();
+        for byte in slice.iter_mut() {
+            *byte = 0;
+        }
+
+        slice[OP] = Op::Request.into();
+        slice[HTYPE] = ETHERNET;
+        slice[HLEN] = 6;
+        slice[COOKIE].copy_from_slice(&MAGIC_COOKIE);
+        slice[OPTIONS.start] = OptionCode::End.into();
+
+        Message {
+            buffer,
+            end: u16(HEADER_SIZE),
+            len: MIN_SIZE,
+        }
+    }
+
+    /* Setters */
+    /// Sets the 'op' field of the header
+    pub fn set_op(&mut self, op: Op) {
+        self.as_mut_slice()[OP] = op.into();
+    }
+
+    /// Sets the 'hops' field of the header
+    pub fn set_hops(&mut self, hops: u8) {
+        self.as_mut_slice()[HOPS] = hops;
+    }
+
+    /// Sets the 'xid' (transaction ID) field of the header
+    pub fn set_xid(&mut self, xid: u32) {
+        NE::write_u32(unsafe { self.as_mut_slice().rm(XID) }, xid)
+    }
+
+    /// Sets the 'secs' field of the header
+    pub fn set_secs(&mut self, secs: u16) {
+        NE::write_u16(unsafe { self.as_mut_slice().rm(SECS) }, secs)
+    }
+
+    /// Sets the BROADCAST flag
+    pub fn set_broadcast(&mut self, broadcast: bool) {
+        set!(
+            self.as_mut_slice()[FLAGS],
+            broadcast,
+            if broadcast { 1 } else { 0 }
+        );
+    }
+
+    /// Sets the 'ciaddr' (client IP address) field of the header
+    pub fn set_ciaddr(&mut self, addr: ipv4::Addr) {
+        self.as_mut_slice()[CIADDR].copy_from_slice(&addr.0)
+    }
+
+    /// Sets the 'yiaddr' ('your' IP address) field of the header
+    pub fn set_yiaddr(&mut self, addr: ipv4::Addr) {
+        self.as_mut_slice()[YIADDR].copy_from_slice(&addr.0)
+    }
+
+    /// Sets the 'siaddr' (next server IP address) field of the header
+    pub fn set_siaddr(&mut self, addr: ipv4::Addr) {
+        self.as_mut_slice()[SIADDR].copy_from_slice(&addr.0)
+    }
+
+    /// Sets the 'giaddr' (relay agent IP address) field of the header
+    pub fn set_giaddr(&mut self, addr: ipv4::Addr) {
+        self.as_mut_slice()[GIADDR].copy_from_slice(&addr.0)
+    }
+
+    /// Sets the 'chaddr' (client hardware address) field of the header to the given MAC address
+    ///
+    /// This also sets 'htype' to Ethernet and 'hlen' to 6
+    pub fn set_chaddr(&mut self, addr: mac::Addr) {
+        let slice = self.as_mut_slice();
+        slice[HTYPE] = ETHERNET;
+        slice[HLEN] = 6;
+        for byte in slice[CHADDR].iter_mut() {
+            *byte = 0;
+        }
+        slice[CHADDR.start..CHADDR.start + 6].copy_from_slice(&addr.0);
+    }
+
+    /// Appends an option to this message
+    ///
+    /// The option is placed before the End option, which is moved to make room for it.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if the buffer is not large enough to hold the option
+    pub fn add_option(&mut self, opt: &DhcpOption<'_>) {
+        let start = usize(self.end);
+        let size = opt.size();
+        assert!(start + size < self.as_slice().len());
+
+        let slice = self.as_mut_slice();
+        opt.write(&mut slice[start..start + size]);
+        slice[start + size] = OptionCode::End.into();
+
+        // NOTE(cast) see the `assert` above; the buffer is never larger than `u16::MAX`
+        self.end = (start + size) as u16;
+        self.len = cmp::max(self.len, self.end + 1);
+    }
+
+    /* Private */
+    fn as_mut_slice(&mut self) -> &mut [u8] {
+        self.buffer.as_mut_slice()
+    }
+}
+
+impl<B> fmt::Debug for Message<B>
+where
+    B: AsSlice<Element = u8>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("dhcp::Message")
+            .field("op", &self.get_op())
+            .field("xid", &self.get_xid())
+            .field("secs", &self.get_secs())
+            .field("broadcast", &self.get_broadcast())
+            .field("ciaddr", &self.get_ciaddr())
+            .field("yiaddr", &self.get_yiaddr())
+            .field("siaddr", &self.get_siaddr())
+            .field("giaddr", &self.get_giaddr())
+            .field("chaddr", &self.get_chaddr())
+            .field("message_type", &self.get_message_type())
+            .finish()
+    }
+}
+
+/// DHCP option
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DhcpOption<'a> {
+    /// Subnet Mask
+    SubnetMask(ipv4::Addr),
+    /// Routers on the client's subnet, in order of preference
+    Router(Addrs<'a>),
+    /// Domain Name Servers, in order of preference
+    DomainNameServer(Addrs<'a>),
+    /// Requested IP Address
+    RequestedIp(ipv4::Addr),
+    /// IP Address Lease Time, in seconds; `u32::MAX` means infinity
+    LeaseTime(u32),
+    /// DHCP Message Type
+    MessageType(MessageType),
+    /// Server Identifier
+    ServerId(ipv4::Addr),
+    /// Parameter Request List: a list of option codes
+    ParameterRequestList(&'a [u8]),
+    /// Renewal (T1) Time Value, in seconds
+    RenewalTime(u32),
+    /// Rebinding (T2) Time Value, in seconds
+    RebindingTime(u32),
+    /// An option this crate doesn't know about or a malformed option
+    Unknown {
+        /// Option code
+        code: u8,
+        /// Contents of the option, excluding the Code and Length fields
+        contents: &'a [u8],
+    },
+}
+
+impl<'a> DhcpOption<'a> {
+    fn parse(code: OptionCode, contents: &'a [u8]) -> Self {
+        let addr = || {
+            let mut addr = ipv4::Addr::UNSPECIFIED;
+            addr.0.copy_from_slice(contents);
+            addr
+        };
+
+        let four = contents.len() == 4;
+        let addrs = !contents.is_empty() && contents.chunks_exact(4).remainder().is_empty();
+
+        match code {
+            OptionCode::SubnetMask if four => DhcpOption::SubnetMask(addr()),
+            OptionCode::Router if addrs => DhcpOption::Router(Addrs { bytes: contents }),
+            OptionCode::DomainNameServer if addrs => {
+                DhcpOption::DomainNameServer(Addrs { bytes: contents })
+            }
+            OptionCode::RequestedIp if four => DhcpOption::RequestedIp(addr()),
+            OptionCode::LeaseTime if four => DhcpOption::LeaseTime(NE::read_u32(contents)),
+            OptionCode::MessageType if contents.len() == 1 => {
+                DhcpOption::MessageType(MessageType::from(contents[0]))
+            }
+            OptionCode::ServerId if four => DhcpOption::ServerId(addr()),
+            OptionCode::ParameterRequestList => DhcpOption::ParameterRequestList(contents),
+            OptionCode::RenewalTime if four => DhcpOption::RenewalTime(NE::read_u32(contents)),
+            OptionCode::RebindingTime if four => DhcpOption::RebindingTime(NE::read_u32(contents)),
+            _ => DhcpOption::Unknown {
+                code: code.into(),
+                contents,
+            },
+        }
+    }
+
+    // Size of this option in bytes, including the Code and Length fields
+    fn size(&self) -> usize {
+        2 + match self {
+            DhcpOption::SubnetMask(..)
+            | DhcpOption::RequestedIp(..)
+            | DhcpOption::LeaseTime(..)
+            | DhcpOption::ServerId(..)
+            | DhcpOption::RenewalTime(..)
+            | DhcpOption::RebindingTime(..) => 4,
+            DhcpOption::Router(addrs) | DhcpOption::DomainNameServer(addrs) => addrs.bytes.len(),
+            DhcpOption::MessageType(..) => 1,
+            DhcpOption::ParameterRequestList(contents) | DhcpOption::Unknown { contents, .. } => {
+                contents.len()
+            }
+        }
+    }
+
+    // Writes this option into `buffer`, which must be exactly `self.size()` bytes long
+    fn write(&self, buffer: &mut [u8]) {
+        let size = buffer.len();
+        assert!(size <= 257);
+
+        let code = match *self {
+            DhcpOption::SubnetMask(addr) => {
+                buffer[2..].copy_from_slice(&addr.0);
+                OptionCode::SubnetMask
+            }
+            DhcpOption::Router(addrs) => {
+                buffer[2..].copy_from_slice(addrs.bytes);
+                OptionCode::Router
+            }
+            DhcpOption::DomainNameServer(addrs) => {
+                buffer[2..].copy_from_slice(addrs.bytes);
+                OptionCode::DomainNameServer
+            }
+            DhcpOption::RequestedIp(addr) => {
+                buffer[2..].copy_from_slice(&addr.0);
+                OptionCode::RequestedIp
+            }
+            DhcpOption::LeaseTime(secs) => {
+                NE::write_u32(&mut buffer[2..], secs);
+                OptionCode::LeaseTime
+            }
+            DhcpOption::MessageType(ty) => {
+                buffer[2] = ty.into();
+                OptionCode::MessageType
+            }
+            DhcpOption::ServerId(addr) => {
+                buffer[2..].copy_from_slice(&addr.0);
+                OptionCode::ServerId
+            }
+            DhcpOption::ParameterRequestList(codes) => {
+                buffer[2..].copy_from_slice(codes);
+                OptionCode::ParameterRequestList
+            }
+            DhcpOption::RenewalTime(secs) => {
+                NE::write_u32(&mut buffer[2..], secs);
+                OptionCode::RenewalTime
+            }
+            DhcpOption::RebindingTime(secs) => {
+                NE::write_u32(&mut buffer[2..], secs);
+                OptionCode::RebindingTime
+            }
+            DhcpOption::Unknown { code, contents } => {
+                buffer[2..].copy_from_slice(contents);
+                OptionCode::from(code)
+            }
+        };
+
+        buffer[0] = code.into();
+        // NOTE(cast) see the `assert` above
+        buffer[1] = (size - 2) as u8;
+    }
+}
+
+/// A list of IPv4 addresses carried by an option
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Addrs<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Addrs<'a> {
+    /// Creates a list from addresses laid out back to back
+    ///
+    /// `Addrs::new(&addr.0)` creates a list that contains a single address
+    ///
+    /// # Panics
+    ///
+    /// This constructor panics if the length of `bytes` is not a non-zero multiple of 4
+    pub fn new(bytes: &'a [u8]) -> Self {
+        assert!(!bytes.is_empty() && bytes.chunks_exact(4).remainder().is_empty());
+
+        Addrs { bytes }
+    }
+
+    /// Returns the first (preferred) address of the list
+    pub fn first(&self) -> ipv4::Addr {
+        let mut addr = ipv4::Addr::UNSPECIFIED;
+        addr.0.copy_from_slice(&self.bytes[..4]);
+        addr
+    }
+
+    /// Returns an iterator over the addresses
+    pub fn iter(&self) -> impl Iterator<Item = ipv4::Addr> + 'a {
+        self.bytes.chunks_exact(4).map(|chunk| {
+            let mut addr = ipv4::Addr::UNSPECIFIED;
+            addr.0.copy_from_slice(chunk);
+            addr
+        })
+    }
+
+    /// Returns the addresses laid out back to back
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.bytes
+    }
+}
+
+/// Iterator over the options of a DHCP message
+///
+/// Pad options are skipped
+#[derive(Clone)]
+pub struct Options<'a> {
+    opts: &'a [u8],
+}
+
+impl<'a> Iterator for Options<'a> {
+    type Item = DhcpOption<'a>;
+
+    fn next(&mut self) -> Option<DhcpOption<'a>> {
+        loop {
+            let code = OptionCode::from(*self.opts.first()?);
+
+            if code == OptionCode::Pad {
+                self.opts = unsafe { self.opts.rf(1..) };
+                continue;
+            }
+
+            // NOTE `scan` already validated the options
+            unsafe {
+                let len = usize::from(*self.opts.gu(1));
+                let contents = self.opts.r(2..2 + len);
+                self.opts = self.opts.rf(2 + len..);
+
+                return Some(DhcpOption::parse(code, contents));
+            }
+        }
+    }
+}
+
+// Validates the options and returns the index of the End option or the length of the options, if
+// there's no End option
+fn scan(opts: &[u8]) -> Result<u16, ()> {
+    let mut cursor = 0;
+
+    while let Some(code) = opts.get(cursor) {
+        match OptionCode::from(*code) {
+            OptionCode::End => break,
+            OptionCode::Pad => cursor += 1,
+            _ => {
+                let len = usize::from(*opts.get(cursor + 1).ok_or(())?);
+                cursor += 2 + len;
+
+                if cursor > opts.len() {
+                    return Err(());
+                }
+            }
+        }
+    }
+
+    u16(cursor).map_err(|_| ())
+}
+
+full_range!(
+    u8,
+    /// 'op' field of a DHCP message
+    #[derive(Clone, Copy, Debug, Eq, PartialEq)]
+    pub enum Op {
+        /// BOOTREQUEST: message sent by a client
+        Request = 1,
+        /// BOOTREPLY: message sent by a server
+        Reply = 2,
+    }
+);
+
+full_range!(
+    u8,
+    /// DHCP message type
+    #[derive(Clone, Copy, Debug, Eq, PartialEq)]
+    pub enum MessageType {
+        /// DHCPDISCOVER
+        Discover = 1,
+        /// DHCPOFFER
+        Offer = 2,
+        /// DHCPREQUEST
+        Request = 3,
+        /// DHCPDECLINE
+        Decline = 4,
+        /// DHCPACK
+        Ack = 5,
+        /// DHCPNAK
+        Nak = 6,
+        /// DHCPRELEASE
+        Release = 7,
+        /// DHCPINFORM
+        Inform = 8,
+    }
+);
+
+// From RFC 2132
+full_range!(
+    u8,
+    /// DHCP option code
+    #[derive(Clone, Copy, Debug, Eq, PartialEq)]
+    pub enum OptionCode {
+        /// Pad
+        Pad = 0,
+        /// Subnet Mask
+        SubnetMask = 1,
+        /// Router
+        Router = 3,
+        /// Domain Name Server
+        DomainNameServer = 6,
+        /// Requested IP Address
+        RequestedIp = 50,
+        /// IP Address Lease Time
+        LeaseTime = 51,
+        /// DHCP Message Type
+        MessageType = 53,
+        /// Server Identifier
+        ServerId = 54,
+        /// Parameter Request List
+        ParameterRequestList = 55,
+        /// Renewal (T1) Time Value
+        RenewalTime = 58,
+        /// Rebinding (T2) Time Value
+        RebindingTime = 59,
+        /// End
+        End = 255,
+    }
+);
+
+#[cfg(test)]
+mod tests {
+    use super::{Addrs, DhcpOption, Message, MessageType, Op, OptionCode, MIN_SIZE};
+    use crate::{ipv4, mac};
+
+    const MAC: mac::Addr = mac::Addr([0x20, 0x19, 0x02, 0x01, 0x23, 0x59]);
+    const SERVER: ipv4::Addr = ipv4::Addr([192, 168, 1, 1]);
+    const CLIENT: ipv4::Addr = ipv4::Addr([192, 168, 1, 33]);
+
+    #[test]
+    fn roundtrip() {
+        let mut array = [0xff; 576];
+        let mut m = Message::new(&mut array[..]);
+        m.set_op(Op::Reply);
+        m.set_xid(0xdead_beef);
+        m.set_broadcast(true);
+        m.set_yiaddr(CLIENT);
+        m.set_chaddr(MAC);
+        m.add_option(&DhcpOption::MessageType(MessageType::Offer));
+        m.add_option(&DhcpOption::ServerId(SERVER));
+        m.add_option(&DhcpOption::LeaseTime(3600));
+        m.add_option(&DhcpOption::SubnetMask(ipv4::Addr([255, 255, 255, 0])));
+        m.add_option(&DhcpOption::Router(Addrs::new(&SERVER.0)));
+        m.add_option(&DhcpOption::DomainNameServer(Addrs::new(&[
+            8, 8, 8, 8, 8, 8, 4, 4,
+        ])));
+        m.add_option(&DhcpOption::Unknown {
+            code: 12,
+            contents: b"jnet",
+        });
+
+        // padded to the minimum BOOTP size
+        assert_eq!(m.len(), MIN_SIZE);
+        let bytes = m.as_bytes();
+        assert_eq!(bytes[0], 2);
+        assert_eq!(&bytes[236..243], &[99, 130, 83, 99, 53, 1, 2]);
+
+        let m = Message::parse(bytes).unwrap();
+        assert_eq!(m.get_op(), Op::Reply);
+        assert_eq!(m.get_htype(), 1);
+        assert_eq!(m.get_hlen(), 6);
+        assert_eq!(m.get_xid(), 0xdead_beef);
+        assert!(m.get_broadcast());
+        assert_eq!(m.get_ciaddr(), ipv4::Addr::UNSPECIFIED);
+        assert_eq!(m.get_yiaddr(), CLIENT);
+        assert_eq!(m.get_chaddr(), MAC);
+        assert_eq!(m.get_message_type(), Some(MessageType::Offer));
+
+        let mut opts = m.options();
+        assert_eq!(
+            opts.next(),
+            Some(DhcpOption::MessageType(MessageType::Offer))
+        );
+        assert_eq!(opts.next(), Some(DhcpOption::ServerId(SERVER)));
+        assert_eq!(opts.next(), Some(DhcpOption::LeaseTime(3600)));
+        assert_eq!(
+            opts.next(),
+            Some(DhcpOption::SubnetMask(ipv4::Addr([255, 255, 255, 0])))
+        );
+        match opts.next() {
+            Some(DhcpOption::Router(addrs)) => assert_eq!(addrs.first(), SERVER),
+            _ => panic!(),
+        }
+        match opts.next() {
+            Some(DhcpOption::DomainNameServer(addrs)) => {
+                let mut servers = addrs.iter();
+                assert_eq!(servers.next(), Some(ipv4::Addr([8, 8, 8, 8])));
+                assert_eq!(servers.next(), Some(ipv4::Addr([8, 8, 4, 4])));
+                assert_eq!(servers.next(), None);
+            }
+            _ => panic!(),
+        }
+        assert_eq!(
+            opts.next(),
+            Some(DhcpOption::Unknown {
+                code: 12,
+                contents: b"jnet"
+            })
+        );
+        assert_eq!(opts.next(), None);
+    }
+
+    #[test]
+    fn parse() {
+        let mut array = [0; 300];
+        Message::new(&mut array[..]);
+
+        // missing magic cookie
+        let mut bad = array;
+        bad[236] = 0;
+        assert!(Message::parse(&bad[..]).is_err());
+
+        // truncated option
+        let mut bad = array;
+        bad[240] = OptionCode::LeaseTime.into();
+        bad[241] = 100;
+        assert!(Message::parse(&bad[..260]).is_err());
+
+        // pad options are skipped; malformed options are reported as unknown
+        let mut ok = array;
+        ok[240..247].copy_from_slice(&[0, 0, 53, 2, 1, 2, 255]);
+        let m = Message::parse(&ok[..]).unwrap();
+        let mut opts = m.options();
+        assert_eq!(
+            opts.next(),
+            Some(DhcpOption::Unknown {
+                code: 53,
+                contents: &[1, 2]
+            })
+        );
+        assert_eq!(opts.next(), None);
+        assert_eq!(m.get_message_type(), None);
+
+        // too short
+        assert!(Message::parse(&array[..239]).is_err());
+    }
+}
@@ -0,0 +1,606 @@
+//! DHCP client
+//!
+//! # References
+//!
+//! - [RFC 2131: Dynamic Host Configuration Protocol][0], section 4.4 "DHCP client behavior"
+//!
+//! [0]: https://tools.ietf.org/html/rfc2131
+
+use core::cmp;
+
+use as_slice::{AsMutSlice, AsSlice};
+use owning_slice::Truncate;
+
+use crate::{
+    dhcp::{DhcpOption, Message, MessageType, Op, CLIENT_PORT, SERVER_PORT},
+    ipv4, mac,
+    time::{Duration, Instant},
+    Invalid,
+};
+
+/// Time to wait for a reply before retransmitting a message for the first time; this timeout is
+/// doubled after every retransmission
+pub const INITIAL_TIMEOUT: Duration = Duration::from_millis(4_000);
+
+/// Maximum time to wait for a reply before retransmitting a message
+pub const MAX_TIMEOUT: Duration = Duration::from_millis(64_000);
+
+/// Minimum time between two retransmissions while renewing or rebinding a lease
+pub const MIN_RENEWAL_TIMEOUT: Duration = Duration::from_millis(60_000);
+
+/// Number of DHCPREQUEST messages sent in the REQUESTING state before starting over
+pub const MAX_REQUESTS: u8 = 4;
+
+/// Longest finite lease the client can track, in seconds (~49.7 days); longer leases are
+/// shortened to this value
+pub const MAX_LEASE_TIME: u32 = u32::MAX / 1_000;
+
+// Options the client asks for
+const PARAMETER_REQUEST_LIST: &[u8] = &[1, 3, 6];
+
+/// State of the client
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum State {
+    /// No lease; about to look for servers
+    Init,
+    /// Waiting for a DHCPOFFER
+    Selecting,
+    /// Waiting for the server to acknowledge the offer
+    Requesting,
+    /// A lease has been obtained
+    Bound,
+    /// Trying to extend the lease with the server that granted it
+    Renewing,
+    /// Trying to extend the lease with any server
+    Rebinding,
+}
+
+/// Configuration parameters obtained from a server
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Lease {
+    /// The address assigned to the client
+    pub addr: ipv4::Addr,
+    /// The Server Identifier of the server that granted the lease
+    pub server: ipv4::Addr,
+    /// Subnet mask
+    pub subnet_mask: Option<ipv4::Addr>,
+    /// Default router
+    pub router: Option<ipv4::Addr>,
+    /// Preferred Domain Name Server
+    pub dns: Option<ipv4::Addr>,
+    /// Duration of the lease, in seconds; `u32::MAX` means infinity
+    ///
+    /// Finite leases are capped to `MAX_LEASE_TIME`
+    pub lease_time: u32,
+}
+
+/// An action the caller must take on behalf of the client
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Action {
+    /// A DHCP message must be sent; build it with `Client::write`
+    Send,
+
+    /// A lease was obtained or renewed; the interface must be configured accordingly
+    Bound(Lease),
+
+    /// The lease expired or the server revoked it; the address must no longer be used
+    Unbound(ipv4::Addr),
+}
+
+/// DHCP client state machine
+///
+/// The client doesn't send or receive packets on its own. The caller feeds it the DHCP messages
+/// it receives on `CLIENT_PORT` with `handle_message` and calls `poll` periodically (e.g. every
+/// 100 ms); when `poll` returns `Action::Send` the caller builds the message with `write` and
+/// sends it.
+pub struct Client {
+    mac: mac::Addr,
+    xid: u32,
+    state: State,
+    // (re)transmission schedule
+    timer: Instant,
+    timeout: Duration,
+    transmissions: u8,
+    // (yiaddr, server identifier) of the selected offer
+    offer: Option<(ipv4::Addr, ipv4::Addr)>,
+    lease: Option<Lease>,
+    // remaining time until T1, T2 and the expiration of the lease, as of `last`
+    last: Instant,
+    t1: Duration,
+    t2: Duration,
+    expiration: Duration,
+    action: Option<Action>,
+}
+
+impl Client {
+    /// Creates a client for the interface with address `mac`
+    ///
+    /// `xid` is the transaction ID of the first exchange; it should be random
+    pub fn new(mac: mac::Addr, xid: u32, now: Instant) -> Self {
+        Client {
+            mac,
+            xid,
+            state: State::Init,
+            timer: now,
+            timeout: Duration::from_millis(0),
+            transmissions: 0,
+            offer: None,
+            lease: None,
+            last: now,
+            t1: Duration::from_millis(0),
+            t2: Duration::from_millis(0),
+            expiration: Duration::from_millis(0),
+            action: None,
+        }
+    }
+
+    /// Returns the state of the client
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    /// Returns the current lease, if any
+    pub fn lease(&self) -> Option<Lease> {
+        self.lease
+    }
+
+    /// Processes a DHCP message sent to the client
+    pub fn handle_message<B>(&mut self, m: &Message<B>, now: Instant)
+    where
+        B: AsSlice<Element = u8>,
+    {
+        if m.get_op() != Op::Reply || m.get_xid() != self.xid || m.get_chaddr() != self.mac {
+            return;
+        }
+
+        let mut ty = None;
+        let mut server = None;
+        let mut lease_time = None;
+        let mut t1 = None;
+        let mut t2 = None;
+        let mut subnet_mask = None;
+        let mut router = None;
+        let mut dns = None;
+        for opt in m.options() {
+            match opt {
+                DhcpOption::MessageType(t) => ty = Some(t),
+                DhcpOption::ServerId(addr) => server = Some(addr),
+                DhcpOption::LeaseTime(secs) => lease_time = Some(secs),
+                DhcpOption::RenewalTime(secs) => t1 = Some(secs),
+                DhcpOption::RebindingTime(secs) => t2 = Some(secs),
+                DhcpOption::SubnetMask(mask) => subnet_mask = Some(mask),
+                DhcpOption::Router(addrs) => router = Some(addrs.first()),
+                DhcpOption::DomainNameServer(addrs) => dns = Some(addrs.first()),
+                _ => {}
+            }
+        }
+
+        match (self.state, ty) {
+            // RFC 2131 - Section 4.4.1
+            (State::Selecting, Some(MessageType::Offer)) => {
+                let yiaddr = m.get_yiaddr();
+
+                if let Some(server) = server {
+                    if yiaddr != ipv4::Addr::UNSPECIFIED {
+                        self.offer = Some((yiaddr, server));
+                        self.transition(State::Requesting, now);
+                    }
+                }
+            }
+
+            // RFC 2131 - Sections 4.4.1 and 4.4.5
+            (State::Requesting, Some(MessageType::Ack))
+            | (State::Renewing, Some(MessageType::Ack))
+            | (State::Rebinding, Some(MessageType::Ack)) => {
+                let server = server
+                    .or_else(|| self.offer.map(|(_, server)| server))
+                    .or_else(|| self.lease.map(|lease| lease.server));
+
+                if let (Some(lease_time), Some(server)) = (lease_time, server) {
+                    let lease = Lease {
+                        addr: m.get_yiaddr(),
+                        server,
+                        subnet_mask,
+                        router,
+                        dns,
+                        lease_time,
+                    };
+
+                    self.bind(lease, t1, t2, now);
+                }
+            }
+
+            (State::Requesting, Some(MessageType::Nak))
+            | (State::Renewing, Some(MessageType::Nak))
+            | (State::Rebinding, Some(MessageType::Nak)) => {
+                if let Some(lease) = self.lease {
+                    self.action = Some(Action::Unbound(lease.addr));
+                }
+
+                self.restart(now);
+            }
+
+            _ => {}
+        }
+    }
+
+    /// Advances the timers of the client
+    ///
+    /// If this returns an `Action` the caller must carry it out *now*. Call this method again
+    /// until it returns `None` as several actions may be due at the same time.
+    pub fn poll(&mut self, now: Instant) -> Option<Action> {
+        if let Some(action) = self.action.take() {
+            return Some(action);
+        }
+
+        // RFC 2131 - Section 4.4.5
+        if let Some(lease) = self.lease {
+            if lease.lease_time != u32::MAX {
+                let elapsed = now.duration_since(self.last);
+                self.last = now;
+                self.t1 = self.t1 - elapsed;
+                self.t2 = self.t2 - elapsed;
+                self.expiration = self.expiration - elapsed;
+
+                let zero = Duration::from_millis(0);
+                if self.expiration == zero {
+                    self.restart(now);
+
+                    return Some(Action::Unbound(lease.addr));
+                } else if self.t2 == zero && self.state != State::Rebinding {
+                    self.xid = self.xid.wrapping_add(1);
+                    self.transition(State::Rebinding, now);
+                } else if self.t1 == zero && self.state == State::Bound {
+                    self.xid = self.xid.wrapping_add(1);
+                    self.transition(State::Renewing, now);
+                }
+            }
+        }
+
+        if self.state == State::Init {
+            self.transition(State::Selecting, now);
+        }
+
+        if self.state == State::Bound
+            || (self.transmissions != 0 && now.duration_since(self.timer) < self.timeout)
+        {
+            return None;
+        }
+
+        if self.state == State::Requesting && self.transmissions >= MAX_REQUESTS {
+            // the server didn't answer; look for another one
+            self.restart(now);
+            return self.poll(now);
+        }
+
+        self.timeout = match self.state {
+            State::Renewing => cmp::max(half(self.t2), MIN_RENEWAL_TIMEOUT),
+            State::Rebinding => cmp::max(half(self.expiration), MIN_RENEWAL_TIMEOUT),
+            _ => {
+                let mut timeout = INITIAL_TIMEOUT;
+                for _ in 0..self.transmissions {
+                    timeout = cmp::min(timeout + timeout, MAX_TIMEOUT);
+                }
+                timeout
+            }
+        };
+        self.timer = now;
+        self.transmissions = self.transmissions.saturating_add(1);
+
+        Some(Action::Send)
+    }
+
+    /// Fills the given IPv4 packet with the DHCP message the client needs to send
+    ///
+    /// This sets the Source and Destination addresses of the packet; the packet must be sent to
+    /// the broadcast MAC address unless its destination is a unicast address. This method should
+    /// only be called after `poll` returned `Action::Send`; otherwise the packet is left untouched.
+    pub fn write<B>(&self, ip: &mut ipv4::Packet<B, Invalid>)
+    where
+        B: AsSlice<Element = u8> + AsMutSlice<Element = u8> + Truncate<u16>,
+    {
+        let (src, dest) = match (self.state, self.lease) {
+            (State::Selecting, _) | (State::Requesting, _) => {
+                (ipv4::Addr::UNSPECIFIED, ipv4::Addr::BROADCAST)
+            }
+            (State::Renewing, Some(lease)) => (lease.addr, lease.server),
+            (State::Rebinding, Some(lease)) => (lease.addr, ipv4::Addr::BROADCAST),
+            _ => return,
+        };
+
+        ip.set_source(src);
+        ip.set_destination(dest);
+        ip.udp(|udp| {
+            udp.set_source(CLIENT_PORT);
+            udp.set_destination(SERVER_PORT);
+            udp.dhcp(|m| {
+                m.set_xid(self.xid);
+                m.set_chaddr(self.mac);
+                // we can't receive unicast IP packets until we have an address
+                m.set_broadcast(src == ipv4::Addr::UNSPECIFIED);
+                m.set_ciaddr(src);
+
+                if self.state == State::Selecting {
+                    m.add_option(&DhcpOption::MessageType(MessageType::Discover));
+                } else {
+                    m.add_option(&DhcpOption::MessageType(MessageType::Request));
+
+                    if let (State::Requesting, Some((addr, server))) = (self.state, self.offer) {
+                        m.add_option(&DhcpOption::RequestedIp(addr));
+                        m.add_option(&DhcpOption::ServerId(server));
+                    }
+                }
+
+                m.add_option(&DhcpOption::ParameterRequestList(PARAMETER_REQUEST_LIST));
+            })
+        });
+    }
+
+    /* Private */
+    fn transition(&mut self, state: State, now: Instant) {
+        self.state = state;
+        self.timer = now;
+        self.transmissions = 0;
+    }
+
+    // Starts over from the INIT state with a new transaction ID
+    fn restart(&mut self, now: Instant) {
+        self.xid = self.xid.wrapping_add(1);
+        self.offer = None;
+        self.lease = None;
+        self.transition(State::Init, now);
+    }
+
+    fn bind(&mut self, mut lease: Lease, t1: Option<u32>, t2: Option<u32>, now: Instant) {
+        if lease.lease_time != u32::MAX {
+            // `Duration` can't represent longer leases
+            lease.lease_time = cmp::min(lease.lease_time, MAX_LEASE_TIME);
+        }
+
+        // RFC 2131 - Section 4.4.5: T1 defaults to 0.5 * lease and T2 to 0.875 * lease
+        let secs = cmp::min(lease.lease_time, MAX_LEASE_TIME);
+        let t2 = cmp::min(t2.unwrap_or(secs - secs / 8), secs);
+        let t1 = cmp::min(t1.unwrap_or(secs / 2), t2);
+
+        self.last = now;
+        self.t1 = Duration::from_secs(t1);
+        self.t2 = Duration::from_secs(t2);
+        self.expiration = Duration::from_secs(secs);
+
+        self.offer = None;
+        self.lease = Some(lease);
+        self.action = Some(Action::Bound(lease));
+        self.transition(State::Bound, now);
+    }
+}
+
+fn half(duration: Duration) -> Duration {
+    Duration::from_millis(duration.as_millis() / 2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Action, Client, Lease, State, MAX_LEASE_TIME};
+    use crate::{
+        dhcp::{self, Addrs, DhcpOption, MessageType, Op},
+        ipv4, mac,
+        time::Instant,
+        udp, Valid,
+    };
+
+    const MAC: mac::Addr = mac::Addr([0x20, 0x19, 0x02, 0x01, 0x23, 0x59]);
+    const SERVER: ipv4::Addr = ipv4::Addr([192, 168, 1, 1]);
+    const CLIENT: ipv4::Addr = ipv4::Addr([192, 168, 1, 33]);
+    const XID: u32 = 0x1234_5678;
+
+    fn at(secs: u32) -> Instant {
+        Instant::from_millis(secs * 1_000)
+    }
+
+    // runs `f` on the DHCP message the client sends
+    fn sent(client: &Client, f: impl FnOnce(&ipv4::Packet<&[u8], Valid>, &dhcp::Message<&[u8]>)) {
+        let mut array = [0; 576];
+        let mut ip = ipv4::Packet::new(&mut array[..]);
+        client.write(&mut ip);
+        let ip = ip.update_checksum();
+
+        let bytes = ip.as_bytes();
+        let ip = ipv4::Packet::parse(bytes).unwrap();
+        let udp = udp::Packet::parse(ip.payload()).unwrap();
+        assert_eq!(udp.get_source(), dhcp::CLIENT_PORT);
+        assert_eq!(udp.get_destination(), dhcp::SERVER_PORT);
+        let m = dhcp::Message::parse(udp.payload()).unwrap();
+        assert_eq!(m.get_op(), Op::Request);
+        assert_eq!(m.get_chaddr(), MAC);
+
+        f(&ip, &m);
+    }
+
+    // builds a reply from the server
+    fn reply(
+        buffer: &mut [u8],
+        xid: u32,
+        ty: MessageType,
+        lease_time: u32,
+    ) -> dhcp::Message<&mut [u8]> {
+        let mut m = dhcp::Message::new(buffer);
+        m.set_op(Op::Reply);
+        m.set_xid(xid);
+        m.set_chaddr(MAC);
+        m.set_yiaddr(CLIENT);
+        m.add_option(&DhcpOption::MessageType(ty));
+        m.add_option(&DhcpOption::ServerId(SERVER));
+        m.add_option(&DhcpOption::LeaseTime(lease_time));
+        m.add_option(&DhcpOption::SubnetMask(ipv4::Addr([255, 255, 255, 0])));
+        m.add_option(&DhcpOption::Router(Addrs::new(&SERVER.0)));
+        m
+    }
+
+    #[test]
+    fn lease() {
+        let mut client = Client::new(MAC, XID, at(0));
+        assert_eq!(client.state(), State::Init);
+
+        // DISCOVER
+        assert_eq!(client.poll(at(0)), Some(Action::Send));
+        assert_eq!(client.state(), State::Selecting);
+        sent(&client, |ip, m| {
+            assert_eq!(ip.get_source(), ipv4::Addr::UNSPECIFIED);
+            assert_eq!(ip.get_destination(), ipv4::Addr::BROADCAST);
+            assert_eq!(m.get_message_type(), Some(MessageType::Discover));
+            assert!(m.get_broadcast());
+        });
+
+        // retransmission with exponential backoff
+        assert_eq!(client.poll(at(3)), None);
+        assert_eq!(client.poll(at(4)), Some(Action::Send));
+        assert_eq!(client.poll(at(11)), None);
+        assert_eq!(client.poll(at(12)), Some(Action::Send));
+
+        // OFFER
+        let mut buffer = [0; 300];
+        let offer = reply(&mut buffer, XID, MessageType::Offer, 3600);
+        client.handle_message(&offer, at(13));
+
+        // REQUEST
+        assert_eq!(client.state(), State::Requesting);
+        assert_eq!(client.poll(at(13)), Some(Action::Send));
+        sent(&client, |ip, m| {
+            assert_eq!(ip.get_destination(), ipv4::Addr::BROADCAST);
+            assert_eq!(m.get_message_type(), Some(MessageType::Request));
+            assert!(m
+                .options()
+                .any(|opt| opt == DhcpOption::RequestedIp(CLIENT)));
+            assert!(m.options().any(|opt| opt == DhcpOption::ServerId(SERVER)));
+        });
+
+        // ACK
+        let mut buffer = [0; 300];
+        let ack = reply(&mut buffer, XID, MessageType::Ack, 3600);
+        client.handle_message(&ack, at(14));
+
+        let lease = Lease {
+            addr: CLIENT,
+            server: SERVER,
+            subnet_mask: Some(ipv4::Addr([255, 255, 255, 0])),
+            router: Some(SERVER),
+            dns: None,
+            lease_time: 3600,
+        };
+        assert_eq!(client.poll(at(14)), Some(Action::Bound(lease)));
+        assert_eq!(client.state(), State::Bound);
+        assert_eq!(client.poll(at(1_000)), None);
+
+        // T1: unicast REQUEST to the server
+        assert_eq!(client.poll(at(14 + 1_800)), Some(Action::Send));
+        assert_eq!(client.state(), State::Renewing);
+        sent(&client, |ip, m| {
+            assert_eq!(ip.get_source(), CLIENT);
+            assert_eq!(ip.get_destination(), SERVER);
+            assert_eq!(m.get_ciaddr(), CLIENT);
+            // new exchange, new transaction ID
+            assert_eq!(m.get_xid(), XID + 1);
+            assert!(!m.options().any(|opt| opt == DhcpOption::ServerId(SERVER)));
+        });
+        assert_eq!(client.poll(at(14 + 1_800 + 59)), None);
+        assert_eq!(client.poll(at(14 + 1_800 + 1_000)), Some(Action::Send));
+
+        // T2: broadcast REQUEST
+        assert_eq!(client.poll(at(14 + 3_150)), Some(Action::Send));
+        assert_eq!(client.state(), State::Rebinding);
+        sent(&client, |ip, m| {
+            assert_eq!(ip.get_destination(), ipv4::Addr::BROADCAST);
+            assert_eq!(m.get_xid(), XID + 2);
+        });
+
+        // late reply to the RENEWING exchange
+        let mut buffer = [0; 300];
+        let ack = reply(&mut buffer, XID + 1, MessageType::Ack, 7200);
+        client.handle_message(&ack, at(14 + 3_151));
+        assert_eq!(client.poll(at(14 + 3_151)), None);
+
+        // renewed
+        let mut buffer = [0; 300];
+        let ack = reply(&mut buffer, XID + 2, MessageType::Ack, 7200);
+        client.handle_message(&ack, at(14 + 3_200));
+        assert_eq!(
+            client.poll(at(14 + 3_200)),
+            Some(Action::Bound(Lease {
+                lease_time: 7200,
+                ..lease
+            }))
+        );
+
+        // expiration
+        for secs in 3_215..3_214 + 7_200 {
+            if client.poll(at(secs)) == Some(Action::Unbound(CLIENT)) {
+                panic!();
+            }
+        }
+        assert_eq!(
+            client.poll(at(3_214 + 7_200)),
+            Some(Action::Unbound(CLIENT))
+        );
+        assert_eq!(client.state(), State::Init);
+        assert_eq!(client.lease(), None);
+    }
+
+    #[test]
+    fn long_lease() {
+        let mut client = Client::new(MAC, XID, at(0));
+        assert_eq!(client.poll(at(0)), Some(Action::Send));
+
+        let mut buffer = [0; 300];
+        let offer = reply(&mut buffer, XID, MessageType::Offer, 100 * 86_400);
+        client.handle_message(&offer, at(0));
+        assert_eq!(client.poll(at(0)), Some(Action::Send));
+
+        // 100 days don't fit in a `Duration`
+        let ack = reply(&mut buffer, XID, MessageType::Ack, 100 * 86_400);
+        client.handle_message(&ack, at(0));
+        match client.poll(at(0)) {
+            Some(Action::Bound(lease)) => assert_eq!(lease.lease_time, MAX_LEASE_TIME),
+            action => panic!("{:?}", action),
+        }
+
+        // T1 < T2 < expiration
+        let t1 = MAX_LEASE_TIME / 2;
+        let t2 = MAX_LEASE_TIME - MAX_LEASE_TIME / 8;
+        assert_eq!(client.poll(at(t1 - 1)), None);
+        assert_eq!(client.poll(at(t1)), Some(Action::Send));
+        assert_eq!(client.state(), State::Renewing);
+        assert_eq!(client.poll(at(t2)), Some(Action::Send));
+        assert_eq!(client.state(), State::Rebinding);
+        assert_eq!(
+            client.poll(at(MAX_LEASE_TIME)),
+            Some(Action::Unbound(CLIENT))
+        );
+    }
+
+    #[test]
+    fn nak() {
+        let mut client = Client::new(MAC, XID, at(0));
+        assert_eq!(client.poll(at(0)), Some(Action::Send));
+
+        let mut buffer = [0; 300];
+        // not for us
+        let offer = reply(&mut buffer, XID + 1, MessageType::Offer, 3600);
+        client.handle_message(&offer, at(1));
+        assert_eq!(client.state(), State::Selecting);
+
+        let offer = reply(&mut buffer, XID, MessageType::Offer, 3600);
+        client.handle_message(&offer, at(1));
+        assert_eq!(client.poll(at(1)), Some(Action::Send));
+
+        let nak = reply(&mut buffer, XID, MessageType::Nak, 0);
+        client.handle_message(&nak, at(2));
+        assert_eq!(client.state(), State::Init);
+
+        // starts over with a new transaction ID
+        assert_eq!(client.poll(at(2)), Some(Action::Send));
+        sent(&client, |_, m| {
+            assert_eq!(m.get_xid(), XID + 1);
+            assert_eq!(m.get_message_type(), Some(MessageType::Discover));
+        });
+    }
+}
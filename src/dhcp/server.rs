@@ -0,0 +1,656 @@
+//! DHCP server
+//!
+//! # References
+//!
+//! - [RFC 2131: Dynamic Host Configuration Protocol][0], section 4.3 "DHCP server behavior"
+//!
+//! [0]: https://tools.ietf.org/html/rfc2131
+
+use core::cmp;
+
+use as_slice::{AsMutSlice, AsSlice};
+use heapless::{ArrayLength, Vec};
+use owning_slice::Truncate;
+
+use crate::{
+    dhcp::{
+        client::MAX_LEASE_TIME, Addrs, DhcpOption, Message, MessageType, Op, CLIENT_PORT,
+        SERVER_PORT,
+    },
+    ipv4, mac,
+    time::{Duration, Instant},
+    Invalid,
+};
+
+/// Time an offered address is reserved for the client while it makes up its mind
+pub const OFFER_TIMEOUT: Duration = Duration::from_millis(60_000);
+
+/// Server configuration
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Config {
+    /// Address of the server; also used as its Server Identifier
+    pub addr: ipv4::Addr,
+    /// First address of the pool of addresses handed out to clients
+    pub pool_start: ipv4::Addr,
+    /// Number of addresses in the pool; the pool must not include 255.255.255.255 or `addr`
+    pub pool_size: u8,
+    /// Subnet mask of the segment
+    pub subnet_mask: ipv4::Addr,
+    /// Default router advertised to clients, if any
+    pub router: Option<ipv4::Addr>,
+    /// Domain Name Server advertised to clients, if any
+    pub dns: Option<ipv4::Addr>,
+    /// Duration of the leases, in seconds
+    ///
+    /// Infinite leases are not supported; `Server::new` caps this value to `MAX_LEASE_TIME`
+    pub lease_time: u32,
+}
+
+/// A reply the server must send; build it with `Server::write`
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Reply {
+    ty: MessageType,
+    xid: u32,
+    chaddr: mac::Addr,
+    ciaddr: ipv4::Addr,
+    yiaddr: ipv4::Addr,
+    broadcast: bool,
+}
+
+impl Reply {
+    /// Returns the type of the reply: DHCPOFFER, DHCPACK or DHCPNAK
+    pub fn get_type(&self) -> MessageType {
+        self.ty
+    }
+
+    /// Returns the address assigned to the client ('yiaddr')
+    pub fn get_yiaddr(&self) -> ipv4::Addr {
+        self.yiaddr
+    }
+
+    /// Returns the IPv4 address the reply must be sent to
+    ///
+    /// NOTE `Server::write` already sets the destination of the IPv4 packet to this address
+    pub fn destination(&self) -> ipv4::Addr {
+        // RFC 2131 - Section 4.1
+        if self.ty == MessageType::Nak {
+            ipv4::Addr::BROADCAST
+        } else if self.ciaddr != ipv4::Addr::UNSPECIFIED {
+            self.ciaddr
+        } else if self.broadcast {
+            ipv4::Addr::BROADCAST
+        } else {
+            self.yiaddr
+        }
+    }
+
+    /// Returns the MAC address the reply must be sent to
+    ///
+    /// This is the address of the client, unless the reply is broadcast
+    pub fn mac_destination(&self) -> mac::Addr {
+        if self.destination() == ipv4::Addr::BROADCAST {
+            mac::Addr::BROADCAST
+        } else {
+            self.chaddr
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum State {
+    Offered,
+    Bound,
+}
+
+/// The binding of an address to a client; see `Server`
+pub struct Binding {
+    mac: mac::Addr,
+    addr: ipv4::Addr,
+    state: State,
+    timer: Instant,
+    lifetime: Duration,
+}
+
+impl Binding {
+    fn has_expired(&self, now: Instant) -> bool {
+        now.duration_since(self.timer) >= self.lifetime
+    }
+}
+
+/// DHCP server that keeps track of up to `N` bindings
+///
+/// The server doesn't send or receive packets on its own. The caller feeds it the DHCP messages it
+/// receives on `SERVER_PORT` with `handle_message` and sends the replies it returns, which are
+/// built with `write`.
+///
+/// Messages forwarded by relay agents, DHCPDECLINE and DHCPINFORM messages are ignored.
+pub struct Server<N>
+where
+    N: ArrayLength<Binding>,
+{
+    config: Config,
+    bindings: Vec<Binding, N>,
+}
+
+impl<N> Server<N>
+where
+    N: ArrayLength<Binding>,
+{
+    /// Creates a server with the given configuration
+    ///
+    /// `config.lease_time` is capped to `MAX_LEASE_TIME`
+    ///
+    /// # Panics
+    ///
+    /// This constructor panics if the pool of addresses includes the limited broadcast address
+    /// (255.255.255.255) or the address of the server
+    pub fn new(mut config: Config) -> Self {
+        let start = u32::from_be_bytes(config.pool_start.0);
+        // NOTE the pool ends right before `end` so this also rejects 255.255.255.255
+        let end = start
+            .checked_add(u32::from(config.pool_size))
+            .expect("address pool out of range");
+        assert!(
+            !(start..end).contains(&u32::from_be_bytes(config.addr.0)),
+            "address pool includes the server address"
+        );
+        config.lease_time = cmp::min(config.lease_time, MAX_LEASE_TIME);
+
+        Server {
+            config,
+            bindings: Vec::new(),
+        }
+    }
+
+    /// Returns the configuration of the server
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Returns the address leased to the client `mac`, if any
+    pub fn lease(&self, mac: mac::Addr, now: Instant) -> Option<ipv4::Addr> {
+        self.bindings
+            .iter()
+            .find(|b| b.mac == mac && b.state == State::Bound && !b.has_expired(now))
+            .map(|b| b.addr)
+    }
+
+    /// Processes a DHCP message sent by a client
+    ///
+    /// Returns the reply the server must send, if any
+    pub fn handle_message<B>(&mut self, m: &Message<B>, now: Instant) -> Option<Reply>
+    where
+        B: AsSlice<Element = u8>,
+    {
+        if m.get_op() != Op::Request
+            || m.get_htype() != 1
+            || m.get_hlen() != 6
+            || m.get_giaddr() != ipv4::Addr::UNSPECIFIED
+        {
+            return None;
+        }
+
+        let mut ty = None;
+        let mut requested = None;
+        let mut server = None;
+        for opt in m.options() {
+            match opt {
+                DhcpOption::MessageType(t) => ty = Some(t),
+                DhcpOption::RequestedIp(addr) => requested = Some(addr),
+                DhcpOption::ServerId(addr) => server = Some(addr),
+                _ => {}
+            }
+        }
+
+        let mac = m.get_chaddr();
+        let ciaddr = m.get_ciaddr();
+        let reply = |ty, yiaddr| {
+            Some(Reply {
+                ty,
+                xid: m.get_xid(),
+                chaddr: mac,
+                ciaddr,
+                yiaddr,
+                broadcast: m.get_broadcast(),
+            })
+        };
+        let nak = || reply(MessageType::Nak, ipv4::Addr::UNSPECIFIED);
+
+        match ty? {
+            // RFC 2131 - Section 4.3.1
+            MessageType::Discover => {
+                let addr = self.allocate(mac, requested, now)?;
+
+                self.bind(mac, addr, State::Offered, now);
+                reply(MessageType::Offer, addr)
+            }
+
+            // RFC 2131 - Section 4.3.2
+            MessageType::Request => {
+                if let Some(server) = server {
+                    // SELECTING
+                    if server != self.config.addr {
+                        // the client accepted an offer from another server
+                        self.remove(|b| b.mac == mac && b.state == State::Offered);
+                        return None;
+                    }
+
+                    match (requested, self.binding(mac)) {
+                        (Some(addr), Some(b)) if b.addr == addr => {
+                            self.bind(mac, addr, State::Bound, now);
+                            reply(MessageType::Ack, addr)
+                        }
+                        _ => nak(),
+                    }
+                } else {
+                    // INIT-REBOOT, RENEWING or REBINDING
+                    let addr = requested.unwrap_or(ciaddr);
+
+                    match self.binding(mac) {
+                        Some(b) if b.addr == addr && !b.has_expired(now) => {
+                            self.bind(mac, addr, State::Bound, now);
+                            reply(MessageType::Ack, addr)
+                        }
+                        // no record of this client; another server may know about it
+                        None if self.offset(addr).is_some() && self.is_free(addr, mac, now) => None,
+                        _ => nak(),
+                    }
+                }
+            }
+
+            // RFC 2131 - Section 4.3.4
+            MessageType::Release => {
+                if server == Some(self.config.addr) || server.is_none() {
+                    self.remove(|b| b.mac == mac && b.addr == ciaddr);
+                }
+
+                None
+            }
+
+            _ => None,
+        }
+    }
+
+    /// Fills the given IPv4 packet with a reply
+    ///
+    /// This sets the Source and Destination addresses of the packet; see `Reply::mac_destination`
+    /// for the MAC address the packet must be sent to
+    pub fn write<B>(&self, reply: &Reply, ip: &mut ipv4::Packet<B, Invalid>)
+    where
+        B: AsSlice<Element = u8> + AsMutSlice<Element = u8> + Truncate<u16>,
+    {
+        let config = &self.config;
+
+        ip.set_source(config.addr);
+        ip.set_destination(reply.destination());
+        ip.udp(|udp| {
+            udp.set_source(SERVER_PORT);
+            udp.set_destination(CLIENT_PORT);
+            udp.dhcp(|m| {
+                m.set_op(Op::Reply);
+                m.set_xid(reply.xid);
+                m.set_chaddr(reply.chaddr);
+                m.set_broadcast(reply.broadcast);
+
+                m.add_option(&DhcpOption::MessageType(reply.ty));
+                m.add_option(&DhcpOption::ServerId(config.addr));
+
+                if reply.ty != MessageType::Nak {
+                    m.set_ciaddr(reply.ciaddr);
+                    m.set_yiaddr(reply.yiaddr);
+
+                    m.add_option(&DhcpOption::LeaseTime(config.lease_time));
+                    m.add_option(&DhcpOption::SubnetMask(config.subnet_mask));
+                    if let Some(router) = config.router.as_ref() {
+                        m.add_option(&DhcpOption::Router(Addrs::new(&router.0)));
+                    }
+                    if let Some(dns) = config.dns.as_ref() {
+                        m.add_option(&DhcpOption::DomainNameServer(Addrs::new(&dns.0)));
+                    }
+                }
+            })
+        });
+    }
+
+    /* Private */
+    fn binding(&self, mac: mac::Addr) -> Option<&Binding> {
+        self.bindings.iter().find(|b| b.mac == mac)
+    }
+
+    // Picks the address to offer to the client `mac`
+    fn allocate(
+        &mut self,
+        mac: mac::Addr,
+        requested: Option<ipv4::Addr>,
+        now: Instant,
+    ) -> Option<ipv4::Addr> {
+        // the address the client had (or still has)
+        if let Some(b) = self.binding(mac) {
+            return Some(b.addr);
+        }
+
+        // the address the client asked for
+        if let Some(addr) = requested {
+            if self.offset(addr).is_some() && self.is_free(addr, mac, now) {
+                return Some(addr);
+            }
+        }
+
+        (0..self.config.pool_size)
+            .map(|i| self.nth(i))
+            .find(|addr| self.is_free(*addr, mac, now))
+    }
+
+    // Binds `addr` to the client `mac`, replacing its previous binding and reclaiming an expired
+    // binding if there's no space left
+    fn bind(&mut self, mac: mac::Addr, addr: ipv4::Addr, state: State, now: Instant) {
+        self.remove(|b| b.mac == mac || (b.addr == addr && b.has_expired(now)));
+
+        if self.bindings.len() == N::to_usize() {
+            if let Some(pos) = self.bindings.iter().position(|b| b.has_expired(now)) {
+                self.bindings.swap_remove(pos);
+            }
+        }
+
+        let lifetime = match state {
+            State::Offered => OFFER_TIMEOUT,
+            State::Bound => Duration::from_secs(self.config.lease_time),
+        };
+
+        self.bindings
+            .push(Binding {
+                mac,
+                addr,
+                state,
+                timer: now,
+                lifetime,
+            })
+            .ok();
+    }
+
+    // Removes all the bindings that match `f`
+    fn remove(&mut self, f: impl Fn(&Binding) -> bool) {
+        while let Some(pos) = self.bindings.iter().position(&f) {
+            self.bindings.swap_remove(pos);
+        }
+    }
+
+    // Is `addr` available to the client `mac`?
+    fn is_free(&self, addr: ipv4::Addr, mac: mac::Addr, now: Instant) -> bool {
+        let taken = self
+            .bindings
+            .iter()
+            .any(|b| b.addr == addr && b.mac != mac && !b.has_expired(now));
+
+        // there must be space left for a new binding
+        !taken
+            && (self.bindings.len() < N::to_usize()
+                || self
+                    .bindings
+                    .iter()
+                    .any(|b| b.has_expired(now) || b.mac == mac))
+    }
+
+    // Position of `addr` in the pool
+    fn offset(&self, addr: ipv4::Addr) -> Option<u8> {
+        let offset =
+            u32::from_be_bytes(addr.0).wrapping_sub(u32::from_be_bytes(self.config.pool_start.0));
+
+        if offset < u32::from(self.config.pool_size) {
+            // NOTE(cast) `pool_size` is a `u8`
+            Some(offset as u8)
+        } else {
+            None
+        }
+    }
+
+    // The `i`-th address of the pool
+    //
+    // NOTE `Server::new` checks that the pool doesn't wrap around, so this can't overflow
+    fn nth(&self, i: u8) -> ipv4::Addr {
+        let start = u32::from_be_bytes(self.config.pool_start.0);
+        ipv4::Addr((start + u32::from(i)).to_be_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use heapless::consts::U2;
+
+    use super::{Config, Reply, Server};
+    use crate::{
+        dhcp::{
+            self,
+            client::{Action, Client, Lease, State, MAX_LEASE_TIME},
+            DhcpOption, MessageType, Op,
+        },
+        ipv4, mac,
+        time::Instant,
+        udp,
+    };
+
+    const SERVER: ipv4::Addr = ipv4::Addr([10, 0, 0, 1]);
+    const MAC_A: mac::Addr = mac::Addr([0x02, 0, 0, 0, 0, 0xa]);
+    const MAC_B: mac::Addr = mac::Addr([0x02, 0, 0, 0, 0, 0xb]);
+    const MAC_C: mac::Addr = mac::Addr([0x02, 0, 0, 0, 0, 0xc]);
+
+    const CONFIG: Config = Config {
+        addr: SERVER,
+        pool_start: ipv4::Addr([10, 0, 0, 100]),
+        pool_size: 2,
+        subnet_mask: ipv4::Addr([255, 255, 255, 0]),
+        router: Some(SERVER),
+        dns: None,
+        lease_time: 3600,
+    };
+
+    fn at(secs: u32) -> Instant {
+        Instant::from_millis(secs * 1_000)
+    }
+
+    // delivers the message the client wants to send to the server and returns the server's reply
+    fn client_to_server(client: &Client, server: &mut Server<U2>, now: Instant) -> Option<Reply> {
+        let mut array = [0; 576];
+        let mut ip = ipv4::Packet::new(&mut array[..]);
+        client.write(&mut ip);
+        let ip = ip.update_checksum();
+
+        let udp = udp::Packet::parse(ip.payload()).unwrap();
+        let m = dhcp::Message::parse(udp.payload()).unwrap();
+        server.handle_message(&m, now)
+    }
+
+    // delivers the server's reply to the client
+    fn server_to_client(server: &Server<U2>, reply: &Reply, client: &mut Client, now: Instant) {
+        let mut array = [0; 576];
+        let mut ip = ipv4::Packet::new(&mut array[..]);
+        server.write(reply, &mut ip);
+        let ip = ip.update_checksum();
+        assert_eq!(ip.get_source(), SERVER);
+        assert_eq!(ip.get_destination(), reply.destination());
+
+        let udp = udp::Packet::parse(ip.payload()).unwrap();
+        assert_eq!(udp.get_destination(), dhcp::CLIENT_PORT);
+        let m = dhcp::Message::parse(udp.payload()).unwrap();
+        client.handle_message(&m, now);
+    }
+
+    // runs the full DISCOVER / OFFER / REQUEST / ACK exchange
+    fn exchange(client: &mut Client, server: &mut Server<U2>, now: Instant) -> Option<Lease> {
+        assert_eq!(client.poll(now), Some(Action::Send));
+        let offer = client_to_server(client, server, now)?;
+        assert_eq!(offer.get_type(), MessageType::Offer);
+        server_to_client(server, &offer, client, now);
+
+        assert_eq!(client.poll(now), Some(Action::Send));
+        let ack = client_to_server(client, server, now).unwrap();
+        assert_eq!(ack.get_type(), MessageType::Ack);
+        server_to_client(server, &ack, client, now);
+
+        match client.poll(now) {
+            Some(Action::Bound(lease)) => Some(lease),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn lease() {
+        let mut server = Server::<U2>::new(CONFIG);
+        let mut a = Client::new(MAC_A, 1, at(0));
+        let mut b = Client::new(MAC_B, 2, at(0));
+        let mut c = Client::new(MAC_C, 3, at(0));
+
+        let lease = exchange(&mut a, &mut server, at(0)).unwrap();
+        assert_eq!(
+            lease,
+            Lease {
+                addr: ipv4::Addr([10, 0, 0, 100]),
+                server: SERVER,
+                subnet_mask: Some(CONFIG.subnet_mask),
+                router: Some(SERVER),
+                dns: None,
+                lease_time: 3600,
+            }
+        );
+        assert_eq!(server.lease(MAC_A, at(0)), Some(lease.addr));
+
+        let lease = exchange(&mut b, &mut server, at(1)).unwrap();
+        assert_eq!(lease.addr, ipv4::Addr([10, 0, 0, 101]));
+
+        // the pool is exhausted: no offer
+        assert_eq!(exchange(&mut c, &mut server, at(2)), None);
+
+        // renewal
+        assert_eq!(a.poll(at(1_800)), Some(Action::Send));
+        assert_eq!(a.state(), State::Renewing);
+        let ack = client_to_server(&a, &mut server, at(1_800)).unwrap();
+        assert_eq!(ack.get_type(), MessageType::Ack);
+        assert_eq!(ack.mac_destination(), MAC_A);
+        server_to_client(&server, &ack, &mut a, at(1_800));
+        assert_eq!(a.poll(at(1_800)).map(|_| a.state()), Some(State::Bound));
+
+        // B's lease expires so its address can be handed out to C
+        assert_eq!(server.lease(MAC_B, at(3_601)), None);
+        let lease = exchange(
+            &mut Client::new(MAC_C, 4, at(3_601)),
+            &mut server,
+            at(3_601),
+        );
+        assert_eq!(lease.map(|l| l.addr), Some(ipv4::Addr([10, 0, 0, 101])));
+    }
+
+    #[test]
+    fn nak_and_release() {
+        let mut server = Server::<U2>::new(CONFIG);
+        let mut buffer = [0; 300];
+
+        // REQUEST for an address outside the pool
+        let mut m = dhcp::Message::new(&mut buffer[..]);
+        m.set_xid(7);
+        m.set_chaddr(MAC_A);
+        m.add_option(&DhcpOption::MessageType(MessageType::Request));
+        m.add_option(&DhcpOption::RequestedIp(ipv4::Addr([192, 168, 1, 33])));
+        let nak = server.handle_message(&m, at(0)).unwrap();
+        assert_eq!(nak.get_type(), MessageType::Nak);
+        assert_eq!(nak.destination(), ipv4::Addr::BROADCAST);
+        assert_eq!(nak.mac_destination(), mac::Addr::BROADCAST);
+
+        let mut array = [0; 576];
+        let mut ip = ipv4::Packet::new(&mut array[..]);
+        server.write(&nak, &mut ip);
+        let udp = udp::Packet::parse(ip.payload()).unwrap();
+        let m = dhcp::Message::parse(udp.payload()).unwrap();
+        assert_eq!(m.get_op(), Op::Reply);
+        assert_eq!(m.get_xid(), 7);
+        assert_eq!(m.get_yiaddr(), ipv4::Addr::UNSPECIFIED);
+        assert_eq!(m.get_message_type(), Some(MessageType::Nak));
+
+        // RELEASE
+        let mut a = Client::new(MAC_A, 1, at(0));
+        let lease = exchange(&mut a, &mut server, at(0)).unwrap();
+
+        let mut buffer = [0; 300];
+        let mut m = dhcp::Message::new(&mut buffer[..]);
+        m.set_chaddr(MAC_A);
+        m.set_ciaddr(lease.addr);
+        m.add_option(&DhcpOption::MessageType(MessageType::Release));
+        m.add_option(&DhcpOption::ServerId(SERVER));
+        assert_eq!(server.handle_message(&m, at(10)), None);
+        assert_eq!(server.lease(MAC_A, at(10)), None);
+    }
+
+    #[test]
+    fn destination() {
+        let reply = Reply {
+            ty: MessageType::Ack,
+            xid: 1,
+            chaddr: MAC_A,
+            ciaddr: ipv4::Addr([10, 0, 0, 100]),
+            yiaddr: ipv4::Addr([10, 0, 0, 100]),
+            broadcast: true,
+        };
+
+        // RENEWING client; 'ciaddr' takes precedence over the broadcast flag
+        assert_eq!(reply.destination(), reply.ciaddr);
+        assert_eq!(reply.mac_destination(), MAC_A);
+
+        let reply = Reply {
+            ciaddr: ipv4::Addr::UNSPECIFIED,
+            ..reply
+        };
+        assert_eq!(reply.destination(), ipv4::Addr::BROADCAST);
+
+        let reply = Reply {
+            broadcast: false,
+            ..reply
+        };
+        assert_eq!(reply.destination(), reply.yiaddr);
+    }
+
+    #[test]
+    fn infinite_lease() {
+        let mut server = Server::<U2>::new(Config {
+            lease_time: u32::MAX,
+            ..CONFIG
+        });
+        assert_eq!(server.config().lease_time, MAX_LEASE_TIME);
+
+        // the OFFER advertises what the server tracks
+        let mut a = Client::new(MAC_A, 1, at(0));
+        let lease = exchange(&mut a, &mut server, at(0)).unwrap();
+        assert_eq!(lease.lease_time, MAX_LEASE_TIME);
+        assert_eq!(
+            server.lease(MAC_A, at(MAX_LEASE_TIME - 1)),
+            Some(lease.addr)
+        );
+        assert_eq!(server.lease(MAC_A, at(MAX_LEASE_TIME)), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "address pool out of range")]
+    fn pool_end() {
+        // the last address of this pool is the limited broadcast address
+        Server::<U2>::new(Config {
+            pool_start: ipv4::Addr([255, 255, 255, 254]),
+            ..CONFIG
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "address pool includes the server address")]
+    fn pool_server_addr() {
+        Server::<U2>::new(Config {
+            pool_start: ipv4::Addr([10, 0, 0, 0]),
+            ..CONFIG
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "address pool out of range")]
+    fn pool_overflow() {
+        Server::<U2>::new(Config {
+            pool_start: ipv4::Addr([255, 255, 255, 255]),
+            ..CONFIG
+        });
+    }
+}
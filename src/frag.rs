@@ -0,0 +1,184 @@
+//! Fragment reassembly shared by the IPv4, IPv6 and 6LoWPAN modules
+
+use cast::usize;
+use heapless::{consts, ArrayLength, Vec};
+
+/// Fragmentation or reassembly error
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Error {
+    /// The packet is not a fragment; it doesn't need to be reassembled
+    NotAFragment,
+
+    /// The fragment is malformed (e.g. a non-final fragment whose payload is not a multiple of 8
+    /// bytes) or inconsistent with the fragments received so far. In the latter case the partially
+    /// reassembled datagram is discarded
+    Malformed,
+
+    /// The fragment overlaps with data received in a previous fragment. The partially reassembled
+    /// datagram is discarded (see RFC 1858 and RFC 5722)
+    Overlap,
+
+    /// The reassembled datagram would not fit in the reassembly buffer. The partially reassembled
+    /// datagram is discarded.
+    ///
+    /// When fragmenting: the datagram is too large to be fragmented, or its unfragmentable part
+    /// doesn't fit in the MTU
+    TooLarge,
+
+    /// All the reassembly slots are in use or the datagram has been split in too many
+    /// non-contiguous pieces
+    Exhausted,
+
+    /// The datagram doesn't fit in the MTU but its DF (Don't Fragment) flag is set
+    DontFragment,
+}
+
+// Fragment bookkeeping shared by the IPv4, IPv6 and 6LoWPAN reassembly buffers
+pub(crate) struct Assembly<M>
+where
+    M: ArrayLength<u8>,
+{
+    // length of the reassembled data; known once the last fragment is received
+    len: Option<u16>,
+    // number of bytes received so far
+    received: u16,
+    // byte ranges (end exclusive) received so far; adjacent ranges are merged
+    ranges: Vec<(u16, u16), consts::U8>,
+    buffer: Vec<u8, M>,
+}
+
+impl<M> Assembly<M>
+where
+    M: ArrayLength<u8>,
+{
+    pub(crate) fn new() -> Self {
+        let mut buffer = Vec::new();
+        buffer.resize(M::to_usize(), 0).ok();
+
+        Assembly {
+            len: None,
+            received: 0,
+            ranges: Vec::new(),
+            buffer,
+        }
+    }
+
+    // Copies `data` into the buffer at `base + offset`; `offset` is relative to the start of the
+    // reassembled data. `data` must not extend past `limit`, an absolute buffer index.
+    //
+    // Exact duplicates of previously received data are ignored, as long as they are consistent
+    // with the original fragment
+    pub(crate) fn insert(
+        &mut self,
+        base: usize,
+        limit: usize,
+        offset: u16,
+        data: &[u8],
+        last: bool,
+    ) -> Result<(), Error> {
+        let end = usize(offset) + data.len();
+        if base + end > limit.min(self.buffer.len()) {
+            return Err(Error::TooLarge);
+        }
+        let end = end as u16;
+
+        self.check(end, last)?;
+
+        let buffer = &self.buffer[base..];
+        if self
+            .ranges
+            .iter()
+            .any(|r| r.0 <= offset && end <= r.1 && buffer[usize(offset)..usize(end)] == *data)
+        {
+            if last && self.len.is_none() {
+                // this data was previously received in a non-final fragment
+                return Err(Error::Malformed);
+            }
+
+            // exact retransmission of data we already have; ignore
+            return Ok(());
+        }
+
+        self.add(offset, end, last)?;
+
+        self.buffer[base + usize(offset)..base + usize(end)].copy_from_slice(data);
+
+        Ok(())
+    }
+
+    // Records the reception of the `start..end` range of the reassembled data, without copying any
+    // data into the buffer
+    pub(crate) fn add(&mut self, start: u16, end: u16, last: bool) -> Result<(), Error> {
+        self.check(end, last)?;
+
+        if self.ranges.iter().any(|r| start < r.1 && r.0 < end) {
+            return Err(Error::Overlap);
+        }
+
+        if last {
+            self.len = Some(end);
+        }
+        self.received += end - start;
+
+        // merge the new range with its neighbors
+        let mut new = (start, end);
+        let mut i = 0;
+        while i < self.ranges.len() {
+            let r = self.ranges[i];
+
+            if r.1 == new.0 || r.0 == new.1 {
+                new = (r.0.min(new.0), r.1.max(new.1));
+                self.ranges.swap_remove(i);
+            } else {
+                i += 1;
+            }
+        }
+
+        self.ranges.push(new).map_err(|_| Error::Exhausted)
+    }
+
+    // Checks that a fragment that ends at `end` is consistent with the fragments received so far
+    fn check(&self, end: u16, last: bool) -> Result<(), Error> {
+        let consistent = match self.len {
+            // only the last fragment reaches the end of the reassembled data
+            Some(len) => end <= len && last == (end == len),
+            // we must not have received data past the end of the datagram
+            None => !last || self.end() <= end,
+        };
+
+        if consistent {
+            Ok(())
+        } else {
+            Err(Error::Malformed)
+        }
+    }
+
+    // Sets the length of the reassembled data
+    pub(crate) fn set_data_len(&mut self, len: u16) -> Result<(), Error> {
+        if self.end() > len || self.len.map(|l| l != len).unwrap_or(false) {
+            // we have received data past the end of the datagram
+            return Err(Error::Malformed);
+        }
+
+        self.len = Some(len);
+        Ok(())
+    }
+
+    // Returns the length of the reassembled data, if known
+    pub(crate) fn data_len(&self) -> Option<u16> {
+        self.len
+    }
+
+    // Returns the end of the highest range of data received so far
+    pub(crate) fn end(&self) -> u16 {
+        self.ranges.iter().map(|r| r.1).max().unwrap_or(0)
+    }
+
+    pub(crate) fn is_complete(&self) -> bool {
+        self.len == Some(self.received)
+    }
+
+    pub(crate) fn buffer_mut(&mut self) -> &mut [u8] {
+        &mut self.buffer
+    }
+}
@@ -6,6 +6,7 @@
 //!
 //! [rfc]: https://tools.ietf.org/html/rfc792
 
+use core::cmp;
 use core::fmt;
 use core::marker::PhantomData;
 use core::ops::{Range, RangeFrom};
@@ -13,11 +14,12 @@ use core::ops::{Range, RangeFrom};
 use as_slice::{AsMutSlice, AsSlice};
 use byteorder::{ByteOrder, NetworkEndian as NE};
 use cast::usize;
+use owning_slice::Truncate;
 
 use crate::{
     fmt::Hex,
     ipv4,
-    sealed::Echo,
+    sealed::{Echo, Error},
     traits::{TryFrom, TryInto, UncheckedIndex},
     Invalid, Unknown, Valid,
 };
@@ -30,9 +32,18 @@ const IDENT: Range<usize> = 4..6;
 const SEQ_NO: Range<usize> = 6..8;
 const PAYLOAD: RangeFrom<usize> = 8..;
 
+/* Error message structure */
+const UNUSED: Range<usize> = 4..8;
+const NEXT_HOP_MTU: Range<usize> = 6..8;
+const POINTER: usize = 4;
+const GATEWAY: Range<usize> = 4..8;
+
 /// Size of the ICMP header
 pub const HEADER_SIZE: u8 = PAYLOAD.start as u8;
 
+/// Number of bytes, past the IP header, of the original datagram that are quoted in error messages
+pub const QUOTED_DATA_SIZE: u8 = 8;
+
 /// ICMP Message
 pub struct Message<BUFFER, TYPE, CHECKSUM>
 where
@@ -50,6 +61,18 @@ pub enum EchoReply {}
 /// [Type State] The Echo Request type
 pub enum EchoRequest {}
 
+/// [Type State] The Destination Unreachable type
+pub enum DestinationUnreachable {}
+
+/// [Type State] The Time Exceeded type
+pub enum TimeExceeded {}
+
+/// [Type State] The Parameter Problem type
+pub enum ParameterProblem {}
+
+/// [Type State] The Redirect type
+pub enum Redirect {}
+
 /* EchoRequest */
 impl<B> Message<B, EchoRequest, Invalid>
 where
@@ -104,6 +127,214 @@ where
     }
 }
 
+/* DestinationUnreachable */
+impl<B> Message<B, DestinationUnreachable, Invalid>
+where
+    B: AsSlice<Element = u8> + AsMutSlice<Element = u8> + Truncate<u16>,
+{
+    /* Constructors */
+    /// Transforms the input buffer into a Destination Unreachable message that quotes the
+    /// offending `datagram`
+    ///
+    /// The Next-Hop MTU field is set to zero; use `set_next_hop_mtu` to change it.
+    ///
+    /// The quote is truncated if `buffer` can't hold all of it
+    ///
+    /// # Panics
+    ///
+    /// This constructor panics if `buffer` is too small to hold the header
+    pub fn destination_unreachable(buffer: B, code: UnreachableCode, datagram: &[u8]) -> Self {
+        unsafe { Message::error(buffer, Type::DestinationUnreachable, code.into(), datagram) }
+    }
+
+    /* Setters */
+    /// Sets the Next-Hop MTU field (RFC 1191) of the header
+    pub fn set_next_hop_mtu(&mut self, mtu: u16) {
+        NE::write_u16(&mut self.header_mut_()[NEXT_HOP_MTU], mtu)
+    }
+}
+
+impl<B, C> Message<B, DestinationUnreachable, C>
+where
+    B: AsSlice<Element = u8>,
+{
+    /* Getters */
+    /// Returns the Code field of the header
+    pub fn get_unreachable_code(&self) -> UnreachableCode {
+        self.get_code().into()
+    }
+
+    /// Returns the Next-Hop MTU field (RFC 1191) of the header
+    ///
+    /// This field is only meaningful when the code is `FragmentationNeeded`
+    pub fn get_next_hop_mtu(&self) -> u16 {
+        NE::read_u16(&self.header_()[NEXT_HOP_MTU])
+    }
+}
+
+/* TimeExceeded */
+impl<B> Message<B, TimeExceeded, Invalid>
+where
+    B: AsSlice<Element = u8> + AsMutSlice<Element = u8> + Truncate<u16>,
+{
+    /* Constructors */
+    /// Transforms the input buffer into a Time Exceeded message that quotes the offending
+    /// `datagram`
+    ///
+    /// The quote is truncated if `buffer` can't hold all of it
+    ///
+    /// # Panics
+    ///
+    /// This constructor panics if `buffer` is too small to hold the header
+    pub fn time_exceeded(buffer: B, code: TimeExceededCode, datagram: &[u8]) -> Self {
+        unsafe { Message::error(buffer, Type::TimeExceeded, code.into(), datagram) }
+    }
+}
+
+impl<B, C> Message<B, TimeExceeded, C>
+where
+    B: AsSlice<Element = u8>,
+{
+    /* Getters */
+    /// Returns the Code field of the header
+    pub fn get_time_exceeded_code(&self) -> TimeExceededCode {
+        self.get_code().into()
+    }
+}
+
+/* ParameterProblem */
+impl<B> Message<B, ParameterProblem, Invalid>
+where
+    B: AsSlice<Element = u8> + AsMutSlice<Element = u8> + Truncate<u16>,
+{
+    /* Constructors */
+    /// Transforms the input buffer into a Parameter Problem message that quotes the offending
+    /// `datagram`
+    ///
+    /// `pointer` is the offset, into `datagram`, of the octet where the error was detected
+    ///
+    /// The quote is truncated if `buffer` can't hold all of it
+    ///
+    /// # Panics
+    ///
+    /// This constructor panics if `buffer` is too small to hold the header
+    pub fn parameter_problem(
+        buffer: B,
+        code: ParameterProblemCode,
+        pointer: u8,
+        datagram: &[u8],
+    ) -> Self {
+        let mut m: Self =
+            unsafe { Message::error(buffer, Type::ParameterProblem, code.into(), datagram) };
+        m.header_mut_()[POINTER] = pointer;
+        m
+    }
+}
+
+impl<B, C> Message<B, ParameterProblem, C>
+where
+    B: AsSlice<Element = u8>,
+{
+    /* Getters */
+    /// Returns the Code field of the header
+    pub fn get_parameter_problem_code(&self) -> ParameterProblemCode {
+        self.get_code().into()
+    }
+
+    /// Returns the Pointer field of the header
+    pub fn get_pointer(&self) -> u8 {
+        self.header_()[POINTER]
+    }
+}
+
+/* Redirect */
+impl<B> Message<B, Redirect, Invalid>
+where
+    B: AsSlice<Element = u8> + AsMutSlice<Element = u8> + Truncate<u16>,
+{
+    /* Constructors */
+    /// Transforms the input buffer into a Redirect message that quotes the offending `datagram`
+    ///
+    /// The quote is truncated if `buffer` can't hold all of it
+    ///
+    /// # Panics
+    ///
+    /// This constructor panics if `buffer` is too small to hold the header
+    pub fn redirect(buffer: B, code: RedirectCode, gateway: ipv4::Addr, datagram: &[u8]) -> Self {
+        let mut m: Self = unsafe { Message::error(buffer, Type::Redirect, code.into(), datagram) };
+        m.header_mut_()[GATEWAY].copy_from_slice(&gateway.0);
+        m
+    }
+}
+
+impl<B, C> Message<B, Redirect, C>
+where
+    B: AsSlice<Element = u8>,
+{
+    /* Getters */
+    /// Returns the Code field of the header
+    pub fn get_redirect_code(&self) -> RedirectCode {
+        self.get_code().into()
+    }
+
+    /// Returns the Gateway Internet Address field of the header
+    pub fn get_gateway(&self) -> ipv4::Addr {
+        unsafe { ipv4::Addr(*(self.as_slice().as_ptr().add(GATEWAY.start) as *const _)) }
+    }
+}
+
+/* DestinationUnreachable OR TimeExceeded OR ParameterProblem OR Redirect */
+impl<B, E, C> Message<B, E, C>
+where
+    B: AsSlice<Element = u8>,
+    E: Error,
+{
+    /* Getters */
+    /// Returns a view into the datagram that triggered this error message
+    ///
+    /// Only the IP header and the first `QUOTED_DATA_SIZE` bytes of its payload are (usually)
+    /// available. This returns `None` if the quoted IP header is truncated or invalid.
+    pub fn get_original_datagram(&self) -> Option<ipv4::Packet<&[u8], Valid>> {
+        let datagram = self.payload();
+
+        if datagram.len() < usize(ipv4::MIN_HEADER_SIZE)
+            || quoted_len(datagram) < header_len(datagram)
+        {
+            None
+        } else {
+            ipv4::Packet::parse(datagram).ok()
+        }
+    }
+}
+
+impl<B, T> Message<B, T, Invalid>
+where
+    B: AsSlice<Element = u8> + AsMutSlice<Element = u8> + Truncate<u16>,
+{
+    /* Private */
+    // NOTE(unsafe) caller must ensure that `type_` matches the type state `T`
+    unsafe fn error(mut buffer: B, type_: Type, code: u8, datagram: &[u8]) -> Self {
+        let blen = buffer.as_slice().len();
+        assert!(blen >= usize(HEADER_SIZE));
+
+        let qlen = cmp::min(quoted_len(datagram), blen - usize(HEADER_SIZE));
+        let len = usize(HEADER_SIZE) + qlen;
+
+        // NOTE(cast) `qlen` is at most 68 bytes
+        buffer.truncate(len as u16);
+
+        let mut m: Message<B, Unknown, Invalid> = Message::unchecked(buffer);
+        m.set_type(type_);
+        m.set_code(code);
+        for byte in &mut m.header_mut_()[UNUSED] {
+            *byte = 0;
+        }
+        m.payload_mut().copy_from_slice(&datagram[..qlen]);
+
+        Message::unchecked(m.buffer)
+    }
+}
+
 /* Unknown */
 impl<B> Message<B, Unknown, Valid>
 where
@@ -217,6 +448,66 @@ where
     }
 }
 
+impl<B, C> TryFrom<Message<B, Unknown, C>> for Message<B, DestinationUnreachable, C>
+where
+    B: AsSlice<Element = u8>,
+{
+    type Error = Message<B, Unknown, C>;
+
+    fn try_from(p: Message<B, Unknown, C>) -> Result<Self, Message<B, Unknown, C>> {
+        if p.get_type() == Type::DestinationUnreachable {
+            Ok(unsafe { Message::unchecked(p.buffer) })
+        } else {
+            Err(p)
+        }
+    }
+}
+
+impl<B, C> TryFrom<Message<B, Unknown, C>> for Message<B, TimeExceeded, C>
+where
+    B: AsSlice<Element = u8>,
+{
+    type Error = Message<B, Unknown, C>;
+
+    fn try_from(p: Message<B, Unknown, C>) -> Result<Self, Message<B, Unknown, C>> {
+        if p.get_type() == Type::TimeExceeded {
+            Ok(unsafe { Message::unchecked(p.buffer) })
+        } else {
+            Err(p)
+        }
+    }
+}
+
+impl<B, C> TryFrom<Message<B, Unknown, C>> for Message<B, ParameterProblem, C>
+where
+    B: AsSlice<Element = u8>,
+{
+    type Error = Message<B, Unknown, C>;
+
+    fn try_from(p: Message<B, Unknown, C>) -> Result<Self, Message<B, Unknown, C>> {
+        if p.get_type() == Type::ParameterProblem {
+            Ok(unsafe { Message::unchecked(p.buffer) })
+        } else {
+            Err(p)
+        }
+    }
+}
+
+impl<B, C> TryFrom<Message<B, Unknown, C>> for Message<B, Redirect, C>
+where
+    B: AsSlice<Element = u8>,
+{
+    type Error = Message<B, Unknown, C>;
+
+    fn try_from(p: Message<B, Unknown, C>) -> Result<Self, Message<B, Unknown, C>> {
+        if p.get_type() == Type::Redirect {
+            Ok(unsafe { Message::unchecked(p.buffer) })
+        } else {
+            Err(p)
+        }
+    }
+}
+
 /* TYPE */
 impl<B, T, C> Message<B, T, C>
 where
@@ -238,6 +529,14 @@ where
             Type::EchoReply
         } else if typeid!(T == EchoRequest) {
             Type::EchoRequest
+        } else if typeid!(T == DestinationUnreachable) {
+            Type::DestinationUnreachable
+        } else if typeid!(T == TimeExceeded) {
+            Type::TimeExceeded
+        } else if typeid!(T == ParameterProblem) {
+            Type::ParameterProblem
+        } else if typeid!(T == Redirect) {
+            Type::Redirect
         } else {
             self.header_()[TYPE].into()
         }
@@ -373,6 +672,84 @@ where
     }
 }
 
+/// NOTE excludes the payload
+impl<B, C> fmt::Debug for Message<B, DestinationUnreachable, C>
+where
+    B: AsSlice<Element = u8>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("icmp::Message")
+            .field("type", &self.get_type())
+            .field("code", &self.get_code())
+            .field("checksum", &Hex(self.get_checksum()))
+            .field("next_hop_mtu", &self.get_next_hop_mtu())
+            // .field("payload", &self.payload())
+            .finish()
+    }
+}
+
+/// NOTE excludes the payload
+impl<B, C> fmt::Debug for Message<B, TimeExceeded, C>
+where
+    B: AsSlice<Element = u8>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("icmp::Message")
+            .field("type", &self.get_type())
+            .field("code", &self.get_code())
+            .field("checksum", &Hex(self.get_checksum()))
+            // .field("payload", &self.payload())
+            .finish()
+    }
+}
+
+/// NOTE excludes the payload
+impl<B, C> fmt::Debug for Message<B, ParameterProblem, C>
+where
+    B: AsSlice<Element = u8>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("icmp::Message")
+            .field("type", &self.get_type())
+            .field("code", &self.get_code())
+            .field("checksum", &Hex(self.get_checksum()))
+            .field("pointer", &self.get_pointer())
+            // .field("payload", &self.payload())
+            .finish()
+    }
+}
+
+/// NOTE excludes the payload
+impl<B, C> fmt::Debug for Message<B, Redirect, C>
+where
+    B: AsSlice<Element = u8>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("icmp::Message")
+            .field("type", &self.get_type())
+            .field("code", &self.get_code())
+            .field("checksum", &Hex(self.get_checksum()))
+            .field("gateway", &self.get_gateway())
+            // .field("payload", &self.payload())
+            .finish()
+    }
+}
+
+// Returns the length of the IP header of `datagram`
+fn header_len(datagram: &[u8]) -> usize {
+    // NOTE IHL is the low nibble of the first byte
+    usize::from(datagram.first().map(|b| b & 0x0f).unwrap_or(0)) * 4
+}
+
+// Returns how many bytes of `datagram` are quoted in an error message: the IP header plus the first
+// `QUOTED_DATA_SIZE` bytes of the payload (RFC 792)
+fn quoted_len(datagram: &[u8]) -> usize {
+    cmp::min(
+        datagram.len(),
+        header_len(datagram) + usize(QUOTED_DATA_SIZE),
+    )
+}
+
 full_range!(
     u8,
     /// ICMP types
@@ -382,8 +759,96 @@ full_range!(
         EchoReply = 0,
         /// Destination Unreachable
         DestinationUnreachable = 3,
+        /// Redirect
+        Redirect = 5,
         /// Echo Request
         EchoRequest = 8,
+        /// Time Exceeded
+        TimeExceeded = 11,
+        /// Parameter Problem
+        ParameterProblem = 12,
+    }
+);
+
+full_range!(
+    u8,
+    /// Destination Unreachable codes
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub enum UnreachableCode {
+        /// Net Unreachable
+        NetUnreachable = 0,
+        /// Host Unreachable
+        HostUnreachable = 1,
+        /// Protocol Unreachable
+        ProtocolUnreachable = 2,
+        /// Port Unreachable
+        PortUnreachable = 3,
+        /// Fragmentation Needed and Don't Fragment was Set
+        FragmentationNeeded = 4,
+        /// Source Route Failed
+        SourceRouteFailed = 5,
+        /// Destination Network Unknown (RFC 1122)
+        DestinationNetworkUnknown = 6,
+        /// Destination Host Unknown (RFC 1122)
+        DestinationHostUnknown = 7,
+        /// Source Host Isolated (RFC 1122)
+        SourceHostIsolated = 8,
+        /// Communication with Destination Network is Administratively Prohibited (RFC 1122)
+        NetworkProhibited = 9,
+        /// Communication with Destination Host is Administratively Prohibited (RFC 1122)
+        HostProhibited = 10,
+        /// Destination Network Unreachable for Type of Service (RFC 1122)
+        NetworkUnreachableForTos = 11,
+        /// Destination Host Unreachable for Type of Service (RFC 1122)
+        HostUnreachableForTos = 12,
+        /// Communication Administratively Prohibited (RFC 1812)
+        CommunicationProhibited = 13,
+        /// Host Precedence Violation (RFC 1812)
+        HostPrecedenceViolation = 14,
+        /// Precedence cutoff in effect (RFC 1812)
+        PrecedenceCutoff = 15,
+    }
+);
+
+full_range!(
+    u8,
+    /// Time Exceeded codes
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub enum TimeExceededCode {
+        /// Time to live exceeded in transit
+        TtlExceeded = 0,
+        /// Fragment reassembly time exceeded
+        FragmentReassembly = 1,
+    }
+);
+
+full_range!(
+    u8,
+    /// Parameter Problem codes
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub enum ParameterProblemCode {
+        /// Pointer indicates the error
+        Pointer = 0,
+        /// Missing a required option (RFC 1108)
+        MissingRequiredOption = 1,
+        /// Bad length (RFC 1812)
+        BadLength = 2,
+    }
+);
+
+full_range!(
+    u8,
+    /// Redirect codes
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub enum RedirectCode {
+        /// Redirect datagrams for the Network
+        Network = 0,
+        /// Redirect datagrams for the Host
+        Host = 1,
+        /// Redirect datagrams for the Type of Service and Network
+        TosNetwork = 2,
+        /// Redirect datagrams for the Type of Service and Host
+        TosHost = 3,
     }
 );
 
@@ -464,4 +929,141 @@ mod tests {
         assert_eq!(icmp.get_identifier(), 4);
         assert_eq!(icmp.get_sequence_number(), 2);
     }
+
+    #[test]
+    fn destination_unreachable() {
+        // the Echo Request from `BYTES` plus some padding that must not be quoted
+        let mut datagram = [0; 28 + 4];
+        datagram[..28].copy_from_slice(&BYTES[14..]);
+
+        let mut array = [0; 128];
+        let mut eth = ether::Frame::new(&mut array[..]);
+        eth.set_destination(MAC_SRC);
+        eth.set_source(MAC_DST);
+
+        eth.ipv4(|ip| {
+            ip.set_destination(IP_SRC);
+            ip.set_source(IP_DST);
+            ip.destination_unreachable(icmp::UnreachableCode::ProtocolUnreachable, &datagram);
+        });
+
+        let ip = ipv4::Packet::parse(eth.payload()).unwrap();
+        assert_eq!(ip.get_protocol(), ipv4::Protocol::Icmp);
+        assert_eq!(
+            usize::from(ip.len()),
+            20 + usize::from(icmp::HEADER_SIZE) + 28
+        );
+
+        let icmp = icmp::Message::parse(ip.payload())
+            .unwrap()
+            .downcast::<icmp::DestinationUnreachable>()
+            .unwrap();
+        assert_eq!(
+            icmp.get_unreachable_code(),
+            icmp::UnreachableCode::ProtocolUnreachable
+        );
+        assert_eq!(icmp.get_next_hop_mtu(), 0);
+
+        let original = icmp.get_original_datagram().unwrap();
+        assert_eq!(original.get_source(), IP_SRC);
+        assert_eq!(original.get_destination(), IP_DST);
+        assert_eq!(original.get_protocol(), ipv4::Protocol::Icmp);
+        assert_eq!(original.payload(), &BYTES[34..]);
+
+        // the quote is truncated to fit in the frame
+        let mut array = [0; 14 + 20 + 8 + 10];
+        let mut eth = ether::Frame::new(&mut array[..]);
+        eth.ipv4(|ip| {
+            ip.destination_unreachable(icmp::UnreachableCode::ProtocolUnreachable, &datagram);
+        });
+
+        let ip = ipv4::Packet::parse(eth.payload()).unwrap();
+        let icmp = icmp::Message::parse(ip.payload()).unwrap();
+        assert_eq!(icmp.payload(), &datagram[..10]);
+    }
+
+    #[test]
+    fn errors() {
+        let datagram = &BYTES[14..];
+        let mut array = [0; 64];
+
+        let mut m = icmp::Message::destination_unreachable(
+            &mut array[..],
+            icmp::UnreachableCode::FragmentationNeeded,
+            datagram,
+        );
+        m.set_next_hop_mtu(576);
+        let m = m.update_checksum();
+        let m = icmp::Message::parse(m.as_bytes())
+            .unwrap()
+            .downcast::<icmp::DestinationUnreachable>()
+            .unwrap();
+        assert_eq!(m.get_next_hop_mtu(), 576);
+
+        let mut array = [0; 64];
+        let m = icmp::Message::time_exceeded(
+            &mut array[..],
+            icmp::TimeExceededCode::TtlExceeded,
+            datagram,
+        )
+        .update_checksum();
+        let m = icmp::Message::parse(m.as_bytes())
+            .unwrap()
+            .downcast::<icmp::TimeExceeded>()
+            .unwrap();
+        assert_eq!(
+            m.get_time_exceeded_code(),
+            icmp::TimeExceededCode::TtlExceeded
+        );
+        assert!(m.get_original_datagram().is_some());
+
+        let mut array = [0; 64];
+        let m = icmp::Message::parameter_problem(
+            &mut array[..],
+            icmp::ParameterProblemCode::Pointer,
+            8,
+            datagram,
+        )
+        .update_checksum();
+        let m = icmp::Message::parse(m.as_bytes())
+            .unwrap()
+            .downcast::<icmp::ParameterProblem>()
+            .unwrap();
+        assert_eq!(
+            m.get_parameter_problem_code(),
+            icmp::ParameterProblemCode::Pointer
+        );
+        assert_eq!(m.get_pointer(), 8);
+
+        let mut array = [0; 64];
+        let gateway = ipv4::Addr([192, 168, 0, 254]);
+        let m =
+            icmp::Message::redirect(&mut array[..], icmp::RedirectCode::Host, gateway, datagram)
+                .update_checksum();
+        let m = icmp::Message::parse(m.as_bytes())
+            .unwrap()
+            .downcast::<icmp::Redirect>()
+            .unwrap();
+        assert_eq!(m.get_redirect_code(), icmp::RedirectCode::Host);
+        assert_eq!(m.get_gateway(), gateway);
+
+        // the quote is truncated to fit in the buffer
+        let mut array = [0; 20];
+        let m = icmp::Message::destination_unreachable(
+            &mut array[..],
+            icmp::UnreachableCode::PortUnreachable,
+            datagram,
+        );
+        assert_eq!(m.as_bytes().len(), 20);
+        assert_eq!(&m.as_bytes()[8..], &datagram[..12]);
+
+        // truncated quote
+        let mut array = [0; 64];
+        let m = icmp::Message::time_exceeded(
+            &mut array[..],
+            icmp::TimeExceededCode::FragmentReassembly,
+            &datagram[..12],
+        );
+        assert!(m.get_original_datagram().is_none());
+    }
 }
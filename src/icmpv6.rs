@@ -10,9 +10,17 @@
 //! - [RFC 2461: Neighbor Discovery for IP Version 6 (IPv6)][1]
 //!
 //! [1]: https://tools.ietf.org/html/rfc2461
+//!
+//! - [RFC 8106: IPv6 Router Advertisement Options for DNS Configuration][2]
+//!
+//! [2]: https://tools.ietf.org/html/rfc8106
+//!
+//! - [RFC 6775: Neighbor Discovery Optimization for IPv6 over 6LoWPANs][3]
+//!
+//! [3]: https://tools.ietf.org/html/rfc6775
 
 use core::{
-    fmt,
+    cmp, fmt,
     marker::PhantomData,
     ops::{Range, RangeFrom},
 };
@@ -24,8 +32,10 @@ use owning_slice::Truncate;
 pub use crate::icmp::{EchoReply, EchoRequest};
 use crate::{
     fmt::Quoted,
-    ieee802154, ipv6, mac,
-    sealed::Echo,
+    ieee802154,
+    ipv6::{self, frag::MIN_MTU},
+    mac,
+    sealed::{Echo, Icmpv6Error},
     traits::{TryFrom, TryInto, UncheckedIndex},
     Unknown,
 };
@@ -66,6 +76,35 @@ mod override_ {
 
 const TARGET: Range<usize> = 8..24;
 
+// RouterSolicitation
+const RS_OPTIONS: RangeFrom<usize> = 8..;
+
+// RouterAdvertisement
+const CUR_HOP_LIMIT: usize = 4;
+const FLAGS: usize = 5;
+const ROUTER_LIFETIME: Range<usize> = 6..8;
+const REACHABLE_TIME: Range<usize> = 8..12;
+const RETRANS_TIMER: Range<usize> = 12..16;
+const RA_OPTIONS: RangeFrom<usize> = 16..;
+
+mod managed {
+    pub const MASK: u8 = (1 << SIZE) - 1;
+    pub const OFFSET: usize = super::other::OFFSET + super::other::SIZE;
+    pub const SIZE: usize = 1;
+}
+
+mod other {
+    pub const MASK: u8 = (1 << SIZE) - 1;
+    pub const OFFSET: usize = 6;
+    pub const SIZE: usize = 1;
+}
+
+// {DestinationUnreachable,PacketTooBig,TimeExceeded,ParameterProblem}
+const UNUSED: Range<usize> = 4..8;
+const MTU: Range<usize> = 4..8;
+const POINTER: Range<usize> = 4..8;
+const INVOKING_PACKET: RangeFrom<usize> = 8..;
+
 /// ICMPv6 Message
 // TODO add 'Checksum = {Valid,Unknown}' type state
 pub struct Message<BUFFER, TYPE>
@@ -279,6 +318,26 @@ where
 /// [Type state]
 pub enum NeighborSolicitation {}
 
+impl<B> Message<B, NeighborSolicitation>
+where
+    B: AsMutSlice<Element = u8> + Truncate<u16>,
+{
+    /* Constructors */
+    /// Transforms the input buffer into a Neighbor Solicitation ICMPv6 message that carries the
+    /// given `options`
+    ///
+    /// # Panics
+    ///
+    /// This constructor panics if the message doesn't fit in `buffer`, or if one of the `options`
+    /// is larger than 2040 octets
+    pub fn neighbor_solicitation(buffer: B, target: ipv6::Addr, options: &[NdOption<'_>]) -> Self {
+        let mut m: Self =
+            unsafe { Message::nd(buffer, Type::NeighborSolicitation, TARGET.end, options) };
+        unsafe { m.as_mut_slice().rm(TARGET).copy_from_slice(&target.0) }
+        m
+    }
+}
+
 impl<B> Message<B, NeighborSolicitation>
 where
     B: AsSlice<Element = u8>,
@@ -690,7 +749,829 @@ where
     }
 }
 
+/// [Type state] Router Solicitation
+pub enum RouterSolicitation {}
+
+impl<B> TryFrom<Message<B, Unknown>> for Message<B, RouterSolicitation>
+where
+    B: AsSlice<Element = u8>,
+{
+    type Error = Message<B, Unknown>;
+
+    fn try_from(m: Message<B, Unknown>) -> Result<Self, Message<B, Unknown>> {
+        // RFC 4861 - Section 6.1.1.  Validation of Router Solicitation Messages
+        // "ICMP Code is 0"
+        // "ICMP length (derived from the IP length) is 8 or more octets"
+        // "All included options have a length that is greater than zero"
+        if m.get_type() == Type::RouterSolicitation
+            && m.get_code() == 0
+            && m.as_slice().len() >= RS_OPTIONS.start
+            && Options::are_valid(&m.as_slice()[RS_OPTIONS])
+        {
+            Ok(unsafe { Message::unchecked(m.buffer) })
+        } else {
+            Err(m)
+        }
+    }
+}
+
+impl<B> Message<B, RouterSolicitation>
+where
+    B: AsMutSlice<Element = u8> + Truncate<u16>,
+{
+    /* Constructors */
+    /// Transforms the input buffer into a Router Solicitation ICMPv6 message that carries the
+    /// given `options`
+    ///
+    /// # Panics
+    ///
+    /// This constructor panics if the message doesn't fit in `buffer`, or if one of the `options`
+    /// is larger than 2040 octets
+    pub fn router_solicitation(buffer: B, options: &[NdOption<'_>]) -> Self {
+        unsafe { Message::nd(buffer, Type::RouterSolicitation, RS_OPTIONS.start, options) }
+    }
+}
+
+impl<B> Message<B, RouterSolicitation>
+where
+    B: AsSlice<Element = u8>,
+{
+    /* Getters */
+    /// Reads the 'Source Link-layer address' option
+    // NOTE this contains padding
+    pub fn get_source_ll(&self) -> Option<&[u8]> {
+        self.options()
+            .filter_map(|opt| {
+                if let NdOption::SourceLinkLayerAddress(addr) = opt {
+                    Some(addr)
+                } else {
+                    None
+                }
+            })
+            .next()
+    }
+
+    /// Returns an iterator over the options of this message
+    pub fn options(&self) -> NdOptions<'_> {
+        // NOTE(unsafe) the options were validated in `try_from` (or written by the constructor)
+        NdOptions {
+            inner: unsafe { Options::new(self.as_slice().rf(RS_OPTIONS)) },
+        }
+    }
+}
+
+impl<B> fmt::Debug for Message<B, RouterSolicitation>
+where
+    B: AsSlice<Element = u8>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("icmpv6::Message<RouterSolicitation>")
+            .field("checksum", &self.get_checksum())
+            .field("source_ll", &self.get_source_ll())
+            .finish()
+    }
+}
+
+/// [Type state] Router Advertisement
+pub enum RouterAdvertisement {}
+
+impl<B> TryFrom<Message<B, Unknown>> for Message<B, RouterAdvertisement>
+where
+    B: AsSlice<Element = u8>,
+{
+    type Error = Message<B, Unknown>;
+
+    fn try_from(m: Message<B, Unknown>) -> Result<Self, Message<B, Unknown>> {
+        // RFC 4861 - Section 6.1.2.  Validation of Router Advertisement Messages
+        // "ICMP Code is 0"
+        // "ICMP length (derived from the IP length) is 16 or more octets"
+        // "All included options have a length that is greater than zero"
+        if m.get_type() == Type::RouterAdvertisement
+            && m.get_code() == 0
+            && m.as_slice().len() >= RA_OPTIONS.start
+            && Options::are_valid(&m.as_slice()[RA_OPTIONS])
+        {
+            Ok(unsafe { Message::unchecked(m.buffer) })
+        } else {
+            Err(m)
+        }
+    }
+}
+
+impl<B> Message<B, RouterAdvertisement>
+where
+    B: AsMutSlice<Element = u8> + Truncate<u16>,
+{
+    /* Constructors */
+    /// Transforms the input buffer into a Router Advertisement ICMPv6 message that carries the
+    /// given `options`
+    ///
+    /// All the fields of the header are set to zero: unspecified hop limit, no flags, not a
+    /// default router and unspecified timers.
+    ///
+    /// # Panics
+    ///
+    /// This constructor panics if the message doesn't fit in `buffer`, or if one of the `options`
+    /// is larger than 2040 octets
+    pub fn router_advertisement(buffer: B, options: &[NdOption<'_>]) -> Self {
+        unsafe { Message::nd(buffer, Type::RouterAdvertisement, RA_OPTIONS.start, options) }
+    }
+}
+
+impl<B> Message<B, RouterAdvertisement>
+where
+    B: AsSlice<Element = u8>,
+{
+    /* Getters */
+    /// Reads the 'Cur Hop Limit' field
+    pub fn get_cur_hop_limit(&self) -> u8 {
+        unsafe { *self.as_slice().gu(CUR_HOP_LIMIT) }
+    }
+
+    /// Reads the 'Managed address configuration' (M) flag
+    pub fn get_managed(&self) -> bool {
+        unsafe { get!(*self.as_slice().gu(FLAGS), managed) == 1 }
+    }
+
+    /// Reads the 'Other configuration' (O) flag
+    pub fn get_other(&self) -> bool {
+        unsafe { get!(*self.as_slice().gu(FLAGS), other) == 1 }
+    }
+
+    /// Reads the 'Router Lifetime' field
+    ///
+    /// This value is in seconds; zero means that the router is not a default router
+    pub fn get_router_lifetime(&self) -> u16 {
+        unsafe { NE::read_u16(self.as_slice().r(ROUTER_LIFETIME)) }
+    }
+
+    /// Reads the 'Reachable Time' field
+    ///
+    /// This value is in milliseconds; zero means unspecified
+    pub fn get_reachable_time(&self) -> u32 {
+        unsafe { NE::read_u32(self.as_slice().r(REACHABLE_TIME)) }
+    }
+
+    /// Reads the 'Retrans Timer' field
+    ///
+    /// This value is in milliseconds; zero means unspecified
+    pub fn get_retrans_timer(&self) -> u32 {
+        unsafe { NE::read_u32(self.as_slice().r(RETRANS_TIMER)) }
+    }
+
+    /// Reads the 'Source Link-layer address' option
+    // NOTE this contains padding
+    pub fn get_source_ll(&self) -> Option<&[u8]> {
+        self.options()
+            .filter_map(|opt| {
+                if let NdOption::SourceLinkLayerAddress(addr) = opt {
+                    Some(addr)
+                } else {
+                    None
+                }
+            })
+            .next()
+    }
+
+    /// Returns an iterator over the options of this message
+    pub fn options(&self) -> NdOptions<'_> {
+        // NOTE(unsafe) the options were validated in `try_from` (or written by the constructor)
+        NdOptions {
+            inner: unsafe { Options::new(self.as_slice().rf(RA_OPTIONS)) },
+        }
+    }
+}
+
+impl<B> Message<B, RouterAdvertisement>
+where
+    B: AsMutSlice<Element = u8>,
+{
+    /* Setters */
+    /// Sets the 'Cur Hop Limit' field
+    pub fn set_cur_hop_limit(&mut self, hop_limit: u8) {
+        unsafe { *self.as_mut_slice().gum(CUR_HOP_LIMIT) = hop_limit }
+    }
+
+    /// Sets the 'Managed address configuration' (M) flag
+    pub fn set_managed(&mut self, managed: bool) {
+        unsafe {
+            set!(
+                *self.as_mut_slice().gum(FLAGS),
+                managed,
+                if managed { 1 } else { 0 }
+            )
+        }
+    }
+
+    /// Sets the 'Other configuration' (O) flag
+    pub fn set_other(&mut self, other: bool) {
+        unsafe {
+            set!(
+                *self.as_mut_slice().gum(FLAGS),
+                other,
+                if other { 1 } else { 0 }
+            )
+        }
+    }
+
+    /// Sets the 'Router Lifetime' field
+    pub fn set_router_lifetime(&mut self, lifetime: u16) {
+        unsafe { NE::write_u16(self.as_mut_slice().rm(ROUTER_LIFETIME), lifetime) }
+    }
+
+    /// Sets the 'Reachable Time' field
+    pub fn set_reachable_time(&mut self, time: u32) {
+        unsafe { NE::write_u32(self.as_mut_slice().rm(REACHABLE_TIME), time) }
+    }
+
+    /// Sets the 'Retrans Timer' field
+    pub fn set_retrans_timer(&mut self, timer: u32) {
+        unsafe { NE::write_u32(self.as_mut_slice().rm(RETRANS_TIMER), timer) }
+    }
+}
+
+impl<B> fmt::Debug for Message<B, RouterAdvertisement>
+where
+    B: AsSlice<Element = u8>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("icmpv6::Message<RouterAdvertisement>")
+            .field("checksum", &self.get_checksum())
+            .field("cur_hop_limit", &self.get_cur_hop_limit())
+            .field("managed", &self.get_managed())
+            .field("other", &self.get_other())
+            .field("router_lifetime", &self.get_router_lifetime())
+            .field("reachable_time", &self.get_reachable_time())
+            .field("retrans_timer", &self.get_retrans_timer())
+            .finish()
+    }
+}
+
+impl<B, T> Message<B, T>
+where
+    B: AsMutSlice<Element = u8> + Truncate<u16>,
+{
+    /* Private */
+    // Writes the header of a Neighbor Discovery message, zeroing the `header_size` first bytes,
+    // followed by the given `options`
+    //
+    // NOTE(unsafe) caller must ensure that `ty` matches the type state `T`
+    unsafe fn nd(mut buffer: B, ty: Type, header_size: usize, options: &[NdOption<'_>]) -> Self {
+        let len = header_size + options.iter().map(|opt| opt.size()).sum::<usize>();
+        assert!(buffer.as_slice().len() >= len);
+
+        // NOTE(cast) the assert above ensures that `len` fits in the buffer
+        buffer.truncate(len as u16);
+
+        let slice = buffer.as_mut_slice();
+        for byte in slice.rtm(..header_size) {
+            *byte = 0;
+        }
+
+        let mut start = header_size;
+        for opt in options {
+            start += opt.write(slice.rfm(start..));
+        }
+
+        let mut m: Message<B, Unknown> = Message::unchecked(buffer);
+        m.set_type(ty);
+        m.set_code(0);
+
+        Message::unchecked(m.buffer)
+    }
+}
+
+/// [Type state] Destination Unreachable
+pub enum DestinationUnreachable {}
+
+/// [Type state] Packet Too Big
+pub enum PacketTooBig {}
+
+/// [Type state] Time Exceeded
+pub enum TimeExceeded {}
+
+/// [Type state] Parameter Problem
+pub enum ParameterProblem {}
+
+impl<B> TryFrom<Message<B, Unknown>> for Message<B, DestinationUnreachable>
+where
+    B: AsSlice<Element = u8>,
+{
+    type Error = Message<B, Unknown>;
+
+    fn try_from(m: Message<B, Unknown>) -> Result<Self, Message<B, Unknown>> {
+        if m.get_type() == Type::DestinationUnreachable
+            && m.as_slice().len() >= INVOKING_PACKET.start
+        {
+            Ok(unsafe { Message::unchecked(m.buffer) })
+        } else {
+            Err(m)
+        }
+    }
+}
+
+impl<B> TryFrom<Message<B, Unknown>> for Message<B, PacketTooBig>
+where
+    B: AsSlice<Element = u8>,
+{
+    type Error = Message<B, Unknown>;
+
+    fn try_from(m: Message<B, Unknown>) -> Result<Self, Message<B, Unknown>> {
+        if m.get_type() == Type::PacketTooBig && m.as_slice().len() >= INVOKING_PACKET.start {
+            Ok(unsafe { Message::unchecked(m.buffer) })
+        } else {
+            Err(m)
+        }
+    }
+}
+
+impl<B> TryFrom<Message<B, Unknown>> for Message<B, TimeExceeded>
+where
+    B: AsSlice<Element = u8>,
+{
+    type Error = Message<B, Unknown>;
+
+    fn try_from(m: Message<B, Unknown>) -> Result<Self, Message<B, Unknown>> {
+        if m.get_type() == Type::TimeExceeded && m.as_slice().len() >= INVOKING_PACKET.start {
+            Ok(unsafe { Message::unchecked(m.buffer) })
+        } else {
+            Err(m)
+        }
+    }
+}
+
+impl<B> TryFrom<Message<B, Unknown>> for Message<B, ParameterProblem>
+where
+    B: AsSlice<Element = u8>,
+{
+    type Error = Message<B, Unknown>;
+
+    fn try_from(m: Message<B, Unknown>) -> Result<Self, Message<B, Unknown>> {
+        if m.get_type() == Type::ParameterProblem && m.as_slice().len() >= INVOKING_PACKET.start {
+            Ok(unsafe { Message::unchecked(m.buffer) })
+        } else {
+            Err(m)
+        }
+    }
+}
+
+impl<B> Message<B, DestinationUnreachable>
+where
+    B: AsMutSlice<Element = u8> + Truncate<u16>,
+{
+    /* Constructors */
+    /// Transforms the input buffer into a Destination Unreachable ICMPv6 message that quotes the
+    /// `invoking` packet
+    ///
+    /// The quoted packet is truncated so that the message, plus an IPv6 header, doesn't exceed the
+    /// minimum IPv6 MTU -- or the size of `buffer`
+    pub fn destination_unreachable(buffer: B, code: UnreachableCode, invoking: &[u8]) -> Self {
+        unsafe { Message::error(buffer, Type::DestinationUnreachable, code.into(), invoking) }
+    }
+}
+
+impl<B> Message<B, DestinationUnreachable>
+where
+    B: AsSlice<Element = u8>,
+{
+    /* Getters */
+    /// Reads the 'Code' field
+    pub fn get_unreachable_code(&self) -> UnreachableCode {
+        self.get_code().into()
+    }
+}
+
+impl<B> Message<B, PacketTooBig>
+where
+    B: AsMutSlice<Element = u8> + Truncate<u16>,
+{
+    /* Constructors */
+    /// Transforms the input buffer into a Packet Too Big ICMPv6 message that quotes the `invoking`
+    /// packet
+    ///
+    /// The quoted packet is truncated so that the message, plus an IPv6 header, doesn't exceed the
+    /// minimum IPv6 MTU -- or the size of `buffer`
+    pub fn packet_too_big(buffer: B, mtu: u32, invoking: &[u8]) -> Self {
+        let mut m: Self = unsafe { Message::error(buffer, Type::PacketTooBig, 0, invoking) };
+        unsafe { NE::write_u32(m.as_mut_slice().rm(MTU), mtu) }
+        m
+    }
+}
+
+impl<B> Message<B, PacketTooBig>
+where
+    B: AsSlice<Element = u8>,
+{
+    /* Getters */
+    /// Reads the 'MTU' field
+    pub fn get_mtu(&self) -> u32 {
+        unsafe { NE::read_u32(self.as_slice().r(MTU)) }
+    }
+}
+
+impl<B> Message<B, TimeExceeded>
+where
+    B: AsMutSlice<Element = u8> + Truncate<u16>,
+{
+    /* Constructors */
+    /// Transforms the input buffer into a Time Exceeded ICMPv6 message that quotes the `invoking`
+    /// packet
+    ///
+    /// The quoted packet is truncated so that the message, plus an IPv6 header, doesn't exceed the
+    /// minimum IPv6 MTU -- or the size of `buffer`
+    pub fn time_exceeded(buffer: B, code: TimeExceededCode, invoking: &[u8]) -> Self {
+        unsafe { Message::error(buffer, Type::TimeExceeded, code.into(), invoking) }
+    }
+}
+
+impl<B> Message<B, TimeExceeded>
+where
+    B: AsSlice<Element = u8>,
+{
+    /* Getters */
+    /// Reads the 'Code' field
+    pub fn get_time_exceeded_code(&self) -> TimeExceededCode {
+        self.get_code().into()
+    }
+}
+
+impl<B> Message<B, ParameterProblem>
+where
+    B: AsMutSlice<Element = u8> + Truncate<u16>,
+{
+    /* Constructors */
+    /// Transforms the input buffer into a Parameter Problem ICMPv6 message that quotes the
+    /// `invoking` packet
+    ///
+    /// `pointer` is the offset, into the `invoking` packet, of the octet where the error was
+    /// detected
+    ///
+    /// The quoted packet is truncated so that the message, plus an IPv6 header, doesn't exceed the
+    /// minimum IPv6 MTU -- or the size of `buffer`
+    pub fn parameter_problem(
+        buffer: B,
+        code: ParameterProblemCode,
+        pointer: u32,
+        invoking: &[u8],
+    ) -> Self {
+        let mut m: Self =
+            unsafe { Message::error(buffer, Type::ParameterProblem, code.into(), invoking) };
+        unsafe { NE::write_u32(m.as_mut_slice().rm(POINTER), pointer) }
+        m
+    }
+}
+
+impl<B> Message<B, ParameterProblem>
+where
+    B: AsSlice<Element = u8>,
+{
+    /* Getters */
+    /// Reads the 'Code' field
+    pub fn get_parameter_problem_code(&self) -> ParameterProblemCode {
+        self.get_code().into()
+    }
+
+    /// Reads the 'Pointer' field
+    pub fn get_pointer(&self) -> u32 {
+        unsafe { NE::read_u32(self.as_slice().r(POINTER)) }
+    }
+}
+
+impl<B, E> Message<B, E>
+where
+    B: AsSlice<Element = u8>,
+    E: Icmpv6Error,
+{
+    /* Getters */
+    /// Immutable view into the packet that triggered this error message
+    ///
+    /// NOTE the invoking packet is usually truncated so its 'Payload length' field can't be
+    /// trusted
+    pub fn invoking_packet(&self) -> &[u8] {
+        unsafe { self.as_slice().rf(INVOKING_PACKET) }
+    }
+}
+
+impl<B, E> Message<B, E>
+where
+    B: AsMutSlice<Element = u8> + Truncate<u16>,
+    E: Icmpv6Error,
+{
+    /* Private */
+    // Writes the header of an error message and quotes as much of the `invoking` packet as
+    // possible without the ICMPv6 message exceeding the minimum IPv6 MTU (RFC 4443 - Section 2.4
+    // (c)), or the `buffer`
+    //
+    // NOTE(unsafe) caller must ensure that `ty` matches the type state `E`
+    unsafe fn error(mut buffer: B, ty: Type, code: u8, invoking: &[u8]) -> Self {
+        let blen = buffer.as_slice().len();
+        assert!(blen >= INVOKING_PACKET.start);
+
+        let max = usize::from(MIN_MTU) - usize::from(ipv6::HEADER_SIZE) - INVOKING_PACKET.start;
+        let qlen = cmp::min(cmp::min(invoking.len(), max), blen - INVOKING_PACKET.start);
+
+        // NOTE(cast) `qlen` is at most `max`, which is smaller than `u16::MAX`
+        buffer.truncate((INVOKING_PACKET.start + qlen) as u16);
+
+        buffer.as_mut_slice().rm(UNUSED).copy_from_slice(&[0; 4]);
+        buffer
+            .as_mut_slice()
+            .rfm(INVOKING_PACKET)
+            .copy_from_slice(&invoking[..qlen]);
+
+        let mut m: Message<B, Unknown> = Message::unchecked(buffer);
+        m.set_type(ty);
+        m.set_code(code);
+
+        Message::unchecked(m.buffer)
+    }
+}
+
+impl<B> fmt::Debug for Message<B, DestinationUnreachable>
+where
+    B: AsSlice<Element = u8>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("icmpv6::Message<DestinationUnreachable>")
+            .field("code", &self.get_code())
+            .field("checksum", &self.get_checksum())
+            .finish()
+    }
+}
+
+impl<B> fmt::Debug for Message<B, PacketTooBig>
+where
+    B: AsSlice<Element = u8>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("icmpv6::Message<PacketTooBig>")
+            .field("code", &self.get_code())
+            .field("checksum", &self.get_checksum())
+            .field("mtu", &self.get_mtu())
+            .finish()
+    }
+}
+
+impl<B> fmt::Debug for Message<B, TimeExceeded>
+where
+    B: AsSlice<Element = u8>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("icmpv6::Message<TimeExceeded>")
+            .field("code", &self.get_code())
+            .field("checksum", &self.get_checksum())
+            .finish()
+    }
+}
+
+impl<B> fmt::Debug for Message<B, ParameterProblem>
+where
+    B: AsSlice<Element = u8>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("icmpv6::Message<ParameterProblem>")
+            .field("code", &self.get_code())
+            .field("checksum", &self.get_checksum())
+            .field("pointer", &self.get_pointer())
+            .finish()
+    }
+}
+
+/// Neighbor Discovery option
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum NdOption<'a> {
+    /// Source Link-layer Address option (SLLAO)
+    ///
+    /// NOTE when parsed, the address may be followed by padding
+    SourceLinkLayerAddress(&'a [u8]),
+    /// Target Link-layer Address option (TLLAO)
+    ///
+    /// NOTE when parsed, the address may be followed by padding
+    TargetLinkLayerAddress(&'a [u8]),
+    /// Prefix Information option
+    PrefixInformation(PrefixInformation),
+    /// MTU option
+    Mtu(u32),
+    /// Recursive DNS Server option (RFC 8106)
+    RecursiveDnsServer(RecursiveDnsServer<'a>),
+    /// 6LoWPAN Context option (RFC 6775)
+    SixLowpanContext(SixLowpanContext),
+    /// An option this crate doesn't know about or a malformed option
+    Unknown {
+        /// Option type
+        ty: u8,
+        /// Contents of the option, excluding the Type and Length fields
+        contents: &'a [u8],
+    },
+}
+
+/// Prefix Information option
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct PrefixInformation {
+    /// Number of leading bits in `prefix` that are valid
+    pub prefix_length: u8,
+    /// On-link flag (L)
+    pub on_link: bool,
+    /// Autonomous address-configuration flag (A)
+    pub autonomous: bool,
+    /// Valid lifetime, in seconds; `u32::MAX` means infinity
+    pub valid_lifetime: u32,
+    /// Preferred lifetime, in seconds; `u32::MAX` means infinity
+    pub preferred_lifetime: u32,
+    /// IPv6 address or prefix of an IPv6 address
+    pub prefix: ipv6::Addr,
+}
+
+/// Recursive DNS Server option
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct RecursiveDnsServer<'a> {
+    /// Maximum time, in seconds, over which the servers may be used; `u32::MAX` means infinity
+    pub lifetime: u32,
+    /// Addresses of the servers, back to back
+    ///
+    /// # Panics
+    ///
+    /// Writing this option panics if the length of this slice is not a non-zero multiple of 16
+    pub servers: &'a [u8],
+}
+
+impl<'a> RecursiveDnsServer<'a> {
+    /// Returns an iterator over the addresses of the servers
+    pub fn servers(&self) -> impl Iterator<Item = ipv6::Addr> + 'a {
+        self.servers.chunks_exact(16).map(|chunk| {
+            let mut addr = ipv6::Addr::UNSPECIFIED;
+            addr.0.copy_from_slice(chunk);
+            addr
+        })
+    }
+}
+
+/// 6LoWPAN Context option
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct SixLowpanContext {
+    /// Number of leading bits in `prefix` that are valid
+    pub context_length: u8,
+    /// Whether the context is valid for compression (C)
+    pub compression: bool,
+    /// Context Identifier
+    pub cid: u8,
+    /// Valid lifetime, in units of 60 seconds
+    pub valid_lifetime: u16,
+    /// Context prefix; the bits past `context_length` are zero
+    pub prefix: ipv6::Addr,
+}
+
+/// Iterator over the options of a Neighbor Discovery message
+#[derive(Clone)]
+pub struct NdOptions<'a> {
+    inner: Options<'a>,
+}
+
+impl<'a> Iterator for NdOptions<'a> {
+    type Item = NdOption<'a>;
+
+    fn next(&mut self) -> Option<NdOption<'a>> {
+        self.inner
+            .next()
+            .map(|opt| NdOption::parse(opt.ty, opt.contents))
+    }
+}
+
+impl<'a> NdOption<'a> {
+    fn parse(ty: OptionType, contents: &'a [u8]) -> Self {
+        match ty {
+            OptionType::SourceLinkLayerAddress => NdOption::SourceLinkLayerAddress(contents),
+            OptionType::TargetLinkLayerAddress => NdOption::TargetLinkLayerAddress(contents),
+            OptionType::PrefixInformation if contents.len() == 30 => {
+                let mut prefix = ipv6::Addr::UNSPECIFIED;
+                prefix.0.copy_from_slice(&contents[14..30]);
+
+                NdOption::PrefixInformation(PrefixInformation {
+                    prefix_length: contents[0],
+                    on_link: contents[1] & (1 << 7) != 0,
+                    autonomous: contents[1] & (1 << 6) != 0,
+                    valid_lifetime: NE::read_u32(&contents[2..6]),
+                    preferred_lifetime: NE::read_u32(&contents[6..10]),
+                    prefix,
+                })
+            }
+            OptionType::Mtu if contents.len() == 6 => NdOption::Mtu(NE::read_u32(&contents[2..6])),
+            OptionType::RecursiveDnsServer if contents.len() > 6 && contents.len() % 16 == 6 => {
+                NdOption::RecursiveDnsServer(RecursiveDnsServer {
+                    lifetime: NE::read_u32(&contents[2..6]),
+                    servers: &contents[6..],
+                })
+            }
+            OptionType::SixLowpanContext if contents.len() == 14 || contents.len() == 22 => {
+                let mut prefix = ipv6::Addr::UNSPECIFIED;
+                let bytes = &contents[6..];
+                prefix.0[..bytes.len()].copy_from_slice(bytes);
+
+                NdOption::SixLowpanContext(SixLowpanContext {
+                    context_length: contents[0],
+                    compression: contents[1] & (1 << 4) != 0,
+                    cid: contents[1] & 0x0f,
+                    valid_lifetime: NE::read_u16(&contents[4..6]),
+                    prefix,
+                })
+            }
+            _ => NdOption::Unknown {
+                ty: ty.into(),
+                contents,
+            },
+        }
+    }
+
+    // Size of this option in bytes, including the Type and Length fields and padding
+    fn size(&self) -> usize {
+        let len = match self {
+            NdOption::SourceLinkLayerAddress(addr) | NdOption::TargetLinkLayerAddress(addr) => {
+                2 + addr.len()
+            }
+            NdOption::PrefixInformation(..) => 32,
+            NdOption::Mtu(..) => 8,
+            NdOption::RecursiveDnsServer(rdnss) => 8 + rdnss.servers.len(),
+            NdOption::SixLowpanContext(ctx) => {
+                if ctx.context_length > 64 {
+                    24
+                } else {
+                    16
+                }
+            }
+            NdOption::Unknown { contents, .. } => 2 + contents.len(),
+        };
+
+        // options are padded to a multiple of 8 octets
+        (len + 7) & !7
+    }
+
+    // Writes this option at the start of `buffer` and returns its size
+    //
+    // # Panics
+    //
+    // This panics if the option is larger than 2040 octets, the most the 8-bit 'Length' field
+    // (in units of 8 octets) can express
+    fn write(&self, buffer: &mut [u8]) -> usize {
+        let size = self.size();
+        assert!(size <= 255 * 8, "ND option larger than 2040 octets");
+
+        let buffer = &mut buffer[..size];
+        for byte in buffer.iter_mut() {
+            *byte = 0;
+        }
+
+        let ty = match self {
+            NdOption::SourceLinkLayerAddress(addr) => {
+                buffer[2..2 + addr.len()].copy_from_slice(addr);
+                OptionType::SourceLinkLayerAddress
+            }
+            NdOption::TargetLinkLayerAddress(addr) => {
+                buffer[2..2 + addr.len()].copy_from_slice(addr);
+                OptionType::TargetLinkLayerAddress
+            }
+            NdOption::PrefixInformation(pi) => {
+                buffer[2] = pi.prefix_length;
+                buffer[3] =
+                    if pi.on_link { 1 << 7 } else { 0 } | if pi.autonomous { 1 << 6 } else { 0 };
+                NE::write_u32(&mut buffer[4..8], pi.valid_lifetime);
+                NE::write_u32(&mut buffer[8..12], pi.preferred_lifetime);
+                buffer[16..32].copy_from_slice(&pi.prefix.0);
+                OptionType::PrefixInformation
+            }
+            NdOption::Mtu(mtu) => {
+                NE::write_u32(&mut buffer[4..8], *mtu);
+                OptionType::Mtu
+            }
+            NdOption::RecursiveDnsServer(rdnss) => {
+                assert!(!rdnss.servers.is_empty() && rdnss.servers.len() % 16 == 0);
+
+                NE::write_u32(&mut buffer[4..8], rdnss.lifetime);
+                buffer[8..].copy_from_slice(rdnss.servers);
+                OptionType::RecursiveDnsServer
+            }
+            NdOption::SixLowpanContext(ctx) => {
+                buffer[2] = ctx.context_length;
+                buffer[3] = if ctx.compression { 1 << 4 } else { 0 } | (ctx.cid & 0x0f);
+                NE::write_u16(&mut buffer[6..8], ctx.valid_lifetime);
+                buffer[8..].copy_from_slice(&ctx.prefix.0[..size - 8]);
+                OptionType::SixLowpanContext
+            }
+            NdOption::Unknown { ty, contents } => {
+                buffer[2..2 + contents.len()].copy_from_slice(contents);
+                OptionType::from(*ty)
+            }
+        };
+
+        buffer[0] = ty.into();
+        // NOTE(cast) `size` is at most 2040 and a multiple of 8 (see `size`)
+        buffer[1] = (size / 8) as u8;
+
+        size
+    }
+}
+
 // See Section 4.6 of RFC 2461
+#[derive(Clone)]
 struct Options<'a> {
     opts: &'a [u8],
 }
@@ -795,6 +1676,14 @@ full_range!(
     /// ICMPv6 types
     #[derive(Clone, Copy, Debug, Eq, PartialEq)]
     pub enum Type {
+        /// Destination unreachable
+        DestinationUnreachable = 1,
+        /// Packet too big
+        PacketTooBig = 2,
+        /// Time exceeded
+        TimeExceeded = 3,
+        /// Parameter problem
+        ParameterProblem = 4,
         /// Echo request
         EchoRequest = 128,
         /// Echo reply
@@ -810,6 +1699,54 @@ full_range!(
     }
 );
 
+full_range!(
+    u8,
+    /// Destination Unreachable codes
+    #[derive(Clone, Copy, Debug, Eq, PartialEq)]
+    pub enum UnreachableCode {
+        /// No route to destination
+        NoRoute = 0,
+        /// Communication with destination administratively prohibited
+        AdministrativelyProhibited = 1,
+        /// Beyond scope of source address
+        BeyondScope = 2,
+        /// Address unreachable
+        AddressUnreachable = 3,
+        /// Port unreachable
+        PortUnreachable = 4,
+        /// Source address failed ingress/egress policy
+        SourcePolicyFailed = 5,
+        /// Reject route to destination
+        RejectRoute = 6,
+    }
+);
+
+full_range!(
+    u8,
+    /// Time Exceeded codes
+    #[derive(Clone, Copy, Debug, Eq, PartialEq)]
+    pub enum TimeExceededCode {
+        /// Hop limit exceeded in transit
+        HopLimitExceeded = 0,
+        /// Fragment reassembly time exceeded
+        FragmentReassembly = 1,
+    }
+);
+
+full_range!(
+    u8,
+    /// Parameter Problem codes
+    #[derive(Clone, Copy, Debug, Eq, PartialEq)]
+    pub enum ParameterProblemCode {
+        /// Erroneous header field encountered
+        ErroneousHeaderField = 0,
+        /// Unrecognized Next Header type encountered
+        UnrecognizedNextHeader = 1,
+        /// Unrecognized IPv6 option encountered
+        UnrecognizedOption = 2,
+    }
+);
+
 full_range!(
     u8,
     /// Option type
@@ -825,5 +1762,233 @@ full_range!(
         RedirectedHeader = 4,
         // MTU
         Mtu = 5,
+        // Recursive DNS Server
+        RecursiveDnsServer = 25,
+        // 6LoWPAN Context
+        SixLowpanContext = 34,
     }
 );
+
+#[cfg(test)]
+mod tests {
+    use crate::{icmpv6, ipv6};
+
+    const SRC: ipv6::Addr =
+        ipv6::Addr([0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0x02, 0, 0, 0, 0, 0, 0, 0x01]);
+    const DEST: ipv6::Addr =
+        ipv6::Addr([0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0x02, 0, 0, 0, 0, 0, 0, 0x02]);
+
+    #[test]
+    fn destination_unreachable() {
+        // a UDP packet sent to a closed port
+        let mut invoking = [0; 64];
+        let mut packet = ipv6::Packet::new(&mut invoking[..]);
+        packet.set_source(DEST);
+        packet.set_destination(SRC);
+        packet.udp(|udp| {
+            udp.set_source(1337);
+            udp.set_destination(1338);
+            udp.set_payload(b"Hello");
+        });
+
+        let mut buffer = [0; 128];
+        let mut ip = ipv6::Packet::new(&mut buffer[..]);
+        ip.set_source(SRC);
+        ip.set_destination(DEST);
+        ip.destination_unreachable(icmpv6::UnreachableCode::PortUnreachable, packet.as_bytes());
+
+        let ip = ipv6::Packet::parse(ip.as_bytes()).unwrap();
+        assert_eq!(ip.get_next_header(), ipv6::NextHeader::Ipv6Icmp);
+
+        let m = icmpv6::Message::parse(ip.payload()).unwrap();
+        assert!(m.verify_checksum(SRC, DEST));
+
+        let m = m.downcast::<icmpv6::DestinationUnreachable>().unwrap();
+        assert_eq!(
+            m.get_unreachable_code(),
+            icmpv6::UnreachableCode::PortUnreachable
+        );
+        assert_eq!(m.invoking_packet(), packet.as_bytes());
+    }
+
+    #[test]
+    fn router_advertisement() {
+        const PREFIX: ipv6::Addr =
+            ipv6::Addr([0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+
+        let pi = icmpv6::PrefixInformation {
+            prefix_length: 64,
+            on_link: true,
+            autonomous: true,
+            valid_lifetime: 2_592_000,
+            preferred_lifetime: 604_800,
+            prefix: PREFIX,
+        };
+        let servers = [PREFIX.0, SRC.0];
+        let mut rdnss = [0; 32];
+        rdnss[..16].copy_from_slice(&servers[0]);
+        rdnss[16..].copy_from_slice(&servers[1]);
+        let rdnss = icmpv6::RecursiveDnsServer {
+            lifetime: 3600,
+            servers: &rdnss,
+        };
+        let ctx = icmpv6::SixLowpanContext {
+            context_length: 64,
+            compression: true,
+            cid: 1,
+            valid_lifetime: 60,
+            prefix: PREFIX,
+        };
+        let mac = [0x02, 0, 0, 0, 0, 0x01];
+        let options = [
+            icmpv6::NdOption::SourceLinkLayerAddress(&mac),
+            icmpv6::NdOption::Mtu(1280),
+            icmpv6::NdOption::PrefixInformation(pi),
+            icmpv6::NdOption::RecursiveDnsServer(rdnss),
+            icmpv6::NdOption::SixLowpanContext(ctx),
+        ];
+
+        let mut buffer = [0; 256];
+        let mut ip = ipv6::Packet::new(&mut buffer[..]);
+        ip.set_source(SRC);
+        ip.set_destination(ipv6::Addr::ALL_NODES);
+        ip.router_advertisement(&options, |ra| {
+            ra.set_cur_hop_limit(64);
+            ra.set_other(true);
+            ra.set_router_lifetime(1800);
+            ra.set_reachable_time(30_000);
+            ra.set_retrans_timer(1_000);
+        });
+
+        let ip = ipv6::Packet::parse(ip.as_bytes()).unwrap();
+        let m = icmpv6::Message::parse(ip.payload()).unwrap();
+        assert!(m.verify_checksum(SRC, ipv6::Addr::ALL_NODES));
+
+        // Prefix Information option (RFC 4861 - Section 4.6.2)
+        assert_eq!(
+            &m.as_bytes()[16 + 8 + 8..][..32],
+            &[
+                3, 4, 64, 0xc0, // type, length, prefix length, flags
+                0x00, 0x27, 0x8d, 0x00, // valid lifetime
+                0x00, 0x09, 0x3a, 0x80, // preferred lifetime
+                0, 0, 0, 0, // reserved
+                0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, // prefix
+            ][..]
+        );
+
+        let ra = m.downcast::<icmpv6::RouterAdvertisement>().unwrap();
+        assert_eq!(ra.get_cur_hop_limit(), 64);
+        assert!(!ra.get_managed());
+        assert!(ra.get_other());
+        assert_eq!(ra.get_router_lifetime(), 1800);
+        assert_eq!(ra.get_reachable_time(), 30_000);
+        assert_eq!(ra.get_retrans_timer(), 1_000);
+        assert_eq!(ra.get_source_ll(), Some(&mac[..]));
+
+        let mut opts = ra.options();
+        assert_eq!(
+            opts.next(),
+            Some(icmpv6::NdOption::SourceLinkLayerAddress(&mac[..]))
+        );
+        assert_eq!(opts.next(), Some(icmpv6::NdOption::Mtu(1280)));
+        assert_eq!(opts.next(), Some(icmpv6::NdOption::PrefixInformation(pi)));
+        match opts.next() {
+            Some(icmpv6::NdOption::RecursiveDnsServer(opt)) => {
+                assert_eq!(opt.lifetime, 3600);
+                let mut addrs = opt.servers();
+                assert_eq!(addrs.next(), Some(ipv6::Addr(servers[0])));
+                assert_eq!(addrs.next(), Some(ipv6::Addr(servers[1])));
+                assert_eq!(addrs.next(), None);
+            }
+            opt => panic!("unexpected option: {:?}", opt),
+        }
+        assert_eq!(opts.next(), Some(icmpv6::NdOption::SixLowpanContext(ctx)));
+        assert_eq!(opts.next(), None);
+    }
+
+    #[test]
+    fn router_solicitation() {
+        let mac = [0x02, 0, 0, 0, 0, 0x02];
+
+        let mut buffer = [0; 128];
+        let mut ip = ipv6::Packet::new(&mut buffer[..]);
+        ip.set_source(DEST);
+        ip.set_destination(ipv6::Addr::ALL_ROUTERS);
+        ip.router_solicitation(&[icmpv6::NdOption::SourceLinkLayerAddress(&mac)]);
+        assert_eq!(ip.get_length(), 16);
+
+        let ip = ipv6::Packet::parse(ip.as_bytes()).unwrap();
+        let m = icmpv6::Message::parse(ip.payload()).unwrap();
+        assert!(m.verify_checksum(DEST, ipv6::Addr::ALL_ROUTERS));
+
+        let rs = m.downcast::<icmpv6::RouterSolicitation>().unwrap();
+        // NOTE 6 bytes of address + 2 bytes of type and length: no padding
+        assert_eq!(rs.get_source_ll(), Some(&mac[..]));
+
+        // zero-length options are rejected
+        let mut bytes = [0; 16];
+        bytes.copy_from_slice(rs.as_bytes());
+        bytes[9] = 0;
+        assert!(icmpv6::Message::parse(&bytes[..])
+            .unwrap()
+            .downcast::<icmpv6::RouterSolicitation>()
+            .is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "ND option larger than 2040 octets")]
+    fn oversized_option() {
+        let contents = [0; 2040];
+
+        let mut buffer = [0; 2200];
+        let mut ip = ipv6::Packet::new(&mut buffer[..]);
+        ip.router_solicitation(&[icmpv6::NdOption::Unknown {
+            ty: 200,
+            contents: &contents,
+        }]);
+    }
+
+    #[test]
+    fn packet_too_big() {
+        // the invoking packet gets truncated to keep the message within the minimum MTU
+        let invoking = [0; 1500];
+        let mut buffer = [0; 1500];
+
+        let m = icmpv6::Message::packet_too_big(&mut buffer[..], 1280, &invoking);
+        assert_eq!(m.as_bytes().len(), 1280 - usize::from(ipv6::HEADER_SIZE));
+
+        let m = icmpv6::Message::parse(m.as_bytes())
+            .unwrap()
+            .downcast::<icmpv6::PacketTooBig>()
+            .unwrap();
+        assert_eq!(m.get_code(), 0);
+        assert_eq!(m.get_mtu(), 1280);
+
+        // ... or to fit in the buffer
+        let mut buffer = [0; 64];
+        let m = icmpv6::Message::time_exceeded(
+            &mut buffer[..],
+            icmpv6::TimeExceededCode::HopLimitExceeded,
+            &invoking,
+        );
+        assert_eq!(m.invoking_packet().len(), 64 - 8);
+
+        let mut buffer = [0; 64];
+        let m = icmpv6::Message::parameter_problem(
+            &mut buffer[..],
+            icmpv6::ParameterProblemCode::UnrecognizedNextHeader,
+            6,
+            &invoking[..40],
+        );
+        let m = icmpv6::Message::parse(m.as_bytes())
+            .unwrap()
+            .downcast::<icmpv6::ParameterProblem>()
+            .unwrap();
+        assert_eq!(
+            m.get_parameter_problem_code(),
+            icmpv6::ParameterProblemCode::UnrecognizedNextHeader
+        );
+        assert_eq!(m.get_pointer(), 6);
+        assert_eq!(m.invoking_packet().len(), 40);
+    }
+}
@@ -16,10 +16,16 @@ use owning_slice::Truncate;
 
 use crate::{
     icmpv6, ipv6,
-    sixlowpan::{iphc, nhc},
+    sixlowpan::{self, iphc, nhc},
     traits::UncheckedIndex,
 };
 
+pub mod beacon;
+pub mod command;
+pub mod ie;
+pub mod mpdu;
+pub mod security;
+
 /* Frame format (Section 7.2.1) */
 // Frame control low byte
 const CONTROLL: usize = 0;
@@ -55,12 +61,30 @@ mod intra_pan {
 
 // Frame control high byte
 const CONTROLH: usize = 1;
+mod seq_suppression {
+    pub const MASK: u8 = (1 << SIZE) - 1;
+    pub const OFFSET: u8 = 0;
+    pub const SIZE: u8 = 1;
+}
+
+mod ie_present {
+    pub const MASK: u8 = (1 << SIZE) - 1;
+    pub const OFFSET: u8 = super::seq_suppression::OFFSET + super::seq_suppression::SIZE;
+    pub const SIZE: u8 = 1;
+}
+
 mod dest_addr_mode {
     pub const MASK: u8 = (1 << SIZE) - 1;
     pub const OFFSET: u8 = 2;
     pub const SIZE: u8 = 2;
 }
 
+mod frame_version {
+    pub const MASK: u8 = (1 << SIZE) - 1;
+    pub const OFFSET: u8 = 4;
+    pub const SIZE: u8 = 2;
+}
+
 mod src_addr_mode {
     pub const MASK: u8 = (1 << SIZE) - 1;
     pub const OFFSET: u8 = 6;
@@ -70,6 +94,9 @@ mod src_addr_mode {
 // Sequence number
 const SEQUENCE: usize = 2;
 
+// Size of the frame control field
+const CONTROL_SIZE: u8 = SEQUENCE as u8;
+
 const HEADER_SIZE: u8 = SEQUENCE as u8 + 1;
 
 /// IEEE 802.15.4 MAC frame
@@ -93,81 +120,45 @@ where
         let len = (|| {
             let slice = bytes.as_slice();
 
-            let mut len = 3u8; // length of (Frame control + Sequence number)
-
             // too small
-            if slice.len() < usize::from(len) {
+            if slice.len() < usize::from(CONTROL_SIZE) {
                 return Err(());
             }
 
-            let ftype = Type::from(get!(slice[CONTROLL], frame_type));
-
-            let dest_addr_mode =
-                AddrMode::checked(get!(slice[CONTROLH], dest_addr_mode)).ok_or(())?;
-            let src_addr_mode = AddrMode::checked(get!(slice[CONTROLH], src_addr_mode)).ok_or(())?;
-
-            len += match dest_addr_mode {
-                AddrMode::None => {
-                    // 7.2.1.1.6 Destination addressing mode subfield
-                    //
-                    // "If this subfield is equal to 0 and the frame type subfield does not specify
-                    // that this frame is an acknowledgment or beacon frame, the source addressing
-                    // mode subfield shall be nonzero"
-                    if ftype != Type::Acknowledgment || ftype != Type::Beacon {
-                        if src_addr_mode == AddrMode::None {
-                            return Err(());
-                        }
-                    }
-
-                    0
-                }
-                AddrMode::Short => 2,
-                AddrMode::Extended => 8,
-            };
-
-            len += match src_addr_mode {
-                AddrMode::None => {
-                    // 7.2.1.1.7 Source addressing mode subfield
-                    //
-                    // "If this subfield is equal to 0 and the frame type subfield does not specify
-                    // that this frame is an acknowledgment frame, the destination addressing mode
-                    // subfield shall be nonzero"
-                    if ftype != Type::Acknowledgment {
-                        if dest_addr_mode == AddrMode::None {
-                            return Err(());
-                        }
-                    }
-
-                    0
-                }
-                AddrMode::Short => 2,
-                AddrMode::Extended => 8,
-            };
+            let layout = Layout::new(slice[CONTROLL], slice[CONTROLH])?;
+            let mut len = layout.end;
 
-            // 7.2.1.3 Destination PAN identifier field
+            // IEEE 802.15.4-2006 - 7.2.1.7 Auxiliary Security Header field
             //
-            // "This field shall be included in the MAC frame only if the destination addressing
-            // mode subfield of the frame control field is nonzero."
-            if dest_addr_mode != AddrMode::None {
-                len += 2;
+            // "The Auxiliary Security Header field [..] shall be present only if the Security
+            // Enabled subfield is set to one."
+            //
+            // NOTE IEEE 802.15.4-2003 frames use a different security scheme that has no such
+            // field; their payload is left as it is
+            if get!(slice[CONTROLL], security_enabled) == 1
+                && get!(slice[CONTROLH], frame_version) != FrameVersion::Ieee802154_2003 as u8
+            {
+                let aux = slice.get(len..).ok_or(())?;
+                len += usize::from(security::AuxHeader::parse(aux).map_err(drop)?.len());
             }
 
-            let intra_pan = get!(slice[CONTROLL], intra_pan);
+            // IEEE 802.15.4-2015 - 7.2.2.5 IE Present field
+            if layout.ie_present {
+                let (size, end) = ie::header_ies_size(slice.get(len..).ok_or(())?)?;
+                len += size;
 
-            // 7.2.1.5 Source PAN identifier field
-            //
-            // "This field shall be included in the MAC frame only if the source addressing mode and
-            // intra-PAN subfields of the frame control field are nonzero and equal to zero,
-            // respectively."
-            if src_addr_mode != AddrMode::None && intra_pan == 0 {
-                len += 2;
+                // NOTE the Payload IEs of secured frames are encrypted so they are considered part
+                // of the payload
+                if end == ie::End::Ht1 && get!(slice[CONTROLL], security_enabled) == 0 {
+                    len += ie::payload_ies_size(&slice[len..])?.0;
+                }
             }
 
-            if slice.len() < usize::from(len) {
+            if slice.len() < len {
                 // too small
                 Err(())
             } else {
-                Ok(len)
+                cast::u8(len).map_err(drop)
             }
         })();
 
@@ -203,104 +194,107 @@ where
     }
 
     /// Reads the 'Intra-PAN' field
+    ///
+    /// This field was renamed to 'PAN ID Compression' in the 2006 revision of the standard
     pub fn get_intra_pan(&self) -> bool {
         get!(self.header_()[CONTROLL], intra_pan) == 1
     }
 
+    /// Reads the 'Sequence Number Suppression' field
+    ///
+    /// This field is always `false` in frames older than IEEE 802.15.4-2015
+    pub fn get_sequence_number_suppression(&self) -> bool {
+        self.layout().seq_suppressed
+    }
+
+    /// Reads the 'IE Present' field
+    ///
+    /// This field is always `false` in frames older than IEEE 802.15.4-2015
+    pub fn get_ie_present(&self) -> bool {
+        self.layout().ie_present
+    }
+
     /// Reads the 'Dest. addressing mode' field
     pub fn get_dest_addr_mode(&self) -> AddrMode {
         unsafe { AddrMode::unchecked(get!(self.header_()[CONTROLH], dest_addr_mode)) }
     }
 
+    /// Reads the 'Frame version' field
+    pub fn get_frame_version(&self) -> FrameVersion {
+        unsafe { FrameVersion::unchecked(get!(self.header_()[CONTROLH], frame_version)) }
+    }
+
     /// Reads the 'Source addressing mode' field
     pub fn get_src_addr_mode(&self) -> AddrMode {
         unsafe { AddrMode::unchecked(get!(self.header_()[CONTROLH], src_addr_mode)) }
     }
 
     /// Reads the 'Sequence number' field
-    pub fn get_sequence_number(&self) -> u8 {
-        self.header_()[SEQUENCE]
+    ///
+    /// Returns `None` if the sequence number has been suppressed
+    pub fn get_sequence_number(&self) -> Option<u8> {
+        if self.layout().seq_suppressed {
+            None
+        } else {
+            Some(unsafe { *self.as_slice().gu(SEQUENCE) })
+        }
     }
 
     /// Reads the 'Destination PAN identifier' field
     pub fn get_dest_pan_id(&self) -> Option<PanId> {
-        // See 7.2.1.3 Destination PAN identifier field
-        if self.get_dest_addr_mode() == AddrMode::None {
-            None
-        } else {
-            Some(PanId(LE::read_u16(unsafe { self.as_slice().r(3..5) })))
-        }
+        self.layout()
+            .dest_pan_id
+            .map(|start| self.read_pan_id(start))
     }
 
     /// Reads the 'Destination address' field
     pub fn get_dest_addr(&self) -> Option<Addr> {
-        let mut start = 3;
-
-        if self.get_dest_pan_id().is_some() {
-            start += 2;
-        }
-
-        Some(match self.get_dest_addr_mode() {
-            AddrMode::None => return None,
-            AddrMode::Short => Addr::Short(ShortAddr(LE::read_u16(unsafe {
-                self.as_slice().r(start..start + 2)
-            }))),
-            AddrMode::Extended => Addr::Extended(ExtendedAddr(LE::read_u64(unsafe {
-                self.as_slice().r(start..start + 8)
-            }))),
-        })
+        let start = self.layout().dest_addr?;
+        Some(self.read_addr(start, self.get_dest_addr_mode()))
     }
 
     /// Reads the 'Source PAN identifier' field
     pub fn get_src_pan_id(&self) -> Option<PanId> {
-        if self.get_src_addr_mode() != AddrMode::None && !self.get_intra_pan() {
-            let mut start = 3;
-
-            if self.get_dest_pan_id().is_some() {
-                start += 2
-            }
+        self.layout()
+            .src_pan_id
+            .map(|start| self.read_pan_id(start))
+    }
 
-            start += match self.get_dest_addr_mode() {
-                AddrMode::None => 0,
-                AddrMode::Short => 2,
-                AddrMode::Extended => 8,
-            };
+    /// Reads the 'Source address' field
+    pub fn get_src_addr(&self) -> Option<Addr> {
+        let start = self.layout().src_addr?;
+        Some(self.read_addr(start, self.get_src_addr_mode()))
+    }
 
-            Some(PanId(LE::read_u16(unsafe {
-                self.as_slice().r(start..start + 2)
-            })))
+    /// Returns a view into the 'Auxiliary Security Header' field, if present
+    ///
+    /// IEEE 802.15.4-2003 frames never have this field, even if security is enabled
+    pub fn get_aux_security_header(&self) -> Option<security::AuxHeader<&[u8]>> {
+        if self.get_security_enabled() && self.get_frame_version() != FrameVersion::Ieee802154_2003
+        {
+            let start = self.layout().end;
+            security::AuxHeader::parse(unsafe { self.header().rf(start..) }).ok()
         } else {
             None
         }
     }
 
-    /// Reads the 'Source address' field
-    pub fn get_src_addr(&self) -> Option<Addr> {
-        let mut start = 3;
-
-        if self.get_dest_pan_id().is_some() {
-            start += 2;
-        }
+    /// Returns an iterator over the Header IEs
+    pub fn header_ies(&self) -> ie::HeaderIes<'_> {
+        ie::HeaderIes::new(self.ies())
+    }
 
-        start += match self.get_dest_addr_mode() {
-            AddrMode::None => 0,
-            AddrMode::Short => 2,
-            AddrMode::Extended => 8,
-        };
+    /// Returns an iterator over the Payload IEs
+    ///
+    /// The Payload IEs of secured frames are encrypted; this iterator yields nothing useful until
+    /// the payload has been unsecured
+    pub fn payload_ies(&self) -> ie::PayloadIes<'_> {
+        let ies = self.ies();
 
-        if self.get_src_pan_id().is_some() {
-            start += 2;
+        match ie::header_ies_size(ies) {
+            Ok((size, ie::End::Ht1)) => ie::PayloadIes::new(unsafe { ies.rf(size..) }),
+            _ => ie::PayloadIes::new(&[]),
         }
-
-        Some(match self.get_src_addr_mode() {
-            AddrMode::None => return None,
-            AddrMode::Short => Addr::Short(ShortAddr(LE::read_u16(unsafe {
-                self.as_slice().r(start..start + 2)
-            }))),
-            AddrMode::Extended => Addr::Extended(ExtendedAddr(LE::read_u64(unsafe {
-                self.as_slice().r(start..start + 8)
-            }))),
-        })
     }
 
     /// Returns an immutable view into the header
@@ -309,6 +303,8 @@ where
     }
 
     /// Returns an immutable view into the payload
+    ///
+    /// The Payload IEs of a secured frame are part of the payload
     pub fn payload(&self) -> &[u8] {
         unsafe { self.as_slice().rf(usize::from(self.payload)..) }
     }
@@ -329,11 +325,172 @@ where
         self.buffer.as_slice()
     }
 
-    fn header_(&self) -> &[u8; HEADER_SIZE as usize] {
-        debug_assert!(self.as_slice().len() >= HEADER_SIZE as usize);
+    fn header_(&self) -> &[u8; CONTROL_SIZE as usize] {
+        debug_assert!(self.as_slice().len() >= CONTROL_SIZE as usize);
 
         unsafe { &*(self.as_slice().as_ptr() as *const _) }
     }
+
+    fn layout(&self) -> Layout {
+        let control = self.header_();
+
+        Layout::new(control[CONTROLL], control[CONTROLH])
+            .unwrap_or_else(|_| unsafe { debug_unreachable!() })
+    }
+
+    // Bytes from the start of the Header IEs to the end of the frame
+    fn ies(&self) -> &[u8] {
+        let layout = self.layout();
+
+        if !layout.ie_present {
+            return &[];
+        }
+
+        let start = layout.end
+            + self
+                .get_aux_security_header()
+                .map(|aux| usize::from(aux.len()))
+                .unwrap_or(0);
+
+        unsafe { self.as_slice().rf(start..) }
+    }
+
+    // The IEs that are part of the header
+    fn header_ies_bytes(&self) -> &[u8] {
+        let ies = self.ies();
+
+        if ies.is_empty() {
+            ies
+        } else {
+            let len = ies.len() - self.payload().len();
+            unsafe { ies.rt(..len) }
+        }
+    }
+
+    // How the list of Header IEs ends
+    fn ies_end(&self) -> ie::End {
+        ie::header_ies_size(self.header_ies_bytes())
+            .map(|(_, end)| end)
+            .unwrap_or(ie::End::Unterminated)
+    }
+
+    fn read_pan_id(&self, start: usize) -> PanId {
+        PanId(LE::read_u16(unsafe { self.as_slice().r(start..start + 2) }))
+    }
+
+    fn read_addr(&self, start: usize, mode: AddrMode) -> Addr {
+        match mode {
+            AddrMode::Extended => Addr::Extended(ExtendedAddr(LE::read_u64(unsafe {
+                self.as_slice().r(start..start + 8)
+            }))),
+            _ => Addr::Short(ShortAddr(LE::read_u16(unsafe {
+                self.as_slice().r(start..start + 2)
+            }))),
+        }
+    }
+}
+
+// Location of the sequence number and addressing fields, as specified by the frame control field
+#[derive(Clone, Copy)]
+struct Layout {
+    seq_suppressed: bool,
+    ie_present: bool,
+    dest_pan_id: Option<usize>,
+    dest_addr: Option<usize>,
+    src_pan_id: Option<usize>,
+    src_addr: Option<usize>,
+    // index at which the addressing fields end
+    end: usize,
+}
+
+impl Layout {
+    fn new(controll: u8, controlh: u8) -> Result<Self, ()> {
+        let ftype = Type::from(get!(controll, frame_type));
+        let version = FrameVersion::checked(get!(controlh, frame_version)).ok_or(())?;
+        let dest_addr_mode = AddrMode::checked(get!(controlh, dest_addr_mode)).ok_or(())?;
+        let src_addr_mode = AddrMode::checked(get!(controlh, src_addr_mode)).ok_or(())?;
+        let pan_id_compression = get!(controll, intra_pan) == 1;
+
+        let has_dest = dest_addr_mode != AddrMode::None;
+        let has_src = src_addr_mode != AddrMode::None;
+
+        let (seq_suppressed, ie_present, has_dest_pan_id, has_src_pan_id) =
+            if version == FrameVersion::Ieee802154 {
+                // IEEE 802.15.4-2015 - Table 7-2 PAN ID Compression field value for frame version
+                // 0b10
+                let (dest, src) = match (dest_addr_mode, src_addr_mode) {
+                    (AddrMode::None, AddrMode::None) => (pan_id_compression, false),
+                    (_, AddrMode::None) => (!pan_id_compression, false),
+                    (AddrMode::None, _) => (false, !pan_id_compression),
+                    (AddrMode::Extended, AddrMode::Extended) => (!pan_id_compression, false),
+                    _ => (true, !pan_id_compression),
+                };
+
+                (
+                    get!(controlh, seq_suppression) == 1,
+                    get!(controlh, ie_present) == 1,
+                    dest,
+                    src,
+                )
+            } else {
+                // 7.2.1.1.6 Destination addressing mode subfield
+                //
+                // "If this subfield is equal to 0 and the frame type subfield does not specify
+                // that this frame is an acknowledgment or beacon frame, the source addressing
+                // mode subfield shall be nonzero"
+                //
+                // 7.2.1.1.7 Source addressing mode subfield
+                //
+                // "If this subfield is equal to 0 and the frame type subfield does not specify
+                // that this frame is an acknowledgment frame, the destination addressing mode
+                // subfield shall be nonzero"
+                if !has_dest && !has_src && ftype != Type::Acknowledgment {
+                    return Err(());
+                }
+
+                // 7.2.1.3 Destination PAN identifier field
+                //
+                // "This field shall be included in the MAC frame only if the destination
+                // addressing mode subfield of the frame control field is nonzero."
+                //
+                // 7.2.1.5 Source PAN identifier field
+                //
+                // "This field shall be included in the MAC frame only if the source addressing
+                // mode and intra-PAN subfields of the frame control field are nonzero and equal to
+                // zero, respectively."
+                (false, false, has_dest, has_src && !pan_id_compression)
+            };
+
+        let mut end = if seq_suppressed {
+            usize::from(CONTROL_SIZE)
+        } else {
+            usize::from(HEADER_SIZE)
+        };
+        let mut next = |present: bool, size: u8| {
+            if present {
+                let start = end;
+                end += usize::from(size);
+                Some(start)
+            } else {
+                None
+            }
+        };
+
+        let dest_pan_id = next(has_dest_pan_id, 2);
+        let dest_addr = next(has_dest, dest_addr_mode.size());
+        let src_pan_id = next(has_src_pan_id, 2);
+        let src_addr = next(has_src, src_addr_mode.size());
+
+        Ok(Layout {
+            seq_suppressed,
+            ie_present,
+            dest_pan_id,
+            dest_addr,
+            src_pan_id,
+            src_addr,
+            end,
+        })
+    }
 }
 
 impl<B> fmt::Debug for Frame<B>
@@ -350,8 +507,12 @@ where
             .field("ack_request", &self.get_ack_request())
             .field("intra_pan", &self.get_intra_pan())
             .field("dest_addr_mode", &self.get_dest_addr_mode())
-            .field("src_addr_mode", &self.get_src_addr_mode())
-            .field("sequence_number", &self.get_sequence_number());
+            .field("frame_version", &self.get_frame_version())
+            .field("src_addr_mode", &self.get_src_addr_mode());
+
+        if let Some(seq) = self.get_sequence_number() {
+            s.field("sequence_number", &seq);
+        }
 
         if let Some(pan_id) = self.get_dest_pan_id() {
             s.field("dest_pan_id", &Display(pan_id));
@@ -379,6 +540,10 @@ where
             _ => {}
         }
 
+        if let Some(aux) = self.get_aux_security_header() {
+            s.field("aux_security_header", &aux);
+        }
+
         // s.field("payload", &self.payload());
         s.finish()
     }
@@ -390,61 +555,273 @@ where
 {
     /* Constructors */
     /// Creates a new data frame from the given buffer
-    pub fn data(mut buffer: B, src_dest: SrcDest) -> Self {
-        let payload = 3 + src_dest.size();
-        assert!(buffer.as_slice().len() >= usize::from(payload));
+    pub fn data(buffer: B, src_dest: SrcDest) -> Self {
+        Self::new(buffer, Type::Data, FrameVersion::Ieee802154_2003, src_dest)
+    }
+
+    /// Creates a new MAC command frame from the given buffer
+    ///
+    /// Use `set_command` to fill in the command
+    pub fn mac_command(buffer: B, src_dest: SrcDest) -> Self {
+        Self::new(
+            buffer,
+            Type::MacCommand,
+            FrameVersion::Ieee802154_2003,
+            src_dest,
+        )
+    }
+
+    /// Creates a new frame of the given type and version from the given buffer
+    ///
+    /// The 'PAN ID Compression' (AKA 'Intra-PAN') field is set according to the rules of the
+    /// specified frame version
+    ///
+    /// # Panics
+    ///
+    /// This constructor panics if the buffer is too small to contain the header, or if
+    /// `src_dest` can't be represented in the given frame version (IEEE 802.15.4-2015 frames
+    /// can't carry two PAN identifiers when both addresses are extended)
+    pub fn new(mut buffer: B, ftype: Type, version: FrameVersion, src_dest: SrcDest) -> Self {
+        // NOTE this is an upper bound on the size of the header
+        assert!(buffer.as_slice().len() >= usize::from(HEADER_SIZE + src_dest.size()));
 
         // Zero the frame control field and sequence number
         buffer.as_mut_slice()[..3].copy_from_slice(&[0, 0, 0]);
-        let mut frame = Frame { buffer, payload };
-
-        frame.set_frame_type(Type::Data);
+        let mut frame = Frame {
+            buffer,
+            payload: HEADER_SIZE,
+        };
 
-        match src_dest {
-            SrcDest::PanCoordToNode { .. } => unimplemented!(),
-            SrcDest::NodeToPanCoord { .. } => unimplemented!(),
+        // (dest. PAN id, dest. address), (source PAN id, source address)
+        let (dest, src) = match src_dest {
+            SrcDest::PanCoordToNode { pan_id, dest_addr } => {
+                (Some((Some(pan_id), dest_addr)), None)
+            }
+            SrcDest::NodeToPanCoord { pan_id, src_addr } => (None, Some((Some(pan_id), src_addr))),
             SrcDest::IntraPan {
                 pan_id,
                 src_addr,
                 dest_addr,
-            } => {
-                frame.set_intra_pan(1);
-
-                let mut start = 3;
-                LE::write_u16(&mut frame.as_mut_slice()[start..start + 2], pan_id.0);
-                start += 2;
-
-                frame.set_dest_addr_mode(dest_addr.mode());
-                match dest_addr {
-                    Addr::Short(sa) => {
-                        LE::write_u16(&mut frame.as_mut_slice()[start..start + 2], sa.0);
-                        start += 2;
-                    }
-                    Addr::Extended(ea) => {
-                        LE::write_u64(&mut frame.as_mut_slice()[start..start + 8], ea.0);
-                        start += 8;
-                    }
+            } => (Some((Some(pan_id), dest_addr)), Some((None, src_addr))),
+            SrcDest::InterPan {
+                src_pan_id,
+                src_addr,
+                dest_pan_id,
+                dest_addr,
+            } => (
+                Some((Some(dest_pan_id), dest_addr)),
+                Some((Some(src_pan_id), src_addr)),
+            ),
+        };
+
+        let pan_id_compression = match version {
+            FrameVersion::Ieee802154 => match (dest, src) {
+                (Some(_), Some((src_pan_id, src_addr))) => {
+                    let both_extended = dest.map(|(_, addr)| addr.mode())
+                        == Some(AddrMode::Extended)
+                        && src_addr.mode() == AddrMode::Extended;
+
+                    assert!(
+                        !(both_extended && src_pan_id.is_some()),
+                        "two PAN IDs can't be used with two extended addresses"
+                    );
+
+                    // NOTE when both addresses are extended only the destination PAN ID is
+                    // present when the PAN ID Compression field is *zero*
+                    src_pan_id.is_none() && !both_extended
                 }
+                _ => false,
+            },
+            _ => src.map(|(pan_id, _)| pan_id.is_none()).unwrap_or(false),
+        };
+
+        frame.set_frame_type(ftype);
+        frame.set_frame_version(version);
+        frame.set_intra_pan(if pan_id_compression { 1 } else { 0 });
+        if let Some((_, addr)) = dest {
+            frame.set_dest_addr_mode(addr.mode());
+        }
+        if let Some((_, addr)) = src {
+            frame.set_src_addr_mode(addr.mode());
+        }
+
+        let layout = frame.layout();
+        assert!(frame.as_slice().len() >= layout.end);
+
+        let fields = [
+            (layout.dest_pan_id, layout.dest_addr, dest),
+            (layout.src_pan_id, layout.src_addr, src),
+        ];
+        for (pan_id_start, addr_start, field) in fields.iter() {
+            if let Some((pan_id, addr)) = field {
+                debug_assert_eq!(pan_id_start.is_some(), pan_id.is_some());
 
-                frame.set_src_addr_mode(src_addr.mode());
-                match src_addr {
-                    Addr::Short(sa) => {
-                        LE::write_u16(&mut frame.as_mut_slice()[start..start + 2], sa.0);
-                        // start += 2;
-                    }
-                    Addr::Extended(ea) => {
-                        LE::write_u64(&mut frame.as_mut_slice()[start..start + 8], ea.0);
-                        // start += 8;
-                    }
+                if let (Some(start), Some(pan_id)) = (pan_id_start, pan_id) {
+                    frame.write_pan_id(*start, *pan_id);
                 }
 
-                frame
+                if let Some(start) = addr_start {
+                    frame.write_addr(*start, *addr);
+                }
             }
-            SrcDest::InterPan { .. } => unimplemented!(),
         }
+
+        frame.payload = layout.end as u8;
+        frame
+    }
+
+    /// Creates a new beacon frame from the given buffer
+    ///
+    /// The frame version is IEEE 802.15.4-2003; use `Frame::new` to create a beacon frame of a
+    /// later version. Use `set_beacon` to fill in the beacon payload
+    pub fn beacon(mut buffer: B, src_pan_id: PanId, src_addr: Addr) -> Self {
+        let payload = HEADER_SIZE + 2 + src_addr.size();
+        assert!(buffer.as_slice().len() >= usize::from(payload));
+
+        // Zero the frame control field and sequence number
+        buffer.as_mut_slice()[..3].copy_from_slice(&[0, 0, 0]);
+        let mut frame = Frame { buffer, payload };
+
+        frame.set_frame_type(Type::Beacon);
+        frame.set_src_addr_mode(src_addr.mode());
+        let start = frame.write_pan_id(usize::from(HEADER_SIZE), src_pan_id);
+        frame.write_addr(start, src_addr);
+
+        frame
     }
 
     /* Setters */
+    /// Suppresses the 'Sequence number' field
+    ///
+    /// This must be called before the payload is filled in
+    ///
+    /// # Panics
+    ///
+    /// This method panics if the frame version is not IEEE 802.15.4-2015
+    pub fn suppress_sequence_number(&mut self) {
+        assert_eq!(self.get_frame_version(), FrameVersion::Ieee802154);
+
+        if self.get_sequence_number_suppression() {
+            return;
+        }
+
+        let end = usize::from(self.payload);
+        self.as_mut_slice().copy_within(SEQUENCE + 1..end, SEQUENCE);
+        self.payload -= 1;
+
+        set!(self.header_mut_()[CONTROLH], seq_suppression, 1);
+    }
+
+    /// Enables security and writes the 'Auxiliary Security Header' field right after the
+    /// addressing fields
+    ///
+    /// This must be called before the payload, or any IE, is filled in. If the frame version is
+    /// IEEE 802.15.4-2003 it's changed to IEEE 802.15.4-2006, as the 2003 revision uses a
+    /// different security scheme
+    ///
+    /// # Panics
+    ///
+    /// This method panics if security is already enabled or if the header doesn't fit in the
+    /// frame
+    pub fn enable_security(
+        &mut self,
+        level: security::SecurityLevel,
+        key_id: security::KeyIdentifier,
+        frame_counter: u32,
+    ) {
+        assert!(!self.get_security_enabled() && !self.get_ie_present());
+
+        let start = usize::from(self.payload);
+        let len = security::AuxHeader::new(
+            &mut self.as_mut_slice()[start..],
+            level,
+            key_id,
+            frame_counter,
+        )
+        .len();
+        self.payload += len;
+
+        set!(self.header_mut_()[CONTROLL], security_enabled, 1);
+        if self.get_frame_version() == FrameVersion::Ieee802154_2003 {
+            self.set_frame_version(FrameVersion::Ieee802154_2006);
+        }
+    }
+
+    /// Appends a Header IE to the header
+    ///
+    /// This must be called before the payload is filled in
+    ///
+    /// # Panics
+    ///
+    /// This method panics if the frame version is not IEEE 802.15.4-2015, if a Payload IE has
+    /// already been appended, if the content is larger than `ie::MAX_HEADER_IE_LENGTH` or if the
+    /// IE doesn't fit in the frame
+    pub fn push_header_ie(&mut self, element_id: u8, content: &[u8]) {
+        assert_eq!(self.get_frame_version(), FrameVersion::Ieee802154);
+        assert_eq!(self.ies_end(), ie::End::Unterminated);
+
+        let start = usize::from(self.payload);
+        let size = ie::write_header_ie(&mut self.as_mut_slice()[start..], element_id, content);
+        self.payload += size as u8;
+
+        set!(self.header_mut_()[CONTROLH], ie_present, 1);
+    }
+
+    /// Appends a Payload IE
+    ///
+    /// The Header Termination 1 IE is inserted before the first Payload IE. This must be called
+    /// before the payload is filled in
+    ///
+    /// # Panics
+    ///
+    /// This method panics if the frame version is not IEEE 802.15.4-2015, if security is enabled
+    /// (Payload IEs of secured frames must be placed in the secured payload), if the IE list has
+    /// already been terminated or if the IE doesn't fit in the frame
+    pub fn push_payload_ie(&mut self, group_id: u8, content: &[u8]) {
+        assert!(!self.get_security_enabled());
+
+        if self.ies_end() == ie::End::Unterminated {
+            self.push_header_ie(ie::HT1, &[]);
+        }
+        assert_eq!(self.ies_end(), ie::End::Ht1);
+
+        let start = usize::from(self.payload);
+        let size = ie::write_payload_ie(&mut self.as_mut_slice()[start..], group_id, content);
+        self.payload += size as u8;
+    }
+
+    /// Terminates the list of IEs
+    ///
+    /// This must be called after pushing IEs if the frame will carry a payload. It appends either a
+    /// Header Termination 2 IE or a Payload Termination IE; it does nothing if there are no IEs
+    pub fn terminate_ies(&mut self) {
+        if !self.get_ie_present() {
+            return;
+        }
+
+        match self.ies_end() {
+            ie::End::Unterminated => self.push_header_ie(ie::HT2, &[]),
+            ie::End::Ht1 => {
+                let ies = self.header_ies_bytes();
+                let terminated = ie::header_ies_size(ies)
+                    .and_then(|(size, _)| ie::payload_ies_size(&ies[size..]))
+                    .map(|(_, terminated)| terminated)
+                    .unwrap_or(false);
+
+                if !terminated {
+                    let start = usize::from(self.payload);
+                    let size = ie::write_payload_ie(
+                        &mut self.as_mut_slice()[start..],
+                        ie::PAYLOAD_TERMINATION,
+                        &[],
+                    );
+                    self.payload += size as u8;
+                }
+            }
+            ie::End::Ht2 => {}
+        }
+    }
+
     /// Sets the 'Ack. request' field to `ack`
     pub fn set_ack_request(&mut self, ack: bool) {
         set!(
@@ -454,9 +831,24 @@ where
         )
     }
 
+    /// Sets the 'Frame pending' field to `pending`
+    pub fn set_frame_pending(&mut self, pending: bool) {
+        set!(
+            self.header_mut_()[CONTROLL],
+            frame_pending,
+            if pending { 1 } else { 0 }
+        )
+    }
+
     /// Sets the 'Sequence number' field to `seq`
+    ///
+    /// # Panics
+    ///
+    /// This method panics if the sequence number has been suppressed
     pub fn set_sequence_number(&mut self, seq: u8) {
-        self.header_mut_()[SEQUENCE] = seq;
+        assert!(!self.get_sequence_number_suppression());
+
+        self.as_mut_slice()[SEQUENCE] = seq;
     }
 
     /// Returns a mutable view into the payload
@@ -469,6 +861,14 @@ where
         set!(self.header_mut_()[CONTROLL], frame_type, u8::from(ftype))
     }
 
+    fn set_frame_version(&mut self, version: FrameVersion) {
+        set!(
+            self.header_mut_()[CONTROLH],
+            frame_version,
+            u8::from(version)
+        )
+    }
+
     fn set_intra_pan(&mut self, ip: u8) {
         set!(self.header_mut_()[CONTROLL], intra_pan, ip)
     }
@@ -486,8 +886,28 @@ where
         self.buffer.as_mut_slice()
     }
 
-    fn header_mut_(&mut self) -> &mut [u8; HEADER_SIZE as usize] {
-        debug_assert!(self.as_slice().len() >= HEADER_SIZE as usize);
+    // Writes `pan_id` at `start` and returns the index right after it
+    fn write_pan_id(&mut self, start: usize, pan_id: PanId) -> usize {
+        LE::write_u16(&mut self.as_mut_slice()[start..start + 2], pan_id.0);
+        start + 2
+    }
+
+    // Writes `addr` at `start` and returns the index right after it
+    fn write_addr(&mut self, start: usize, addr: Addr) -> usize {
+        match addr {
+            Addr::Short(sa) => {
+                LE::write_u16(&mut self.as_mut_slice()[start..start + 2], sa.0);
+                start + 2
+            }
+            Addr::Extended(ea) => {
+                LE::write_u64(&mut self.as_mut_slice()[start..start + 8], ea.0);
+                start + 8
+            }
+        }
+    }
+
+    fn header_mut_(&mut self) -> &mut [u8; CONTROL_SIZE as usize] {
+        debug_assert!(self.as_slice().len() >= CONTROL_SIZE as usize);
 
         unsafe { &mut *(self.as_mut_slice().as_mut_ptr() as *mut _) }
     }
@@ -497,6 +917,26 @@ impl<B> Frame<B>
 where
     B: AsMutSlice<Element = u8> + Truncate<u8>,
 {
+    /* Constructors */
+    /// Creates an acknowledgment frame that acknowledges the frame with sequence number `seq`
+    pub fn ack(mut buffer: B, seq: u8) -> Self {
+        assert!(buffer.as_slice().len() >= usize::from(HEADER_SIZE));
+
+        // Zero the frame control field
+        buffer.as_mut_slice()[..2].copy_from_slice(&[0, 0]);
+        buffer.truncate(HEADER_SIZE);
+
+        let mut frame = Frame {
+            buffer,
+            payload: HEADER_SIZE,
+        };
+        frame.set_frame_type(Type::Acknowledgment);
+        frame.set_sequence_number(seq);
+
+        frame
+    }
+
+    /* Setters */
     /// Fills the payload with the given data and adjusts the length of the frame
     pub fn set_payload(&mut self, payload: &[u8]) {
         assert!(self.payload().len() >= payload.len());
@@ -517,6 +957,7 @@ where
         let ctxt = iphc::Context {
             source: self.get_src_addr(),
             destination: self.get_dest_addr(),
+            table: None,
         };
 
         let mut packet = iphc::Packet::new(
@@ -551,6 +992,7 @@ where
         let ctxt = iphc::Context {
             source: self.get_src_addr(),
             destination: self.get_dest_addr(),
+            table: None,
         };
 
         let mut packet = iphc::Packet::new(
@@ -578,6 +1020,146 @@ where
         self.buffer.truncate(len);
     }
 
+    /// Fills the payload with the given data and protects it using CCM*
+    ///
+    /// `src_addr` is the extended address of the originator of the frame; it's used to build the
+    /// CCM* nonce. The Message Integrity Code (MIC) is appended to the payload
+    ///
+    /// When encryption is enabled the beacon fields of a beacon frame, and the Command Frame
+    /// Identifier of a MAC command frame, are authenticated but sent in the clear
+    ///
+    /// # Panics
+    ///
+    /// This method panics if security is not enabled, if the payload plus the MIC doesn't fit in
+    /// the frame or if the payload of a beacon or MAC command frame is malformed
+    pub fn set_secured_payload<C>(&mut self, cipher: &C, src_addr: ExtendedAddr, payload: &[u8])
+    where
+        C: security::BlockCipher,
+    {
+        let (level, frame_counter) = {
+            let aux = self
+                .get_aux_security_header()
+                .expect("security is not enabled");
+            (aux.get_security_level(), aux.get_frame_counter())
+        };
+        let mic_size = usize::from(level.mic_size());
+        let plen = payload.len();
+
+        assert!(self.payload().len() >= plen + mic_size);
+        self.payload_mut()[..plen].copy_from_slice(payload);
+
+        let open = open_payload_len(self.get_type(), payload)
+            .expect("malformed beacon or MAC command payload");
+        let nonce = security::nonce(src_addr, frame_counter, level);
+        let start = usize::from(self.payload);
+        let end = start + plen + mic_size;
+        let (frame, rest) = self.as_mut_slice().split_at_mut(start + plen);
+        let mic = &mut rest[..mic_size];
+        if level.is_encrypted() {
+            let (a, m) = frame.split_at_mut(start + open);
+            security::encrypt(cipher, &nonce, a, m, mic);
+        } else {
+            // NOTE the payload is only authenticated
+            security::encrypt(cipher, &nonce, frame, &mut [], mic);
+        }
+
+        self.buffer
+            .truncate(cast::u8(end).unwrap_or_else(|_| unsafe { debug_unreachable!() }));
+    }
+
+    /// Verifies and decrypts, in place, the payload of a secured frame
+    ///
+    /// `src_addr` is the extended address of the originator of the frame. On success the MIC is
+    /// removed from the payload; on failure the frame is left unchanged
+    ///
+    /// Secured IEEE 802.15.4-2003 frames are rejected with `security::Error::UnsupportedVersion`
+    pub fn unsecure_payload<C>(
+        &mut self,
+        cipher: &C,
+        src_addr: ExtendedAddr,
+    ) -> Result<(), security::Error>
+    where
+        C: security::BlockCipher,
+    {
+        if !self.get_security_enabled() {
+            return Err(security::Error::NotSecured);
+        }
+
+        if self.get_frame_version() == FrameVersion::Ieee802154_2003 {
+            return Err(security::Error::UnsupportedVersion);
+        }
+
+        let (level, frame_counter) = {
+            let aux = self
+                .get_aux_security_header()
+                .ok_or(security::Error::NotSecured)?;
+            (aux.get_security_level(), aux.get_frame_counter())
+        };
+        let mic_size = usize::from(level.mic_size());
+
+        if self.payload().len() < mic_size {
+            return Err(security::Error::Truncated);
+        }
+
+        let start = usize::from(self.payload);
+        let end = self.as_slice().len() - mic_size;
+        let open = open_payload_len(self.get_type(), &self.as_slice()[start..end])
+            .ok_or(security::Error::Truncated)?;
+        let nonce = security::nonce(src_addr, frame_counter, level);
+        let (frame, mic) = self.as_mut_slice().split_at_mut(end);
+        if level.is_encrypted() {
+            let (a, m) = frame.split_at_mut(start + open);
+            security::decrypt(cipher, &nonce, a, m, mic)?;
+        } else {
+            security::decrypt(cipher, &nonce, frame, &mut [], mic)?;
+        }
+
+        self.buffer
+            .truncate(cast::u8(end).unwrap_or_else(|_| unsafe { debug_unreachable!() }));
+        Ok(())
+    }
+
+    /// Fills the payload with a beacon that contains the given GTS descriptors and pending
+    /// addresses
+    ///
+    /// The beacon payload initially spans the rest of the frame; use `Beacon::set_payload` to fill
+    /// it
+    pub fn set_beacon<F>(
+        &mut self,
+        gts_descriptors: &[beacon::GtsDescriptor],
+        pending_addrs: &[Addr],
+        f: F,
+    ) where
+        F: FnOnce(&mut beacon::Beacon<&mut [u8]>),
+    {
+        let mut beacon = beacon::Beacon::new(self.payload_mut(), gts_descriptors, pending_addrs);
+        f(&mut beacon);
+
+        let len = (beacon.bytes().len() + self.header().len()) as u8;
+        self.buffer.truncate(len);
+    }
+
+    /// Fills the payload with the given MAC command
+    pub fn set_command(&mut self, command: command::Command) {
+        let len = command.write(self.payload_mut()) + self.payload;
+        self.buffer.truncate(len);
+    }
+
+    /// Fills the payload with an uncompressed IPv6 packet
+    ///
+    /// The IPv6 packet initially spans the whole payload; use `ipv6::Packet::truncate` to shrink it
+    pub fn ipv6<F>(&mut self, f: F)
+    where
+        F: FnOnce(&mut ipv6::Packet<&mut [u8]>),
+    {
+        let mut packet = sixlowpan::new_ipv6(self.payload_mut());
+        f(&mut packet);
+
+        // NOTE(+1) IPv6 dispatch
+        let len = (1 + packet.as_bytes().len() + self.header().len()) as u8;
+        self.buffer.truncate(len);
+    }
+
     /// Fills the payload with a UDP packet
     pub fn udp<F>(
         &mut self,
@@ -597,6 +1179,7 @@ where
         let ctxt = iphc::Context {
             source: self.get_src_addr(),
             destination: self.get_dest_addr(),
+            table: None,
         };
 
         let mut ip_packet = iphc::Packet::new(
@@ -662,8 +1245,8 @@ pub enum SrcDest {
 impl SrcDest {
     fn size(&self) -> u8 {
         match *self {
-            SrcDest::PanCoordToNode { .. } => unimplemented!(),
-            SrcDest::NodeToPanCoord { .. } => unimplemented!(),
+            SrcDest::PanCoordToNode { dest_addr, .. } => 2 + dest_addr.size(),
+            SrcDest::NodeToPanCoord { src_addr, .. } => 2 + src_addr.size(),
             SrcDest::IntraPan {
                 src_addr,
                 dest_addr,
@@ -678,6 +1261,26 @@ impl SrcDest {
     }
 }
 
+// Length of the open part of a MAC payload: the part that CCM* authenticates but doesn't encrypt
+// (see section 7.6.3.4 of IEEE 802.15.4-2006). Returns `None` if the payload is malformed
+fn open_payload_len(ftype: Type, payload: &[u8]) -> Option<usize> {
+    match ftype {
+        // superframe specification, GTS fields and pending address fields
+        Type::Beacon => beacon::Beacon::parse(payload)
+            .ok()
+            .map(|beacon| beacon.bytes().len() - beacon.payload().len()),
+        // Command Frame Identifier
+        Type::MacCommand => {
+            if payload.is_empty() {
+                None
+            } else {
+                Some(1)
+            }
+        }
+        _ => Some(0),
+    }
+}
+
 full_range!(
     u8,
     /// Frame type
@@ -722,12 +1325,55 @@ impl AddrMode {
     }
 }
 
+impl AddrMode {
+    fn size(self) -> u8 {
+        match self {
+            AddrMode::None => 0,
+            AddrMode::Short => 2,
+            AddrMode::Extended => 8,
+        }
+    }
+}
+
 impl From<AddrMode> for u8 {
     fn from(am: AddrMode) -> u8 {
         am as u8
     }
 }
 
+/// Frame version
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FrameVersion {
+    /// IEEE 802.15.4-2003
+    Ieee802154_2003 = 0b00,
+    /// IEEE 802.15.4-2006
+    Ieee802154_2006 = 0b01,
+    /// IEEE 802.15.4 (2015 and later revisions)
+    Ieee802154 = 0b10,
+}
+
+impl FrameVersion {
+    // Returns `None` if bits equals the reserved value (0b11)
+    fn checked(bits: u8) -> Option<Self> {
+        Some(match bits & 0b11 {
+            0b00 => FrameVersion::Ieee802154_2003,
+            0b01 => FrameVersion::Ieee802154_2006,
+            0b10 => FrameVersion::Ieee802154,
+            _ => return None,
+        })
+    }
+
+    unsafe fn unchecked(bits: u8) -> Self {
+        Self::checked(bits).unwrap_or_else(|| debug_unreachable!())
+    }
+}
+
+impl From<FrameVersion> for u8 {
+    fn from(version: FrameVersion) -> u8 {
+        version as u8
+    }
+}
+
 /// An address, either short or extended
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum Addr {
@@ -868,7 +1514,8 @@ impl From<ExtendedAddr> for Addr {
 mod tests {
     use rand::{self, RngCore};
 
-    use super::{Addr, ExtendedAddr, Frame, PanId, ShortAddr, SrcDest, Type};
+    use super::{beacon, command, Addr, ExtendedAddr, Frame, PanId, ShortAddr, SrcDest, Type};
+    use crate::{ipv6, sixlowpan::Dispatch};
 
     #[test]
     fn data() {
@@ -916,4 +1563,557 @@ mod tests {
             ExtendedAddr(0x09_0A_0B_0C_0D_0E_0F_10)
         );
     }
+
+    #[test]
+    fn src_dest() {
+        fn roundtrip(src_dest: SrcDest, bytes: &[u8]) {
+            let mut buf = [0; 128];
+            rand::thread_rng().fill_bytes(&mut buf);
+            let mut frame = Frame::data(&mut buf[..], src_dest);
+            frame.set_payload(&[0xaa, 0xbb]);
+
+            assert_eq!(frame.as_bytes(), bytes);
+
+            let frame = Frame::parse(bytes).unwrap();
+            assert_eq!(frame.get_type(), Type::Data);
+            assert_eq!(frame.payload(), &[0xaa, 0xbb]);
+        }
+
+        // NOTE the expected bytes were encoded by hand, field by field, following the general MAC
+        // frame format of section 7.2.1 of IEEE 802.15.4-2006; they were not produced by `Frame`
+
+        let pan_id = PanId(0xbeef);
+        let short = ShortAddr(0x01_02);
+        let extended = ExtendedAddr(0x01_02_03_04_05_06_07_08);
+
+        // PAN coordinator -> node
+        let bytes = [
+            0x01, 0x08, 0x00, // frame control (data, short destination) + sequence number
+            0xef, 0xbe, 0x02, 0x01, // destination PAN id + address
+            0xaa, 0xbb, // payload
+        ];
+        roundtrip(
+            SrcDest::PanCoordToNode {
+                pan_id,
+                dest_addr: short.into(),
+            },
+            &bytes,
+        );
+
+        let frame = Frame::parse(&bytes[..]).unwrap();
+        assert_eq!(frame.get_intra_pan(), false);
+        assert_eq!(frame.get_dest_pan_id(), Some(pan_id));
+        assert_eq!(frame.get_dest_addr(), Some(short.into()));
+        assert_eq!(frame.get_src_pan_id(), None);
+        assert_eq!(frame.get_src_addr(), None);
+
+        // node -> PAN coordinator
+        let bytes = [
+            0x01, 0xc0, 0x00, // frame control (data, extended source) + sequence number
+            0xef, 0xbe, 0x08, 0x07, 0x06, 0x05, 0x04, 0x03, 0x02, 0x01, // source
+            0xaa, 0xbb, // payload
+        ];
+        roundtrip(
+            SrcDest::NodeToPanCoord {
+                pan_id,
+                src_addr: extended.into(),
+            },
+            &bytes,
+        );
+
+        let frame = Frame::parse(&bytes[..]).unwrap();
+        assert_eq!(frame.get_intra_pan(), false);
+        assert_eq!(frame.get_dest_pan_id(), None);
+        assert_eq!(frame.get_dest_addr(), None);
+        assert_eq!(frame.get_src_pan_id(), Some(pan_id));
+        assert_eq!(frame.get_src_addr(), Some(extended.into()));
+
+        // intra-PAN
+        let bytes = [
+            0x41, 0x88,
+            0x00, // frame control (data, PAN ID compression, short addresses) + sequence
+            0xef, 0xbe, 0xff, 0xff, 0x02, 0x01, // PAN id + destination + source
+            0xaa, 0xbb, // payload
+        ];
+        roundtrip(
+            SrcDest::IntraPan {
+                pan_id,
+                src_addr: short.into(),
+                dest_addr: ShortAddr::BROADCAST.into(),
+            },
+            &bytes,
+        );
+
+        let frame = Frame::parse(&bytes[..]).unwrap();
+        assert_eq!(frame.get_intra_pan(), true);
+        assert_eq!(frame.get_dest_pan_id(), Some(pan_id));
+        assert_eq!(frame.get_dest_addr(), Some(ShortAddr::BROADCAST.into()));
+        assert_eq!(frame.get_src_pan_id(), None);
+        assert_eq!(frame.get_src_addr(), Some(short.into()));
+
+        // inter-PAN
+        let bytes = [
+            0x01, 0x8c,
+            0x00, // frame control (data, extended destination, short source) + sequence
+            0xef, 0xbe, 0x08, 0x07, 0x06, 0x05, 0x04, 0x03, 0x02, 0x01, // destination
+            0xad, 0xde, 0x02, 0x01, // source PAN id + address
+            0xaa, 0xbb, // payload
+        ];
+        roundtrip(
+            SrcDest::InterPan {
+                src_pan_id: PanId(0xdead),
+                src_addr: short.into(),
+                dest_pan_id: pan_id,
+                dest_addr: extended.into(),
+            },
+            &bytes,
+        );
+
+        let frame = Frame::parse(&bytes[..]).unwrap();
+        assert_eq!(frame.get_intra_pan(), false);
+        assert_eq!(frame.get_dest_pan_id(), Some(pan_id));
+        assert_eq!(frame.get_dest_addr(), Some(extended.into()));
+        assert_eq!(frame.get_src_pan_id(), Some(PanId(0xdead)));
+        assert_eq!(frame.get_src_addr(), Some(short.into()));
+    }
+
+    #[test]
+    fn ack() {
+        let mut buf = [0xff; 16];
+        let mut frame = Frame::ack(&mut buf[..], 42);
+        frame.set_frame_pending(true);
+
+        assert_eq!(frame.as_bytes(), &[0x12, 0x00, 42]);
+
+        let frame = Frame::parse(&[0x12, 0x00, 42][..]).unwrap();
+        assert_eq!(frame.get_type(), Type::Acknowledgment);
+        assert!(frame.get_frame_pending());
+        assert_eq!(frame.get_sequence_number(), Some(42));
+        assert_eq!(frame.get_dest_addr(), None);
+        assert_eq!(frame.get_src_addr(), None);
+    }
+
+    #[test]
+    fn beacon() {
+        let mut buf = [0xff; 128];
+        let mut frame = Frame::beacon(&mut buf[..], PanId(0xbeef), ShortAddr(0x0000).into());
+        frame.set_sequence_number(1);
+        frame.set_beacon(&[], &[ShortAddr(0x0102).into()], |beacon| {
+            beacon.set_beacon_order(15);
+            beacon.set_superframe_order(15);
+            beacon.set_final_cap_slot(15);
+            beacon.set_pan_coordinator(true);
+            beacon.set_association_permit(true);
+            beacon.set_payload(&[]);
+        });
+
+        let bytes = &[
+            0x00, 0x80, 0x01, // frame control + sequence number
+            0xef, 0xbe, 0x00, 0x00, // source PAN id + address
+            0xff, 0xcf, 0x00, 0x01, 0x02, 0x01, // beacon
+        ];
+        assert_eq!(frame.as_bytes(), bytes);
+
+        let frame = Frame::parse(&bytes[..]).unwrap();
+        assert_eq!(frame.get_type(), Type::Beacon);
+        assert_eq!(frame.get_src_pan_id(), Some(PanId(0xbeef)));
+        assert_eq!(frame.get_src_addr(), Some(ShortAddr(0x0000).into()));
+
+        let beacon = beacon::Beacon::parse(frame.payload()).unwrap();
+        assert!(beacon.get_association_permit());
+        assert!(beacon
+            .pending_addrs()
+            .eq(Some(ShortAddr(0x0102).into()).into_iter()));
+    }
+
+    #[test]
+    fn mac_command() {
+        let mut ci = command::CapabilityInformation::default();
+        ci.set_allocate_address(true);
+
+        let mut buf = [0xff; 128];
+        let mut frame = Frame::mac_command(
+            &mut buf[..],
+            SrcDest::InterPan {
+                src_pan_id: PanId::BROADCAST,
+                src_addr: ExtendedAddr(0x01_02_03_04_05_06_07_08).into(),
+                dest_pan_id: PanId(0xbeef),
+                dest_addr: ShortAddr(0x0000).into(),
+            },
+        );
+        frame.set_ack_request(true);
+        frame.set_command(command::Command::AssociationRequest(ci));
+
+        let bytes = &[
+            0x23, 0xc8, 0x00, // frame control + sequence number
+            0xef, 0xbe, 0x00, 0x00, // destination PAN id + address
+            0xff, 0xff, 0x08, 0x07, 0x06, 0x05, 0x04, 0x03, 0x02, 0x01, // source
+            0x01, 0x80, // association request
+        ];
+        assert_eq!(frame.as_bytes(), bytes);
+
+        let frame = Frame::parse(&bytes[..]).unwrap();
+        assert_eq!(frame.get_type(), Type::MacCommand);
+        assert_eq!(
+            command::Command::parse(frame.payload()),
+            Ok(command::Command::AssociationRequest(ci))
+        );
+
+        // the addressing fields of these commands are mandated by section 7.3 of
+        // IEEE 802.15.4-2006
+        fn roundtrip(src_dest: SrcDest, cmd: command::Command, bytes: &[u8]) {
+            let mut buf = [0xff; 128];
+            let mut frame = Frame::mac_command(&mut buf[..], src_dest);
+            frame.set_ack_request(cmd == command::Command::DataRequest);
+            frame.set_command(cmd);
+            assert_eq!(frame.as_bytes(), bytes);
+
+            let frame = Frame::parse(bytes).unwrap();
+            assert_eq!(frame.get_type(), Type::MacCommand);
+            assert_eq!(command::Command::parse(frame.payload()), Ok(cmd));
+        }
+
+        // beacon request (7.3.7): broadcast destination, no source addressing fields
+        roundtrip(
+            SrcDest::PanCoordToNode {
+                pan_id: PanId::BROADCAST,
+                dest_addr: ShortAddr::BROADCAST.into(),
+            },
+            command::Command::BeaconRequest,
+            &[
+                0x03, 0x08, 0x00, // frame control + sequence number
+                0xff, 0xff, 0xff, 0xff, // destination PAN id + address
+                0x07, // beacon request
+            ],
+        );
+
+        // data request to the PAN coordinator (7.3.4): the destination may be omitted
+        roundtrip(
+            SrcDest::NodeToPanCoord {
+                pan_id: PanId(0xbeef),
+                src_addr: ShortAddr(0x01_02).into(),
+            },
+            command::Command::DataRequest,
+            &[
+                0x23, 0x80, 0x00, // frame control + sequence number
+                0xef, 0xbe, 0x02, 0x01, // source PAN id + address
+                0x04, // data request
+            ],
+        );
+
+        // data request to a coordinator of the same PAN (7.3.4)
+        roundtrip(
+            SrcDest::IntraPan {
+                pan_id: PanId(0xbeef),
+                src_addr: ShortAddr(0x01_02).into(),
+                dest_addr: ShortAddr(0x0000).into(),
+            },
+            command::Command::DataRequest,
+            &[
+                0x63, 0x88, 0x00, // frame control + sequence number
+                0xef, 0xbe, 0x00, 0x00, 0x02, 0x01, // PAN id + destination + source
+                0x04, // data request
+            ],
+        );
+    }
+
+    #[test]
+    fn security() {
+        use super::{
+            security::{aes::Aes128, Error, KeyIdentifier, SecurityLevel},
+            FrameVersion,
+        };
+
+        // Test vectors from Annex C.2 of IEEE 802.15.4-2006
+        let cipher = Aes128::new(&[
+            0xc0, 0xc1, 0xc2, 0xc3, 0xc4, 0xc5, 0xc6, 0xc7, 0xc8, 0xc9, 0xca, 0xcb, 0xcc, 0xcd,
+            0xce, 0xcf,
+        ]);
+        let src_addr = ExtendedAddr(0xacde_4800_0000_0001);
+        let pan_id = PanId(0x4321);
+
+        let unsecure = |bytes: &[u8], level, payload: &[u8]| {
+            let mut buf = [0; 128];
+            buf[..bytes.len()].copy_from_slice(bytes);
+            let mut frame = Frame::parse(&mut buf[..bytes.len()]).unwrap();
+            let aux = frame.get_aux_security_header().unwrap();
+            assert_eq!(aux.get_security_level(), level);
+            assert_eq!(aux.get_key_identifier(), KeyIdentifier::Implicit);
+            assert_eq!(aux.get_frame_counter(), 5);
+
+            frame.unsecure_payload(&cipher, src_addr).unwrap();
+            assert_eq!(frame.payload(), payload);
+        };
+
+        // C.2.1 MAC beacon frame: authentication only, 64-bit MIC
+        let bytes = [
+            0x08, 0xd0, 0x84, // frame control + sequence number
+            0x21, 0x43, 0x01, 0x00, 0x00, 0x00, 0x00, 0x48, 0xde, 0xac, // source
+            0x02, 0x05, 0x00, 0x00, 0x00, // auxiliary security header
+            0x55, 0xcf, 0x00, 0x00, 0x51, 0x52, 0x53, 0x54, // beacon payload
+            0x22, 0x3b, 0xc1, 0xec, 0x84, 0x1a, 0xb5, 0x53, // MIC
+        ];
+        let payload = [0x55, 0xcf, 0x00, 0x00, 0x51, 0x52, 0x53, 0x54];
+
+        let mut buf = [0; 128];
+        let mut frame = Frame::new(
+            &mut buf[..],
+            Type::Beacon,
+            FrameVersion::Ieee802154_2006,
+            SrcDest::NodeToPanCoord {
+                pan_id,
+                src_addr: src_addr.into(),
+            },
+        );
+        frame.set_sequence_number(0x84);
+        frame.enable_security(SecurityLevel::Mic64, KeyIdentifier::Implicit, 5);
+        frame.set_secured_payload(&cipher, src_addr, &payload);
+        assert_eq!(frame.as_bytes(), &bytes[..]);
+
+        unsecure(&bytes, SecurityLevel::Mic64, &payload);
+
+        // the MIC covers the header: wrong originator, tampered header and tampered MIC
+        let mut buf = bytes;
+        let mut frame = Frame::parse(&mut buf[..]).unwrap();
+        assert_eq!(
+            frame.unsecure_payload(&cipher, ExtendedAddr(0xacde_4800_0000_0002)),
+            Err(Error::InvalidMic)
+        );
+        let mut buf = bytes;
+        buf[2] ^= 1;
+        let mut frame = Frame::parse(&mut buf[..]).unwrap();
+        assert_eq!(
+            frame.unsecure_payload(&cipher, src_addr),
+            Err(Error::InvalidMic)
+        );
+        let mut buf = bytes;
+        buf[bytes.len() - 1] ^= 1;
+        let tampered = buf;
+        let mut frame = Frame::parse(&mut buf[..]).unwrap();
+        assert_eq!(
+            frame.unsecure_payload(&cipher, src_addr),
+            Err(Error::InvalidMic)
+        );
+        // the frame is left unchanged
+        assert_eq!(frame.as_bytes(), &tampered[..]);
+
+        // C.2.2 MAC data frame: encryption only
+        let bytes = [
+            0x69, 0xdc, 0x84, // frame control + sequence number
+            0x21, 0x43, 0x02, 0x00, 0x00, 0x00, 0x00, 0x48, 0xde, 0xac, // destination
+            0x01, 0x00, 0x00, 0x00, 0x00, 0x48, 0xde, 0xac, // source
+            0x04, 0x05, 0x00, 0x00, 0x00, // auxiliary security header
+            0xd4, 0x3e, 0x02, 0x2b, // encrypted payload
+        ];
+        let payload = [0x61, 0x62, 0x63, 0x64];
+
+        let mut buf = [0; 128];
+        let mut frame = Frame::new(
+            &mut buf[..],
+            Type::Data,
+            FrameVersion::Ieee802154_2006,
+            SrcDest::IntraPan {
+                pan_id,
+                src_addr: src_addr.into(),
+                dest_addr: ExtendedAddr(0xacde_4800_0000_0002).into(),
+            },
+        );
+        frame.set_sequence_number(0x84);
+        frame.set_ack_request(true);
+        frame.enable_security(SecurityLevel::Enc, KeyIdentifier::Implicit, 5);
+        frame.set_secured_payload(&cipher, src_addr, &payload);
+        assert_eq!(frame.as_bytes(), &bytes[..]);
+
+        unsecure(&bytes, SecurityLevel::Enc, &payload);
+
+        // C.2.3 MAC command frame: encryption and 64-bit MIC
+        let bytes = [
+            0x2b, 0xdc, 0x84, // frame control + sequence number
+            0x21, 0x43, 0x02, 0x00, 0x00, 0x00, 0x00, 0x48, 0xde, 0xac, // destination
+            0xff, 0xff, 0x01, 0x00, 0x00, 0x00, 0x00, 0x48, 0xde, 0xac, // source
+            0x06, 0x05, 0x00, 0x00, 0x00, // auxiliary security header
+            0x01, // Command Frame Identifier (association request), sent in the clear
+            0xd8, // encrypted capability information
+            0x4f, 0xde, 0x52, 0x90, 0x61, 0xf9, 0xc6, 0xf1, // MIC
+        ];
+        let payload = [0x01, 0xce];
+
+        let mut buf = [0; 128];
+        let mut frame = Frame::new(
+            &mut buf[..],
+            Type::MacCommand,
+            FrameVersion::Ieee802154_2006,
+            SrcDest::InterPan {
+                src_pan_id: PanId::BROADCAST,
+                src_addr: src_addr.into(),
+                dest_pan_id: pan_id,
+                dest_addr: ExtendedAddr(0xacde_4800_0000_0002).into(),
+            },
+        );
+        frame.set_sequence_number(0x84);
+        frame.set_ack_request(true);
+        frame.enable_security(SecurityLevel::EncMic64, KeyIdentifier::Implicit, 5);
+        frame.set_secured_payload(&cipher, src_addr, &payload);
+        assert_eq!(frame.as_bytes(), &bytes[..]);
+
+        unsecure(&bytes, SecurityLevel::EncMic64, &payload);
+
+        // IEEE 802.15.4-2003 frames have no auxiliary security header
+        let mut buf = bytes;
+        buf[1] &= !0b0011_0000;
+        let mut frame = Frame::parse(&mut buf[..]).unwrap();
+        assert!(frame.get_aux_security_header().is_none());
+        assert_eq!(
+            frame.unsecure_payload(&cipher, src_addr),
+            Err(Error::UnsupportedVersion)
+        );
+
+        // not secured
+        let mut buf = [0; 128];
+        let mut frame = Frame::data(
+            &mut buf[..],
+            SrcDest::IntraPan {
+                pan_id,
+                src_addr: src_addr.into(),
+                dest_addr: ShortAddr(0x0304).into(),
+            },
+        );
+        frame.set_payload(&payload);
+        assert_eq!(
+            frame.unsecure_payload(&cipher, src_addr),
+            Err(Error::NotSecured)
+        );
+    }
+
+    #[test]
+    fn ieee802154_2015() {
+        use super::{
+            ie::{HeaderIe, PayloadIe},
+            FrameVersion,
+        };
+
+        let mut buf = [0xff; 128];
+        let mut frame = Frame::new(
+            &mut buf[..],
+            Type::Data,
+            FrameVersion::Ieee802154,
+            SrcDest::IntraPan {
+                pan_id: PanId(0xbeef),
+                src_addr: ShortAddr(0x0102).into(),
+                dest_addr: ShortAddr(0x0304).into(),
+            },
+        );
+        frame.suppress_sequence_number();
+        frame.push_header_ie(0x1a, &[0xaa, 0xbb]);
+        frame.push_payload_ie(1, &[0xcc]);
+        frame.terminate_ies();
+        frame.set_payload(&[0x11, 0x22]);
+
+        let bytes = &[
+            0x41, 0xab, // frame control
+            0xef, 0xbe, 0x04, 0x03, 0x02, 0x01, // addressing fields
+            0x02, 0x0d, 0xaa, 0xbb, // Header IE
+            0x00, 0x3f, // HT1
+            0x01, 0x88, 0xcc, // Payload IE
+            0x00, 0xf8, // Payload Termination IE
+            0x11, 0x22, // payload
+        ];
+        assert_eq!(frame.as_bytes(), bytes);
+
+        let frame = Frame::parse(&bytes[..]).unwrap();
+        assert_eq!(frame.get_frame_version(), FrameVersion::Ieee802154);
+        assert!(frame.get_sequence_number_suppression());
+        assert!(frame.get_ie_present());
+        assert_eq!(frame.get_sequence_number(), None);
+        assert_eq!(frame.get_dest_pan_id(), Some(PanId(0xbeef)));
+        assert_eq!(frame.get_dest_addr(), Some(ShortAddr(0x0304).into()));
+        assert_eq!(frame.get_src_pan_id(), None);
+        assert_eq!(frame.get_src_addr(), Some(ShortAddr(0x0102).into()));
+        assert!(frame.header_ies().eq(Some(HeaderIe {
+            element_id: 0x1a,
+            content: &[0xaa, 0xbb],
+        })
+        .into_iter()));
+        assert!(frame.payload_ies().eq(Some(PayloadIe {
+            group_id: 1,
+            content: &[0xcc],
+        })
+        .into_iter()));
+        assert_eq!(frame.payload(), &[0x11, 0x22]);
+    }
+
+    #[test]
+    fn pan_id_compression() {
+        use super::FrameVersion;
+
+        let ext = ExtendedAddr(0x01_02_03_04_05_06_07_08);
+
+        // IEEE 802.15.4-2015 extended -> extended: only the destination PAN ID is present
+        let mut buf = [0; 128];
+        let frame = Frame::new(
+            &mut buf[..],
+            Type::Data,
+            FrameVersion::Ieee802154,
+            SrcDest::IntraPan {
+                pan_id: PanId(0xbeef),
+                src_addr: ext.into(),
+                dest_addr: ext.into(),
+            },
+        );
+        assert_eq!(frame.get_intra_pan(), false);
+        assert_eq!(frame.get_dest_pan_id(), Some(PanId(0xbeef)));
+        assert_eq!(frame.get_src_pan_id(), None);
+        assert_eq!(frame.header().len(), 3 + 2 + 8 + 8);
+
+        // same addressing but the PAN ID Compression field is set: no PAN ID at all
+        let mut bytes = [0; 3 + 8 + 8];
+        bytes[..3].copy_from_slice(&[0x41, 0xec, 0x00]);
+        let frame = Frame::parse(&bytes[..]).unwrap();
+        assert_eq!(frame.get_dest_pan_id(), None);
+        assert_eq!(frame.get_src_pan_id(), None);
+        assert_eq!(frame.get_dest_addr(), Some(ExtendedAddr(0).into()));
+        assert_eq!(frame.get_src_addr(), Some(ExtendedAddr(0).into()));
+        assert_eq!(frame.payload(), &[]);
+
+        // IEEE 802.15.4-2015 frames without addresses are valid (e.g. an Enhanced Ack)
+        let frame = Frame::parse(&[0x02, 0x21][..]).unwrap();
+        assert_eq!(frame.get_type(), Type::Acknowledgment);
+        assert_eq!(frame.get_sequence_number(), None);
+
+        // but not IEEE 802.15.4-2003 data frames
+        assert!(Frame::parse(&[0x01, 0x00, 0x00][..]).is_err());
+
+        // reserved frame version
+        assert!(Frame::parse(&[0x01, 0xb8, 0x00, 0xef, 0xbe, 0x04, 0x03][..]).is_err());
+    }
+
+    #[test]
+    fn ipv6() {
+        let mut buf = [0; 128];
+        let mut frame = Frame::data(
+            &mut buf[..],
+            SrcDest::IntraPan {
+                pan_id: PanId(0xbeef),
+                dest_addr: ShortAddr(0x0304).into(),
+                src_addr: ShortAddr(0x0102).into(),
+            },
+        );
+        frame.ipv6(|ip| {
+            ip.set_next_header(ipv6::NextHeader::Udp);
+            ip.payload_mut()[..4].copy_from_slice(&[1, 2, 3, 4]);
+            ip.truncate(4);
+        });
+
+        let frame = Frame::parse(frame.as_bytes()).unwrap();
+        // IPv6 dispatch + IPv6 header + payload
+        assert_eq!(frame.payload().len(), 1 + 40 + 4);
+
+        match Dispatch::parse(frame.payload()).unwrap() {
+            Dispatch::Ipv6(ip) => {
+                assert_eq!(ip.get_next_header(), ipv6::NextHeader::Udp);
+                assert_eq!(ip.payload(), &[1, 2, 3, 4]);
+            }
+            d => panic!("expected an IPv6 packet; got {:?}", d),
+        }
+    }
 }
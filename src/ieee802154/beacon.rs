@@ -0,0 +1,593 @@
+//! Beacon frame payload
+//!
+//! # References
+//!
+//! - [IEEE 802.15.4-2003 standard][standard], Section 7.2.2.1 Beacon frame format
+//!
+//! [standard]: https://www.iith.ac.in/~tbr/teaching/docs/802.15.4-2003.pdf
+
+use core::{fmt, ops::Range};
+
+use as_slice::{AsMutSlice, AsSlice};
+use byteorder::{ByteOrder, LE};
+use cast::usize;
+use owning_slice::Truncate;
+
+use super::{Addr, ExtendedAddr, ShortAddr};
+use crate::traits::UncheckedIndex;
+
+/* Beacon payload format (Section 7.2.2.1) */
+const SUPERFRAME_SPEC: Range<usize> = 0..2;
+
+// Superframe specification (Section 7.2.2.1.2); this is a little endian 16-bit word
+mod beacon_order {
+    pub const MASK: u16 = (1 << SIZE) - 1;
+    pub const OFFSET: u16 = 0;
+    pub const SIZE: u16 = 4;
+}
+
+mod superframe_order {
+    pub const MASK: u16 = (1 << SIZE) - 1;
+    pub const OFFSET: u16 = super::beacon_order::OFFSET + super::beacon_order::SIZE;
+    pub const SIZE: u16 = 4;
+}
+
+mod final_cap_slot {
+    pub const MASK: u16 = (1 << SIZE) - 1;
+    pub const OFFSET: u16 = super::superframe_order::OFFSET + super::superframe_order::SIZE;
+    pub const SIZE: u16 = 4;
+}
+
+mod battery_life_extension {
+    pub const MASK: u16 = (1 << SIZE) - 1;
+    pub const OFFSET: u16 = super::final_cap_slot::OFFSET + super::final_cap_slot::SIZE;
+    pub const SIZE: u16 = 1;
+}
+
+mod pan_coordinator {
+    pub const MASK: u16 = (1 << SIZE) - 1;
+    pub const OFFSET: u16 = 14;
+    pub const SIZE: u16 = 1;
+}
+
+mod association_permit {
+    pub const MASK: u16 = (1 << SIZE) - 1;
+    pub const OFFSET: u16 = super::pan_coordinator::OFFSET + super::pan_coordinator::SIZE;
+    pub const SIZE: u16 = 1;
+}
+
+// GTS specification (Section 7.2.2.1.3)
+const GTS_SPEC: usize = 2;
+
+mod gts_descriptor_count {
+    pub const MASK: u8 = (1 << SIZE) - 1;
+    pub const OFFSET: u8 = 0;
+    pub const SIZE: u8 = 3;
+}
+
+mod gts_permit {
+    pub const MASK: u8 = (1 << SIZE) - 1;
+    pub const OFFSET: u8 = 7;
+    pub const SIZE: u8 = 1;
+}
+
+// GTS directions (Section 7.2.2.1.4); only present if the descriptor count is not zero
+const GTS_DIRECTIONS: usize = 3;
+
+// GTS descriptor (Section 7.2.2.1.5)
+const GTS_DESCRIPTOR_SIZE: usize = 3;
+
+mod starting_slot {
+    pub const MASK: u8 = (1 << SIZE) - 1;
+    pub const OFFSET: u8 = 0;
+    pub const SIZE: u8 = 4;
+}
+
+mod length {
+    pub const MASK: u8 = (1 << SIZE) - 1;
+    pub const OFFSET: u8 = super::starting_slot::OFFSET + super::starting_slot::SIZE;
+    pub const SIZE: u8 = 4;
+}
+
+// Pending address specification (Section 7.2.2.1.6)
+mod short_addr_count {
+    pub const MASK: u8 = (1 << SIZE) - 1;
+    pub const OFFSET: u8 = 0;
+    pub const SIZE: u8 = 3;
+}
+
+mod extended_addr_count {
+    pub const MASK: u8 = (1 << SIZE) - 1;
+    pub const OFFSET: u8 = 4;
+    pub const SIZE: u8 = 3;
+}
+
+/// Maximum number of GTS descriptors a beacon can carry
+pub const MAX_GTS_DESCRIPTORS: u8 = gts_descriptor_count::MASK;
+
+/// Maximum number of pending addresses, of each kind, a beacon can carry
+pub const MAX_PENDING_ADDRS: u8 = short_addr_count::MASK;
+
+/// Beacon frame payload
+pub struct Beacon<BUFFER>
+where
+    BUFFER: AsSlice<Element = u8>,
+{
+    buffer: BUFFER,
+    // index at which the beacon payload starts
+    payload: u8,
+}
+
+impl<B> Beacon<B>
+where
+    B: AsSlice<Element = u8>,
+{
+    /* Constructors */
+    /// Parses the payload of a beacon frame
+    pub fn parse(bytes: B) -> Result<Self, B> {
+        let slice = bytes.as_slice();
+
+        if slice.len() <= GTS_SPEC {
+            return Err(bytes);
+        }
+
+        let ngts = usize(get!(slice[GTS_SPEC], gts_descriptor_count));
+        let pending = pending_spec_index(ngts);
+
+        if slice.len() <= pending {
+            return Err(bytes);
+        }
+
+        let nshort = usize(get!(slice[pending], short_addr_count));
+        let nextended = usize(get!(slice[pending], extended_addr_count));
+        let payload = pending + 1 + 2 * nshort + 8 * nextended;
+
+        if slice.len() < payload {
+            return Err(bytes);
+        }
+
+        Ok(Beacon {
+            buffer: bytes,
+            payload: payload as u8,
+        })
+    }
+
+    /* Getters */
+    /// Reads the 'Beacon Order' field
+    pub fn get_beacon_order(&self) -> u8 {
+        get!(self.get_superframe_spec(), beacon_order) as u8
+    }
+
+    /// Reads the 'Superframe Order' field
+    pub fn get_superframe_order(&self) -> u8 {
+        get!(self.get_superframe_spec(), superframe_order) as u8
+    }
+
+    /// Reads the 'Final CAP Slot' field
+    pub fn get_final_cap_slot(&self) -> u8 {
+        get!(self.get_superframe_spec(), final_cap_slot) as u8
+    }
+
+    /// Reads the 'Battery Life Extension' field
+    pub fn get_battery_life_extension(&self) -> bool {
+        get!(self.get_superframe_spec(), battery_life_extension) == 1
+    }
+
+    /// Reads the 'PAN Coordinator' field
+    pub fn get_pan_coordinator(&self) -> bool {
+        get!(self.get_superframe_spec(), pan_coordinator) == 1
+    }
+
+    /// Reads the 'Association Permit' field
+    pub fn get_association_permit(&self) -> bool {
+        get!(self.get_superframe_spec(), association_permit) == 1
+    }
+
+    /// Reads the 'GTS Permit' field
+    pub fn get_gts_permit(&self) -> bool {
+        get!(unsafe { *self.as_slice().gu(GTS_SPEC) }, gts_permit) == 1
+    }
+
+    /// Returns an iterator over the GTS descriptors
+    pub fn gts_descriptors(&self) -> GtsDescriptors<'_> {
+        let ngts = self.gts_descriptor_count();
+
+        if ngts == 0 {
+            GtsDescriptors {
+                directions: 0,
+                descriptors: &[],
+            }
+        } else {
+            unsafe {
+                GtsDescriptors {
+                    directions: *self.as_slice().gu(GTS_DIRECTIONS),
+                    descriptors: self
+                        .as_slice()
+                        .r(GTS_DIRECTIONS + 1..GTS_DIRECTIONS + 1 + ngts * GTS_DESCRIPTOR_SIZE),
+                }
+            }
+        }
+    }
+
+    /// Returns an iterator over the pending addresses
+    ///
+    /// Short addresses are yielded first, then extended addresses
+    pub fn pending_addrs(&self) -> PendingAddrs<'_> {
+        let pending = pending_spec_index(self.gts_descriptor_count());
+
+        unsafe {
+            let spec = *self.as_slice().gu(pending);
+            let short_end = pending + 1 + 2 * usize(get!(spec, short_addr_count));
+
+            PendingAddrs {
+                short: self.as_slice().r(pending + 1..short_end),
+                extended: self.as_slice().r(short_end..usize(self.payload)),
+            }
+        }
+    }
+
+    /// Immutable view into the beacon payload
+    pub fn payload(&self) -> &[u8] {
+        unsafe { self.as_slice().rf(usize(self.payload)..) }
+    }
+
+    /// Byte representation of the beacon, including its payload
+    pub fn bytes(&self) -> &[u8] {
+        self.as_slice()
+    }
+
+    /* Private */
+    fn as_slice(&self) -> &[u8] {
+        self.buffer.as_slice()
+    }
+
+    fn get_superframe_spec(&self) -> u16 {
+        LE::read_u16(unsafe { self.as_slice().r(SUPERFRAME_SPEC) })
+    }
+
+    fn gts_descriptor_count(&self) -> usize {
+        usize(get!(
+            unsafe { *self.as_slice().gu(GTS_SPEC) },
+            gts_descriptor_count
+        ))
+    }
+}
+
+impl<B> Beacon<B>
+where
+    B: AsMutSlice<Element = u8>,
+{
+    /* Constructors */
+    /// Transforms the given buffer into a beacon with the given GTS descriptors and pending
+    /// addresses
+    ///
+    /// All the superframe specification fields, and the 'GTS Permit' field, are set to zero. The
+    /// beacon payload spans the rest of the buffer
+    ///
+    /// # Panics
+    ///
+    /// This constructor panics if `buffer` is too small to contain the beacon, if there are more
+    /// than `MAX_GTS_DESCRIPTORS` GTS descriptors or if there are more than `MAX_PENDING_ADDRS`
+    /// pending addresses of either kind
+    pub fn new(mut buffer: B, gts_descriptors: &[GtsDescriptor], pending_addrs: &[Addr]) -> Self {
+        let ngts = gts_descriptors.len();
+        let nshort = pending_addrs
+            .iter()
+            .filter(|addr| addr.mode() == super::AddrMode::Short)
+            .count();
+        let nextended = pending_addrs.len() - nshort;
+
+        assert!(ngts <= usize(MAX_GTS_DESCRIPTORS));
+        assert!(nshort <= usize(MAX_PENDING_ADDRS) && nextended <= usize(MAX_PENDING_ADDRS));
+
+        let pending = pending_spec_index(ngts);
+        let payload = pending + 1 + 2 * nshort + 8 * nextended;
+        assert!(buffer.as_slice().len() >= payload);
+
+        let slice = buffer.as_mut_slice();
+        slice[SUPERFRAME_SPEC].copy_from_slice(&[0, 0]);
+        slice[GTS_SPEC] = (ngts as u8) << gts_descriptor_count::OFFSET;
+
+        if ngts != 0 {
+            let mut directions = 0;
+            for (i, (gts, chunk)) in gts_descriptors
+                .iter()
+                .zip(slice[GTS_DIRECTIONS + 1..pending].chunks_mut(GTS_DESCRIPTOR_SIZE))
+                .enumerate()
+            {
+                if gts.direction == Direction::Receive {
+                    directions |= 1 << i;
+                }
+
+                LE::write_u16(&mut chunk[..2], gts.short_addr.0);
+                chunk[2] = ((gts.starting_slot & starting_slot::MASK) << starting_slot::OFFSET)
+                    | ((gts.length & length::MASK) << length::OFFSET);
+            }
+
+            slice[GTS_DIRECTIONS] = directions;
+        }
+
+        slice[pending] = ((nshort as u8) << short_addr_count::OFFSET)
+            | ((nextended as u8) << extended_addr_count::OFFSET);
+
+        let mut start = pending + 1;
+        for addr in pending_addrs {
+            if let Addr::Short(sa) = addr {
+                LE::write_u16(&mut slice[start..start + 2], sa.0);
+                start += 2;
+            }
+        }
+
+        for addr in pending_addrs {
+            if let Addr::Extended(ea) = addr {
+                LE::write_u64(&mut slice[start..start + 8], ea.0);
+                start += 8;
+            }
+        }
+
+        Beacon {
+            buffer,
+            payload: payload as u8,
+        }
+    }
+
+    /* Setters */
+    /// Sets the 'Beacon Order' field
+    pub fn set_beacon_order(&mut self, order: u8) {
+        self.update_superframe_spec(|spec| set!(*spec, beacon_order, u16::from(order)));
+    }
+
+    /// Sets the 'Superframe Order' field
+    pub fn set_superframe_order(&mut self, order: u8) {
+        self.update_superframe_spec(|spec| set!(*spec, superframe_order, u16::from(order)));
+    }
+
+    /// Sets the 'Final CAP Slot' field
+    pub fn set_final_cap_slot(&mut self, slot: u8) {
+        self.update_superframe_spec(|spec| set!(*spec, final_cap_slot, u16::from(slot)));
+    }
+
+    /// Sets the 'Battery Life Extension' field
+    pub fn set_battery_life_extension(&mut self, ble: bool) {
+        self.update_superframe_spec(|spec| set!(*spec, battery_life_extension, u16::from(ble)));
+    }
+
+    /// Sets the 'PAN Coordinator' field
+    pub fn set_pan_coordinator(&mut self, coord: bool) {
+        self.update_superframe_spec(|spec| set!(*spec, pan_coordinator, u16::from(coord)));
+    }
+
+    /// Sets the 'Association Permit' field
+    pub fn set_association_permit(&mut self, permit: bool) {
+        self.update_superframe_spec(|spec| set!(*spec, association_permit, u16::from(permit)));
+    }
+
+    /// Sets the 'GTS Permit' field
+    pub fn set_gts_permit(&mut self, permit: bool) {
+        set!(self.as_mut_slice()[GTS_SPEC], gts_permit, u8::from(permit));
+    }
+
+    /// Mutable view into the beacon payload
+    pub fn payload_mut(&mut self) -> &mut [u8] {
+        let start = usize(self.payload);
+        unsafe { self.as_mut_slice().rfm(start..) }
+    }
+
+    /* Private */
+    fn as_mut_slice(&mut self) -> &mut [u8] {
+        self.buffer.as_mut_slice()
+    }
+
+    fn update_superframe_spec(&mut self, f: impl FnOnce(&mut u16)) {
+        let mut spec = self.get_superframe_spec();
+        f(&mut spec);
+        LE::write_u16(&mut self.as_mut_slice()[SUPERFRAME_SPEC], spec);
+    }
+}
+
+impl<B> Beacon<B>
+where
+    B: AsMutSlice<Element = u8> + Truncate<u8>,
+{
+    /// Fills the beacon payload with the given data and adjusts the length of the beacon
+    pub fn set_payload(&mut self, payload: &[u8]) {
+        let plen = payload.len();
+
+        self.payload_mut()[..plen].copy_from_slice(payload);
+        self.buffer.truncate(self.payload + plen as u8);
+    }
+}
+
+impl<B> fmt::Debug for Beacon<B>
+where
+    B: AsSlice<Element = u8>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("beacon::Beacon")
+            .field("beacon_order", &self.get_beacon_order())
+            .field("superframe_order", &self.get_superframe_order())
+            .field("final_cap_slot", &self.get_final_cap_slot())
+            .field("battery_life_extension", &self.get_battery_life_extension())
+            .field("pan_coordinator", &self.get_pan_coordinator())
+            .field("association_permit", &self.get_association_permit())
+            .field("gts_permit", &self.get_gts_permit())
+            // .field("payload", &self.payload())
+            .finish()
+    }
+}
+
+/// GTS descriptor
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct GtsDescriptor {
+    /// Short address of the device the GTS is allocated to
+    pub short_addr: ShortAddr,
+    /// Superframe slot at which the GTS begins
+    pub starting_slot: u8,
+    /// Number of contiguous superframe slots the GTS spans
+    pub length: u8,
+    /// Direction of the GTS
+    pub direction: Direction,
+}
+
+/// GTS direction, from the point of view of the device
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Direction {
+    /// Transmit-only GTS
+    Transmit,
+    /// Receive-only GTS
+    Receive,
+}
+
+/// Iterator over the GTS descriptors of a beacon
+pub struct GtsDescriptors<'a> {
+    directions: u8,
+    descriptors: &'a [u8],
+}
+
+impl Iterator for GtsDescriptors<'_> {
+    type Item = GtsDescriptor;
+
+    fn next(&mut self) -> Option<GtsDescriptor> {
+        if self.descriptors.len() < GTS_DESCRIPTOR_SIZE {
+            return None;
+        }
+
+        let (head, tail) = self.descriptors.split_at(GTS_DESCRIPTOR_SIZE);
+        self.descriptors = tail;
+
+        let direction = if self.directions & 1 == 1 {
+            Direction::Receive
+        } else {
+            Direction::Transmit
+        };
+        self.directions >>= 1;
+
+        Some(GtsDescriptor {
+            short_addr: ShortAddr(LE::read_u16(&head[..2])),
+            starting_slot: get!(head[2], starting_slot),
+            length: get!(head[2], length),
+            direction,
+        })
+    }
+}
+
+/// Iterator over the pending addresses of a beacon
+pub struct PendingAddrs<'a> {
+    short: &'a [u8],
+    extended: &'a [u8],
+}
+
+impl Iterator for PendingAddrs<'_> {
+    type Item = Addr;
+
+    fn next(&mut self) -> Option<Addr> {
+        if self.short.len() >= 2 {
+            let (head, tail) = self.short.split_at(2);
+            self.short = tail;
+
+            Some(Addr::Short(ShortAddr(LE::read_u16(head))))
+        } else if self.extended.len() >= 8 {
+            let (head, tail) = self.extended.split_at(8);
+            self.extended = tail;
+
+            Some(Addr::Extended(ExtendedAddr(LE::read_u64(head))))
+        } else {
+            None
+        }
+    }
+}
+
+// Index of the 'Pending Address Specification' field
+fn pending_spec_index(ngts: usize) -> usize {
+    if ngts == 0 {
+        GTS_SPEC + 1
+    } else {
+        GTS_DIRECTIONS + 1 + ngts * GTS_DESCRIPTOR_SIZE
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Beacon, Direction, GtsDescriptor};
+    use crate::ieee802154::{Addr, ExtendedAddr, ShortAddr};
+
+    #[test]
+    fn roundtrip() {
+        let gts = [
+            GtsDescriptor {
+                short_addr: ShortAddr(0x0102),
+                starting_slot: 12,
+                length: 2,
+                direction: Direction::Transmit,
+            },
+            GtsDescriptor {
+                short_addr: ShortAddr(0x0304),
+                starting_slot: 14,
+                length: 1,
+                direction: Direction::Receive,
+            },
+        ];
+        let pending: [Addr; 2] = [
+            ExtendedAddr(0x01_02_03_04_05_06_07_08).into(),
+            ShortAddr(0x0506).into(),
+        ];
+
+        let mut buf = [0xff; 64];
+        let mut beacon = Beacon::new(&mut buf[..], &gts, &pending);
+        beacon.set_beacon_order(15);
+        beacon.set_superframe_order(15);
+        beacon.set_final_cap_slot(11);
+        beacon.set_pan_coordinator(true);
+        beacon.set_association_permit(true);
+        beacon.set_gts_permit(true);
+        beacon.set_payload(&[0xaa]);
+
+        let bytes = beacon.bytes();
+        assert_eq!(
+            bytes,
+            &[
+                0xff, 0xcb, // superframe spec
+                0x82, // GTS spec
+                0x02, // GTS directions
+                0x02, 0x01, 0x2c, // GTS #0
+                0x04, 0x03, 0x1e, // GTS #1
+                0x11, // pending address spec
+                0x06, 0x05, // short
+                0x08, 0x07, 0x06, 0x05, 0x04, 0x03, 0x02, 0x01, // extended
+                0xaa, // beacon payload
+            ][..]
+        );
+
+        let beacon = Beacon::parse(bytes).unwrap();
+        assert_eq!(beacon.get_beacon_order(), 15);
+        assert_eq!(beacon.get_superframe_order(), 15);
+        assert_eq!(beacon.get_final_cap_slot(), 11);
+        assert!(!beacon.get_battery_life_extension());
+        assert!(beacon.get_pan_coordinator());
+        assert!(beacon.get_association_permit());
+        assert!(beacon.get_gts_permit());
+        assert!(beacon.gts_descriptors().eq(gts.iter().cloned()));
+        assert!(beacon
+            .pending_addrs()
+            .eq([pending[1], pending[0]].iter().cloned()));
+        assert_eq!(beacon.payload(), &[0xaa]);
+    }
+
+    #[test]
+    fn minimal() {
+        // beacon of a non beacon-enabled PAN: no GTS, no pending addresses
+        let bytes = [0xff, 0xcf, 0x00, 0x00];
+
+        let beacon = Beacon::parse(&bytes[..]).unwrap();
+        assert_eq!(beacon.get_beacon_order(), 15);
+        assert_eq!(beacon.get_final_cap_slot(), 15);
+        assert!(beacon.get_pan_coordinator());
+        assert!(beacon.get_association_permit());
+        assert_eq!(beacon.gts_descriptors().count(), 0);
+        assert_eq!(beacon.pending_addrs().count(), 0);
+        assert_eq!(beacon.payload(), &[]);
+
+        // pending address list is truncated
+        assert!(Beacon::parse(&[0xff, 0xcf, 0x00, 0x01, 0x00][..]).is_err());
+    }
+}
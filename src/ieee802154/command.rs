@@ -0,0 +1,465 @@
+//! MAC command frame payload
+//!
+//! # References
+//!
+//! - [IEEE 802.15.4-2003 standard][standard], Section 7.3 MAC command frames
+//!
+//! [standard]: https://www.iith.ac.in/~tbr/teaching/docs/802.15.4-2003.pdf
+
+use byteorder::{ByteOrder, LE};
+
+use super::{PanId, ShortAddr};
+
+/* Command frame format (Section 7.3) */
+const IDENTIFIER: usize = 0;
+const PAYLOAD: usize = 1;
+
+// Capability information field (Section 7.3.1.1.2)
+mod alternate_pan_coordinator {
+    pub const MASK: u8 = (1 << SIZE) - 1;
+    pub const OFFSET: u8 = 0;
+    pub const SIZE: u8 = 1;
+}
+
+mod device_type {
+    pub const MASK: u8 = (1 << SIZE) - 1;
+    pub const OFFSET: u8 =
+        super::alternate_pan_coordinator::OFFSET + super::alternate_pan_coordinator::SIZE;
+    pub const SIZE: u8 = 1;
+}
+
+mod power_source {
+    pub const MASK: u8 = (1 << SIZE) - 1;
+    pub const OFFSET: u8 = super::device_type::OFFSET + super::device_type::SIZE;
+    pub const SIZE: u8 = 1;
+}
+
+mod receiver_on_when_idle {
+    pub const MASK: u8 = (1 << SIZE) - 1;
+    pub const OFFSET: u8 = super::power_source::OFFSET + super::power_source::SIZE;
+    pub const SIZE: u8 = 1;
+}
+
+mod security_capability {
+    pub const MASK: u8 = (1 << SIZE) - 1;
+    pub const OFFSET: u8 = 6;
+    pub const SIZE: u8 = 1;
+}
+
+mod allocate_address {
+    pub const MASK: u8 = (1 << SIZE) - 1;
+    pub const OFFSET: u8 = super::security_capability::OFFSET + super::security_capability::SIZE;
+    pub const SIZE: u8 = 1;
+}
+
+// GTS characteristics field (Section 7.3.3.1.2)
+mod gts_length {
+    pub const MASK: u8 = (1 << SIZE) - 1;
+    pub const OFFSET: u8 = 0;
+    pub const SIZE: u8 = 4;
+}
+
+mod gts_direction {
+    pub const MASK: u8 = (1 << SIZE) - 1;
+    pub const OFFSET: u8 = super::gts_length::OFFSET + super::gts_length::SIZE;
+    pub const SIZE: u8 = 1;
+}
+
+mod gts_allocation {
+    pub const MASK: u8 = (1 << SIZE) - 1;
+    pub const OFFSET: u8 = super::gts_direction::OFFSET + super::gts_direction::SIZE;
+    pub const SIZE: u8 = 1;
+}
+
+/// Size of the largest MAC command, 'Coordinator realignment'
+pub const MAX_SIZE: u8 = 8;
+
+/// MAC command parsing error
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Error {
+    /// The payload is empty or too short to hold the command
+    Truncated,
+
+    /// The command frame identifier is reserved or not supported
+    UnknownIdentifier(u8),
+}
+
+full_range!(
+    u8,
+    /// Command frame identifier
+    #[derive(Clone, Copy, Debug, Eq, PartialEq)]
+    pub enum Identifier {
+        /// Association request
+        AssociationRequest = 0x01,
+        /// Association response
+        AssociationResponse = 0x02,
+        /// Disassociation notification
+        DisassociationNotification = 0x03,
+        /// Data request
+        DataRequest = 0x04,
+        /// PAN ID conflict notification
+        PanIdConflictNotification = 0x05,
+        /// Orphan notification
+        OrphanNotification = 0x06,
+        /// Beacon request
+        BeaconRequest = 0x07,
+        /// Coordinator realignment
+        CoordinatorRealignment = 0x08,
+        /// GTS request
+        GtsRequest = 0x09,
+    }
+);
+
+/// MAC command
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Command {
+    /// Association request
+    AssociationRequest(CapabilityInformation),
+    /// Association response
+    AssociationResponse {
+        /// Short address allocated by the coordinator; `0xfffe` means that the device must use
+        /// its extended address
+        short_addr: ShortAddr,
+        /// Association status
+        status: AssociationStatus,
+    },
+    /// Disassociation notification
+    DisassociationNotification(DisassociationReason),
+    /// Data request
+    DataRequest,
+    /// PAN ID conflict notification
+    PanIdConflictNotification,
+    /// Orphan notification
+    OrphanNotification,
+    /// Beacon request
+    BeaconRequest,
+    /// Coordinator realignment
+    CoordinatorRealignment {
+        /// PAN identifier the coordinator intends to use
+        pan_id: PanId,
+        /// Short address of the coordinator
+        coord_short_addr: ShortAddr,
+        /// Logical channel the coordinator intends to use
+        logical_channel: u8,
+        /// Short address of the orphaned device; `0xffff` if the command is broadcast
+        short_addr: ShortAddr,
+    },
+    /// GTS request
+    GtsRequest(GtsCharacteristics),
+}
+
+impl Command {
+    /// Parses the payload of a MAC command frame
+    ///
+    /// Trailing bytes (e.g. the 'Channel page' field added in later revisions of the standard)
+    /// are ignored
+    pub fn parse(bytes: &[u8]) -> Result<Self, Error> {
+        let id = Identifier::from(*bytes.get(IDENTIFIER).ok_or(Error::Truncated)?);
+        let payload = &bytes[PAYLOAD..];
+
+        let size = payload_size(id).ok_or_else(|| Error::UnknownIdentifier(id.into()))?;
+        if payload.len() < usize::from(size) {
+            return Err(Error::Truncated);
+        }
+
+        Ok(match id {
+            Identifier::AssociationRequest => {
+                Command::AssociationRequest(CapabilityInformation(payload[0]))
+            }
+            Identifier::AssociationResponse => Command::AssociationResponse {
+                short_addr: ShortAddr(LE::read_u16(&payload[..2])),
+                status: AssociationStatus::from(payload[2]),
+            },
+            Identifier::DisassociationNotification => {
+                Command::DisassociationNotification(DisassociationReason::from(payload[0]))
+            }
+            Identifier::DataRequest => Command::DataRequest,
+            Identifier::PanIdConflictNotification => Command::PanIdConflictNotification,
+            Identifier::OrphanNotification => Command::OrphanNotification,
+            Identifier::BeaconRequest => Command::BeaconRequest,
+            Identifier::CoordinatorRealignment => Command::CoordinatorRealignment {
+                pan_id: PanId(LE::read_u16(&payload[..2])),
+                coord_short_addr: ShortAddr(LE::read_u16(&payload[2..4])),
+                logical_channel: payload[4],
+                short_addr: ShortAddr(LE::read_u16(&payload[5..7])),
+            },
+            Identifier::GtsRequest => Command::GtsRequest(GtsCharacteristics(payload[0])),
+            Identifier::Unknown(_) => unsafe { debug_unreachable!() },
+        })
+    }
+
+    /// Returns the command frame identifier
+    pub fn identifier(&self) -> Identifier {
+        match *self {
+            Command::AssociationRequest(..) => Identifier::AssociationRequest,
+            Command::AssociationResponse { .. } => Identifier::AssociationResponse,
+            Command::DisassociationNotification(..) => Identifier::DisassociationNotification,
+            Command::DataRequest => Identifier::DataRequest,
+            Command::PanIdConflictNotification => Identifier::PanIdConflictNotification,
+            Command::OrphanNotification => Identifier::OrphanNotification,
+            Command::BeaconRequest => Identifier::BeaconRequest,
+            Command::CoordinatorRealignment { .. } => Identifier::CoordinatorRealignment,
+            Command::GtsRequest(..) => Identifier::GtsRequest,
+        }
+    }
+
+    /// Returns the size of this command, including the identifier
+    pub fn size(&self) -> u8 {
+        // NOTE(+1) command frame identifier
+        1 + payload_size(self.identifier()).unwrap_or_else(|| unsafe { debug_unreachable!() })
+    }
+
+    /// Writes this command into the start of the given buffer and returns the number of bytes
+    /// written
+    ///
+    /// # Panics
+    ///
+    /// This method panics if `buffer` is smaller than `self.size()`
+    pub fn write(&self, buffer: &mut [u8]) -> u8 {
+        let size = self.size();
+        let buffer = &mut buffer[..usize::from(size)];
+
+        buffer[IDENTIFIER] = self.identifier().into();
+        let payload = &mut buffer[PAYLOAD..];
+
+        match *self {
+            Command::AssociationRequest(ci) => payload[0] = ci.0,
+            Command::AssociationResponse { short_addr, status } => {
+                LE::write_u16(&mut payload[..2], short_addr.0);
+                payload[2] = status.into();
+            }
+            Command::DisassociationNotification(reason) => payload[0] = reason.into(),
+            Command::DataRequest
+            | Command::PanIdConflictNotification
+            | Command::OrphanNotification
+            | Command::BeaconRequest => {}
+            Command::CoordinatorRealignment {
+                pan_id,
+                coord_short_addr,
+                logical_channel,
+                short_addr,
+            } => {
+                LE::write_u16(&mut payload[..2], pan_id.0);
+                LE::write_u16(&mut payload[2..4], coord_short_addr.0);
+                payload[4] = logical_channel;
+                LE::write_u16(&mut payload[5..7], short_addr.0);
+            }
+            Command::GtsRequest(gc) => payload[0] = gc.0,
+        }
+
+        size
+    }
+}
+
+full_range!(
+    u8,
+    /// Association status (Section 7.3.1.2.3)
+    #[derive(Clone, Copy, Debug, Eq, PartialEq)]
+    pub enum AssociationStatus {
+        /// Association successful
+        Successful = 0x00,
+        /// PAN at capacity
+        PanAtCapacity = 0x01,
+        /// PAN access denied
+        PanAccessDenied = 0x02,
+    }
+);
+
+full_range!(
+    u8,
+    /// Disassociation reason (Section 7.3.1.3.2)
+    #[derive(Clone, Copy, Debug, Eq, PartialEq)]
+    pub enum DisassociationReason {
+        /// The coordinator wishes the device to leave the PAN
+        CoordinatorRequest = 0x01,
+        /// The device wishes to leave the PAN
+        DeviceRequest = 0x02,
+    }
+);
+
+/// Capability information field of the 'Association request' command
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct CapabilityInformation(pub u8);
+
+impl CapabilityInformation {
+    /* Getters */
+    /// Reads the 'Alternate PAN Coordinator' field
+    pub fn get_alternate_pan_coordinator(&self) -> bool {
+        get!(self.0, alternate_pan_coordinator) == 1
+    }
+
+    /// Reads the 'Device Type' field; `true` means Full-Function Device
+    pub fn get_device_type(&self) -> bool {
+        get!(self.0, device_type) == 1
+    }
+
+    /// Reads the 'Power Source' field; `true` means mains powered
+    pub fn get_power_source(&self) -> bool {
+        get!(self.0, power_source) == 1
+    }
+
+    /// Reads the 'Receiver On When Idle' field
+    pub fn get_receiver_on_when_idle(&self) -> bool {
+        get!(self.0, receiver_on_when_idle) == 1
+    }
+
+    /// Reads the 'Security Capability' field
+    pub fn get_security_capability(&self) -> bool {
+        get!(self.0, security_capability) == 1
+    }
+
+    /// Reads the 'Allocate Address' field
+    pub fn get_allocate_address(&self) -> bool {
+        get!(self.0, allocate_address) == 1
+    }
+
+    /* Setters */
+    /// Sets the 'Alternate PAN Coordinator' field
+    pub fn set_alternate_pan_coordinator(&mut self, apc: bool) {
+        set!(self.0, alternate_pan_coordinator, u8::from(apc))
+    }
+
+    /// Sets the 'Device Type' field; `true` means Full-Function Device
+    pub fn set_device_type(&mut self, ffd: bool) {
+        set!(self.0, device_type, u8::from(ffd))
+    }
+
+    /// Sets the 'Power Source' field; `true` means mains powered
+    pub fn set_power_source(&mut self, mains: bool) {
+        set!(self.0, power_source, u8::from(mains))
+    }
+
+    /// Sets the 'Receiver On When Idle' field
+    pub fn set_receiver_on_when_idle(&mut self, on: bool) {
+        set!(self.0, receiver_on_when_idle, u8::from(on))
+    }
+
+    /// Sets the 'Security Capability' field
+    pub fn set_security_capability(&mut self, security: bool) {
+        set!(self.0, security_capability, u8::from(security))
+    }
+
+    /// Sets the 'Allocate Address' field
+    pub fn set_allocate_address(&mut self, allocate: bool) {
+        set!(self.0, allocate_address, u8::from(allocate))
+    }
+}
+
+/// GTS characteristics field of the 'GTS request' command
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct GtsCharacteristics(pub u8);
+
+impl GtsCharacteristics {
+    /* Getters */
+    /// Reads the 'GTS Length' field
+    pub fn get_length(&self) -> u8 {
+        get!(self.0, gts_length)
+    }
+
+    /// Reads the 'GTS Direction' field
+    pub fn get_direction(&self) -> super::beacon::Direction {
+        if get!(self.0, gts_direction) == 1 {
+            super::beacon::Direction::Receive
+        } else {
+            super::beacon::Direction::Transmit
+        }
+    }
+
+    /// Reads the 'Characteristics Type' field; `true` means allocation, `false` deallocation
+    pub fn get_allocation(&self) -> bool {
+        get!(self.0, gts_allocation) == 1
+    }
+
+    /* Setters */
+    /// Sets the 'GTS Length' field
+    pub fn set_length(&mut self, len: u8) {
+        set!(self.0, gts_length, len)
+    }
+
+    /// Sets the 'GTS Direction' field
+    pub fn set_direction(&mut self, dir: super::beacon::Direction) {
+        set!(
+            self.0,
+            gts_direction,
+            u8::from(dir == super::beacon::Direction::Receive)
+        )
+    }
+
+    /// Sets the 'Characteristics Type' field; `true` means allocation, `false` deallocation
+    pub fn set_allocation(&mut self, allocation: bool) {
+        set!(self.0, gts_allocation, u8::from(allocation))
+    }
+}
+
+// Size of the command payload; `None` if the command is unknown
+fn payload_size(id: Identifier) -> Option<u8> {
+    Some(match id {
+        Identifier::AssociationRequest => 1,
+        Identifier::AssociationResponse => 3,
+        Identifier::DisassociationNotification => 1,
+        Identifier::DataRequest => 0,
+        Identifier::PanIdConflictNotification => 0,
+        Identifier::OrphanNotification => 0,
+        Identifier::BeaconRequest => 0,
+        Identifier::CoordinatorRealignment => 7,
+        Identifier::GtsRequest => 1,
+        Identifier::Unknown(_) => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AssociationStatus, CapabilityInformation, Command, Error};
+    use crate::ieee802154::{PanId, ShortAddr};
+
+    #[test]
+    fn roundtrip() {
+        fn roundtrip(cmd: Command, bytes: &[u8]) {
+            let mut buf = [0; super::MAX_SIZE as usize];
+            let n = cmd.write(&mut buf);
+
+            assert_eq!(&buf[..usize::from(n)], bytes);
+            assert_eq!(Command::parse(bytes), Ok(cmd));
+        }
+
+        let mut ci = CapabilityInformation::default();
+        ci.set_power_source(true);
+        ci.set_receiver_on_when_idle(true);
+        ci.set_allocate_address(true);
+        roundtrip(Command::AssociationRequest(ci), &[0x01, 0x8c]);
+
+        roundtrip(
+            Command::AssociationResponse {
+                short_addr: ShortAddr(0x1234),
+                status: AssociationStatus::Successful,
+            },
+            &[0x02, 0x34, 0x12, 0x00],
+        );
+
+        roundtrip(Command::DataRequest, &[0x04]);
+        roundtrip(Command::BeaconRequest, &[0x07]);
+        roundtrip(Command::OrphanNotification, &[0x06]);
+
+        roundtrip(
+            Command::CoordinatorRealignment {
+                pan_id: PanId(0xbeef),
+                coord_short_addr: ShortAddr(0x0000),
+                logical_channel: 26,
+                short_addr: ShortAddr(0x1234),
+            },
+            &[0x08, 0xef, 0xbe, 0x00, 0x00, 0x1a, 0x34, 0x12],
+        );
+    }
+
+    #[test]
+    fn invalid() {
+        // empty
+        assert_eq!(Command::parse(&[]), Err(Error::Truncated));
+
+        // unknown identifier
+        assert_eq!(Command::parse(&[0x0a]), Err(Error::UnknownIdentifier(0x0a)));
+
+        // truncated 'Association response'
+        assert_eq!(Command::parse(&[0x02, 0x34, 0x12]), Err(Error::Truncated));
+    }
+}
@@ -0,0 +1,296 @@
+//! Information Elements (IEEE 802.15.4-2015)
+//!
+//! # References
+//!
+//! - IEEE 802.15.4-2015 standard, Section 7.4 Information Elements
+
+use byteorder::{ByteOrder, LE};
+
+// Header IE descriptor (Section 7.4.2.1)
+mod header_length {
+    pub const MASK: u16 = (1 << SIZE) - 1;
+    pub const OFFSET: u16 = 0;
+    pub const SIZE: u16 = 7;
+}
+
+mod element_id {
+    pub const MASK: u16 = (1 << SIZE) - 1;
+    pub const OFFSET: u16 = super::header_length::OFFSET + super::header_length::SIZE;
+    pub const SIZE: u16 = 8;
+}
+
+// Payload IE descriptor (Section 7.4.3.1)
+mod payload_length {
+    pub const MASK: u16 = (1 << SIZE) - 1;
+    pub const OFFSET: u16 = 0;
+    pub const SIZE: u16 = 11;
+}
+
+mod group_id {
+    pub const MASK: u16 = (1 << SIZE) - 1;
+    pub const OFFSET: u16 = super::payload_length::OFFSET + super::payload_length::SIZE;
+    pub const SIZE: u16 = 4;
+}
+
+mod ty {
+    pub const MASK: u16 = (1 << SIZE) - 1;
+    pub const OFFSET: u16 = 15;
+    pub const SIZE: u16 = 1;
+}
+
+/// Size of an IE descriptor
+pub const DESCRIPTOR_SIZE: u8 = 2;
+
+/// Maximum length of the content of a Header IE
+pub const MAX_HEADER_IE_LENGTH: u8 = header_length::MASK as u8;
+
+/// Element ID of the Header Termination 1 IE, which precedes Payload IEs
+pub const HT1: u8 = 0x7e;
+
+/// Element ID of the Header Termination 2 IE, which precedes the payload when there are no
+/// Payload IEs
+pub const HT2: u8 = 0x7f;
+
+/// Group ID of the Payload Termination IE
+pub const PAYLOAD_TERMINATION: u8 = 0xf;
+
+/// Header Information Element
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct HeaderIe<'a> {
+    /// Element ID
+    pub element_id: u8,
+    /// Content of the element
+    pub content: &'a [u8],
+}
+
+/// Payload Information Element
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct PayloadIe<'a> {
+    /// Group ID
+    pub group_id: u8,
+    /// Content of the element; usually a list of nested IEs
+    pub content: &'a [u8],
+}
+
+/// Iterator over Header IEs
+///
+/// Iteration stops at the first termination IE (HT1 or HT2), which is not yielded
+#[derive(Clone)]
+pub struct HeaderIes<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> HeaderIes<'a> {
+    /// Iterates over the Header IEs at the start of `bytes`
+    pub fn new(bytes: &'a [u8]) -> Self {
+        HeaderIes { bytes }
+    }
+}
+
+impl<'a> Iterator for HeaderIes<'a> {
+    type Item = HeaderIe<'a>;
+
+    fn next(&mut self) -> Option<HeaderIe<'a>> {
+        let (ie, rest) = next_header_ie(self.bytes)?;
+
+        if ie.element_id == HT1 || ie.element_id == HT2 {
+            self.bytes = &[];
+            None
+        } else {
+            self.bytes = rest;
+            Some(ie)
+        }
+    }
+}
+
+/// Iterator over Payload IEs
+///
+/// Iteration stops at the first Payload Termination IE, which is not yielded
+#[derive(Clone)]
+pub struct PayloadIes<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> PayloadIes<'a> {
+    /// Iterates over the Payload IEs at the start of `bytes`
+    pub fn new(bytes: &'a [u8]) -> Self {
+        PayloadIes { bytes }
+    }
+}
+
+impl<'a> Iterator for PayloadIes<'a> {
+    type Item = PayloadIe<'a>;
+
+    fn next(&mut self) -> Option<PayloadIe<'a>> {
+        let (ie, rest) = next_payload_ie(self.bytes)?;
+
+        if ie.group_id == PAYLOAD_TERMINATION {
+            self.bytes = &[];
+            None
+        } else {
+            self.bytes = rest;
+            Some(ie)
+        }
+    }
+}
+
+/// How a list of Header IEs ends
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum End {
+    /// No termination IE; the IEs run to the end of the frame
+    Unterminated,
+    /// HT1: Payload IEs follow
+    Ht1,
+    /// HT2: the payload follows
+    Ht2,
+}
+
+// Returns the size of the Header IEs list at the start of `bytes`, including the termination IE
+pub(crate) fn header_ies_size(mut bytes: &[u8]) -> Result<(usize, End), ()> {
+    let mut size = 0;
+
+    while !bytes.is_empty() {
+        let (ie, rest) = next_header_ie(bytes).ok_or(())?;
+        size += usize::from(DESCRIPTOR_SIZE) + ie.content.len();
+        bytes = rest;
+
+        match ie.element_id {
+            HT1 => return Ok((size, End::Ht1)),
+            HT2 => return Ok((size, End::Ht2)),
+            _ => {}
+        }
+    }
+
+    Ok((size, End::Unterminated))
+}
+
+// Returns the size of the Payload IEs list at the start of `bytes`, including the termination IE,
+// and whether the list was terminated
+pub(crate) fn payload_ies_size(mut bytes: &[u8]) -> Result<(usize, bool), ()> {
+    let mut size = 0;
+
+    while !bytes.is_empty() {
+        let (ie, rest) = next_payload_ie(bytes).ok_or(())?;
+        size += usize::from(DESCRIPTOR_SIZE) + ie.content.len();
+        bytes = rest;
+
+        if ie.group_id == PAYLOAD_TERMINATION {
+            return Ok((size, true));
+        }
+    }
+
+    Ok((size, false))
+}
+
+// Writes a Header IE at the start of `buffer` and returns its size
+pub(crate) fn write_header_ie(buffer: &mut [u8], element_id: u8, content: &[u8]) -> usize {
+    assert!(content.len() <= usize::from(MAX_HEADER_IE_LENGTH));
+
+    let desc = (content.len() as u16) << header_length::OFFSET
+        | u16::from(element_id) << self::element_id::OFFSET;
+
+    write(buffer, desc, content)
+}
+
+// Writes a Payload IE at the start of `buffer` and returns its size
+pub(crate) fn write_payload_ie(buffer: &mut [u8], group_id: u8, content: &[u8]) -> usize {
+    assert!(content.len() <= usize::from(payload_length::MASK));
+
+    let desc = (content.len() as u16) << payload_length::OFFSET
+        | (u16::from(group_id) & self::group_id::MASK) << self::group_id::OFFSET
+        | 1 << ty::OFFSET;
+
+    write(buffer, desc, content)
+}
+
+fn write(buffer: &mut [u8], desc: u16, content: &[u8]) -> usize {
+    let size = usize::from(DESCRIPTOR_SIZE) + content.len();
+
+    LE::write_u16(&mut buffer[..2], desc);
+    buffer[2..size].copy_from_slice(content);
+
+    size
+}
+
+fn next_header_ie(bytes: &[u8]) -> Option<(HeaderIe<'_>, &[u8])> {
+    let (desc, content, rest) = next_ie(bytes, 0, header_length::MASK)?;
+
+    Some((
+        HeaderIe {
+            element_id: get!(desc, element_id) as u8,
+            content,
+        },
+        rest,
+    ))
+}
+
+fn next_payload_ie(bytes: &[u8]) -> Option<(PayloadIe<'_>, &[u8])> {
+    let (desc, content, rest) = next_ie(bytes, 1, payload_length::MASK)?;
+
+    Some((
+        PayloadIe {
+            group_id: get!(desc, group_id) as u8,
+            content,
+        },
+        rest,
+    ))
+}
+
+// Splits `bytes` into (descriptor, content, rest)
+fn next_ie(bytes: &[u8], ty: u16, length_mask: u16) -> Option<(u16, &[u8], &[u8])> {
+    if bytes.len() < usize::from(DESCRIPTOR_SIZE) {
+        return None;
+    }
+
+    let desc = LE::read_u16(&bytes[..2]);
+    if get!(desc, ty) != ty {
+        return None;
+    }
+
+    let end = usize::from(DESCRIPTOR_SIZE) + usize::from(desc & length_mask);
+    if bytes.len() < end {
+        return None;
+    }
+
+    Some((desc, &bytes[2..end], &bytes[end..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{HeaderIe, HeaderIes, PayloadIe, PayloadIes};
+
+    #[test]
+    fn iter() {
+        let bytes = [
+            0x02, 0x0d, 0xaa, 0xbb, // Header IE 0x1a
+            0x00, 0x3f, // HT1
+            0x01, 0x88, 0xcc, // Payload IE (group 1)
+            0x00, 0xf8, // Payload Termination IE
+        ];
+
+        let mut ies = HeaderIes::new(&bytes);
+        assert_eq!(
+            ies.next(),
+            Some(HeaderIe {
+                element_id: 0x1a,
+                content: &[0xaa, 0xbb],
+            })
+        );
+        assert_eq!(ies.next(), None);
+        assert_eq!(super::header_ies_size(&bytes), Ok((6, super::End::Ht1)));
+
+        let mut ies = PayloadIes::new(&bytes[6..]);
+        assert_eq!(
+            ies.next(),
+            Some(PayloadIe {
+                group_id: 1,
+                content: &[0xcc],
+            })
+        );
+        assert_eq!(ies.next(), None);
+        assert_eq!(super::payload_ies_size(&bytes[6..]), Ok((5, true)));
+
+        // truncated content
+        assert!(super::header_ies_size(&bytes[..3]).is_err());
+    }
+}
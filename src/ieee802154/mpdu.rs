@@ -0,0 +1,343 @@
+//! MAC frames that carry their footer (Frame Check Sequence)
+//!
+//! Radios usually compute and check the FCS in hardware and strip it from the frames they hand
+//! over to software. This module is for the cases where that's not true: software radios, packet
+//! captures, test fixtures, etc.
+//!
+//! # References
+//!
+//! - IEEE 802.15.4-2003 standard, Section 7.2.1.9 FCS field
+
+use core::{fmt, marker::PhantomData};
+
+use as_slice::{AsMutSlice, AsSlice};
+use byteorder::{ByteOrder, LE};
+use owning_slice::Truncate;
+
+use super::Frame;
+use crate::{fmt::Hex, Invalid, Valid};
+
+/// Size of the MAC footer
+pub const FCS_SIZE: u8 = 2;
+
+/// MAC frame followed by its Frame Check Sequence
+// NOTE(invariant) `buffer` holds a frame that `Frame::parse` accepts followed by `FCS_SIZE` bytes,
+// and it's at most 255 bytes long
+pub struct Mpdu<BUFFER, FCS>
+where
+    BUFFER: AsSlice<Element = u8>,
+{
+    buffer: BUFFER,
+    // index at which the payload of the frame starts
+    payload: u8,
+    _fcs: PhantomData<FCS>,
+}
+
+impl<B> Mpdu<B, Valid>
+where
+    B: AsSlice<Element = u8>,
+{
+    /* Constructors */
+    /// Parses bytes into a MAC frame followed by a valid FCS
+    pub fn parse(bytes: B) -> Result<Self, B> {
+        Mpdu::parse_unverified(bytes)?
+            .verify_fcs()
+            .map_err(|mpdu| mpdu.buffer)
+    }
+}
+
+impl<B> Mpdu<B, Valid>
+where
+    B: AsSlice<Element = u8> + Truncate<u8>,
+{
+    /* Miscellaneous */
+    /// Strips the footer and returns the MAC frame
+    pub fn into_frame(self) -> Frame<B> {
+        let len = self.frame_len();
+        let mut buffer = self.buffer;
+        buffer.truncate(cast::u8(len).unwrap_or_else(|_| unsafe { debug_unreachable!() }));
+
+        Frame {
+            buffer,
+            payload: self.payload,
+        }
+    }
+}
+
+impl<B, F> Mpdu<B, F>
+where
+    B: AsSlice<Element = u8>,
+{
+    /* Getters */
+    /// Reads the 'FCS' field
+    pub fn get_fcs(&self) -> u16 {
+        LE::read_u16(&self.as_slice()[self.frame_len()..])
+    }
+
+    /// Returns a view into the MAC frame
+    pub fn frame(&self) -> Frame<&[u8]> {
+        Frame {
+            buffer: &self.as_slice()[..self.frame_len()],
+            payload: self.payload,
+        }
+    }
+
+    /// Returns the byte representation of the frame and its footer
+    pub fn as_bytes(&self) -> &[u8] {
+        self.as_slice()
+    }
+
+    /* Miscellaneous */
+    /// Frees the underlying buffer
+    pub fn free(self) -> B {
+        self.buffer
+    }
+
+    /* Private */
+    fn as_slice(&self) -> &[u8] {
+        self.buffer.as_slice()
+    }
+
+    fn frame_len(&self) -> usize {
+        self.as_slice().len() - usize::from(FCS_SIZE)
+    }
+}
+
+impl<B> Mpdu<B, Invalid>
+where
+    B: AsSlice<Element = u8>,
+{
+    /* Constructors */
+    /// Parses bytes into a MAC frame followed by a FCS that has not been verified
+    ///
+    /// Unlike `Mpdu::parse` this accepts frames whose FCS doesn't match (e.g. to inspect or count
+    /// frames corrupted in transit). Use `verify_fcs` to check the footer
+    pub fn parse_unverified(bytes: B) -> Result<Self, B> {
+        let payload = {
+            let slice = bytes.as_slice();
+
+            if slice.len() < usize::from(FCS_SIZE) || cast::u8(slice.len()).is_err() {
+                None
+            } else {
+                Frame::parse(&slice[..slice.len() - usize::from(FCS_SIZE)])
+                    .ok()
+                    .map(|frame| frame.payload)
+            }
+        };
+
+        if let Some(payload) = payload {
+            Ok(Mpdu {
+                buffer: bytes,
+                payload,
+                _fcs: PhantomData,
+            })
+        } else {
+            Err(bytes)
+        }
+    }
+
+    /* Miscellaneous */
+    /// Checks that the FCS field matches the contents of the frame
+    pub fn verify_fcs(self) -> Result<Mpdu<B, Valid>, Self> {
+        let slice = self.as_slice();
+        let (frame, footer) = slice.split_at(self.frame_len());
+
+        if fcs(frame) == LE::read_u16(footer) {
+            Ok(Mpdu {
+                buffer: self.buffer,
+                payload: self.payload,
+                _fcs: PhantomData,
+            })
+        } else {
+            Err(self)
+        }
+    }
+}
+
+impl<B> Mpdu<B, Invalid>
+where
+    B: AsMutSlice<Element = u8> + Truncate<u8>,
+{
+    /* Constructors */
+    /// Builds a MAC frame using the closure `f` and reserves space for its footer
+    ///
+    /// The closure is handed the whole buffer minus `FCS_SIZE` bytes and must return a frame that
+    /// starts at the beginning of that slice; the FCS field is left unpopulated until `update_fcs`
+    /// is called.
+    ///
+    /// # Panics
+    ///
+    /// This constructor panics if the given `buffer` is smaller than `FCS_SIZE`, if the returned
+    /// frame doesn't start at the beginning of the slice handed to `f`, or if the frame plus its
+    /// footer is larger than 255 bytes
+    pub fn new<F>(mut buffer: B, f: F) -> Self
+    where
+        F: for<'a> FnOnce(&'a mut [u8]) -> Frame<&'a mut [u8]>,
+    {
+        let blen = buffer.as_slice().len();
+        assert!(blen >= usize::from(FCS_SIZE));
+
+        let bytes = &mut buffer.as_mut_slice()[..blen - usize::from(FCS_SIZE)];
+        let start = bytes.as_ptr();
+        let max = bytes.len();
+        let frame = f(bytes);
+        let frame = frame.as_bytes();
+        // NOTE the closure could return a frame backed by some other (`'static`) buffer
+        assert!(
+            frame.as_ptr() == start && frame.len() <= max,
+            "the frame must be built in the slice handed to the closure"
+        );
+        let len = frame.len();
+
+        let total = len + usize::from(FCS_SIZE);
+        assert!(
+            total <= usize::from(u8::MAX),
+            "the frame and its footer don't fit in 255 bytes"
+        );
+        buffer.truncate(cast::u8(total).unwrap_or_else(|_| unsafe { debug_unreachable!() }));
+
+        // NOTE the contents may have been modified through `Frame::payload_mut` and the like, so
+        // check that they still form a valid frame
+        let payload = Frame::parse(&buffer.as_slice()[..len])
+            .map(|frame| frame.payload)
+            .unwrap_or_else(|_| panic!("the closure returned a malformed frame"));
+
+        Mpdu {
+            buffer,
+            payload,
+            _fcs: PhantomData,
+        }
+    }
+
+    /* Miscellaneous */
+    /// Computes and writes the FCS field
+    pub fn update_fcs(mut self) -> Mpdu<B, Valid> {
+        let start = self.frame_len();
+        let fcs = fcs(&self.as_slice()[..start]);
+        LE::write_u16(&mut self.buffer.as_mut_slice()[start..], fcs);
+
+        Mpdu {
+            buffer: self.buffer,
+            payload: self.payload,
+            _fcs: PhantomData,
+        }
+    }
+}
+
+impl<B, F> fmt::Debug for Mpdu<B, F>
+where
+    B: AsSlice<Element = u8>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ieee802154::Mpdu")
+            .field("frame", &self.frame())
+            .field("fcs", &Hex(self.get_fcs()))
+            .finish()
+    }
+}
+
+/// Computes the Frame Check Sequence of the given MAC header and payload
+///
+/// This is the 16-bit ITU-T CRC: generator polynomial `x^16 + x^12 + x^5 + 1`, initial value of
+/// zero, with bits processed least significant first.
+pub fn fcs(bytes: &[u8]) -> u16 {
+    const POLY: u16 = 0x8408; // 0x1021 reflected
+
+    let mut crc = 0;
+    for byte in bytes {
+        crc ^= u16::from(*byte);
+
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ POLY
+            } else {
+                crc >> 1
+            };
+        }
+    }
+
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Mpdu, FCS_SIZE};
+    use crate::ieee802154::{Addr, Frame, PanId, ShortAddr, SrcDest, Type};
+
+    #[test]
+    fn fcs() {
+        // CRC-16/KERMIT check value
+        assert_eq!(super::fcs(b"123456789"), 0x2189);
+
+        // Acknowledgment frame
+        assert_eq!(super::fcs(&[0x02, 0x00, 0x2a]), 0x3be0);
+    }
+
+    #[test]
+    fn roundtrip() {
+        const PAN_ID: PanId = PanId(0xbeef);
+        const SRC: ShortAddr = ShortAddr(0x0001);
+        const DEST: ShortAddr = ShortAddr(0x0002);
+
+        let mut array = [0; 128];
+        let mpdu = Mpdu::new(&mut array[..], |buffer| {
+            let mut frame = Frame::data(
+                buffer,
+                SrcDest::IntraPan {
+                    pan_id: PAN_ID,
+                    src_addr: SRC.into(),
+                    dest_addr: DEST.into(),
+                },
+            );
+            frame.set_sequence_number(7);
+            frame.set_payload(&[0xde, 0xad]);
+            frame
+        })
+        .update_fcs();
+
+        let len = mpdu.as_bytes().len();
+        assert_eq!(len, 9 + 2 + usize::from(FCS_SIZE));
+        assert_eq!(mpdu.get_fcs(), super::fcs(&mpdu.as_bytes()[..len - 2]));
+
+        let bytes = mpdu.as_bytes();
+        let mpdu = Mpdu::parse(bytes).unwrap();
+        assert_eq!(mpdu.frame().get_type(), Type::Data);
+        assert_eq!(mpdu.frame().get_sequence_number(), Some(7));
+        assert_eq!(mpdu.frame().get_src_addr(), Some(Addr::Short(SRC)));
+
+        let frame = mpdu.into_frame();
+        assert_eq!(frame.get_dest_addr(), Some(Addr::Short(DEST)));
+        assert_eq!(frame.payload(), &[0xde, 0xad]);
+
+        // corrupted frame
+        let mut corrupted = [0; 13];
+        corrupted.copy_from_slice(bytes);
+        corrupted[10] ^= 1;
+        assert!(Mpdu::parse(&corrupted[..]).is_err());
+
+        // ... can still be inspected
+        let mpdu = Mpdu::parse_unverified(&corrupted[..]).unwrap();
+        assert_eq!(mpdu.frame().payload(), &[0xde, 0xac]);
+        let mpdu = mpdu.verify_fcs().unwrap_err();
+        assert!(mpdu.get_fcs() != super::fcs(&corrupted[..len - 2]));
+
+        // missing footer
+        assert!(Mpdu::parse(&bytes[..3]).is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "the frame must be built in the slice handed to the closure")]
+    fn new_offset() {
+        let mut array = [0; 128];
+        // the frame doesn't start at the beginning of the buffer
+        Mpdu::new(&mut array[..], |buffer| {
+            Frame::data(
+                &mut buffer[1..],
+                SrcDest::PanCoordToNode {
+                    pan_id: PanId(0xbeef),
+                    dest_addr: ShortAddr(0x0002).into(),
+                },
+            )
+        });
+    }
+}
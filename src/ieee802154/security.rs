@@ -0,0 +1,567 @@
+//! MAC sublayer security: auxiliary security header and CCM* frame protection
+//!
+//! # References
+//!
+//! - IEEE 802.15.4-2006 standard, Section 7.5.8 Frame security and Section 7.6.2 Auxiliary
+//!   security header
+//!
+//! - [RFC 3610: Counter with CBC-MAC (CCM)][0]
+//!
+//! [0]: https://tools.ietf.org/html/rfc3610
+
+use core::{fmt, ops::Range};
+
+use as_slice::{AsMutSlice, AsSlice};
+use byteorder::{ByteOrder, NetworkEndian as NE, LE};
+use cast::usize;
+
+use super::ExtendedAddr;
+use crate::traits::UncheckedIndex;
+
+pub mod aes;
+
+/* Auxiliary security header format (Section 7.6.2) */
+const SECURITY_CONTROL: usize = 0;
+mod security_level {
+    pub const MASK: u8 = (1 << SIZE) - 1;
+    pub const OFFSET: u8 = 0;
+    pub const SIZE: u8 = 3;
+}
+
+mod key_id_mode {
+    pub const MASK: u8 = (1 << SIZE) - 1;
+    pub const OFFSET: u8 = super::security_level::OFFSET + super::security_level::SIZE;
+    pub const SIZE: u8 = 2;
+}
+
+const FRAME_COUNTER: Range<usize> = 1..5;
+const KEY_IDENTIFIER: usize = 5;
+
+/// Size of the CCM* nonce
+pub const NONCE_SIZE: usize = 13;
+
+/// Block size of the cipher used by CCM*
+pub const BLOCK_SIZE: usize = 16;
+
+/// A CCM* nonce
+pub type Nonce = [u8; NONCE_SIZE];
+
+/// Frame unsecuring error
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Error {
+    /// Security is not enabled in the frame
+    NotSecured,
+
+    /// The frame is an IEEE 802.15.4-2003 frame; the security scheme of that revision is not
+    /// supported
+    UnsupportedVersion,
+
+    /// The payload is shorter than the Message Integrity Code (MIC), or too short to hold the
+    /// fields that a beacon or MAC command frame sends in the clear
+    Truncated,
+
+    /// The MIC doesn't match the frame contents; the frame was tampered with or was secured with
+    /// a different key or originator
+    InvalidMic,
+}
+
+/// Auxiliary security header
+pub struct AuxHeader<BUFFER>
+where
+    BUFFER: AsSlice<Element = u8>,
+{
+    buffer: BUFFER,
+}
+
+impl<B> AuxHeader<B>
+where
+    B: AsSlice<Element = u8>,
+{
+    /* Constructors */
+    /// Parses the bytes as an auxiliary security header
+    ///
+    /// Any byte that follows the header is ignored
+    pub fn parse(bytes: B) -> Result<Self, B> {
+        if let Some(len) = size(bytes.as_slice()) {
+            if bytes.as_slice().len() >= usize::from(len) {
+                return Ok(AuxHeader { buffer: bytes });
+            }
+        }
+
+        Err(bytes)
+    }
+
+    /* Getters */
+    /// Reads the 'Security Level' field
+    pub fn get_security_level(&self) -> SecurityLevel {
+        SecurityLevel::from(get!(self.security_control(), security_level))
+    }
+
+    /// Reads the 'Frame Counter' field
+    pub fn get_frame_counter(&self) -> u32 {
+        LE::read_u32(unsafe { self.as_slice().r(FRAME_COUNTER) })
+    }
+
+    /// Reads the 'Key Identifier Mode' and 'Key Identifier' fields
+    pub fn get_key_identifier(&self) -> KeyIdentifier {
+        let bytes = unsafe { self.as_slice().rf(KEY_IDENTIFIER..) };
+
+        match get!(self.security_control(), key_id_mode) {
+            0 => KeyIdentifier::Implicit,
+            1 => KeyIdentifier::Index(bytes[0]),
+            2 => {
+                let mut source = [0; 4];
+                source.copy_from_slice(&bytes[..4]);
+                KeyIdentifier::Source4(source, bytes[4])
+            }
+            _ => {
+                let mut source = [0; 8];
+                source.copy_from_slice(&bytes[..8]);
+                KeyIdentifier::Source8(source, bytes[8])
+            }
+        }
+    }
+
+    /// Returns the size of the header
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> u8 {
+        size(self.as_slice()).unwrap_or_else(|| unsafe { debug_unreachable!() })
+    }
+
+    /// Returns the byte representation of this header
+    pub fn as_bytes(&self) -> &[u8] {
+        let len = usize::from(self.len());
+        unsafe { self.as_slice().rt(..len) }
+    }
+
+    /* Private */
+    fn as_slice(&self) -> &[u8] {
+        self.buffer.as_slice()
+    }
+
+    fn security_control(&self) -> u8 {
+        unsafe { *self.as_slice().gu(SECURITY_CONTROL) }
+    }
+}
+
+impl<B> AuxHeader<B>
+where
+    B: AsMutSlice<Element = u8>,
+{
+    /* Constructors */
+    /// Writes a new auxiliary security header into the start of the given buffer
+    ///
+    /// # Panics
+    ///
+    /// This constructor panics if `buffer` is too small to contain the header
+    pub fn new(
+        mut buffer: B,
+        level: SecurityLevel,
+        key_id: KeyIdentifier,
+        frame_counter: u32,
+    ) -> Self {
+        let slice = buffer.as_mut_slice();
+        assert!(slice.len() >= usize(KEY_IDENTIFIER as u8 + key_id.size()));
+
+        slice[SECURITY_CONTROL] =
+            (u8::from(level) << security_level::OFFSET) | (key_id.mode() << key_id_mode::OFFSET);
+        LE::write_u32(&mut slice[FRAME_COUNTER], frame_counter);
+
+        let key = &mut slice[KEY_IDENTIFIER..];
+        match key_id {
+            KeyIdentifier::Implicit => {}
+            KeyIdentifier::Index(index) => key[0] = index,
+            KeyIdentifier::Source4(source, index) => {
+                key[..4].copy_from_slice(&source);
+                key[4] = index;
+            }
+            KeyIdentifier::Source8(source, index) => {
+                key[..8].copy_from_slice(&source);
+                key[8] = index;
+            }
+        }
+
+        AuxHeader { buffer }
+    }
+
+    /* Setters */
+    /// Sets the 'Frame Counter' field
+    pub fn set_frame_counter(&mut self, frame_counter: u32) {
+        LE::write_u32(
+            unsafe { self.buffer.as_mut_slice().rm(FRAME_COUNTER) },
+            frame_counter,
+        );
+    }
+}
+
+impl<B> fmt::Debug for AuxHeader<B>
+where
+    B: AsSlice<Element = u8>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("security::AuxHeader")
+            .field("security_level", &self.get_security_level())
+            .field("frame_counter", &self.get_frame_counter())
+            .field("key_identifier", &self.get_key_identifier())
+            .finish()
+    }
+}
+
+/// Security level (Table 95)
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SecurityLevel {
+    /// No security
+    None = 0b000,
+    /// 32-bit MIC, no encryption
+    Mic32 = 0b001,
+    /// 64-bit MIC, no encryption
+    Mic64 = 0b010,
+    /// 128-bit MIC, no encryption
+    Mic128 = 0b011,
+    /// Encryption, no MIC
+    Enc = 0b100,
+    /// Encryption and 32-bit MIC
+    EncMic32 = 0b101,
+    /// Encryption and 64-bit MIC
+    EncMic64 = 0b110,
+    /// Encryption and 128-bit MIC
+    EncMic128 = 0b111,
+}
+
+impl SecurityLevel {
+    /// Size of the Message Integrity Code (MIC) in bytes
+    pub fn mic_size(self) -> u8 {
+        match u8::from(self) & 0b11 {
+            0b00 => 0,
+            0b01 => 4,
+            0b10 => 8,
+            _ => 16,
+        }
+    }
+
+    /// Whether the frame payload is encrypted at this security level
+    pub fn is_encrypted(self) -> bool {
+        u8::from(self) & 0b100 != 0
+    }
+}
+
+impl From<u8> for SecurityLevel {
+    fn from(bits: u8) -> Self {
+        match bits & 0b111 {
+            0b000 => SecurityLevel::None,
+            0b001 => SecurityLevel::Mic32,
+            0b010 => SecurityLevel::Mic64,
+            0b011 => SecurityLevel::Mic128,
+            0b100 => SecurityLevel::Enc,
+            0b101 => SecurityLevel::EncMic32,
+            0b110 => SecurityLevel::EncMic64,
+            _ => SecurityLevel::EncMic128,
+        }
+    }
+}
+
+impl From<SecurityLevel> for u8 {
+    fn from(level: SecurityLevel) -> u8 {
+        level as u8
+    }
+}
+
+/// Key identifier (Section 7.6.2.3)
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum KeyIdentifier {
+    /// Key is determined implicitly from the originator and recipient(s) of the frame
+    Implicit,
+    /// Key is determined from the 'Key Index' field and the default key source
+    Index(u8),
+    /// Key is determined from a 4-byte 'Key Source' field and the 'Key Index' field
+    Source4([u8; 4], u8),
+    /// Key is determined from an 8-byte 'Key Source' field and the 'Key Index' field
+    Source8([u8; 8], u8),
+}
+
+impl KeyIdentifier {
+    fn mode(&self) -> u8 {
+        match *self {
+            KeyIdentifier::Implicit => 0b00,
+            KeyIdentifier::Index(..) => 0b01,
+            KeyIdentifier::Source4(..) => 0b10,
+            KeyIdentifier::Source8(..) => 0b11,
+        }
+    }
+
+    fn size(&self) -> u8 {
+        key_id_size(self.mode())
+    }
+}
+
+/// A block cipher with a 128-bit block size, like AES-128
+///
+/// CCM* only uses the forward (encryption) direction of the cipher
+pub trait BlockCipher {
+    /// Encrypts `block` in place
+    fn encrypt_block(&self, block: &mut [u8; BLOCK_SIZE]);
+}
+
+/// Builds the CCM* nonce of a frame (Section 7.6.3.2)
+pub fn nonce(src_addr: ExtendedAddr, frame_counter: u32, level: SecurityLevel) -> Nonce {
+    let mut nonce = [0; NONCE_SIZE];
+
+    nonce[..8].copy_from_slice(&src_addr.ne_bytes());
+    NE::write_u32(&mut nonce[8..12], frame_counter);
+    nonce[12] = level.into();
+
+    nonce
+}
+
+/// Encrypts `m` in place and computes the MIC over `a` and `m` using CCM*
+///
+/// The size of the MIC is given by the length of `mic`: one of 0, 4, 6, 8, 10, 12, 14 or 16. With
+/// a MIC size of zero `a` is ignored and `m` is only encrypted.
+///
+/// # Panics
+///
+/// This function panics if `mic` has an invalid length, or if `a` or `m` are longer than 65279
+/// bytes
+pub fn encrypt<C>(cipher: &C, nonce: &Nonce, a: &[u8], m: &mut [u8], mic: &mut [u8])
+where
+    C: BlockCipher,
+{
+    if !mic.is_empty() {
+        let t = cbc_mac(cipher, nonce, a, m, mic.len());
+        mic.copy_from_slice(&t[..mic.len()]);
+    }
+
+    ctr(cipher, nonce, m, mic);
+}
+
+/// Decrypts `m` in place and verifies the MIC over `a` and `m` using CCM*
+///
+/// On MIC mismatch this function returns `Error::InvalidMic` and leaves `m` as it was (encrypted)
+///
+/// # Panics
+///
+/// This function panics if `mic` has an invalid length, or if `a` or `m` are longer than 65279
+/// bytes
+pub fn decrypt<C>(
+    cipher: &C,
+    nonce: &Nonce,
+    a: &[u8],
+    m: &mut [u8],
+    mic: &[u8],
+) -> Result<(), Error>
+where
+    C: BlockCipher,
+{
+    let mut u = [0; BLOCK_SIZE];
+    let u = &mut u[..mic.len()];
+    u.copy_from_slice(mic);
+
+    ctr(cipher, nonce, m, u);
+
+    if !mic.is_empty() {
+        let t = cbc_mac(cipher, nonce, a, m, mic.len());
+
+        // NOTE compare the whole MIC to not leak the position of the first mismatch
+        let diff = t.iter().zip(u.iter()).fold(0, |acc, (x, y)| acc | (x ^ y));
+        if diff != 0 {
+            // restore the ciphertext
+            ctr(cipher, nonce, m, &mut []);
+            return Err(Error::InvalidMic);
+        }
+    }
+
+    Ok(())
+}
+
+/* Private */
+// Size of the 'Key Identifier' field given the 'Key Identifier Mode'
+fn key_id_size(mode: u8) -> u8 {
+    match mode & 0b11 {
+        0b00 => 0,
+        0b01 => 1,
+        0b10 => 5,
+        _ => 9,
+    }
+}
+
+// Size of the auxiliary security header that starts at `bytes`
+fn size(bytes: &[u8]) -> Option<u8> {
+    let sc = *bytes.get(SECURITY_CONTROL)?;
+
+    Some(KEY_IDENTIFIER as u8 + key_id_size(get!(sc, key_id_mode)))
+}
+
+// Length of the 'Length' field of CCM*; 802.15.4 fixes it to 2
+const L: u8 = 2;
+
+// Computes the authentication tag `T` (RFC 3610 section 2.2)
+fn cbc_mac<C>(cipher: &C, nonce: &Nonce, a: &[u8], m: &[u8], mic_size: usize) -> [u8; BLOCK_SIZE]
+where
+    C: BlockCipher,
+{
+    assert!([4, 6, 8, 10, 12, 14, 16].contains(&mic_size));
+    assert!(a.len() < 0xff00 && m.len() < 0xff00);
+
+    let mut mac = CbcMac {
+        cipher,
+        x: [0; BLOCK_SIZE],
+        pos: 0,
+    };
+
+    // B_0
+    let adata = if a.is_empty() { 0 } else { 1 << 6 };
+    let mut b0 = [0; BLOCK_SIZE];
+    b0[0] = adata | (((mic_size as u8 - 2) / 2) << 3) | (L - 1);
+    b0[1..1 + NONCE_SIZE].copy_from_slice(nonce);
+    NE::write_u16(&mut b0[1 + NONCE_SIZE..], m.len() as u16);
+    mac.update(&b0);
+
+    if !a.is_empty() {
+        let mut la = [0; 2];
+        NE::write_u16(&mut la, a.len() as u16);
+        mac.update(&la);
+        mac.update(a);
+        mac.pad();
+    }
+
+    mac.update(m);
+    mac.pad();
+
+    mac.x
+}
+
+// Counter mode encryption (RFC 3610 section 2.3); `m` is XOR-ed with the key stream blocks S_1,
+// S_2, ..., and `u` with S_0
+fn ctr<C>(cipher: &C, nonce: &Nonce, m: &mut [u8], u: &mut [u8])
+where
+    C: BlockCipher,
+{
+    let mut a = [0; BLOCK_SIZE];
+    a[0] = L - 1;
+    a[1..1 + NONCE_SIZE].copy_from_slice(nonce);
+
+    let mut s = a;
+    cipher.encrypt_block(&mut s);
+    for (byte, s) in u.iter_mut().zip(s.iter()) {
+        *byte ^= s;
+    }
+
+    for (i, chunk) in m.chunks_mut(BLOCK_SIZE).enumerate() {
+        NE::write_u16(&mut a[1 + NONCE_SIZE..], i as u16 + 1);
+
+        let mut s = a;
+        cipher.encrypt_block(&mut s);
+        for (byte, s) in chunk.iter_mut().zip(s.iter()) {
+            *byte ^= s;
+        }
+    }
+}
+
+struct CbcMac<'c, C>
+where
+    C: BlockCipher,
+{
+    cipher: &'c C,
+    x: [u8; BLOCK_SIZE],
+    pos: usize,
+}
+
+impl<C> CbcMac<'_, C>
+where
+    C: BlockCipher,
+{
+    fn update(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.x[self.pos] ^= byte;
+            self.pos += 1;
+
+            if self.pos == BLOCK_SIZE {
+                self.cipher.encrypt_block(&mut self.x);
+                self.pos = 0;
+            }
+        }
+    }
+
+    // zero pads the current block
+    fn pad(&mut self) {
+        if self.pos != 0 {
+            self.cipher.encrypt_block(&mut self.x);
+            self.pos = 0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{aes::Aes128, AuxHeader, KeyIdentifier, SecurityLevel};
+
+    #[test]
+    fn aux_header() {
+        let mut buf = [0xff; 16];
+        let h = AuxHeader::new(
+            &mut buf[..],
+            SecurityLevel::EncMic32,
+            KeyIdentifier::Source4([1, 2, 3, 4], 5),
+            0x0102_0304,
+        );
+
+        assert_eq!(
+            h.as_bytes(),
+            &[0x15, 0x04, 0x03, 0x02, 0x01, 0x01, 0x02, 0x03, 0x04, 0x05]
+        );
+
+        let h = AuxHeader::parse(&buf[..]).unwrap();
+        assert_eq!(h.len(), 10);
+        assert_eq!(h.get_security_level(), SecurityLevel::EncMic32);
+        assert_eq!(h.get_frame_counter(), 0x0102_0304);
+        assert_eq!(
+            h.get_key_identifier(),
+            KeyIdentifier::Source4([1, 2, 3, 4], 5)
+        );
+
+        // truncated 'Key Identifier' field
+        assert!(AuxHeader::parse(&buf[..9]).is_err());
+    }
+
+    // RFC 3610, Packet Vector #1
+    #[test]
+    fn ccm() {
+        let cipher = Aes128::new(&[
+            0xc0, 0xc1, 0xc2, 0xc3, 0xc4, 0xc5, 0xc6, 0xc7, 0xc8, 0xc9, 0xca, 0xcb, 0xcc, 0xcd,
+            0xce, 0xcf,
+        ]);
+        let nonce = [
+            0x00, 0x00, 0x00, 0x03, 0x02, 0x01, 0x00, 0xa0, 0xa1, 0xa2, 0xa3, 0xa4, 0xa5,
+        ];
+        let a = [0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07];
+        let mut m = [0; 23];
+        for (i, byte) in m.iter_mut().enumerate() {
+            *byte = 8 + i as u8;
+        }
+        let plaintext = m;
+        let mut mic = [0; 8];
+
+        super::encrypt(&cipher, &nonce, &a, &mut m, &mut mic);
+
+        assert_eq!(
+            &m[..],
+            &[
+                0x58, 0x8c, 0x97, 0x9a, 0x61, 0xc6, 0x63, 0xd2, 0xf0, 0x66, 0xd0, 0xc2, 0xc0, 0xf9,
+                0x89, 0x80, 0x6d, 0x5f, 0x6b, 0x61, 0xda, 0xc3, 0x84,
+            ][..]
+        );
+        assert_eq!(mic, [0x17, 0xe8, 0xd1, 0x2c, 0xfd, 0xf9, 0x26, 0xe0]);
+
+        let ciphertext = m;
+        assert!(super::decrypt(&cipher, &nonce, &a, &mut m, &mic).is_ok());
+        assert_eq!(m, plaintext);
+
+        // tampered associated data
+        let mut m = ciphertext;
+        assert_eq!(
+            super::decrypt(&cipher, &nonce, &a[1..], &mut m, &mic),
+            Err(super::Error::InvalidMic)
+        );
+        assert_eq!(m, ciphertext);
+    }
+}
@@ -18,11 +18,14 @@ use owning_slice::{IntoSliceFrom, Truncate};
 
 use crate::{
     fmt::Hex,
-    icmp,
+    icmp, tcp,
     traits::{UncheckedIndex, UxxExt},
     udp, Invalid, Valid,
 };
 
+pub mod frag;
+pub mod link_local;
+
 /* Packet structure */
 const VERSION_IHL: usize = 0;
 mod ihl {
@@ -388,16 +391,65 @@ where
         self.truncate(len);
     }
 
+    /// Fills the payload with a Destination Unreachable ICMP message that quotes the offending
+    /// `datagram`
+    ///
+    /// The quote is truncated if the payload can't hold all of it
+    pub fn destination_unreachable(&mut self, code: icmp::UnreachableCode, datagram: &[u8]) {
+        self.set_protocol(Protocol::Icmp);
+        let len = icmp::Message::destination_unreachable(self.payload_mut(), code, datagram)
+            .update_checksum()
+            .len();
+        self.truncate(len);
+    }
+
+    /// Fills the payload with a Time Exceeded ICMP message that quotes the offending `datagram`
+    ///
+    /// The quote is truncated if the payload can't hold all of it
+    pub fn time_exceeded(&mut self, code: icmp::TimeExceededCode, datagram: &[u8]) {
+        self.set_protocol(Protocol::Icmp);
+        let len = icmp::Message::time_exceeded(self.payload_mut(), code, datagram)
+            .update_checksum()
+            .len();
+        self.truncate(len);
+    }
+
     /// Fills the payload with an UDP packet
+    ///
+    /// NOTE the Source and Destination fields of this packet must be set *before* calling this
+    /// method as they are used to compute the checksum of the UDP packet
     pub fn udp<F>(&mut self, f: F)
     where
-        F: FnOnce(&mut udp::Packet<&mut [u8]>),
+        F: FnOnce(&mut udp::Packet<&mut [u8], Invalid>),
     {
+        let src = self.get_source();
+        let dest = self.get_destination();
+
         self.set_protocol(Protocol::Udp);
         let len = {
             let mut udp = udp::Packet::new(self.payload_mut());
             f(&mut udp);
-            udp.len()
+            udp.update_ipv4_checksum(src, dest).len()
+        };
+        self.truncate(len);
+    }
+
+    /// Fills the payload with a TCP segment
+    ///
+    /// NOTE the Source and Destination fields of this packet must be set *before* calling this
+    /// method as they are used to compute the checksum of the TCP segment
+    pub fn tcp<F>(&mut self, f: F)
+    where
+        F: FnOnce(&mut tcp::Segment<&mut [u8], Invalid>),
+    {
+        let src = self.get_source();
+        let dest = self.get_destination();
+
+        self.set_protocol(Protocol::Tcp);
+        let len = {
+            let mut tcp = tcp::Segment::new(self.payload_mut());
+            f(&mut tcp);
+            tcp.update_ipv4_checksum(src, dest).len()
         };
         self.truncate(len);
     }
@@ -641,6 +693,9 @@ impl Addr {
 
     /// Unspecified address
     pub const UNSPECIFIED: Self = Addr([0; 4]);
+
+    /// Limited broadcast address
+    pub const BROADCAST: Self = Addr([255; 4]);
 }
 
 impl fmt::Debug for Addr {
@@ -1154,6 +1209,20 @@ pub(crate) fn compute_checksum(header: &[u8], cksum_pos: usize) -> u16 {
     !sum.low()
 }
 
+/// Computes the (unfolded) sum of the IPv4 pseudo-header used by the UDP and TCP checksums
+pub(crate) fn pseudo_header_sum(src: Addr, dest: Addr, protocol: Protocol, len: u16) -> u32 {
+    let mut sum = 0u32;
+
+    for chunk in src.0.chunks_exact(2).chain(dest.0.chunks_exact(2)) {
+        sum += u32(NE::read_u16(chunk));
+    }
+
+    sum += u32(u8::from(protocol));
+    sum += u32(len);
+
+    sum
+}
+
 /// Verifies the IPv4 checksum of the header
 pub(crate) fn verify_checksum(header: &[u8]) -> bool {
     debug_assert!(header.len() % 2 == 0);
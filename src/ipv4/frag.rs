@@ -0,0 +1,602 @@
+//! IPv4 fragmentation and reassembly
+//!
+//! # References
+//!
+//! - [RFC 791: Internet protocol][0], section 3.2 "Fragmentation and Reassembly"
+//!
+//! [0]: https://tools.ietf.org/html/rfc791
+//!
+//! - [RFC 1858: Security Considerations for IP Fragment Filtering][1]
+//!
+//! [1]: https://tools.ietf.org/html/rfc1858
+
+use core::marker::PhantomData;
+
+use as_slice::AsSlice;
+use cast::usize;
+use heapless::{ArrayLength, Vec};
+
+pub use crate::frag::Error;
+use crate::{
+    frag::Assembly,
+    ipv4::{Addr, Packet, MIN_HEADER_SIZE},
+    time::{Duration, Instant},
+    Invalid, Valid,
+};
+
+/// Maximum size of the IPv4 header (IHL = 15)
+const MAX_HEADER_SIZE: usize = 60;
+
+/// Minimum MTU every IPv4 link must support (see RFC 791)
+pub const MIN_MTU: u16 = 68;
+
+#[derive(Clone, Copy, Eq, PartialEq)]
+struct Key {
+    source: Addr,
+    destination: Addr,
+    protocol: u8,
+    identification: u16,
+}
+
+impl Key {
+    fn of<B, C>(packet: &Packet<B, C>) -> Self
+    where
+        B: AsSlice<Element = u8>,
+    {
+        Key {
+            source: packet.get_source(),
+            destination: packet.get_destination(),
+            protocol: packet.get_protocol().into(),
+            identification: packet.get_identification(),
+        }
+    }
+}
+
+/// A reassembly slot; see `Reassembler`
+///
+/// A slot holds the partially reassembled payload of a single datagram.
+pub struct Slot<M>
+where
+    M: ArrayLength<u8>,
+{
+    key: Key,
+    start: Instant,
+    // length of the header of the first fragment; `0` if it hasn't been received yet
+    header_len: u8,
+    // the header of the first fragment is stored right before the payload, which always starts at
+    // `MAX_HEADER_SIZE`
+    assembly: Assembly<M>,
+    // the reassembled datagram has been handed to the caller
+    done: bool,
+}
+
+/// Fixed capacity reassembly buffer
+///
+/// `N` is the maximum number of datagrams that can be reassembled concurrently and `M` is the size
+/// of the buffer used to reassemble each datagram. `M` includes space for the largest IPv4 header
+/// (60 bytes) so the largest datagram payload that can be reassembled is `M - 60` bytes.
+///
+/// Fragments that belong to the same datagram are identified by the (Source, Destination,
+/// Protocol, Identification) tuple. Fragments that overlap with previously received data cause the
+/// whole datagram to be discarded; this defends against the "teardrop" and the "overlapping
+/// fragment" attacks.
+///
+/// Each datagram must be completely reassembled within `timeout` of the arrival of its first
+/// fragment. The caller drives time by passing the current `Instant` to `insert` and `expire`.
+pub struct Reassembler<N, M>
+where
+    N: ArrayLength<Slot<M>>,
+    M: ArrayLength<u8>,
+{
+    slots: Vec<Slot<M>, N>,
+    timeout: Duration,
+}
+
+impl<N, M> Reassembler<N, M>
+where
+    N: ArrayLength<Slot<M>>,
+    M: ArrayLength<u8>,
+{
+    /// Creates a new reassembly buffer
+    ///
+    /// RFC 791 recommends an initial `timeout` of 15 seconds
+    pub fn new(timeout: Duration) -> Self {
+        Reassembler {
+            slots: Vec::new(),
+            timeout,
+        }
+    }
+
+    /// Feeds a fragment into the reassembly buffer
+    ///
+    /// Returns the reassembled datagram when `fragment` is the last missing piece; otherwise
+    /// returns `Ok(None)`.
+    ///
+    /// NOTE expired datagrams are discarded by this method so calling `expire` is only required
+    /// when no fragments are being received
+    pub fn insert<B>(
+        &mut self,
+        fragment: &Packet<B, Valid>,
+        now: Instant,
+    ) -> Result<Option<Packet<&mut [u8], Valid>>, Error>
+    where
+        B: AsSlice<Element = u8>,
+    {
+        let mf = fragment.get_mf();
+        let offset = usize(fragment.get_fragment_offset()) * 8;
+        let payload = fragment.payload();
+
+        if !mf && offset == 0 {
+            return Err(Error::NotAFragment);
+        }
+
+        // all fragments but the last must carry a multiple of 8 bytes
+        if mf && (payload.is_empty() || payload.len() & 0b111 != 0) {
+            return Err(Error::Malformed);
+        }
+
+        if offset + payload.len() + MAX_HEADER_SIZE > usize(u16::MAX) {
+            return Err(Error::Malformed);
+        }
+        let offset = offset as u16;
+
+        self.expire(now);
+
+        let key = Key::of(fragment);
+        let pos = if let Some(pos) = self.slots.iter().position(|slot| slot.key == key) {
+            pos
+        } else {
+            self.slots
+                .push(Slot {
+                    key,
+                    start: now,
+                    header_len: 0,
+                    assembly: Assembly::new(),
+                    done: false,
+                })
+                .map_err(|_| Error::Exhausted)?;
+
+            self.slots.len() - 1
+        };
+
+        match Self::update(&mut self.slots[pos], fragment.header(), payload, offset, mf) {
+            Err(e) => {
+                self.slots.swap_remove(pos);
+                Err(e)
+            }
+
+            Ok(false) => Ok(None),
+
+            Ok(true) => {
+                let slot = &mut self.slots[pos];
+                slot.done = true;
+
+                let header_len = usize(slot.header_len);
+                let payload_len = slot.assembly.data_len().unwrap_or(0);
+                let total_len = header_len as u16 + payload_len;
+
+                let start = MAX_HEADER_SIZE - header_len;
+                let end = MAX_HEADER_SIZE + usize(payload_len);
+                let mut packet: Packet<_, Invalid> = Packet {
+                    buffer: &mut slot.assembly.buffer_mut()[start..end],
+                    _checksum: PhantomData,
+                };
+
+                unsafe { packet.set_total_length(total_len) }
+                packet.set_mf(false);
+                packet.set_fragment_offset(0);
+
+                Ok(Some(packet.update_checksum()))
+            }
+        }
+    }
+
+    /// Discards the datagrams that have not been reassembled within the timeout
+    pub fn expire(&mut self, now: Instant) {
+        let timeout = self.timeout;
+
+        let mut i = 0;
+        while i < self.slots.len() {
+            let slot = &self.slots[i];
+
+            if slot.done || now.duration_since(slot.start) >= timeout {
+                self.slots.swap_remove(i);
+            } else {
+                i += 1;
+            }
+        }
+    }
+
+    /// Returns the number of datagrams that are currently being reassembled
+    pub fn len(&self) -> usize {
+        self.slots.iter().filter(|slot| !slot.done).count()
+    }
+
+    /// Returns `true` if no datagram is being reassembled
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Returns `true` if the datagram has been completely reassembled
+    fn update(
+        slot: &mut Slot<M>,
+        header: &[u8],
+        payload: &[u8],
+        offset: u16,
+        mf: bool,
+    ) -> Result<bool, Error> {
+        let limit = M::to_usize();
+        slot.assembly
+            .insert(MAX_HEADER_SIZE, limit, offset, payload, !mf)?;
+
+        if offset == 0 {
+            let header_len = header.len();
+            slot.assembly.buffer_mut()[MAX_HEADER_SIZE - header_len..MAX_HEADER_SIZE]
+                .copy_from_slice(header);
+            slot.header_len = header_len as u8;
+        }
+
+        Ok(slot.header_len != 0 && slot.assembly.is_complete())
+    }
+}
+
+/// Splits a datagram into fragments that fit in the given MTU
+///
+/// The fragments are written into caller provided buffers using the `next_fragment` method.
+pub struct Fragmenter<'a> {
+    header: &'a [u8],
+    payload: &'a [u8],
+    // fragment offset and MF flag of the original packet; the packet may already be a fragment
+    base_offset: u16,
+    mf: bool,
+    // payload bytes that have been sent so far
+    sent: u16,
+    mtu: u16,
+    done: bool,
+}
+
+impl<'a> Fragmenter<'a> {
+    /// Prepares `packet` for fragmentation
+    ///
+    /// Returns `Error::DontFragment` if `packet` doesn't fit in the MTU and its DF (Don't
+    /// Fragment) flag is set. The sender is expected to send an ICMP "Fragmentation Needed"
+    /// message in that case.
+    ///
+    /// # Panics
+    ///
+    /// This constructor panics if `mtu` is smaller than `MIN_MTU`
+    pub fn new<B, C>(packet: &'a Packet<B, C>, mtu: u16) -> Result<Self, Error>
+    where
+        B: AsSlice<Element = u8>,
+    {
+        assert!(mtu >= MIN_MTU);
+
+        if packet.get_df() && usize(packet.get_total_length()) > usize(mtu) {
+            return Err(Error::DontFragment);
+        }
+
+        Ok(Fragmenter {
+            header: packet.header(),
+            payload: packet.payload(),
+            base_offset: packet.get_fragment_offset() * 8,
+            mf: packet.get_mf(),
+            sent: 0,
+            mtu,
+            done: false,
+        })
+    }
+
+    /// Returns `true` if all the fragments have been produced
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
+    /// Writes the next fragment into `buffer`
+    ///
+    /// Returns `None` if all the fragments have already been produced. The size of the fragment is
+    /// limited by both the MTU and the size of `buffer`.
+    ///
+    /// The first fragment carries the whole header of the original packet; the following fragments
+    /// only carry the options that must be copied into every fragment.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if `buffer` can't hold the header plus 8 bytes of payload.
+    pub fn next_fragment<'b>(
+        &mut self,
+        buffer: &'b mut [u8],
+    ) -> Option<Packet<&'b mut [u8], Valid>> {
+        if self.done {
+            return None;
+        }
+
+        let is_first = self.sent == 0;
+
+        let header_len = if is_first {
+            buffer[..self.header.len()].copy_from_slice(self.header);
+            self.header.len()
+        } else {
+            copy_header(self.header, buffer)
+        };
+
+        let size = usize(self.mtu).min(buffer.len());
+        assert!(size >= header_len + 8);
+
+        let remaining = self.payload.len() - usize(self.sent);
+        let max_payload = (size - header_len) & !0b111;
+        let (payload_len, is_last) = if remaining <= max_payload {
+            (remaining, true)
+        } else {
+            (max_payload, false)
+        };
+
+        let start = usize(self.sent);
+        buffer[header_len..header_len + payload_len]
+            .copy_from_slice(&self.payload[start..start + payload_len]);
+
+        let mut packet: Packet<_, Invalid> = Packet {
+            buffer: &mut buffer[..header_len + payload_len],
+            _checksum: PhantomData,
+        };
+
+        unsafe {
+            packet.set_ihl((header_len / 4) as u8);
+            packet.set_total_length((header_len + payload_len) as u16);
+        }
+        packet.set_mf(if is_last { self.mf } else { true });
+        packet.set_fragment_offset((self.base_offset + self.sent) / 8);
+
+        self.sent += payload_len as u16;
+        self.done = is_last;
+
+        Some(packet.update_checksum())
+    }
+}
+
+// Copies the fixed part of the `header` plus the options that must be copied into every fragment
+// into `buffer`. Returns the size of the new header
+fn copy_header(header: &[u8], buffer: &mut [u8]) -> usize {
+    const COPIED: u8 = 1 << 7;
+    const END_OF_OPTION_LIST: u8 = 0;
+    const NO_OPERATION: u8 = 1;
+
+    let fixed = usize(MIN_HEADER_SIZE);
+    buffer[..fixed].copy_from_slice(&header[..fixed]);
+
+    let mut len = fixed;
+    let mut options = &header[fixed..];
+    while let Some(&ty) = options.first() {
+        let opt_len = match ty {
+            END_OF_OPTION_LIST => break,
+            NO_OPERATION => 1,
+            _ => match options.get(1) {
+                Some(&n) if n >= 2 && usize(n) <= options.len() => usize(n),
+                // malformed option
+                _ => break,
+            },
+        };
+
+        if ty & COPIED != 0 {
+            buffer[len..len + opt_len].copy_from_slice(&options[..opt_len]);
+            len += opt_len;
+        }
+
+        options = &options[opt_len..];
+    }
+
+    // pad with End of Option List
+    while len & 0b11 != 0 {
+        buffer[len] = END_OF_OPTION_LIST;
+        len += 1;
+    }
+
+    len
+}
+
+#[cfg(test)]
+mod tests {
+    use heapless::consts;
+
+    use super::{Error, Fragmenter, Reassembler};
+    use crate::{
+        ipv4,
+        time::{Duration, Instant},
+        Valid,
+    };
+
+    const SRC: ipv4::Addr = ipv4::Addr([192, 168, 0, 1]);
+    const DEST: ipv4::Addr = ipv4::Addr([192, 168, 0, 33]);
+
+    fn datagram(buffer: &mut [u8], payload_len: u16) -> ipv4::Packet<&mut [u8], Valid> {
+        let mut ip = ipv4::Packet::new(buffer);
+        ip.set_source(SRC);
+        ip.set_destination(DEST);
+        ip.set_protocol(ipv4::Protocol::Udp);
+        ip.set_identification(0xbeef);
+        ip.set_df(false);
+        for (i, byte) in ip.payload_mut().iter_mut().enumerate() {
+            *byte = i as u8;
+        }
+        ip.truncate(payload_len);
+        ip.update_checksum()
+    }
+
+    #[test]
+    fn roundtrip() {
+        let mut buffer = [0; 1020];
+        let ip = datagram(&mut buffer, 1000);
+
+        let mut fragments = [[0; 300]; 4];
+        let mut lens = [0; 4];
+        {
+            let mut fragmenter = Fragmenter::new(&ip, 300).unwrap();
+            for (i, buf) in fragments.iter_mut().enumerate() {
+                let frag = fragmenter.next_fragment(buf).unwrap();
+                assert_eq!(frag.get_mf(), i != 3);
+                assert_eq!(usize::from(frag.get_fragment_offset()) * 8, i * 280);
+                lens[i] = frag.len();
+            }
+            assert!(fragmenter.is_done());
+            assert!(fragmenter.next_fragment(&mut [0; 300]).is_none());
+        }
+        assert_eq!(lens, [300, 300, 300, 180]);
+
+        let mut reassembler: Reassembler<consts::U2, consts::U2048> =
+            Reassembler::new(Duration::from_secs(15));
+
+        // out of order arrival
+        for &i in &[3, 1, 0] {
+            let frag = ipv4::Packet::parse(&fragments[i][..usize::from(lens[i])]).unwrap();
+            assert!(reassembler
+                .insert(&frag, Instant::from_millis(0))
+                .unwrap()
+                .is_none());
+        }
+
+        let frag = ipv4::Packet::parse(&fragments[2][..usize::from(lens[2])]).unwrap();
+        let reassembled = reassembler
+            .insert(&frag, Instant::from_millis(100))
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(reassembled.as_bytes(), ip.as_bytes());
+
+        // the slot is freed
+        assert!(reassembler.is_empty());
+    }
+
+    #[test]
+    fn overlap() {
+        let mut buffer = [0; 120];
+        let mut ip = datagram(&mut buffer, 100).set_mf(true);
+        ip.truncate(48);
+        let first = ip.update_checksum();
+
+        let mut reassembler: Reassembler<consts::U1, consts::U256> =
+            Reassembler::new(Duration::from_secs(15));
+        assert!(reassembler
+            .insert(&first, Instant::from_millis(0))
+            .unwrap()
+            .is_none());
+
+        // exact duplicates are ignored
+        assert!(reassembler
+            .insert(&first, Instant::from_millis(0))
+            .unwrap()
+            .is_none());
+        assert_eq!(reassembler.len(), 1);
+
+        // teardrop: the last fragment overlaps with the first one
+        let mut buffer = [0; 120];
+        let mut ip = datagram(&mut buffer, 16).set_fragment_offset(4);
+        ip.set_mf(false);
+        let last = ip.update_checksum();
+        assert_eq!(
+            reassembler.insert(&last, Instant::from_millis(0)).err(),
+            Some(Error::Overlap)
+        );
+
+        // the whole datagram was discarded
+        assert!(reassembler.is_empty());
+
+        // a retransmission that disagrees on the MF flag is inconsistent with the original
+        let mut buffer = [0; 120];
+        let mut ip = datagram(&mut buffer, 16).set_fragment_offset(6);
+        ip.set_mf(true);
+        let middle = ip.update_checksum();
+        assert!(reassembler
+            .insert(&middle, Instant::from_millis(0))
+            .unwrap()
+            .is_none());
+
+        let mut buffer = [0; 120];
+        let last = datagram(&mut buffer, 16)
+            .set_fragment_offset(6)
+            .update_checksum();
+        assert_eq!(
+            reassembler.insert(&last, Instant::from_millis(0)).err(),
+            Some(Error::Malformed)
+        );
+        assert!(reassembler.is_empty());
+    }
+
+    #[test]
+    fn timeout() {
+        let mut buffer = [0; 120];
+        let first = datagram(&mut buffer, 48).set_mf(true).update_checksum();
+
+        let mut buffer = [0; 120];
+        let last = datagram(&mut buffer, 16)
+            .set_fragment_offset(6)
+            .update_checksum();
+
+        let mut reassembler: Reassembler<consts::U1, consts::U256> =
+            Reassembler::new(Duration::from_secs(15));
+        assert!(reassembler
+            .insert(&first, Instant::from_millis(0))
+            .unwrap()
+            .is_none());
+
+        reassembler.expire(Instant::from_millis(15_000));
+        assert!(reassembler.is_empty());
+
+        // a lone last fragment is not enough
+        assert!(reassembler
+            .insert(&last, Instant::from_millis(15_001))
+            .unwrap()
+            .is_none());
+
+        // the slot is in use
+        let mut buffer = [0; 120];
+        let mut other = datagram(&mut buffer, 48).set_identification(1);
+        other.set_mf(true);
+        let other = other.update_checksum();
+        assert_eq!(
+            reassembler
+                .insert(&other, Instant::from_millis(15_002))
+                .err(),
+            Some(Error::Exhausted)
+        );
+    }
+
+    #[test]
+    fn fragmenter() {
+        // DF set
+        let mut buffer = [0; 120];
+        let ip = datagram(&mut buffer, 100).set_df(true).update_checksum();
+        assert_eq!(Fragmenter::new(&ip, 68).err(), Some(Error::DontFragment));
+
+        // options: Security (copied) and Record Route (not copied)
+        let mut buffer = [0; 140];
+        let ip = datagram(&mut buffer, 100);
+        let mut bytes = [0; 132];
+        bytes[..20].copy_from_slice(&ip.header()[..20]);
+        bytes[0] = 0x48; // IHL = 8
+        bytes[3] = 132; // Total Length
+        bytes[20..24].copy_from_slice(&[130, 4, 0xab, 0xcd]); // Security
+        bytes[24..27].copy_from_slice(&[7, 3, 4]); // Record Route
+        bytes[27] = 0; // End of Option List
+        bytes[32..].copy_from_slice(ip.payload());
+        let cksum = ipv4::compute_checksum(&bytes[..32], 10);
+        bytes[10] = (cksum >> 8) as u8;
+        bytes[11] = cksum as u8;
+
+        let ip = ipv4::Packet::parse(&bytes[..]).unwrap();
+        let mut fragmenter = Fragmenter::new(&ip, 88).unwrap();
+
+        let mut buffer = [0; 88];
+        let first = fragmenter.next_fragment(&mut buffer).unwrap();
+        assert_eq!(first.get_ihl(), 8);
+        assert_eq!(&first.header()[20..], &bytes[20..32]);
+        assert!(first.get_mf());
+        assert_eq!(first.payload(), &ip.payload()[..56]);
+
+        let mut buffer = [0; 88];
+        let second = fragmenter.next_fragment(&mut buffer).unwrap();
+        assert_eq!(second.get_ihl(), 6);
+        assert_eq!(&second.header()[20..], &[130, 4, 0xab, 0xcd]);
+        assert_eq!(second.payload(), &ip.payload()[56..]);
+        assert!(!second.get_mf());
+        assert!(ipv4::Packet::parse(second.as_bytes()).is_ok());
+    }
+}
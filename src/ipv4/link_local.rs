@@ -0,0 +1,316 @@
+//! Dynamic configuration of IPv4 link-local addresses (169.254/16)
+//!
+//! # References
+//!
+//! - [RFC 3927: Dynamic Configuration of IPv4 Link-Local Addresses][0]
+//!
+//! [0]: https://tools.ietf.org/html/rfc3927
+
+use as_slice::AsSlice;
+use byteorder::{ByteOrder, NetworkEndian as NE};
+
+pub use crate::arp::resolver::{
+    ANNOUNCE_INTERVAL, ANNOUNCE_NUM, ANNOUNCE_WAIT, DEFEND_INTERVAL, PROBE_MIN, PROBE_NUM,
+    PROBE_WAIT,
+};
+use crate::{
+    arp::{
+        self,
+        resolver::{Acd, Message},
+    },
+    ipv4::Addr,
+    mac,
+    time::{Duration, Instant},
+};
+
+/// Maximum time between two probe packets (`PROBE_MAX`)
+pub const PROBE_MAX: Duration = Duration::from_millis(2_000);
+
+/// Number of conflicts after which the rate of address selection is limited (`MAX_CONFLICTS`)
+pub const MAX_CONFLICTS: u8 = 10;
+
+/// Time between two address selections once `MAX_CONFLICTS` has been reached
+/// (`RATE_LIMIT_INTERVAL`)
+pub const RATE_LIMIT_INTERVAL: Duration = Duration::from_millis(60_000);
+
+/// An action the caller must take on behalf of the state machine
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Action {
+    /// Send this ARP message
+    Send(Message),
+
+    /// No other host answered the probes: this address can now be used
+    Claimed(Addr),
+
+    /// Another host is using this address; it must no longer be used. A new address will be
+    /// selected and probed
+    Conflict(Addr),
+}
+
+/// IPv4 link-local address autoconfiguration state machine
+///
+/// Candidate addresses are drawn from a pseudo-random sequence seeded with the MAC address of the
+/// interface so a device selects the same address every time it joins a network, unless that
+/// address is taken.
+///
+/// The state machine doesn't send or receive packets on its own. The caller feeds it the ARP
+/// packets it receives with `handle_packet` and calls `poll` periodically (e.g. every 100 ms) to
+/// learn which ARP messages to send. ARP packets must keep being fed after the address has been
+/// claimed so that it can be defended.
+pub struct LinkLocal {
+    mac: mac::Addr,
+    rng: u32,
+    candidate: Addr,
+    conflicts: u8,
+    // probing, announcing and defending `candidate` is done as in `arp::Resolver`
+    acd: Acd,
+    // the first probe is due when `timer + wait` is reached; `None` once probing has started
+    timer: Instant,
+    wait: Option<Duration>,
+    probe_interval: Duration,
+    // action to return on the next `poll`
+    pending: Option<Action>,
+}
+
+impl LinkLocal {
+    /// Starts the autoconfiguration of the interface with address `mac`
+    pub fn new(mac: mac::Addr, now: Instant) -> Self {
+        // NOTE xorshift can't escape the all-zeros state
+        let seed = (NE::read_u32(&mac.0[2..]) ^ (u32::from(NE::read_u16(&mac.0[..2])) << 16)) | 1;
+
+        let mut ll = LinkLocal {
+            mac,
+            rng: seed,
+            candidate: Addr::UNSPECIFIED,
+            conflicts: 0,
+            acd: Acd::Unconfigured,
+            timer: now,
+            wait: None,
+            probe_interval: PROBE_MIN,
+            pending: None,
+        };
+        ll.select(now);
+        ll
+    }
+
+    /// Returns the claimed address, if any
+    pub fn address(&self) -> Option<Addr> {
+        self.acd.address()
+    }
+
+    /// Returns the address that's currently being probed or used
+    pub fn candidate(&self) -> Addr {
+        self.candidate
+    }
+
+    /// Processes an incoming ARP packet
+    pub fn handle_packet<B>(&mut self, arp: &arp::Packet<B>, now: Instant)
+    where
+        B: AsSlice<Element = u8>,
+    {
+        if arp.get_sha() == self.mac {
+            // our own packet, looped back
+            return;
+        }
+
+        // RFC 3927 - Section 2.2.1 and 2.5
+        let bound = self.address().is_some();
+        if let Some(addr) = self.acd.handle_packet(arp, now) {
+            if bound {
+                self.pending = Some(Action::Conflict(addr));
+            }
+
+            self.select(now);
+        }
+    }
+
+    /// Advances the state machine
+    ///
+    /// If this returns an `Action` the caller must carry it out *now*. Call this method again
+    /// until it returns `None` as several actions may be due at the same time.
+    pub fn poll(&mut self, now: Instant) -> Option<Action> {
+        if let Some(action) = self.pending.take() {
+            return Some(action);
+        }
+
+        if let Some(wait) = self.wait {
+            if now.duration_since(self.timer) < wait {
+                return None;
+            }
+
+            self.wait = None;
+        }
+
+        let bound = self.address().is_some();
+        let message = self.acd.poll(now, self.probe_interval)?;
+
+        if let Message::Probe(..) = message {
+            self.probe_interval = PROBE_MIN + self.random(PROBE_MAX - PROBE_MIN);
+        }
+
+        match self.address() {
+            Some(addr) if !bound => {
+                // report the claim before the first announcement
+                self.pending = Some(Action::Send(message));
+
+                Some(Action::Claimed(addr))
+            }
+
+            _ => Some(Action::Send(message)),
+        }
+    }
+
+    /* Private */
+    // Selects a new candidate address and schedules its first probe
+    fn select(&mut self, now: Instant) {
+        if self.candidate != Addr::UNSPECIFIED {
+            // the previous candidate was in use
+            self.conflicts = self.conflicts.saturating_add(1);
+        }
+
+        // RFC 3927 - Section 2.1: 169.254.1.0 - 169.254.254.255
+        let r = self.next();
+        // NOTE(cast) no truncation: `r % 254` is less than 254
+        self.candidate = Addr([169, 254, 1 + (r % 254) as u8, (r >> 8) as u8]);
+
+        // RFC 3927 - Section 2.2.1
+        let wait = if self.conflicts >= MAX_CONFLICTS {
+            RATE_LIMIT_INTERVAL
+        } else {
+            Duration::from_millis(0)
+        };
+
+        // NOTE conflicts are detected from now on, not only once the first probe has been sent
+        self.acd = Acd::probing(self.candidate, now);
+        self.timer = now;
+        self.wait = Some(wait + self.random(PROBE_WAIT));
+    }
+
+    // Returns a random duration in the range `0..=max`
+    fn random(&mut self, max: Duration) -> Duration {
+        Duration::from_millis(self.next() % (max.as_millis() + 1))
+    }
+
+    // xorshift32
+    fn next(&mut self) -> u32 {
+        let mut x = self.rng;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.rng = x;
+        x
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Action, LinkLocal, MAX_CONFLICTS};
+    use crate::{
+        arp::{self, resolver::Message},
+        ipv4::Addr,
+        mac,
+        time::Instant,
+    };
+
+    const MAC: mac::Addr = mac::Addr([0x20, 0x19, 0x02, 0x01, 0x23, 0x59]);
+    const PEER_MAC: mac::Addr = mac::Addr([0x02, 0, 0, 0, 0, 2]);
+
+    fn at(millis: u32) -> Instant {
+        Instant::from_millis(millis)
+    }
+
+    fn packet(buffer: &mut [u8], message: Message) -> arp::Packet<&mut [u8]> {
+        let mut arp = arp::Packet::new(buffer);
+        arp.set_sha(PEER_MAC);
+        message.write(&mut arp);
+        arp
+    }
+
+    // runs the state machine until the next action and returns it along with the current time
+    fn next(ll: &mut LinkLocal, now: &mut u32) -> Action {
+        loop {
+            if let Some(action) = ll.poll(at(*now)) {
+                return action;
+            }
+
+            *now += 100;
+        }
+    }
+
+    #[test]
+    fn claim() {
+        let mut ll = LinkLocal::new(MAC, at(0));
+        let addr = ll.candidate();
+        assert_eq!(&addr.0[..2], &[169, 254]);
+        assert!(addr.0[2] >= 1 && addr.0[2] <= 254);
+
+        // the first candidate only depends on the MAC address
+        assert_eq!(LinkLocal::new(MAC, at(1_000)).candidate(), addr);
+
+        let mut now = 0;
+        let mut last = 0;
+        for i in 0..3 {
+            assert_eq!(next(&mut ll, &mut now), Action::Send(Message::Probe(addr)));
+            if i == 0 {
+                assert!(now <= 1_000);
+            } else {
+                assert!(now - last >= 1_000 && now - last <= 2_100);
+            }
+            last = now;
+        }
+
+        assert_eq!(next(&mut ll, &mut now), Action::Claimed(addr));
+        assert!(now - last >= 2_000);
+        assert_eq!(ll.address(), Some(addr));
+
+        assert_eq!(
+            next(&mut ll, &mut now),
+            Action::Send(Message::Announce(addr))
+        );
+        last = now;
+        assert_eq!(
+            next(&mut ll, &mut now),
+            Action::Send(Message::Announce(addr))
+        );
+        assert!(now - last >= 2_000);
+
+        // a conflicting announcement is defended against ...
+        let mut buffer = [0; 28];
+        let announcement = packet(&mut buffer, Message::Announce(addr));
+        ll.handle_packet(&announcement, at(now));
+        assert_eq!(
+            ll.poll(at(now)),
+            Some(Action::Send(Message::Announce(addr)))
+        );
+
+        // ... but only once per `DEFEND_INTERVAL`
+        now += 1_000;
+        ll.handle_packet(&announcement, at(now));
+        assert_eq!(ll.poll(at(now)), Some(Action::Conflict(addr)));
+        assert_eq!(ll.address(), None);
+        assert!(ll.candidate() != addr);
+    }
+
+    #[test]
+    fn conflicts() {
+        let mut ll = LinkLocal::new(MAC, at(0));
+        let mut now = 0;
+
+        let mut buffer = [0; 28];
+        for _ in 0..MAX_CONFLICTS {
+            let addr = ll.candidate();
+            assert_eq!(next(&mut ll, &mut now), Action::Send(Message::Probe(addr)));
+
+            // another host is probing the same address
+            let probe = packet(&mut buffer, Message::Probe(addr));
+            ll.handle_packet(&probe, at(now));
+            assert!(ll.candidate() != addr);
+        }
+
+        // address selection is now rate limited
+        let start = now;
+        let addr: Addr = ll.candidate();
+        assert_eq!(next(&mut ll, &mut now), Action::Send(Message::Probe(addr)));
+        assert!(now - start >= 60_000);
+    }
+}
@@ -19,7 +19,12 @@ use hash32_derive::Hash32;
 use owning_slice::Truncate;
 
 pub use crate::ipv4::Protocol as NextHeader;
-use crate::{fmt::Quoted, icmpv6, mac, traits::UncheckedIndex, udp};
+use crate::{fmt::Quoted, icmpv6, mac, tcp, traits::UncheckedIndex, udp, Invalid};
+
+pub mod ext;
+pub mod frag;
+pub mod neighbor;
+pub mod slaac;
 
 /* Packet structure */
 const V: usize = 0;
@@ -76,8 +81,8 @@ where
             return Err(());
         }
 
-        if p.get_next_header().is_ipv6_extension_header() {
-            // currently unsupported
+        if ext::Headers::validate(p.get_next_header(), p.payload()).is_err() {
+            // malformed extension header chain
             return Err(());
         }
 
@@ -132,17 +137,48 @@ where
     }
 
     /// Immutable view into the payload
+    ///
+    /// NOTE the payload includes the extension headers, if any. Use `upper_layer_payload` to skip
+    /// them
     pub fn payload(&self) -> &[u8] {
-        // NOTE we reject packets that contain extension headers in `parse`
         unsafe { self.as_slice().rf(PAYLOAD) }
     }
 
+    /// Returns an iterator over the extension headers of this packet
+    pub fn extension_headers(&self) -> ext::Headers<'_> {
+        // NOTE the extension header chain was validated in `parse`
+        unsafe { ext::Headers::new(self.get_next_header(), self.payload()) }
+    }
+
+    /// Returns the protocol of the upper-layer header, i.e. the Next Header value that follows the
+    /// last extension header
+    ///
+    /// NOTE if the packet is a non-first fragment this is the protocol of the fragmented datagram
+    /// but the upper-layer header is not present in this packet
+    pub fn upper_layer_protocol(&self) -> NextHeader {
+        self.upper_layer().0
+    }
+
+    /// Immutable view into the upper-layer payload, i.e. the bytes that follow the last extension
+    /// header
+    ///
+    /// NOTE if the packet is a fragment (see `extension_headers`) this is the fragment data
+    pub fn upper_layer_payload(&self) -> &[u8] {
+        self.upper_layer().1
+    }
+
     /// Returns the byte representation of this packet
     pub fn as_bytes(&self) -> &[u8] {
         self.as_slice()
     }
 
     /* Private */
+    fn upper_layer(&self) -> (NextHeader, &[u8]) {
+        let mut headers = self.extension_headers();
+        while headers.next().is_some() {}
+        headers.rest()
+    }
+
     fn header(&self) -> &[u8; HEADER_SIZE as usize] {
         debug_assert!(self.as_slice().len() >= usize(HEADER_SIZE));
 
@@ -229,12 +265,9 @@ where
 
     /// Sets the 'Next Header' field
     ///
-    /// # Panics
-    ///
-    /// This function panics if `nh` is an extension header (currently not supported)
+    /// **NOTE**: If `nh` is an extension header the caller must fill the start of the payload with
+    /// a well formed extension header chain
     pub fn set_next_header(&mut self, nh: NextHeader) {
-        assert!(!nh.is_ipv6_extension_header());
-
         self.header_mut()[NEXT_HEADER] = nh.into();
     }
 
@@ -315,8 +348,82 @@ where
         self.truncate(len);
     }
 
+    /// Fills the payload with a Neighbor Solicitation ICMPv6 message that carries the given
+    /// `options`
+    ///
+    /// NOTE the Source and Destination fields of this packet must be set *before* calling this
+    /// method as they are used to compute the checksum of the ICMPv6 message
+    pub fn neighbor_solicitation(&mut self, target: Addr, options: &[icmpv6::NdOption<'_>]) {
+        self.icmpv6(|buffer| icmpv6::Message::neighbor_solicitation(buffer, target, options))
+    }
+
+    /// Fills the payload with a Router Solicitation ICMPv6 message that carries the given
+    /// `options`
+    ///
+    /// NOTE the Source and Destination fields of this packet must be set *before* calling this
+    /// method as they are used to compute the checksum of the ICMPv6 message
+    pub fn router_solicitation(&mut self, options: &[icmpv6::NdOption<'_>]) {
+        self.icmpv6(|buffer| icmpv6::Message::router_solicitation(buffer, options))
+    }
+
+    /// Fills the payload with a Router Advertisement ICMPv6 message that carries the given
+    /// `options`
+    ///
+    /// NOTE the Source and Destination fields of this packet must be set *before* calling this
+    /// method as they are used to compute the checksum of the ICMPv6 message
+    pub fn router_advertisement(
+        &mut self,
+        options: &[icmpv6::NdOption<'_>],
+        f: impl FnOnce(&mut icmpv6::Message<&mut [u8], icmpv6::RouterAdvertisement>),
+    ) {
+        self.icmpv6(|buffer| {
+            let mut message = icmpv6::Message::router_advertisement(buffer, options);
+            f(&mut message);
+            message
+        })
+    }
+
+    /// Fills the payload with a Destination Unreachable ICMPv6 message that quotes the `invoking`
+    /// packet
+    ///
+    /// NOTE the Source and Destination fields of this packet must be set *before* calling this
+    /// method as they are used to compute the checksum of the ICMPv6 message
+    pub fn destination_unreachable(&mut self, code: icmpv6::UnreachableCode, invoking: &[u8]) {
+        self.icmpv6(|buffer| icmpv6::Message::destination_unreachable(buffer, code, invoking))
+    }
+
+    /// Fills the payload with a Packet Too Big ICMPv6 message that quotes the `invoking` packet
+    ///
+    /// NOTE the Source and Destination fields of this packet must be set *before* calling this
+    /// method as they are used to compute the checksum of the ICMPv6 message
+    pub fn packet_too_big(&mut self, mtu: u32, invoking: &[u8]) {
+        self.icmpv6(|buffer| icmpv6::Message::packet_too_big(buffer, mtu, invoking))
+    }
+
+    /// Fills the payload with a Time Exceeded ICMPv6 message that quotes the `invoking` packet
+    ///
+    /// NOTE the Source and Destination fields of this packet must be set *before* calling this
+    /// method as they are used to compute the checksum of the ICMPv6 message
+    pub fn time_exceeded(&mut self, code: icmpv6::TimeExceededCode, invoking: &[u8]) {
+        self.icmpv6(|buffer| icmpv6::Message::time_exceeded(buffer, code, invoking))
+    }
+
+    /// Fills the payload with a Parameter Problem ICMPv6 message that quotes the `invoking`
+    /// packet
+    ///
+    /// NOTE the Source and Destination fields of this packet must be set *before* calling this
+    /// method as they are used to compute the checksum of the ICMPv6 message
+    pub fn parameter_problem(
+        &mut self,
+        code: icmpv6::ParameterProblemCode,
+        pointer: u32,
+        invoking: &[u8],
+    ) {
+        self.icmpv6(|buffer| icmpv6::Message::parameter_problem(buffer, code, pointer, invoking))
+    }
+
     /// Fills the payload with a UDP packet
-    pub fn udp(&mut self, f: impl FnOnce(&mut udp::Packet<&mut [u8]>)) {
+    pub fn udp(&mut self, f: impl FnOnce(&mut udp::Packet<&mut [u8], Invalid>)) {
         let src = self.get_source();
         let dest = self.get_destination();
 
@@ -326,9 +433,25 @@ where
 
         f(&mut packet);
 
-        packet.update_ipv6_checksum(src, dest);
+        let len = packet.update_ipv6_checksum(src, dest).len();
+        self.truncate(len);
+    }
+
+    /// Fills the payload with a TCP segment
+    ///
+    /// NOTE the Source and Destination fields of this packet must be set *before* calling this
+    /// method as they are used to compute the checksum of the TCP segment
+    pub fn tcp(&mut self, f: impl FnOnce(&mut tcp::Segment<&mut [u8], Invalid>)) {
+        let src = self.get_source();
+        let dest = self.get_destination();
+
+        self.set_next_header(NextHeader::Tcp);
+
+        let mut segment = tcp::Segment::new(self.payload_mut());
 
-        let len = packet.as_bytes().len() as u16;
+        f(&mut segment);
+
+        let len = segment.update_ipv6_checksum(src, dest).len();
         self.truncate(len);
     }
 
@@ -339,6 +462,24 @@ where
             self.buffer.truncate(len + u16(HEADER_SIZE));
         }
     }
+
+    /* Private */
+    fn icmpv6<T>(
+        &mut self,
+        f: impl for<'a> FnOnce(&'a mut [u8]) -> icmpv6::Message<&'a mut [u8], T>,
+    ) {
+        let src = self.get_source();
+        let dest = self.get_destination();
+
+        self.set_next_header(NextHeader::Ipv6Icmp);
+
+        let mut message = f(self.payload_mut());
+        message.update_checksum(src, dest);
+
+        // NOTE(cast) the message never exceeds the minimum IPv6 MTU
+        let len = message.as_bytes().len() as u16;
+        self.truncate(len);
+    }
 }
 
 impl<B> fmt::Debug for Packet<B>
@@ -437,6 +578,21 @@ impl fmt::Display for Addr {
     }
 }
 
+/// Computes the (unfolded) sum of the IPv6 pseudo-header used by upper-layer checksums
+pub(crate) fn pseudo_header_sum(src: Addr, dest: Addr, next_header: NextHeader, len: u32) -> u32 {
+    let mut sum = 0u32;
+
+    for chunk in src.0.chunks_exact(2).chain(dest.0.chunks_exact(2)) {
+        sum += u32::from(NE::read_u16(chunk));
+    }
+
+    sum += len >> 16;
+    sum += len & 0xffff;
+    sum += u32::from(u8::from(next_header));
+
+    sum
+}
+
 #[cfg(test)]
 mod tests {
     use crate::ipv6;
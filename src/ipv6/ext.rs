@@ -0,0 +1,542 @@
+//! IPv6 extension headers
+//!
+//! # References
+//!
+//! - [RFC 8200: Internet Protocol, Version 6 (IPv6) Specification][0], section 4
+//!
+//! [0]: https://tools.ietf.org/html/rfc8200
+//!
+//! - [RFC 2711: IPv6 Router Alert Option][1]
+//!
+//! [1]: https://tools.ietf.org/html/rfc2711
+
+use core::{fmt, option::Option as CoreOption};
+
+use byteorder::{ByteOrder, NetworkEndian as NE};
+use cast::usize;
+
+use crate::{ipv6::NextHeader, traits::UncheckedIndex};
+
+/* Common structure */
+const NEXT_HEADER: usize = 0;
+const HDR_EXT_LEN: usize = 1;
+
+/* Routing header */
+const ROUTING_TYPE: usize = 2;
+const SEGMENTS_LEFT: usize = 3;
+
+/* Fragment header */
+const FRAGMENT_OFFSET_M: core::ops::Range<usize> = 2..4;
+mod m {
+    pub const MASK: u16 = (1 << SIZE) - 1;
+    pub const OFFSET: usize = 0;
+    pub const SIZE: usize = 1;
+}
+mod fragment_offset {
+    pub const MASK: u16 = (1 << SIZE) - 1;
+    pub const OFFSET: usize = 3;
+    pub const SIZE: usize = 13;
+}
+const IDENTIFICATION: core::ops::Range<usize> = 4..8;
+
+/// Size of the Fragment header
+pub const FRAGMENT_HEADER_SIZE: u8 = 8;
+
+/// An IPv6 extension header
+#[derive(Clone, Copy, Debug)]
+pub enum Header<'a> {
+    /// Hop-by-Hop Options header
+    HopByHop(OptionsHeader<'a>),
+    /// Routing header
+    Routing(Routing<'a>),
+    /// Fragment header
+    Fragment(Fragment<'a>),
+    /// Destination Options header
+    DestinationOptions(OptionsHeader<'a>),
+}
+
+impl<'a> Header<'a> {
+    /// Returns the Next Header field of this extension header
+    pub fn get_next_header(&self) -> NextHeader {
+        let bytes = match self {
+            Header::HopByHop(h) | Header::DestinationOptions(h) => h.bytes,
+            Header::Routing(h) => h.bytes,
+            Header::Fragment(h) => h.bytes,
+        };
+
+        unsafe { (*bytes.gu(NEXT_HEADER)).into() }
+    }
+
+    /// Returns the byte representation of this extension header
+    pub fn as_bytes(&self) -> &'a [u8] {
+        match self {
+            Header::HopByHop(h) | Header::DestinationOptions(h) => h.bytes,
+            Header::Routing(h) => h.bytes,
+            Header::Fragment(h) => h.bytes,
+        }
+    }
+}
+
+/// Hop-by-Hop Options or Destination Options header
+#[derive(Clone, Copy)]
+pub struct OptionsHeader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> OptionsHeader<'a> {
+    /// Returns the Next Header field
+    pub fn get_next_header(&self) -> NextHeader {
+        unsafe { (*self.bytes.gu(NEXT_HEADER)).into() }
+    }
+
+    /// Returns the Hdr Ext Len field; the length of the header in 8-octet units, not including
+    /// the first 8 octets
+    pub fn get_hdr_ext_len(&self) -> u8 {
+        unsafe { *self.bytes.gu(HDR_EXT_LEN) }
+    }
+
+    /// Returns an iterator over the options contained in this header
+    pub fn options(&self) -> Options<'a> {
+        Options {
+            opts: unsafe { self.bytes.rf(2..) },
+        }
+    }
+}
+
+impl<'a> fmt::Debug for OptionsHeader<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        struct Options<'a>(super::ext::Options<'a>);
+
+        impl<'a> fmt::Debug for Options<'a> {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.debug_list().entries(self.0.clone()).finish()
+            }
+        }
+
+        f.debug_struct("ipv6::ext::OptionsHeader")
+            .field("next_header", &self.get_next_header())
+            .field("hdr_ext_len", &self.get_hdr_ext_len())
+            .field("options", &Options(self.options()))
+            .finish()
+    }
+}
+
+/// Routing header
+#[derive(Clone, Copy)]
+pub struct Routing<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Routing<'a> {
+    /// Returns the Next Header field
+    pub fn get_next_header(&self) -> NextHeader {
+        unsafe { (*self.bytes.gu(NEXT_HEADER)).into() }
+    }
+
+    /// Returns the Hdr Ext Len field; the length of the header in 8-octet units, not including
+    /// the first 8 octets
+    pub fn get_hdr_ext_len(&self) -> u8 {
+        unsafe { *self.bytes.gu(HDR_EXT_LEN) }
+    }
+
+    /// Returns the Routing Type field
+    pub fn get_routing_type(&self) -> u8 {
+        unsafe { *self.bytes.gu(ROUTING_TYPE) }
+    }
+
+    /// Returns the Segments Left field
+    pub fn get_segments_left(&self) -> u8 {
+        unsafe { *self.bytes.gu(SEGMENTS_LEFT) }
+    }
+
+    /// Returns the type-specific data
+    pub fn data(&self) -> &'a [u8] {
+        unsafe { self.bytes.rf(SEGMENTS_LEFT + 1..) }
+    }
+}
+
+impl<'a> fmt::Debug for Routing<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ipv6::ext::Routing")
+            .field("next_header", &self.get_next_header())
+            .field("hdr_ext_len", &self.get_hdr_ext_len())
+            .field("routing_type", &self.get_routing_type())
+            .field("segments_left", &self.get_segments_left())
+            // .field("data", &self.data())
+            .finish()
+    }
+}
+
+/// Fragment header
+#[derive(Clone, Copy)]
+pub struct Fragment<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Fragment<'a> {
+    /// Returns the Next Header field
+    pub fn get_next_header(&self) -> NextHeader {
+        unsafe { (*self.bytes.gu(NEXT_HEADER)).into() }
+    }
+
+    /// Returns the Fragment Offset field, in 8-octet units
+    pub fn get_fragment_offset(&self) -> u16 {
+        get!(
+            NE::read_u16(unsafe { self.bytes.r(FRAGMENT_OFFSET_M) }),
+            fragment_offset
+        )
+    }
+
+    /// Returns the M (More Fragments) flag
+    pub fn get_m(&self) -> bool {
+        get!(NE::read_u16(unsafe { self.bytes.r(FRAGMENT_OFFSET_M) }), m) == 1
+    }
+
+    /// Returns the Identification field
+    pub fn get_identification(&self) -> u32 {
+        NE::read_u32(unsafe { self.bytes.r(IDENTIFICATION) })
+    }
+
+    /// Is this an atomic fragment? (see RFC 6946)
+    ///
+    /// An atomic fragment is a Fragment header that contains the whole datagram; it must be
+    /// processed as a non-fragmented packet
+    pub fn is_atomic(&self) -> bool {
+        self.get_fragment_offset() == 0 && !self.get_m()
+    }
+}
+
+impl<'a> fmt::Debug for Fragment<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ipv6::ext::Fragment")
+            .field("next_header", &self.get_next_header())
+            .field("fragment_offset", &self.get_fragment_offset())
+            .field("m", &self.get_m())
+            .field("identification", &self.get_identification())
+            .finish()
+    }
+}
+
+/// Iterator over the extension headers of an IPv6 packet
+///
+/// The iteration stops at the first header that's not a Hop-by-Hop Options, Routing, Fragment or
+/// Destination Options header; this is the upper-layer header. The iteration also stops after a
+/// Fragment header of a non-first fragment as the bytes that follow are not headers.
+#[derive(Clone)]
+pub struct Headers<'a> {
+    next_header: NextHeader,
+    bytes: &'a [u8],
+    // stop after the Fragment header of a non-first fragment
+    done: bool,
+}
+
+impl<'a> Headers<'a> {
+    // NOTE: Caller must ensure that `validate` succeeds before using this as an iterator
+    pub(crate) unsafe fn new(next_header: NextHeader, bytes: &'a [u8]) -> Self {
+        Headers {
+            next_header,
+            bytes,
+            done: false,
+        }
+    }
+
+    /// Returns the Next Header value that follows the headers yielded so far and the bytes that
+    /// follow them
+    ///
+    /// Once the iterator is exhausted these are the upper-layer protocol and its payload (or the
+    /// fragment data, if the packet is a non-first fragment)
+    pub fn rest(&self) -> (NextHeader, &'a [u8]) {
+        (self.next_header, self.bytes)
+    }
+
+    // Checks that the extension header chain is well formed
+    pub(crate) fn validate(mut next_header: NextHeader, bytes: &[u8]) -> Result<(), ()> {
+        let mut offset = 0;
+        let mut is_first = true;
+
+        loop {
+            let rest = &bytes[offset..];
+            let len = match next_header {
+                NextHeader::Hopopt | NextHeader::Ipv6Route | NextHeader::Ipv6Opts => {
+                    if next_header == NextHeader::Hopopt && !is_first {
+                        // the Hop-by-Hop Options header must immediately follow the IPv6 header
+                        return Err(());
+                    }
+
+                    if rest.len() < 8 {
+                        return Err(());
+                    }
+
+                    let len = (usize(rest[HDR_EXT_LEN]) + 1) * 8;
+                    if len > rest.len() {
+                        return Err(());
+                    }
+
+                    if next_header != NextHeader::Ipv6Route && !Options::are_valid(&rest[2..len]) {
+                        return Err(());
+                    }
+
+                    len
+                }
+
+                NextHeader::Ipv6Frag => {
+                    if rest.len() < usize(FRAGMENT_HEADER_SIZE) {
+                        return Err(());
+                    }
+
+                    let frag = Fragment { bytes: rest };
+                    if frag.get_fragment_offset() != 0 {
+                        // what follows is fragment data
+                        return Ok(());
+                    }
+
+                    usize(FRAGMENT_HEADER_SIZE)
+                }
+
+                _ => return Ok(()),
+            };
+
+            next_header = rest[NEXT_HEADER].into();
+            offset += len;
+            is_first = false;
+        }
+    }
+}
+
+impl<'a> Iterator for Headers<'a> {
+    type Item = Header<'a>;
+
+    fn next(&mut self) -> CoreOption<Header<'a>> {
+        if self.done {
+            return None;
+        }
+
+        unsafe {
+            let (header, len) = match self.next_header {
+                NextHeader::Hopopt | NextHeader::Ipv6Opts => {
+                    let len = (usize(*self.bytes.gu(HDR_EXT_LEN)) + 1) * 8;
+                    let header = OptionsHeader {
+                        bytes: self.bytes.rt(..len),
+                    };
+
+                    if self.next_header == NextHeader::Hopopt {
+                        (Header::HopByHop(header), len)
+                    } else {
+                        (Header::DestinationOptions(header), len)
+                    }
+                }
+
+                NextHeader::Ipv6Route => {
+                    let len = (usize(*self.bytes.gu(HDR_EXT_LEN)) + 1) * 8;
+                    let header = Routing {
+                        bytes: self.bytes.rt(..len),
+                    };
+
+                    (Header::Routing(header), len)
+                }
+
+                NextHeader::Ipv6Frag => {
+                    let len = usize(FRAGMENT_HEADER_SIZE);
+                    let header = Fragment {
+                        bytes: self.bytes.rt(..len),
+                    };
+
+                    self.done = header.get_fragment_offset() != 0;
+
+                    (Header::Fragment(header), len)
+                }
+
+                _ => return None,
+            };
+
+            self.next_header = header.get_next_header();
+            self.bytes = self.bytes.rf(len..);
+
+            Some(header)
+        }
+    }
+}
+
+/// A Hop-by-Hop or Destination option
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Option<'a> {
+    /// Pad1 option
+    Pad1,
+    /// PadN option; contains the number of padding bytes, not including the Type and Length bytes
+    PadN(u8),
+    /// Router Alert option (see RFC 2711)
+    RouterAlert(RouterAlert),
+    /// Unknown option
+    Unknown {
+        /// Option type
+        ty: u8,
+        /// Option data
+        data: &'a [u8],
+    },
+}
+
+const PAD1: u8 = 0;
+const PADN: u8 = 1;
+const ROUTER_ALERT: u8 = 5;
+
+full_range!(
+    u16,
+    /// Router Alert value
+    #[derive(Clone, Copy, Debug, Eq, PartialEq)]
+    pub enum RouterAlert {
+        /// Datagram contains a Multicast Listener Discovery message
+        Mld = 0,
+        /// Datagram contains RSVP message
+        Rsvp = 1,
+        /// Datagram contains an Active Networks message
+        ActiveNetworks = 2,
+    }
+);
+
+/// Iterator over the options of a Hop-by-Hop or Destination Options header
+#[derive(Clone)]
+pub struct Options<'a> {
+    opts: &'a [u8],
+}
+
+impl<'a> Options<'a> {
+    fn are_valid(mut opts: &[u8]) -> bool {
+        while let Some(&ty) = opts.first() {
+            if ty == PAD1 {
+                opts = &opts[1..];
+                continue;
+            }
+
+            if opts.len() < 2 {
+                // not big enough to contain the Type and Length
+                return false;
+            }
+
+            let len = 2 + usize(opts[1]);
+            if len > opts.len() {
+                return false;
+            }
+
+            opts = &opts[len..];
+        }
+
+        true
+    }
+}
+
+impl<'a> Iterator for Options<'a> {
+    type Item = Option<'a>;
+
+    fn next(&mut self) -> CoreOption<Option<'a>> {
+        unsafe {
+            let ty = *self.opts.first()?;
+            if ty == PAD1 {
+                self.opts = self.opts.rf(1..);
+                return Some(Option::Pad1);
+            }
+
+            let len = usize(*self.opts.gu(1));
+            let data = self.opts.r(2..2 + len);
+            self.opts = self.opts.rf(2 + len..);
+
+            Some(match (ty, len) {
+                (PADN, _) => Option::PadN(len as u8),
+                (ROUTER_ALERT, 2) => Option::RouterAlert(NE::read_u16(data).into()),
+                _ => Option::Unknown { ty, data },
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Header, Option, RouterAlert};
+    use crate::ipv6;
+
+    const BYTES: &[u8] = &[
+        0x60, 0, 0, 0, // version, traffic class, flow label
+        0, 32, // payload length
+        0,  // next header: Hop-by-Hop Options
+        1,  // hop limit
+        0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, // source
+        0xff, 0x02, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x16, // destination
+        44,   // hbh: next header: Fragment
+        0,    // hbh: hdr ext len
+        5, 2, 0, 0, // hbh: Router Alert = MLD
+        1, 0,  // hbh: PadN
+        58, // frag: next header: ICMPv6
+        0,  // frag: reserved
+        0, 0, // frag: fragment offset & M
+        0, 0, 0, 42, // frag: identification
+        143, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, // payload
+    ];
+
+    #[test]
+    fn chain() {
+        let ip = ipv6::Packet::parse(BYTES).unwrap();
+        assert_eq!(ip.get_next_header(), ipv6::NextHeader::Hopopt);
+
+        let mut headers = ip.extension_headers();
+        match headers.next() {
+            Some(Header::HopByHop(hbh)) => {
+                assert_eq!(hbh.get_next_header(), ipv6::NextHeader::Ipv6Frag);
+
+                let mut opts = hbh.options();
+                assert_eq!(opts.next(), Some(Option::RouterAlert(RouterAlert::Mld)));
+                assert_eq!(opts.next(), Some(Option::PadN(0)));
+                assert_eq!(opts.next(), None);
+            }
+            _ => panic!(),
+        }
+
+        match headers.next() {
+            Some(Header::Fragment(frag)) => {
+                assert!(frag.is_atomic());
+                assert_eq!(frag.get_identification(), 42);
+            }
+            _ => panic!(),
+        }
+
+        assert!(headers.next().is_none());
+
+        assert_eq!(ip.upper_layer_protocol(), ipv6::NextHeader::Ipv6Icmp);
+        assert_eq!(ip.upper_layer_payload(), &BYTES[56..]);
+    }
+
+    #[test]
+    fn non_first_fragment() {
+        let mut bytes = [0; 72];
+        bytes.copy_from_slice(BYTES);
+        // fragment offset = 1 (8 octets), M = 1
+        bytes[50] = 0;
+        bytes[51] = 0b1001;
+        // what follows is not a header
+        bytes[56] = 0;
+
+        let ip = ipv6::Packet::parse(&bytes[..]).unwrap();
+        assert_eq!(ip.extension_headers().count(), 2);
+        assert_eq!(ip.upper_layer_protocol(), ipv6::NextHeader::Ipv6Icmp);
+        assert_eq!(ip.upper_layer_payload(), &bytes[56..]);
+    }
+
+    #[test]
+    fn malformed() {
+        // Hop-by-Hop Options header after the Fragment header
+        let mut bytes = [0; 72];
+        bytes.copy_from_slice(BYTES);
+        bytes[6] = 44;
+        bytes[40] = 0;
+        bytes[42] = 0;
+        bytes[43] = 0;
+        assert!(ipv6::Packet::parse(&bytes[..]).is_err());
+
+        // option that extends past the end of the header
+        let mut bytes = [0; 72];
+        bytes.copy_from_slice(BYTES);
+        bytes[47] = 1;
+        assert!(ipv6::Packet::parse(&bytes[..]).is_err());
+
+        // truncated header
+        assert!(ipv6::Packet::parse(&BYTES[..44]).is_err());
+    }
+}
@@ -0,0 +1,661 @@
+//! IPv6 fragmentation and reassembly
+//!
+//! # References
+//!
+//! - [RFC 8200: Internet Protocol, Version 6 (IPv6) Specification][0], section 4.5
+//!   "Fragment Header"
+//!
+//! [0]: https://tools.ietf.org/html/rfc8200
+//!
+//! - [RFC 5722: Handling of Overlapping IPv6 Fragments][1]
+//!
+//! [1]: https://tools.ietf.org/html/rfc5722
+//!
+//! - [RFC 6946: Processing of IPv6 "Atomic" Fragments][2]
+//!
+//! [2]: https://tools.ietf.org/html/rfc6946
+
+use as_slice::AsSlice;
+use byteorder::{ByteOrder, NetworkEndian as NE};
+use cast::usize;
+use heapless::{ArrayLength, Vec};
+
+pub use crate::frag::Error;
+use crate::{
+    frag::Assembly,
+    ipv6::{
+        ext::{Header, FRAGMENT_HEADER_SIZE},
+        Addr, NextHeader, Packet, HEADER_SIZE, NEXT_HEADER,
+    },
+    time::{Duration, Instant},
+};
+
+/// Minimum MTU every IPv6 link must support (see RFC 8200)
+pub const MIN_MTU: u16 = 1280;
+
+/// Time limit for the reassembly of a datagram, counted from the arrival of its first fragment
+pub const TIMEOUT: Duration = Duration::from_millis(60_000);
+
+#[derive(Clone, Copy, Eq, PartialEq)]
+struct Key {
+    source: Addr,
+    destination: Addr,
+    identification: u32,
+}
+
+/// A reassembly slot; see `Reassembler`
+///
+/// A slot holds the partially reassembled fragmentable part of a single datagram.
+pub struct Slot<M>
+where
+    M: ArrayLength<u8>,
+{
+    key: Key,
+    start: Instant,
+    // length of the unfragmentable part of the first fragment; `0` if it hasn't been received yet
+    header_len: u16,
+    // position of the Next Header field that must be updated once the datagram is reassembled
+    nh_pos: u16,
+    // Next Header value of the Fragment header of the first fragment
+    next_header: u8,
+    // the fragmentable part is stored at the start of the buffer; the unfragmentable part of the
+    // first fragment is stored at the end of the buffer
+    assembly: Assembly<M>,
+    // the reassembled datagram has been handed to the caller
+    done: bool,
+}
+
+/// Fixed capacity reassembly buffer
+///
+/// `N` is the maximum number of datagrams that can be reassembled concurrently and `M` is the size
+/// of the reassembly buffer of each datagram, i.e. the maximum size of a reassembled datagram.
+///
+/// Fragments that belong to the same datagram are identified by the (Source, Destination,
+/// Identification) tuple. As per RFC 5722, fragments that overlap with previously received data
+/// cause the whole datagram to be discarded.
+///
+/// Each datagram must be completely reassembled within `TIMEOUT` of the arrival of its first
+/// fragment. The caller drives time by passing the current `Instant` to `insert` and `expire`.
+pub struct Reassembler<N, M>
+where
+    N: ArrayLength<Slot<M>>,
+    M: ArrayLength<u8>,
+{
+    slots: Vec<Slot<M>, N>,
+}
+
+impl<N, M> Reassembler<N, M>
+where
+    N: ArrayLength<Slot<M>>,
+    M: ArrayLength<u8>,
+{
+    /// Creates a new reassembly buffer
+    pub fn new() -> Self {
+        Reassembler { slots: Vec::new() }
+    }
+
+    /// Feeds a fragment into the reassembly buffer
+    ///
+    /// Returns the reassembled datagram when `fragment` is the last missing piece; otherwise
+    /// returns `Ok(None)`. The Fragment header is not present in the reassembled datagram.
+    ///
+    /// Packets that don't contain a Fragment header and atomic fragments (see RFC 6946) are
+    /// rejected with `Error::NotAFragment`. The caller is expected to process them as regular
+    /// packets; `Packet::upper_layer_payload` skips the Fragment header of an atomic fragment.
+    ///
+    /// NOTE expired datagrams are discarded by this method so calling `expire` is only required
+    /// when no fragments are being received
+    pub fn insert<B>(
+        &mut self,
+        fragment: &Packet<B>,
+        now: Instant,
+    ) -> Result<Option<Packet<&mut [u8]>>, Error>
+    where
+        B: AsSlice<Element = u8>,
+    {
+        // locate the Fragment header; the headers that precede it form the unfragmentable part
+        let mut nh_pos = NEXT_HEADER;
+        let mut header_len = usize(HEADER_SIZE);
+        let mut headers = fragment.extension_headers();
+        let frag = loop {
+            match headers.next() {
+                Some(Header::Fragment(frag)) => break frag,
+                Some(header) => {
+                    nh_pos = header_len;
+                    header_len += header.as_bytes().len();
+                }
+                None => return Err(Error::NotAFragment),
+            }
+        };
+
+        if frag.is_atomic() {
+            return Err(Error::NotAFragment);
+        }
+
+        let end = usize(HEADER_SIZE) + usize(fragment.get_length());
+        let bytes = fragment.as_bytes();
+        if end > bytes.len() {
+            // truncated packet
+            return Err(Error::Malformed);
+        }
+
+        let last = !frag.get_m();
+        let offset = usize(frag.get_fragment_offset()) * 8;
+        let data = &bytes[header_len + usize(FRAGMENT_HEADER_SIZE)..end];
+
+        // all fragments but the last must carry a multiple of 8 bytes
+        if !last && (data.is_empty() || data.len() & 0b111 != 0) {
+            return Err(Error::Malformed);
+        }
+
+        // the Payload Length of the reassembled datagram must fit in 16 bits
+        if header_len - usize(HEADER_SIZE) + offset + data.len() > usize(u16::MAX) {
+            return Err(Error::Malformed);
+        }
+        let offset = offset as u16;
+
+        self.expire(now);
+
+        let key = Key {
+            source: fragment.get_source(),
+            destination: fragment.get_destination(),
+            identification: frag.get_identification(),
+        };
+        let pos = if let Some(pos) = self.slots.iter().position(|slot| slot.key == key) {
+            pos
+        } else {
+            self.slots
+                .push(Slot {
+                    key,
+                    start: now,
+                    header_len: 0,
+                    nh_pos: 0,
+                    next_header: 0,
+                    assembly: Assembly::new(),
+                    done: false,
+                })
+                .map_err(|_| Error::Exhausted)?;
+
+            self.slots.len() - 1
+        };
+
+        let header = &bytes[..header_len];
+        let next_header = frag.get_next_header();
+        match Self::update(
+            &mut self.slots[pos],
+            header,
+            nh_pos,
+            next_header,
+            data,
+            offset,
+            last,
+        ) {
+            Err(e) => {
+                self.slots.swap_remove(pos);
+                Err(e)
+            }
+
+            Ok(false) => Ok(None),
+
+            Ok(true) => {
+                let slot = &mut self.slots[pos];
+                slot.done = true;
+
+                let header_len = usize(slot.header_len);
+                let data_len = usize(slot.assembly.data_len().unwrap_or(0));
+                let nh_pos = usize(slot.nh_pos);
+                let next_header = slot.next_header;
+
+                // move the unfragmentable part in front of the fragmentable part
+                let buffer = slot.assembly.buffer_mut();
+                buffer.rotate_right(header_len);
+                buffer[nh_pos] = next_header;
+
+                let mut packet = Packet {
+                    buffer: &mut buffer[..header_len + data_len],
+                };
+
+                // NOTE(cast) the Payload Length was checked above
+                unsafe { packet.set_length((header_len + data_len - usize(HEADER_SIZE)) as u16) }
+
+                Ok(Some(packet))
+            }
+        }
+    }
+
+    /// Discards the datagrams that have not been reassembled within `TIMEOUT`
+    pub fn expire(&mut self, now: Instant) {
+        let mut i = 0;
+        while i < self.slots.len() {
+            let slot = &self.slots[i];
+
+            if slot.done || now.duration_since(slot.start) >= TIMEOUT {
+                self.slots.swap_remove(i);
+            } else {
+                i += 1;
+            }
+        }
+    }
+
+    /// Returns the number of datagrams that are currently being reassembled
+    pub fn len(&self) -> usize {
+        self.slots.iter().filter(|slot| !slot.done).count()
+    }
+
+    /// Returns `true` if no datagram is being reassembled
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Returns `true` if the datagram has been completely reassembled
+    fn update(
+        slot: &mut Slot<M>,
+        header: &[u8],
+        nh_pos: usize,
+        next_header: NextHeader,
+        data: &[u8],
+        offset: u16,
+        last: bool,
+    ) -> Result<bool, Error> {
+        let size = M::to_usize();
+
+        // leave room for the unfragmentable part
+        let header_len = if slot.header_len != 0 {
+            usize(slot.header_len)
+        } else if offset == 0 {
+            header.len()
+        } else {
+            usize(HEADER_SIZE)
+        };
+        if header_len > size {
+            return Err(Error::TooLarge);
+        }
+
+        slot.assembly
+            .insert(0, size - header_len, offset, data, last)?;
+
+        if offset == 0 && slot.header_len == 0 {
+            if usize(slot.assembly.end()) + header.len() > size {
+                return Err(Error::TooLarge);
+            }
+
+            slot.assembly.buffer_mut()[size - header.len()..].copy_from_slice(header);
+            slot.header_len = header.len() as u16;
+            slot.nh_pos = nh_pos as u16;
+            slot.next_header = next_header.into();
+        }
+
+        Ok(slot.header_len != 0 && slot.assembly.is_complete())
+    }
+}
+
+impl<N, M> Default for Reassembler<N, M>
+where
+    N: ArrayLength<Slot<M>>,
+    M: ArrayLength<u8>,
+{
+    fn default() -> Self {
+        Reassembler::new()
+    }
+}
+
+/// Splits a packet into fragments that fit in the link MTU
+///
+/// The unfragmentable part of the packet (the IPv6 header plus the extension headers up to, and
+/// including, the last Hop-by-Hop Options or Routing header) is copied into every fragment.
+pub struct Fragmenter<'a> {
+    header: &'a [u8],
+    data: &'a [u8],
+    // position of the Next Header field that must point to the Fragment header
+    nh_pos: usize,
+    identification: u32,
+    // fragmentable bytes that have been sent so far
+    sent: u16,
+    mtu: u16,
+    done: bool,
+}
+
+impl<'a> Fragmenter<'a> {
+    /// Prepares `packet` for fragmentation
+    ///
+    /// All the fragments will use the given `identification` value. If `packet` already fits in
+    /// the MTU it'll be produced as a single, unmodified "fragment".
+    ///
+    /// Returns `Error::Malformed` if `packet` is truncated or already contains a Fragment header,
+    /// and `Error::TooLarge` if its unfragmentable part doesn't leave room for any data in the
+    /// MTU.
+    ///
+    /// # Panics
+    ///
+    /// This constructor panics if `mtu` is smaller than `MIN_MTU`
+    pub fn new<B>(packet: &'a Packet<B>, mtu: u16, identification: u32) -> Result<Self, Error>
+    where
+        B: AsSlice<Element = u8>,
+    {
+        assert!(mtu >= MIN_MTU);
+
+        let bytes = packet.as_bytes();
+        let end = usize(HEADER_SIZE) + usize(packet.get_length());
+        if end > bytes.len() {
+            return Err(Error::Malformed);
+        }
+
+        let mut nh_pos = NEXT_HEADER;
+        let mut header_len = usize(HEADER_SIZE);
+        let mut offset = header_len;
+        for header in packet.extension_headers() {
+            offset += header.as_bytes().len();
+
+            match header {
+                Header::Fragment(_) => return Err(Error::Malformed),
+                Header::HopByHop(_) | Header::Routing(_) => {
+                    nh_pos = offset - header.as_bytes().len();
+                    header_len = offset;
+                }
+                Header::DestinationOptions(_) => {}
+            }
+        }
+
+        if end > usize(mtu) && header_len + usize(FRAGMENT_HEADER_SIZE) + 8 > usize(mtu) {
+            return Err(Error::TooLarge);
+        }
+
+        Ok(Fragmenter {
+            header: &bytes[..header_len],
+            data: &bytes[header_len..end],
+            nh_pos,
+            identification,
+            sent: 0,
+            mtu,
+            done: false,
+        })
+    }
+
+    /// Returns `true` if all the fragments have been produced
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
+    /// Writes the next fragment into `buffer`
+    ///
+    /// Returns `None` if all the fragments have already been produced. The size of the fragment is
+    /// limited by both the MTU and the size of `buffer`.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if `buffer` can't hold the unfragmentable part, the Fragment header and 8
+    /// bytes of data.
+    pub fn next_fragment<'b>(&mut self, buffer: &'b mut [u8]) -> Option<Packet<&'b mut [u8]>> {
+        if self.done {
+            return None;
+        }
+
+        let header_len = self.header.len();
+        let total_len = header_len + self.data.len();
+        if self.sent == 0 && total_len <= usize(self.mtu) {
+            // no need to fragment
+            buffer[..header_len].copy_from_slice(self.header);
+            buffer[header_len..total_len].copy_from_slice(self.data);
+
+            self.done = true;
+
+            return Some(Packet {
+                buffer: &mut buffer[..total_len],
+            });
+        }
+
+        let frag_end = header_len + usize(FRAGMENT_HEADER_SIZE);
+        let size = usize(self.mtu).min(buffer.len());
+        assert!(size >= frag_end + 8);
+
+        let remaining = self.data.len() - usize(self.sent);
+        let max_data = (size - frag_end) & !0b111;
+        let (data_len, is_last) = if remaining <= max_data {
+            (remaining, true)
+        } else {
+            (max_data, false)
+        };
+
+        buffer[..header_len].copy_from_slice(self.header);
+        buffer[self.nh_pos] = NextHeader::Ipv6Frag.into();
+
+        // Fragment header
+        let frag = &mut buffer[header_len..frag_end];
+        frag[0] = self.header[self.nh_pos];
+        frag[1] = 0;
+        NE::write_u16(&mut frag[2..4], self.sent | if is_last { 0 } else { 1 });
+        NE::write_u32(&mut frag[4..8], self.identification);
+
+        let start = usize(self.sent);
+        buffer[frag_end..frag_end + data_len].copy_from_slice(&self.data[start..start + data_len]);
+
+        let mut packet = Packet {
+            buffer: &mut buffer[..frag_end + data_len],
+        };
+
+        // NOTE(cast) `size` is bounded by the MTU
+        unsafe { packet.set_length((frag_end + data_len - usize(HEADER_SIZE)) as u16) }
+
+        self.sent += data_len as u16;
+        self.done = is_last;
+
+        Some(packet)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use heapless::consts;
+
+    use super::{Error, Fragmenter, Reassembler, TIMEOUT};
+    use crate::{
+        ipv6::{self, NextHeader, NEXT_HEADER},
+        time::Instant,
+    };
+
+    const SRC: ipv6::Addr = ipv6::Addr([
+        0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0x02, 0x11, 0x22, 0xff, 0xfe, 0x33, 0x44, 0x55,
+    ]);
+    const DEST: ipv6::Addr = ipv6::Addr([
+        0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0x02, 0x11, 0x22, 0xff, 0xfe, 0x33, 0x44, 0x66,
+    ]);
+
+    // IPv6 header + Hop-by-Hop Options header (PadN) + `data_len` bytes of UDP "data"
+    fn datagram(buffer: &mut [u8], data_len: usize) -> ipv6::Packet<&mut [u8]> {
+        let mut ip = ipv6::Packet::new(&mut buffer[..40 + 8 + data_len]);
+        ip.set_source(SRC);
+        ip.set_destination(DEST);
+        // NOTE `set_next_header` doesn't accept extension headers
+        ip.header_mut()[NEXT_HEADER] = NextHeader::Hopopt.into();
+
+        let payload = ip.payload_mut();
+        payload[..8].copy_from_slice(&[17, 0, 1, 4, 0, 0, 0, 0]);
+        for (i, byte) in payload[8..].iter_mut().enumerate() {
+            *byte = i as u8;
+        }
+
+        ip
+    }
+
+    // IPv6 header + Fragment header + `data_len` bytes of UDP "data"
+    fn fragment(
+        buffer: &mut [u8],
+        offset: u16,
+        m: bool,
+        identification: u32,
+        data_len: usize,
+    ) -> ipv6::Packet<&mut [u8]> {
+        let mut ip = ipv6::Packet::new(&mut buffer[..40 + 8 + data_len]);
+        ip.set_source(SRC);
+        ip.set_destination(DEST);
+        ip.header_mut()[NEXT_HEADER] = NextHeader::Ipv6Frag.into();
+
+        let payload = ip.payload_mut();
+        let offset_m = offset | if m { 1 } else { 0 };
+        let id = identification.to_be_bytes();
+        payload[..8].copy_from_slice(&[
+            17,
+            0,
+            (offset_m >> 8) as u8,
+            offset_m as u8,
+            id[0],
+            id[1],
+            id[2],
+            id[3],
+        ]);
+
+        ip
+    }
+
+    #[test]
+    fn roundtrip() {
+        let mut buffer = [0; 3048];
+        let ip = datagram(&mut buffer, 3000);
+
+        let mut fragments = [[0; 1280]; 3];
+        let mut lens = [0; 3];
+        {
+            let mut fragmenter = Fragmenter::new(&ip, 1280, 0xdead_beef).unwrap();
+            for (i, buf) in fragments.iter_mut().enumerate() {
+                let frag = fragmenter.next_fragment(buf).unwrap();
+                assert_eq!(frag.get_next_header(), NextHeader::Hopopt);
+
+                let header = frag.extension_headers().nth(1).unwrap();
+                if let ipv6::ext::Header::Fragment(header) = header {
+                    assert_eq!(header.get_next_header(), NextHeader::Udp);
+                    assert_eq!(header.get_m(), i != 2);
+                    assert_eq!(usize::from(header.get_fragment_offset()) * 8, i * 1224);
+                    assert_eq!(header.get_identification(), 0xdead_beef);
+                } else {
+                    panic!("expected a Fragment header");
+                }
+
+                lens[i] = frag.as_bytes().len();
+            }
+            assert!(fragmenter.is_done());
+            assert!(fragmenter.next_fragment(&mut [0; 1280]).is_none());
+        }
+        assert_eq!(lens, [1280, 1280, 48 + 8 + 552]);
+
+        let mut reassembler: Reassembler<consts::U2, consts::U4096> = Reassembler::new();
+
+        // out of order arrival
+        for &i in &[2, 0] {
+            let frag = ipv6::Packet::parse(&fragments[i][..lens[i]]).unwrap();
+            assert!(reassembler
+                .insert(&frag, Instant::from_millis(0))
+                .unwrap()
+                .is_none());
+        }
+
+        let frag = ipv6::Packet::parse(&fragments[1][..lens[1]]).unwrap();
+        let reassembled = reassembler
+            .insert(&frag, Instant::from_millis(100))
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(reassembled.as_bytes(), ip.as_bytes());
+
+        // the slot is freed
+        assert!(reassembler.is_empty());
+    }
+
+    #[test]
+    fn overlap() {
+        let mut buffer = [0; 128];
+        let first = fragment(&mut buffer, 0, true, 1, 48);
+
+        let mut reassembler: Reassembler<consts::U1, consts::U256> = Reassembler::new();
+        assert!(reassembler
+            .insert(&first, Instant::from_millis(0))
+            .unwrap()
+            .is_none());
+
+        // exact duplicates are ignored
+        assert!(reassembler
+            .insert(&first, Instant::from_millis(0))
+            .unwrap()
+            .is_none());
+        assert_eq!(reassembler.len(), 1);
+
+        // the last fragment overlaps with the first one
+        let mut buffer = [0; 128];
+        let last = fragment(&mut buffer, 32, false, 1, 32);
+        assert_eq!(
+            reassembler.insert(&last, Instant::from_millis(0)).err(),
+            Some(Error::Overlap)
+        );
+
+        // the whole datagram was discarded
+        assert!(reassembler.is_empty());
+    }
+
+    #[test]
+    fn timeout() {
+        let mut buffer = [0; 128];
+        let first = fragment(&mut buffer, 0, true, 1, 48);
+
+        let mut reassembler: Reassembler<consts::U1, consts::U256> = Reassembler::new();
+        assert!(reassembler
+            .insert(&first, Instant::from_millis(0))
+            .unwrap()
+            .is_none());
+
+        reassembler.expire(Instant::from_millis(TIMEOUT.as_millis() - 1));
+        assert_eq!(reassembler.len(), 1);
+
+        reassembler.expire(Instant::from_millis(TIMEOUT.as_millis()));
+        assert!(reassembler.is_empty());
+    }
+
+    #[test]
+    fn not_a_fragment() {
+        let mut reassembler: Reassembler<consts::U1, consts::U256> = Reassembler::new();
+
+        let mut buffer = [0; 128];
+        let ip = datagram(&mut buffer, 16);
+        assert_eq!(
+            reassembler.insert(&ip, Instant::from_millis(0)).err(),
+            Some(Error::NotAFragment)
+        );
+
+        // atomic fragment
+        let mut buffer = [0; 128];
+        let ip = fragment(&mut buffer, 0, false, 1, 16);
+        assert_eq!(
+            reassembler.insert(&ip, Instant::from_millis(0)).err(),
+            Some(Error::NotAFragment)
+        );
+        assert_eq!(ip.upper_layer_protocol(), NextHeader::Udp);
+        assert_eq!(ip.upper_layer_payload().len(), 16);
+
+        // non-final fragment whose size is not a multiple of 8
+        let mut buffer = [0; 128];
+        let ip = fragment(&mut buffer, 0, true, 1, 12);
+        assert_eq!(
+            reassembler.insert(&ip, Instant::from_millis(0)).err(),
+            Some(Error::Malformed)
+        );
+
+        assert!(reassembler.is_empty());
+    }
+
+    #[test]
+    fn fragmenter() {
+        // fits in the MTU
+        let mut buffer = [0; 128];
+        let ip = datagram(&mut buffer, 16);
+        let mut fragmenter = Fragmenter::new(&ip, 1280, 0).unwrap();
+        let mut buf = [0; 1280];
+        assert_eq!(
+            fragmenter.next_fragment(&mut buf).unwrap().as_bytes(),
+            ip.as_bytes()
+        );
+        assert!(fragmenter.is_done());
+
+        // already a fragment
+        let mut buffer = [0; 128];
+        let ip = fragment(&mut buffer, 0, true, 1, 16);
+        assert_eq!(Fragmenter::new(&ip, 1280, 0).err(), Some(Error::Malformed));
+    }
+}
@@ -0,0 +1,573 @@
+//! Neighbor cache and Neighbor Unreachability Detection (NUD)
+//!
+//! # References
+//!
+//! - [RFC 4861: Neighbor Discovery for IP version 6 (IPv6)][0], sections 7.2 "Address
+//!   Resolution", 7.3 "Neighbor Unreachability Detection" and 10 "Protocol Constants"
+//!
+//! [0]: https://tools.ietf.org/html/rfc4861
+
+use as_slice::AsSlice;
+use heapless::{ArrayLength, Vec};
+
+use crate::{
+    icmpv6::{self, NeighborAdvertisement},
+    ipv6::Addr,
+    time::{Duration, Instant},
+};
+
+/// Time between retransmitted Neighbor Solicitations, when resolving or probing a neighbor and
+/// during Duplicate Address Detection (`RETRANS_TIMER`)
+pub const RETRANS_TIMER: Duration = Duration::from_millis(1_000);
+
+/// Number of multicast Neighbor Solicitations sent while resolving an address
+/// (`MAX_MULTICAST_SOLICIT`)
+pub const MAX_MULTICAST_SOLICIT: u8 = 3;
+
+/// Number of unicast Neighbor Solicitations sent while probing a neighbor
+/// (`MAX_UNICAST_SOLICIT`)
+pub const MAX_UNICAST_SOLICIT: u8 = 3;
+
+/// Time a neighbor is considered reachable after receiving a reachability confirmation
+/// (`REACHABLE_TIME`)
+pub const REACHABLE_TIME: Duration = Duration::from_millis(30_000);
+
+/// Time to wait for an upper-layer reachability confirmation before probing a neighbor
+/// (`DELAY_FIRST_PROBE_TIME`)
+pub const DELAY_FIRST_PROBE_TIME: Duration = Duration::from_millis(5_000);
+
+/// Neighbor cache error
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Error {
+    /// The packet doesn't fit in the buffer of the cache entry
+    TooLarge,
+
+    /// All the entries are in use and none of them can be evicted
+    Exhausted,
+}
+
+/// Reachability state of a neighbor
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum State {
+    /// Address resolution is in progress; the link-layer address is not yet known
+    Incomplete,
+    /// The neighbor was recently known to be reachable
+    Reachable,
+    /// The neighbor is no longer known to be reachable; no traffic has been sent to it since
+    Stale,
+    /// Traffic was sent to a `Stale` neighbor; waiting for an upper-layer reachability
+    /// confirmation before probing it
+    Delay,
+    /// Unicast Neighbor Solicitations are being sent to verify reachability
+    Probe,
+}
+
+/// An action the caller must take on behalf of the cache
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Action<L> {
+    /// Send a Neighbor Solicitation for `target`
+    ///
+    /// If `ll_addr` is `None` the solicitation must be sent to the solicited-node multicast
+    /// address of `target` (and the link-layer broadcast / multicast address); otherwise it must
+    /// be unicast to `target` at `ll_addr`. The solicitation should carry a Source Link-layer
+    /// Address option.
+    Solicit {
+        /// Target address
+        target: Addr,
+        /// Link-layer address of the target, if known
+        ll_addr: Option<L>,
+    },
+
+    /// `addr` is unreachable and has been removed from the cache; its pending packet, if any, has
+    /// been dropped
+    Unreachable(Addr),
+}
+
+/// An entry of the neighbor cache; see `Cache`
+pub struct Entry<L, M>
+where
+    M: ArrayLength<u8>,
+{
+    addr: Addr,
+    ll_addr: Option<L>,
+    state: State,
+    // when the current state was entered or, in the Incomplete and Probe states, when the last
+    // solicitation was sent
+    timer: Instant,
+    // number of solicitations sent
+    probes: u8,
+    // packet waiting for address resolution; empty if none
+    pending: Vec<u8, M>,
+}
+
+/// Fixed capacity neighbor cache
+///
+/// `L` is the link-layer address type (e.g. `mac::Addr` or `ieee802154::Addr`), `N` is the
+/// maximum number of neighbors and `M` is the maximum size of the packet that can be queued while
+/// the address of a neighbor is being resolved. At most one packet is queued per neighbor; newer
+/// packets replace older ones.
+///
+/// The cache doesn't send or receive packets on its own. The caller resolves addresses with
+/// `send`, feeds it the Neighbor Discovery messages it receives and calls `poll` periodically
+/// (e.g. every 100 ms) to learn which Neighbor Solicitations to send.
+pub struct Cache<L, N, M>
+where
+    N: ArrayLength<Entry<L, M>>,
+    M: ArrayLength<u8>,
+{
+    entries: Vec<Entry<L, M>, N>,
+}
+
+impl<L, N, M> Cache<L, N, M>
+where
+    L: Copy + Eq,
+    N: ArrayLength<Entry<L, M>>,
+    M: ArrayLength<u8>,
+{
+    /// Creates an empty neighbor cache
+    pub fn new() -> Self {
+        Cache {
+            entries: Vec::new(),
+        }
+    }
+
+    /// Resolves the link-layer address of `addr` in order to send it `packet`
+    ///
+    /// Returns `Ok(Some(ll_addr))` if the address is known; the caller must then send `packet`
+    /// itself. Otherwise `packet` is queued until address resolution completes and `Ok(None)` is
+    /// returned; see `poll`.
+    pub fn send(&mut self, addr: Addr, packet: &[u8], now: Instant) -> Result<Option<L>, Error> {
+        if let Some(entry) = self.entry_mut(addr) {
+            // RFC 4861 - Section 7.3.3
+            if entry.state == State::Stale {
+                entry.state = State::Delay;
+                entry.timer = now;
+            }
+
+            if entry.state == State::Incomplete {
+                entry.pending = queue(packet)?;
+            }
+
+            return Ok(entry.ll_addr);
+        }
+
+        // RFC 4861 - Section 7.2.2
+        let pending = queue(packet)?;
+
+        self.insert(Entry {
+            addr,
+            ll_addr: None,
+            state: State::Incomplete,
+            timer: now,
+            probes: 0,
+            pending,
+        })?;
+
+        Ok(None)
+    }
+
+    /// Processes a Neighbor Advertisement and the link-layer address found in its Target
+    /// Link-layer Address option, if any
+    ///
+    /// If this completes the address resolution of the neighbor, its link-layer address and the
+    /// packet that was waiting for it are returned; the caller must send the packet.
+    pub fn handle_neighbor_advertisement<B>(
+        &mut self,
+        na: &icmpv6::Message<B, NeighborAdvertisement>,
+        target_ll: Option<L>,
+        now: Instant,
+    ) -> Option<(L, &[u8])>
+    where
+        B: AsSlice<Element = u8>,
+    {
+        // RFC 4861 - Section 7.2.5
+        let solicited = na.get_solicited();
+        let override_ = na.get_override();
+        let entry = self.entry_mut(na.get_target())?;
+
+        if entry.state == State::Incomplete {
+            let ll_addr = target_ll?;
+
+            entry.ll_addr = Some(ll_addr);
+            entry.state = if solicited {
+                State::Reachable
+            } else {
+                State::Stale
+            };
+            entry.timer = now;
+
+            return Some(entry.take_pending(ll_addr));
+        }
+
+        let changed = match (target_ll, entry.ll_addr) {
+            (Some(new), Some(old)) => new != old,
+            _ => false,
+        };
+
+        if !override_ && changed {
+            if entry.state == State::Reachable {
+                entry.state = State::Stale;
+                entry.timer = now;
+            }
+        } else {
+            if let Some(ll_addr) = target_ll {
+                entry.ll_addr = Some(ll_addr);
+            }
+
+            if solicited {
+                entry.state = State::Reachable;
+                entry.timer = now;
+            } else if changed {
+                entry.state = State::Stale;
+                entry.timer = now;
+            }
+        }
+
+        None
+    }
+
+    /// Processes the Source Link-layer Address option of a Neighbor Solicitation, Router
+    /// Solicitation or Router Advertisement sent from `src`
+    ///
+    /// If this completes the address resolution of the neighbor, its link-layer address and the
+    /// packet that was waiting for it are returned; the caller must send the packet.
+    ///
+    /// NOTE messages sent from the unspecified address must not be passed to this method
+    pub fn handle_source_ll(&mut self, src: Addr, ll_addr: L, now: Instant) -> Option<(L, &[u8])> {
+        // RFC 4861 - Section 7.2.3
+        let pos = if let Some(pos) = self.entries.iter().position(|entry| entry.addr == src) {
+            pos
+        } else {
+            self.insert(Entry {
+                addr: src,
+                ll_addr: Some(ll_addr),
+                state: State::Stale,
+                timer: now,
+                probes: 0,
+                pending: Vec::new(),
+            })
+            .ok()?;
+
+            return None;
+        };
+
+        let entry = &mut self.entries[pos];
+        if entry.ll_addr != Some(ll_addr) {
+            let incomplete = entry.state == State::Incomplete;
+
+            entry.ll_addr = Some(ll_addr);
+            entry.state = State::Stale;
+            entry.timer = now;
+
+            if incomplete {
+                return Some(entry.take_pending(ll_addr));
+            }
+        }
+
+        None
+    }
+
+    /// Records a reachability confirmation from an upper-layer protocol (e.g. a TCP
+    /// acknowledgment of new data) for `addr`
+    pub fn confirm_reachability(&mut self, addr: Addr, now: Instant) {
+        if let Some(entry) = self.entry_mut(addr) {
+            if entry.ll_addr.is_some() {
+                entry.state = State::Reachable;
+                entry.timer = now;
+            }
+        }
+    }
+
+    /// Advances the timers of the cache
+    ///
+    /// If this returns an `Action` the caller must carry it out *now*. Call this method again
+    /// until it returns `None` as several actions may be due at the same time.
+    pub fn poll(&mut self, now: Instant) -> Option<Action<L>> {
+        let mut i = 0;
+        while i < self.entries.len() {
+            let entry = &mut self.entries[i];
+            let elapsed = now.duration_since(entry.timer);
+
+            match entry.state {
+                State::Incomplete | State::Probe => {
+                    let max = if entry.state == State::Incomplete {
+                        MAX_MULTICAST_SOLICIT
+                    } else {
+                        MAX_UNICAST_SOLICIT
+                    };
+
+                    if entry.probes == 0 || elapsed >= RETRANS_TIMER {
+                        if entry.probes < max {
+                            entry.probes += 1;
+                            entry.timer = now;
+
+                            return Some(Action::Solicit {
+                                target: entry.addr,
+                                ll_addr: entry.ll_addr,
+                            });
+                        } else {
+                            let addr = entry.addr;
+                            self.entries.swap_remove(i);
+
+                            return Some(Action::Unreachable(addr));
+                        }
+                    }
+                }
+
+                State::Reachable => {
+                    if elapsed >= REACHABLE_TIME {
+                        entry.state = State::Stale;
+                        entry.timer = now;
+                    }
+                }
+
+                State::Delay => {
+                    if elapsed >= DELAY_FIRST_PROBE_TIME {
+                        entry.state = State::Probe;
+                        entry.probes = 0;
+                        continue;
+                    }
+                }
+
+                State::Stale => {}
+            }
+
+            i += 1;
+        }
+
+        None
+    }
+
+    /// Returns the link-layer address of `addr`, if known, without affecting its state
+    pub fn get(&self, addr: Addr) -> Option<L> {
+        self.entries
+            .iter()
+            .find(|entry| entry.addr == addr)
+            .and_then(|entry| entry.ll_addr)
+    }
+
+    /// Returns the reachability state of `addr`, if it's in the cache
+    pub fn state(&self, addr: Addr) -> Option<State> {
+        self.entries
+            .iter()
+            .find(|entry| entry.addr == addr)
+            .map(|entry| entry.state)
+    }
+
+    /// Removes `addr` from the cache
+    pub fn remove(&mut self, addr: Addr) {
+        if let Some(pos) = self.entries.iter().position(|entry| entry.addr == addr) {
+            self.entries.swap_remove(pos);
+        }
+    }
+
+    /// Returns the number of neighbors in the cache
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns `true` if the cache is empty
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /* Private */
+    fn entry_mut(&mut self, addr: Addr) -> Option<&mut Entry<L, M>> {
+        self.entries.iter_mut().find(|entry| entry.addr == addr)
+    }
+
+    // Inserts a new entry, evicting a `Stale` entry if the cache is full
+    fn insert(&mut self, entry: Entry<L, M>) -> Result<(), Error> {
+        if self.entries.len() == N::to_usize() {
+            let pos = self
+                .entries
+                .iter()
+                .position(|entry| entry.state == State::Stale)
+                .ok_or(Error::Exhausted)?;
+
+            self.entries.swap_remove(pos);
+        }
+
+        self.entries.push(entry).map_err(|_| Error::Exhausted)
+    }
+}
+
+impl<L, N, M> Default for Cache<L, N, M>
+where
+    L: Copy + Eq,
+    N: ArrayLength<Entry<L, M>>,
+    M: ArrayLength<u8>,
+{
+    fn default() -> Self {
+        Cache::new()
+    }
+}
+
+impl<L, M> Entry<L, M>
+where
+    M: ArrayLength<u8>,
+{
+    // NOTE the packet is left in the buffer; it's only ever read while the entry is `Incomplete`
+    // and the entry never goes back to that state
+    fn take_pending(&self, ll_addr: L) -> (L, &[u8]) {
+        (ll_addr, &self.pending)
+    }
+}
+
+fn queue<M>(packet: &[u8]) -> Result<Vec<u8, M>, Error>
+where
+    M: ArrayLength<u8>,
+{
+    let mut pending = Vec::new();
+    pending
+        .extend_from_slice(packet)
+        .map_err(|_| Error::TooLarge)?;
+    Ok(pending)
+}
+
+#[cfg(test)]
+mod tests {
+    use heapless::consts::{U2, U64};
+
+    use super::{Action, Cache, Error, State};
+    use crate::{
+        icmpv6,
+        ipv6::Addr,
+        mac,
+        time::{Duration, Instant},
+    };
+
+    const A: Addr = Addr([0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xa]);
+    const B: Addr = Addr([0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xb]);
+    const C: Addr = Addr([0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xc]);
+
+    const MAC_A: mac::Addr = mac::Addr([0x02, 0, 0, 0, 0, 0xa]);
+    const MAC_B: mac::Addr = mac::Addr([0x02, 0, 0, 0, 0, 0xb]);
+
+    fn at(millis: u32) -> Instant {
+        Instant::from_millis(millis)
+    }
+
+    fn advertisement(
+        buffer: &mut [u8],
+        target: Addr,
+        solicited: bool,
+        override_: bool,
+    ) -> icmpv6::Message<&mut [u8], icmpv6::NeighborAdvertisement> {
+        let mut na = icmpv6::Message::neighbor_advertisement(buffer, 0);
+        na.set_target(target);
+        na.set_solicited(solicited);
+        na.set_override(override_);
+        na
+    }
+
+    #[test]
+    fn resolution() {
+        let mut cache = Cache::<mac::Addr, U2, U64>::new();
+
+        assert_eq!(cache.send(A, b"hello", at(0)), Ok(None));
+        assert_eq!(cache.state(A), Some(State::Incomplete));
+
+        // newer packets replace older ones
+        assert_eq!(cache.send(A, b"world", at(0)), Ok(None));
+        assert_eq!(cache.send(A, &[0; 65], at(0)), Err(Error::TooLarge));
+        assert_eq!(cache.send(A, b"world", at(0)), Ok(None));
+
+        assert_eq!(
+            cache.poll(at(0)),
+            Some(Action::Solicit {
+                target: A,
+                ll_addr: None
+            })
+        );
+        assert_eq!(cache.poll(at(500)), None);
+
+        let mut buffer = [0; 24];
+        let na = advertisement(&mut buffer, A, true, true);
+        assert_eq!(
+            cache.handle_neighbor_advertisement(&na, Some(MAC_A), at(600)),
+            Some((MAC_A, &b"world"[..]))
+        );
+        assert_eq!(cache.state(A), Some(State::Reachable));
+        assert_eq!(cache.send(A, b"!", at(700)), Ok(Some(MAC_A)));
+        assert_eq!(cache.poll(at(1_600)), None);
+    }
+
+    #[test]
+    fn unreachable() {
+        let mut cache = Cache::<mac::Addr, U2, U64>::new();
+
+        assert_eq!(cache.send(A, b"hello", at(0)), Ok(None));
+        for i in 0..3 {
+            assert_eq!(
+                cache.poll(at(i * 1_000)),
+                Some(Action::Solicit {
+                    target: A,
+                    ll_addr: None
+                })
+            );
+        }
+
+        assert_eq!(cache.poll(at(3_000)), Some(Action::Unreachable(A)));
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn nud() {
+        let mut cache = Cache::<mac::Addr, U2, U64>::new();
+
+        // learned from a Neighbor Solicitation
+        assert_eq!(cache.handle_source_ll(A, MAC_A, at(0)), None);
+        assert_eq!(cache.state(A), Some(State::Stale));
+
+        // sending traffic starts the DELAY timer
+        assert_eq!(cache.send(A, b"hello", at(0)), Ok(Some(MAC_A)));
+        assert_eq!(cache.state(A), Some(State::Delay));
+
+        // no confirmation arrived: probe the neighbor
+        let t = Duration::from_millis(5_000);
+        assert_eq!(
+            cache.poll(at(0) + t),
+            Some(Action::Solicit {
+                target: A,
+                ll_addr: Some(MAC_A)
+            })
+        );
+        assert_eq!(cache.state(A), Some(State::Probe));
+
+        // a solicited advertisement confirms reachability
+        let mut buffer = [0; 24];
+        let na = advertisement(&mut buffer, A, true, false);
+        assert_eq!(
+            cache.handle_neighbor_advertisement(&na, None, at(5_500)),
+            None
+        );
+        assert_eq!(cache.state(A), Some(State::Reachable));
+
+        // reachability expires
+        assert_eq!(cache.poll(at(35_500)), None);
+        assert_eq!(cache.state(A), Some(State::Stale));
+
+        // an unsolicited advertisement without the override flag doesn't change the address
+        let mut buffer = [0; 24];
+        let na = advertisement(&mut buffer, A, false, false);
+        cache.handle_neighbor_advertisement(&na, Some(MAC_B), at(36_000));
+        assert_eq!(cache.get(A), Some(MAC_A));
+    }
+
+    #[test]
+    fn eviction() {
+        let mut cache = Cache::<mac::Addr, U2, U64>::new();
+
+        assert_eq!(cache.send(A, b"hello", at(0)), Ok(None));
+        assert_eq!(cache.send(B, b"hello", at(0)), Ok(None));
+        assert_eq!(cache.send(C, b"hello", at(0)), Err(Error::Exhausted));
+
+        // stale entries can be evicted
+        cache.remove(B);
+        cache.handle_source_ll(B, MAC_B, at(0));
+        assert_eq!(cache.send(C, b"hello", at(0)), Ok(None));
+        assert_eq!(cache.get(B), None);
+        assert_eq!(cache.len(), 2);
+    }
+}
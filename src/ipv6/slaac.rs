@@ -0,0 +1,568 @@
+//! IPv6 Stateless Address Autoconfiguration (SLAAC)
+//!
+//! # References
+//!
+//! - [RFC 4862: IPv6 Stateless Address Autoconfiguration][0]
+//!
+//! [0]: https://tools.ietf.org/html/rfc4862
+//!
+//! - [RFC 4861: Neighbor Discovery for IP version 6 (IPv6)][1], section 10 "Protocol Constants"
+//!
+//! [1]: https://tools.ietf.org/html/rfc4861
+
+use as_slice::AsSlice;
+use heapless::{ArrayLength, Vec};
+
+use super::neighbor::RETRANS_TIMER;
+use crate::{
+    icmpv6::{self, NdOption, NeighborAdvertisement, NeighborSolicitation, RouterAdvertisement},
+    ieee802154,
+    ipv6::Addr,
+    mac,
+    time::{Duration, Instant},
+};
+
+/// Number of Neighbor Solicitations sent while performing Duplicate Address Detection
+/// (`DupAddrDetectTransmits`)
+///
+/// The probes are `neighbor::RETRANS_TIMER` apart, and the address is assigned
+/// `neighbor::RETRANS_TIMER` after the last one
+pub const DUP_ADDR_DETECT_TRANSMITS: u8 = 1;
+
+// RFC 4862 - Section 5.5.3 (e)
+const TWO_HOURS: Duration = Duration::from_millis(2 * 60 * 60 * 1_000);
+
+/// Interface Identifier: the low 64 bits of an autoconfigured address
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct InterfaceId(pub [u8; 8]);
+
+impl From<mac::Addr> for InterfaceId {
+    fn from(addr: mac::Addr) -> Self {
+        InterfaceId(addr.eui_64())
+    }
+}
+
+impl From<ieee802154::ExtendedAddr> for InterfaceId {
+    fn from(addr: ieee802154::ExtendedAddr) -> Self {
+        InterfaceId(addr.eui_64())
+    }
+}
+
+/// State of an autoconfigured address
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum State {
+    /// Duplicate Address Detection is in progress; the address must not be used yet
+    Tentative,
+    /// The address can be used without restriction
+    Preferred,
+    /// The address can still be used by existing communications but not by new ones
+    Deprecated,
+}
+
+/// An autoconfigured address
+#[derive(Clone, Debug)]
+pub struct Address {
+    addr: Addr,
+    state: State,
+    // remaining lifetimes; `None` means infinity
+    valid: Option<Duration>,
+    preferred: Option<Duration>,
+    // number of DAD probes sent so far and when the last one was sent
+    probes: u8,
+    last_probe: Instant,
+}
+
+impl Address {
+    /// Returns the address
+    pub fn addr(&self) -> Addr {
+        self.addr
+    }
+
+    /// Returns the state of the address
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    /// Returns the remaining valid lifetime of the address; `None` means infinity
+    pub fn valid_lifetime(&self) -> Option<Duration> {
+        self.valid
+    }
+
+    /// Returns the remaining preferred lifetime of the address; `None` means infinity
+    pub fn preferred_lifetime(&self) -> Option<Duration> {
+        self.preferred
+    }
+
+    /// Returns `true` if the address is assigned to the interface, i.e. if packets addressed to
+    /// it must be accepted
+    pub fn is_assigned(&self) -> bool {
+        self.state == State::Preferred || self.state == State::Deprecated
+    }
+
+    fn tentative(
+        addr: Addr,
+        valid: Option<Duration>,
+        preferred: Option<Duration>,
+        now: Instant,
+    ) -> Self {
+        Address {
+            addr,
+            state: State::Tentative,
+            valid,
+            preferred,
+            probes: 0,
+            last_probe: now,
+        }
+    }
+}
+
+/// SLAAC state machine
+///
+/// `N` is the maximum number of addresses, including the link-local address, that will be
+/// tracked. Prefixes advertised once the table is full are ignored.
+///
+/// The state machine doesn't send or receive packets on its own: the caller feeds it the Router
+/// Advertisements and Neighbor Discovery messages it receives, and calls `poll` periodically (e.g.
+/// once every second) to advance its timers and to learn when to send a Duplicate Address
+/// Detection probe.
+///
+/// Lifetimes longer than ~49.7 days are treated as if they were ~49.7 days long, unless they are
+/// infinite.
+///
+/// A tentative address that turns out to be a duplicate is dropped from the table, freeing its
+/// slot; the caller must not use it and may want to log the conflict (RFC 4862 - Section 5.4.5).
+///
+/// Two parts of Duplicate Address Detection are left to the caller:
+///
+/// - the random delay of up to one second (`MAX_RTR_SOLICITATION_DELAY`) before the first probe of
+///   an address (RFC 4862 - Section 5.4.2). `poll` asks for a probe as soon as an address is formed
+/// - discarding our own probes when they are looped back by the link (RFC 4862 - Section 5.4.3).
+///   A looped back probe looks like another node performing DAD on the same address
+pub struct Slaac<N>
+where
+    N: ArrayLength<Address>,
+{
+    iid: InterfaceId,
+    addrs: Vec<Address, N>,
+    last_poll: Instant,
+}
+
+impl<N> Slaac<N>
+where
+    N: ArrayLength<Address>,
+{
+    /// Creates a new state machine and starts autoconfiguring the link-local address
+    ///
+    /// # Panics
+    ///
+    /// This constructor panics if `N` is zero
+    pub fn new(iid: InterfaceId, now: Instant) -> Self {
+        assert!(N::to_usize() > 0);
+
+        let mut link_local = [0; 16];
+        link_local[0] = 0xfe;
+        link_local[1] = 0x80;
+        link_local[8..].copy_from_slice(&iid.0);
+
+        let mut addrs = Vec::new();
+        // NOTE(ok) can't fail because of the `assert` above
+        addrs
+            .push(Address::tentative(Addr(link_local), None, None, now))
+            .ok();
+
+        Slaac {
+            iid,
+            addrs,
+            last_poll: now,
+        }
+    }
+
+    /// Processes a Router Advertisement
+    ///
+    /// Addresses are formed from the Prefix Information options that have the Autonomous flag
+    /// set. Newly formed addresses start in the `Tentative` state.
+    pub fn handle_router_advertisement<B>(
+        &mut self,
+        ra: &icmpv6::Message<B, RouterAdvertisement>,
+        now: Instant,
+    ) where
+        B: AsSlice<Element = u8>,
+    {
+        self.advance(now);
+
+        for opt in ra.options() {
+            let pi = if let NdOption::PrefixInformation(pi) = opt {
+                pi
+            } else {
+                continue;
+            };
+
+            // RFC 4862 - Section 5.5.3 (a), (b) and (c)
+            if !pi.autonomous
+                || pi.prefix.is_link_local()
+                || pi.preferred_lifetime > pi.valid_lifetime
+            {
+                continue;
+            }
+
+            // the prefix and the interface identifier must add up to 128 bits
+            if pi.prefix_length != 64 {
+                continue;
+            }
+
+            let mut addr = pi.prefix;
+            addr.0[8..].copy_from_slice(&self.iid.0);
+
+            let valid = lifetime(pi.valid_lifetime);
+            let preferred = lifetime(pi.preferred_lifetime);
+
+            if let Some(entry) = self.addrs.iter_mut().find(|entry| entry.addr == addr) {
+                // RFC 4862 - Section 5.5.3 (e)
+                entry.preferred = preferred;
+                if entry.state != State::Tentative {
+                    entry.state = if preferred == Some(Duration::from_millis(0)) {
+                        State::Deprecated
+                    } else {
+                        State::Preferred
+                    };
+                }
+
+                let remaining = entry.valid;
+                if millis(valid) > millis(Some(TWO_HOURS)) || millis(valid) > millis(remaining) {
+                    entry.valid = valid;
+                } else if millis(remaining) > millis(Some(TWO_HOURS)) {
+                    entry.valid = Some(TWO_HOURS);
+                } else {
+                    // ignore the lifetime to prevent denial of service attacks
+                }
+            } else if valid != Some(Duration::from_millis(0)) {
+                // RFC 4862 - Section 5.5.3 (d)
+                self.addrs
+                    .push(Address::tentative(addr, valid, preferred, now))
+                    .ok();
+            }
+        }
+    }
+
+    /// Processes a Neighbor Solicitation sent from `source`
+    ///
+    /// Returns `true` if the solicitation reveals that one of our tentative addresses is a
+    /// duplicate. The address is then dropped
+    pub fn handle_neighbor_solicitation<B>(
+        &mut self,
+        ns: &icmpv6::Message<B, NeighborSolicitation>,
+        source: Addr,
+    ) -> bool
+    where
+        B: AsSlice<Element = u8>,
+    {
+        // RFC 4862 - Section 5.4.3: only solicitations that are part of another node's DAD reveal
+        // a duplicate; the others are address resolution requests that must be ignored
+        source.is_unspecified() && self.drop_duplicate(ns.get_target())
+    }
+
+    /// Processes a Neighbor Advertisement
+    ///
+    /// Returns `true` if the advertisement reveals that one of our tentative addresses is a
+    /// duplicate. The address is then dropped
+    pub fn handle_neighbor_advertisement<B>(
+        &mut self,
+        na: &icmpv6::Message<B, NeighborAdvertisement>,
+    ) -> bool
+    where
+        B: AsSlice<Element = u8>,
+    {
+        // RFC 4862 - Section 5.4.4
+        self.drop_duplicate(na.get_target())
+    }
+
+    /// Advances the timers of the state machine
+    ///
+    /// If this returns an address the caller must send a Duplicate Address Detection probe for it
+    /// *now*: a Neighbor Solicitation whose target is the returned address, sent from the
+    /// unspecified address to the solicited-node multicast address of the target. Call this method
+    /// again until it returns `None` as several probes may be due at the same time.
+    pub fn poll(&mut self, now: Instant) -> Option<Addr> {
+        self.advance(now);
+
+        for entry in self.addrs.iter_mut() {
+            if entry.state != State::Tentative {
+                continue;
+            }
+
+            if entry.probes != 0 && now.duration_since(entry.last_probe) < RETRANS_TIMER {
+                continue;
+            }
+
+            if entry.probes < DUP_ADDR_DETECT_TRANSMITS {
+                entry.probes += 1;
+                entry.last_probe = now;
+
+                return Some(entry.addr);
+            } else {
+                // no one objected to the last probe
+                entry.state = if entry.preferred == Some(Duration::from_millis(0)) {
+                    State::Deprecated
+                } else {
+                    State::Preferred
+                };
+            }
+        }
+
+        None
+    }
+
+    /// Returns the tracked addresses, in no particular order
+    pub fn addresses(&self) -> &[Address] {
+        &self.addrs
+    }
+
+    /// Returns `true` if `addr` is assigned to the interface
+    pub fn is_assigned(&self, addr: Addr) -> bool {
+        self.addrs
+            .iter()
+            .any(|entry| entry.addr == addr && entry.is_assigned())
+    }
+
+    /// Returns the link-local address, if it has been assigned
+    pub fn link_local_address(&self) -> Option<Addr> {
+        self.addrs
+            .iter()
+            .find(|entry| entry.addr.is_link_local() && entry.is_assigned())
+            .map(|entry| entry.addr)
+    }
+
+    /// Returns a preferred global address, if any
+    pub fn global_address(&self) -> Option<Addr> {
+        self.addrs
+            .iter()
+            .find(|entry| !entry.addr.is_link_local() && entry.state == State::Preferred)
+            .map(|entry| entry.addr)
+    }
+
+    /* Private */
+    // Ages the lifetimes of the addresses
+    fn advance(&mut self, now: Instant) {
+        let elapsed = now.duration_since(self.last_poll);
+        self.last_poll = now;
+
+        let mut i = 0;
+        while i < self.addrs.len() {
+            let entry = &mut self.addrs[i];
+
+            if let Some(preferred) = entry.preferred.as_mut() {
+                *preferred = *preferred - elapsed;
+            }
+
+            if let Some(valid) = entry.valid.as_mut() {
+                *valid = *valid - elapsed;
+            }
+
+            if entry.valid == Some(Duration::from_millis(0)) {
+                self.addrs.swap_remove(i);
+            } else {
+                if entry.preferred == Some(Duration::from_millis(0))
+                    && entry.state == State::Preferred
+                {
+                    entry.state = State::Deprecated;
+                }
+
+                i += 1;
+            }
+        }
+    }
+
+    // Removes the tentative address `target`, if tracked, as DAD failed for it
+    fn drop_duplicate(&mut self, target: Addr) -> bool {
+        if let Some(i) = self
+            .addrs
+            .iter()
+            .position(|entry| entry.addr == target && entry.state == State::Tentative)
+        {
+            self.addrs.swap_remove(i);
+            true
+        } else {
+            false
+        }
+    }
+}
+
+// Converts a lifetime in seconds into a `Duration`; `None` means infinity
+fn lifetime(secs: u32) -> Option<Duration> {
+    if secs == u32::MAX {
+        None
+    } else {
+        Some(Duration::from_secs(secs))
+    }
+}
+
+// Makes lifetimes comparable
+fn millis(lifetime: Option<Duration>) -> u64 {
+    lifetime
+        .map(|d| u64::from(d.as_millis()))
+        .unwrap_or(u64::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use heapless::consts::U4;
+
+    use super::{InterfaceId, Slaac, State};
+    use crate::{
+        icmpv6::{self, NdOption, PrefixInformation},
+        ipv6::Addr,
+        mac,
+        time::{Duration, Instant},
+    };
+
+    const MAC: mac::Addr = mac::Addr([0x20, 0x18, 0x03, 0x01, 0x00, 0x00]);
+
+    const PREFIX: Addr = Addr([0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+
+    const GLOBAL: Addr = Addr([
+        0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0x22, 0x18, 0x03, 0xff, 0xfe, 0x01, 0x00, 0x00,
+    ]);
+
+    fn at(secs: u32) -> Instant {
+        Instant::from_millis(0) + Duration::from_secs(secs)
+    }
+
+    fn ra(slaac: &mut Slaac<U4>, valid: u32, preferred: u32, now: Instant) {
+        let mut buffer = [0; 64];
+        let m = icmpv6::Message::router_advertisement(
+            &mut buffer[..],
+            &[NdOption::PrefixInformation(PrefixInformation {
+                prefix_length: 64,
+                on_link: true,
+                autonomous: true,
+                valid_lifetime: valid,
+                preferred_lifetime: preferred,
+                prefix: PREFIX,
+            })],
+        );
+
+        slaac.handle_router_advertisement(&m, now);
+    }
+
+    #[test]
+    fn link_local() {
+        let mut slaac = Slaac::<U4>::new(InterfaceId::from(MAC), at(0));
+
+        assert_eq!(slaac.link_local_address(), None);
+        assert_eq!(slaac.poll(at(0)), Some(MAC.into_link_local_address()));
+        assert_eq!(slaac.poll(at(0)), None);
+        assert_eq!(slaac.link_local_address(), None);
+
+        // nobody objected within `RETRANS_TIMER`
+        assert_eq!(slaac.poll(at(1)), None);
+        assert_eq!(
+            slaac.link_local_address(),
+            Some(MAC.into_link_local_address())
+        );
+        assert!(slaac.is_assigned(MAC.into_link_local_address()));
+    }
+
+    #[test]
+    fn global() {
+        let mut slaac = Slaac::<U4>::new(InterfaceId::from(MAC), at(0));
+        while slaac.poll(at(0)).is_some() {}
+        slaac.poll(at(1));
+
+        ra(&mut slaac, 20, 10, at(2));
+        assert_eq!(slaac.poll(at(2)), Some(GLOBAL));
+        assert_eq!(slaac.poll(at(3)), None);
+        assert_eq!(slaac.global_address(), Some(GLOBAL));
+
+        // the preferred lifetime expires
+        slaac.poll(at(12));
+        assert_eq!(slaac.global_address(), None);
+        assert!(slaac.is_assigned(GLOBAL));
+
+        // the valid lifetime expires
+        slaac.poll(at(22));
+        assert!(!slaac.is_assigned(GLOBAL));
+        assert_eq!(slaac.addresses().len(), 1);
+    }
+
+    #[test]
+    fn two_hours_rule() {
+        let mut slaac = Slaac::<U4>::new(InterfaceId::from(MAC), at(0));
+
+        ra(&mut slaac, 3 * 60 * 60, 60, at(0));
+        // an advertisement can't shorten the valid lifetime below two hours ...
+        ra(&mut slaac, 60, 60, at(0));
+
+        let global = slaac
+            .addresses()
+            .iter()
+            .find(|addr| addr.addr() == GLOBAL)
+            .unwrap();
+        assert_eq!(
+            global.valid_lifetime(),
+            Some(Duration::from_secs(2 * 60 * 60))
+        );
+
+        // ... but it can extend it
+        ra(&mut slaac, u32::MAX, u32::MAX, at(0));
+        let global = slaac
+            .addresses()
+            .iter()
+            .find(|addr| addr.addr() == GLOBAL)
+            .unwrap();
+        assert_eq!(global.valid_lifetime(), None);
+    }
+
+    #[test]
+    fn duplicate() {
+        let mut slaac = Slaac::<U4>::new(InterfaceId::from(MAC), at(0));
+        let link_local = MAC.into_link_local_address();
+        assert_eq!(slaac.poll(at(0)), Some(link_local));
+
+        // address resolution: not a duplicate
+        let mut buffer = [0; 24];
+        let ns = icmpv6::Message::neighbor_solicitation(&mut buffer[..], link_local, &[]);
+        assert!(!slaac.handle_neighbor_solicitation(&ns, PREFIX));
+
+        assert_eq!(slaac.addresses()[0].state(), State::Tentative);
+
+        // another node is performing DAD on the same address: the address is dropped
+        assert!(slaac.handle_neighbor_solicitation(&ns, Addr::UNSPECIFIED));
+        assert!(slaac.addresses().is_empty());
+
+        slaac.poll(at(1));
+        assert_eq!(slaac.link_local_address(), None);
+
+        // a node that already owns the address advertises it
+        ra(&mut slaac, 60, 60, at(1));
+        assert_eq!(slaac.poll(at(1)), Some(GLOBAL));
+
+        let mut buffer = [0; 24];
+        let mut na = icmpv6::Message::neighbor_advertisement(&mut buffer[..], 0);
+        na.set_target(GLOBAL);
+        assert!(slaac.handle_neighbor_advertisement(&na));
+
+        slaac.poll(at(2));
+        assert_eq!(slaac.global_address(), None);
+        assert!(slaac.addresses().is_empty());
+
+        // the freed slots can be used by other addresses
+        for i in 0..4 {
+            let mut buffer = [0; 64];
+            let mut prefix = PREFIX;
+            prefix.0[7] = i;
+            let m = icmpv6::Message::router_advertisement(
+                &mut buffer[..],
+                &[NdOption::PrefixInformation(PrefixInformation {
+                    prefix_length: 64,
+                    on_link: true,
+                    autonomous: true,
+                    valid_lifetime: 60,
+                    preferred_lifetime: 60,
+                    prefix,
+                })],
+            );
+            slaac.handle_router_advertisement(&m, at(2));
+        }
+        assert_eq!(slaac.addresses().len(), 4);
+    }
+}
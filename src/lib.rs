@@ -104,6 +104,7 @@ extern crate pretty_assertions;
 mod macros;
 
 mod fmt;
+mod frag;
 mod sealed;
 mod traits;
 
@@ -123,10 +124,14 @@ pub mod icmp;
 pub mod icmpv6;
 
 // Transport layer
+pub mod tcp;
 pub mod udp;
 
 // Application layer
 pub mod coap;
+pub mod dhcp;
+
+pub mod time;
 
 /// [Type State] Unknown
 pub enum Unknown {}
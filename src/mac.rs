@@ -54,7 +54,8 @@ impl Addr {
         ipv6::Addr(bytes)
     }
 
-    fn eui_64(self) -> [u8; 8] {
+    /// Converts this MAC address into a modified EUI-64 identifier (see RFC 4291)
+    pub fn eui_64(&self) -> [u8; 8] {
         let mut bytes = [0; 8];
 
         bytes[..3].copy_from_slice(&self.0[..3]);
@@ -1,7 +1,28 @@
-use crate::icmp::{EchoReply, EchoRequest};
+use crate::{
+    icmp::{
+        DestinationUnreachable, EchoReply, EchoRequest, ParameterProblem, Redirect, TimeExceeded,
+    },
+    icmpv6,
+};
 
 // [Type State] EchoReply or EchoRequest
 pub trait Echo: 'static {}
 
 impl Echo for EchoReply {}
 impl Echo for EchoRequest {}
+
+// [Type State] An ICMP error message: one that quotes the datagram that triggered it
+pub trait Error: 'static {}
+
+impl Error for DestinationUnreachable {}
+impl Error for ParameterProblem {}
+impl Error for Redirect {}
+impl Error for TimeExceeded {}
+
+// [Type State] An ICMPv6 error message: one that quotes the packet that triggered it
+pub trait Icmpv6Error: 'static {}
+
+impl Icmpv6Error for icmpv6::DestinationUnreachable {}
+impl Icmpv6Error for icmpv6::PacketTooBig {}
+impl Icmpv6Error for icmpv6::ParameterProblem {}
+impl Icmpv6Error for icmpv6::TimeExceeded {}
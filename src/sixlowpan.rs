@@ -10,5 +10,164 @@
 //!
 //! [1]: https://tools.ietf.org/html/rfc6282
 
+use crate::ipv6;
+
+pub mod frag;
 pub mod iphc;
+pub mod mesh;
 pub mod nhc;
+
+// Dispatch value of an uncompressed IPv6 header
+const IPV6_DISPATCH: u8 = 0b0100_0001;
+
+/// Parses `bytes` as an uncompressed IPv6 packet preceded by the IPv6 dispatch
+///
+/// `bytes` is returned back if it doesn't start with the IPv6 dispatch or if the IPv6 header is
+/// malformed
+pub fn parse_ipv6(bytes: &[u8]) -> Result<ipv6::Packet<&[u8]>, &[u8]> {
+    if bytes.first() != Some(&IPV6_DISPATCH) {
+        return Err(bytes);
+    }
+
+    ipv6::Packet::parse(&bytes[1..]).map_err(|_| bytes)
+}
+
+/// Writes the IPv6 dispatch at the start of `buffer` and transforms the rest of the buffer into an
+/// uncompressed IPv6 packet
+///
+/// See `ipv6::Packet::new` for the default values of the IPv6 header fields
+///
+/// # Panics
+///
+/// This function panics if `buffer` can't hold the dispatch and the IPv6 header
+pub fn new_ipv6(buffer: &mut [u8]) -> ipv6::Packet<&mut [u8]> {
+    assert!(buffer.len() > usize::from(ipv6::HEADER_SIZE));
+
+    buffer[0] = IPV6_DISPATCH;
+    ipv6::Packet::new(&mut buffer[1..])
+}
+
+/// 6LoWPAN encapsulated packet, demultiplexed by its dispatch value
+///
+/// Mesh and BC0 headers precede other 6LoWPAN headers; their payload can be demultiplexed again
+/// using `Dispatch::parse`
+#[derive(Debug)]
+pub enum Dispatch<'a> {
+    /// Mesh Addressing header
+    Mesh(mesh::Header<&'a [u8]>),
+    /// Broadcast header
+    Bc0(mesh::Bc0<&'a [u8]>),
+    /// First fragment
+    Frag1(frag::Frag1<&'a [u8]>),
+    /// Subsequent fragment
+    FragN(frag::FragN<&'a [u8]>),
+    /// LOWPAN_IPHC compressed IPv6 packet
+    Iphc(iphc::Packet<&'a [u8]>),
+    /// Uncompressed IPv6 packet
+    Ipv6(ipv6::Packet<&'a [u8]>),
+}
+
+impl<'a> Dispatch<'a> {
+    /// Demultiplexes `bytes` (e.g. the payload of an 802.15.4 frame) using the dispatch value at
+    /// its start
+    ///
+    /// This returns `bytes` back if it's not a 6LoWPAN packet (NALP), if its dispatch value is
+    /// reserved or if the header that follows the dispatch is malformed
+    pub fn parse(bytes: &'a [u8]) -> Result<Self, &'a [u8]> {
+        let dispatch = *bytes.first().ok_or(bytes)?;
+
+        Ok(if dispatch == IPV6_DISPATCH {
+            Dispatch::Ipv6(parse_ipv6(bytes)?)
+        } else if dispatch == mesh::BC0 {
+            Dispatch::Bc0(mesh::Bc0::parse(bytes)?)
+        } else if dispatch >> 6 == 0b10 {
+            Dispatch::Mesh(mesh::Header::parse(bytes)?)
+        } else if dispatch >> 5 == 0b011 {
+            Dispatch::Iphc(iphc::Packet::parse(bytes)?)
+        } else if dispatch >> 3 == 0b11000 {
+            Dispatch::Frag1(frag::Frag1::parse(bytes)?)
+        } else if dispatch >> 3 == 0b11100 {
+            Dispatch::FragN(frag::FragN::parse(bytes)?)
+        } else {
+            return Err(bytes);
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Dispatch;
+    use crate::ipv6;
+
+    #[test]
+    fn dispatch() {
+        // Mesh (short addresses) + BC0 + IPHC
+        let bytes = [
+            0b10_1_1_1111, // Mesh: V + F + Hops Left
+            0x12,          // originator
+            0x34,
+            0xff, // final destination
+            0xff,
+            0x50, // BC0
+            7,    // sequence number
+            0b011_11_0_11,
+            0b0_0_11_0_0_11,
+            17, // next header
+        ];
+
+        let mesh = match Dispatch::parse(&bytes).unwrap() {
+            Dispatch::Mesh(mesh) => mesh,
+            d => panic!("expected a Mesh header; got {:?}", d),
+        };
+        assert_eq!(mesh.get_hops_left(), 15);
+
+        let bc0 = match Dispatch::parse(mesh.payload()).unwrap() {
+            Dispatch::Bc0(bc0) => bc0,
+            d => panic!("expected a BC0 header; got {:?}", d),
+        };
+        assert_eq!(bc0.get_sequence_number(), 7);
+
+        match Dispatch::parse(bc0.payload()).unwrap() {
+            Dispatch::Iphc(_) => {}
+            d => panic!("expected an IPHC packet; got {:?}", d),
+        }
+
+        let mut bytes = [0; 45];
+        let mut ip = super::new_ipv6(&mut bytes);
+        ip.set_next_header(ipv6::NextHeader::Udp);
+        ip.set_hop_limit(64);
+        ip.payload_mut().copy_from_slice(&[1, 2, 3, 4]);
+        assert_eq!(bytes[0], 0x41);
+        assert_eq!(bytes[1] >> 4, 6);
+
+        match Dispatch::parse(&bytes).unwrap() {
+            Dispatch::Ipv6(ip) => {
+                assert_eq!(ip.get_next_header(), ipv6::NextHeader::Udp);
+                assert_eq!(ip.get_hop_limit(), 64);
+                assert_eq!(ip.get_length(), 4);
+                assert_eq!(ip.payload(), &[1, 2, 3, 4]);
+            }
+            d => panic!("expected an IPv6 packet; got {:?}", d),
+        }
+
+        // truncated IPv6 header
+        assert!(Dispatch::parse(&bytes[..40]).is_err());
+
+        match Dispatch::parse(&[0b11000_000, 80, 0, 1]).unwrap() {
+            Dispatch::Frag1(_) => {}
+            d => panic!("expected a FRAG1 header; got {:?}", d),
+        }
+
+        match Dispatch::parse(&[0b11100_000, 80, 0, 1, 3]).unwrap() {
+            Dispatch::FragN(_) => {}
+            d => panic!("expected a FRAGN header; got {:?}", d),
+        }
+
+        // NALP
+        let nalp = [0b00_000000, 0];
+        assert_eq!(Dispatch::parse(&nalp).err(), Some(&nalp[..]));
+        // reserved
+        assert!(Dispatch::parse(&[0b0100_0010, 0]).is_err());
+        assert!(Dispatch::parse(&[]).is_err());
+    }
+}
@@ -0,0 +1,935 @@
+//! 6LoWPAN fragmentation and reassembly
+//!
+//! # References
+//!
+//! - [RFC 4944: Transmission of IPv6 Packets over IEEE 802.15.4 Networks][0], section 5.3
+//!   "Fragmentation Type and Header"
+//!
+//! [0]: https://tools.ietf.org/html/rfc4944
+//!
+//! - [RFC 6282: Compression Format for IPv6 Datagrams over IEEE 802.15.4-Based Networks][1],
+//!   section 2
+//!
+//! [1]: https://tools.ietf.org/html/rfc6282
+//!
+//! NOTE as per RFC 6282 the 'datagram_size' and 'datagram_offset' fields refer to the
+//! *uncompressed* IPv6 datagram even when the first fragment carries a compressed header
+
+use core::fmt;
+
+use as_slice::{AsMutSlice, AsSlice};
+use byteorder::{ByteOrder, NetworkEndian as NE};
+use cast::usize;
+use heapless::{ArrayLength, Vec};
+
+pub use crate::frag::Error;
+use crate::{
+    frag::Assembly,
+    ieee802154 as ll, ipv6,
+    sixlowpan::{iphc, nhc, IPV6_DISPATCH},
+    time::{Duration, Instant},
+    traits::UncheckedIndex,
+};
+
+/* Header format */
+const DISPATCH_SIZE: core::ops::Range<usize> = 0..2;
+mod dispatch {
+    pub const MASK: u16 = (1 << SIZE) - 1;
+    pub const OFFSET: usize = super::datagram_size::OFFSET + super::datagram_size::SIZE;
+    pub const SIZE: usize = 5;
+    pub const FRAG1: u16 = 0b11000;
+    pub const FRAGN: u16 = 0b11100;
+}
+mod datagram_size {
+    pub const MASK: u16 = (1 << SIZE) - 1;
+    pub const OFFSET: usize = 0;
+    pub const SIZE: usize = 11;
+}
+const DATAGRAM_TAG: core::ops::Range<usize> = 2..4;
+const DATAGRAM_OFFSET: usize = 4;
+
+/// Size of the FRAG1 header
+pub const FRAG1_HEADER_SIZE: u8 = 4;
+
+/// Size of the FRAGN header
+pub const FRAGN_HEADER_SIZE: u8 = 5;
+
+/// Largest 'datagram_size' value that can be encoded in a fragment header
+pub const MAX_DATAGRAM_SIZE: u16 = datagram_size::MASK;
+
+// Room left in front of the reassembled datagram; the compressed headers of the first fragment may
+// be (slightly) larger than their uncompressed form (e.g. the IPv6 dispatch byte)
+const HEADROOM: usize = 8;
+
+/// First fragment (FRAG1)
+pub struct Frag1<BUFFER>
+where
+    BUFFER: AsSlice<Element = u8>,
+{
+    buffer: BUFFER,
+}
+
+impl<B> Frag1<B>
+where
+    B: AsSlice<Element = u8>,
+{
+    /* Constructors */
+    /// Parses the bytes as a FRAG1 header plus its payload
+    pub fn parse(bytes: B) -> Result<Self, B> {
+        if bytes.as_slice().len() < usize(FRAG1_HEADER_SIZE) {
+            return Err(bytes);
+        }
+
+        let f = Frag1 { buffer: bytes };
+
+        if get!(NE::read_u16(&f.as_slice()[DISPATCH_SIZE]), dispatch) != dispatch::FRAG1 {
+            return Err(f.buffer);
+        }
+
+        Ok(f)
+    }
+
+    /* Getters */
+    /// Reads the 'datagram_size' field
+    ///
+    /// This is the size of the *uncompressed* IPv6 datagram
+    pub fn get_datagram_size(&self) -> u16 {
+        get!(
+            NE::read_u16(unsafe { self.as_slice().r(DISPATCH_SIZE) }),
+            datagram_size
+        )
+    }
+
+    /// Reads the 'datagram_tag' field
+    pub fn get_datagram_tag(&self) -> u16 {
+        NE::read_u16(unsafe { self.as_slice().r(DATAGRAM_TAG) })
+    }
+
+    /// Immutable view into the payload
+    ///
+    /// The payload of the first fragment starts with the (possibly compressed) IPv6 header
+    pub fn payload(&self) -> &[u8] {
+        unsafe { self.as_slice().rf(usize(FRAG1_HEADER_SIZE)..) }
+    }
+
+    /// Byte representation of this fragment
+    pub fn bytes(&self) -> &[u8] {
+        self.as_slice()
+    }
+
+    /* Private */
+    fn as_slice(&self) -> &[u8] {
+        self.buffer.as_slice()
+    }
+}
+
+impl<B> Frag1<B>
+where
+    B: AsMutSlice<Element = u8>,
+{
+    /* Constructors */
+    /// Transforms the given buffer into a FRAG1 fragment
+    ///
+    /// The 'datagram_size' and 'datagram_tag' fields are set to zero
+    ///
+    /// # Panics
+    ///
+    /// This constructor panics if `buffer` is smaller than `FRAG1_HEADER_SIZE`
+    pub fn new(buffer: B) -> Self {
+        assert!(buffer.as_slice().len() >= usize(FRAG1_HEADER_SIZE));
+
+        let mut f = Frag1 { buffer };
+
+        NE::write_u16(
+            &mut f.as_mut_slice()[DISPATCH_SIZE],
+            dispatch::FRAG1 << dispatch::OFFSET,
+        );
+        f.set_datagram_tag(0);
+
+        f
+    }
+
+    /* Setters */
+    /// Sets the 'datagram_size' field
+    ///
+    /// # Panics
+    ///
+    /// This method panics if `size` is greater than `MAX_DATAGRAM_SIZE`
+    pub fn set_datagram_size(&mut self, size: u16) {
+        assert!(size <= MAX_DATAGRAM_SIZE);
+
+        set_datagram_size(self.as_mut_slice(), size)
+    }
+
+    /// Sets the 'datagram_tag' field
+    pub fn set_datagram_tag(&mut self, tag: u16) {
+        NE::write_u16(&mut self.as_mut_slice()[DATAGRAM_TAG], tag)
+    }
+
+    /// Mutable view into the payload
+    pub fn payload_mut(&mut self) -> &mut [u8] {
+        unsafe { self.as_mut_slice().rfm(usize(FRAG1_HEADER_SIZE)..) }
+    }
+
+    /* Private */
+    fn as_mut_slice(&mut self) -> &mut [u8] {
+        self.buffer.as_mut_slice()
+    }
+}
+
+impl<B> fmt::Debug for Frag1<B>
+where
+    B: AsSlice<Element = u8>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("frag::Frag1")
+            .field("datagram_size", &self.get_datagram_size())
+            .field("datagram_tag", &self.get_datagram_tag())
+            // .field("payload", &self.payload())
+            .finish()
+    }
+}
+
+/// Subsequent fragment (FRAGN)
+pub struct FragN<BUFFER>
+where
+    BUFFER: AsSlice<Element = u8>,
+{
+    buffer: BUFFER,
+}
+
+impl<B> FragN<B>
+where
+    B: AsSlice<Element = u8>,
+{
+    /* Constructors */
+    /// Parses the bytes as a FRAGN header plus its payload
+    pub fn parse(bytes: B) -> Result<Self, B> {
+        if bytes.as_slice().len() < usize(FRAGN_HEADER_SIZE) {
+            return Err(bytes);
+        }
+
+        let f = FragN { buffer: bytes };
+
+        if get!(NE::read_u16(&f.as_slice()[DISPATCH_SIZE]), dispatch) != dispatch::FRAGN {
+            return Err(f.buffer);
+        }
+
+        Ok(f)
+    }
+
+    /* Getters */
+    /// Reads the 'datagram_size' field
+    ///
+    /// This is the size of the *uncompressed* IPv6 datagram
+    pub fn get_datagram_size(&self) -> u16 {
+        get!(
+            NE::read_u16(unsafe { self.as_slice().r(DISPATCH_SIZE) }),
+            datagram_size
+        )
+    }
+
+    /// Reads the 'datagram_tag' field
+    pub fn get_datagram_tag(&self) -> u16 {
+        NE::read_u16(unsafe { self.as_slice().r(DATAGRAM_TAG) })
+    }
+
+    /// Reads the 'datagram_offset' field, in 8-octet units
+    pub fn get_datagram_offset(&self) -> u8 {
+        unsafe { *self.as_slice().gu(DATAGRAM_OFFSET) }
+    }
+
+    /// Immutable view into the payload
+    pub fn payload(&self) -> &[u8] {
+        unsafe { self.as_slice().rf(usize(FRAGN_HEADER_SIZE)..) }
+    }
+
+    /// Byte representation of this fragment
+    pub fn bytes(&self) -> &[u8] {
+        self.as_slice()
+    }
+
+    /* Private */
+    fn as_slice(&self) -> &[u8] {
+        self.buffer.as_slice()
+    }
+}
+
+impl<B> FragN<B>
+where
+    B: AsMutSlice<Element = u8>,
+{
+    /* Constructors */
+    /// Transforms the given buffer into a FRAGN fragment
+    ///
+    /// The 'datagram_size', 'datagram_tag' and 'datagram_offset' fields are set to zero
+    ///
+    /// # Panics
+    ///
+    /// This constructor panics if `buffer` is smaller than `FRAGN_HEADER_SIZE`
+    pub fn new(buffer: B) -> Self {
+        assert!(buffer.as_slice().len() >= usize(FRAGN_HEADER_SIZE));
+
+        let mut f = FragN { buffer };
+
+        NE::write_u16(
+            &mut f.as_mut_slice()[DISPATCH_SIZE],
+            dispatch::FRAGN << dispatch::OFFSET,
+        );
+        f.set_datagram_tag(0);
+        f.set_datagram_offset(0);
+
+        f
+    }
+
+    /* Setters */
+    /// Sets the 'datagram_size' field
+    ///
+    /// # Panics
+    ///
+    /// This method panics if `size` is greater than `MAX_DATAGRAM_SIZE`
+    pub fn set_datagram_size(&mut self, size: u16) {
+        assert!(size <= MAX_DATAGRAM_SIZE);
+
+        set_datagram_size(self.as_mut_slice(), size)
+    }
+
+    /// Sets the 'datagram_tag' field
+    pub fn set_datagram_tag(&mut self, tag: u16) {
+        NE::write_u16(&mut self.as_mut_slice()[DATAGRAM_TAG], tag)
+    }
+
+    /// Sets the 'datagram_offset' field, in 8-octet units
+    pub fn set_datagram_offset(&mut self, offset: u8) {
+        self.as_mut_slice()[DATAGRAM_OFFSET] = offset;
+    }
+
+    /// Mutable view into the payload
+    pub fn payload_mut(&mut self) -> &mut [u8] {
+        unsafe { self.as_mut_slice().rfm(usize(FRAGN_HEADER_SIZE)..) }
+    }
+
+    /* Private */
+    fn as_mut_slice(&mut self) -> &mut [u8] {
+        self.buffer.as_mut_slice()
+    }
+}
+
+impl<B> fmt::Debug for FragN<B>
+where
+    B: AsSlice<Element = u8>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("frag::FragN")
+            .field("datagram_size", &self.get_datagram_size())
+            .field("datagram_tag", &self.get_datagram_tag())
+            .field("datagram_offset", &self.get_datagram_offset())
+            // .field("payload", &self.payload())
+            .finish()
+    }
+}
+
+#[derive(Clone, Copy, Eq, PartialEq)]
+struct Key {
+    source: ll::Addr,
+    tag: u16,
+    size: u16,
+}
+
+/// A reassembly slot; see `Reassembler`
+///
+/// A slot holds the partially reassembled payload of a single datagram.
+pub struct Slot<M>
+where
+    M: ArrayLength<u8>,
+{
+    key: Key,
+    start: Instant,
+    // index of the buffer at which the reassembled datagram starts; `None` until the first
+    // fragment is received
+    first: Option<u16>,
+    // the uncompressed byte at offset `o` of the datagram is stored at index `HEADROOM + o` of the
+    // buffer; the (compressed) headers of the first fragment are stored right before the data that
+    // follows them
+    assembly: Assembly<M>,
+    // the reassembled datagram has been handed to the caller
+    done: bool,
+}
+
+/// Fixed capacity reassembly buffer
+///
+/// `N` is the maximum number of datagrams that can be reassembled concurrently and `M` is the size
+/// of the reassembly buffer of each datagram; the buffer must be slightly larger (8 bytes) than the
+/// largest datagram to reassemble.
+///
+/// Fragments that belong to the same datagram are identified by the (link-layer source,
+/// 'datagram_tag', 'datagram_size') tuple. Fragments that overlap with previously received data
+/// cause the whole datagram to be discarded.
+///
+/// Each datagram must be completely reassembled within `timeout` of the arrival of its first
+/// fragment. The caller drives time by passing the current `Instant` to `insert` and `expire`.
+pub struct Reassembler<N, M>
+where
+    N: ArrayLength<Slot<M>>,
+    M: ArrayLength<u8>,
+{
+    slots: Vec<Slot<M>, N>,
+    timeout: Duration,
+}
+
+impl<N, M> Reassembler<N, M>
+where
+    N: ArrayLength<Slot<M>>,
+    M: ArrayLength<u8>,
+{
+    /// Creates a new reassembly buffer
+    ///
+    /// RFC 4944 sets the maximum `timeout` to 60 seconds
+    pub fn new(timeout: Duration) -> Self {
+        Reassembler {
+            slots: Vec::new(),
+            timeout,
+        }
+    }
+
+    /// Feeds a fragment, the payload of an 802.15.4 frame sent from `source`, into the reassembly
+    /// buffer
+    ///
+    /// Returns the reassembled datagram when `fragment` is the last missing piece; otherwise
+    /// returns `Ok(None)`. The reassembled datagram is laid out as if it had been sent in a single
+    /// frame: it starts with the dispatch (e.g. LOWPAN_IPHC) of the first fragment.
+    ///
+    /// NOTE expired datagrams are discarded by this method so calling `expire` is only required
+    /// when no fragments are being received
+    pub fn insert(
+        &mut self,
+        source: ll::Addr,
+        fragment: &[u8],
+        now: Instant,
+    ) -> Result<Option<&mut [u8]>, Error> {
+        let (key, offset, data) = if let Ok(f) = Frag1::parse(fragment) {
+            let key = Key {
+                source,
+                tag: f.get_datagram_tag(),
+                size: f.get_datagram_size(),
+            };
+
+            (key, None, &fragment[usize(FRAG1_HEADER_SIZE)..])
+        } else if let Ok(f) = FragN::parse(fragment) {
+            let key = Key {
+                source,
+                tag: f.get_datagram_tag(),
+                size: f.get_datagram_size(),
+            };
+
+            (
+                key,
+                Some(u16::from(f.get_datagram_offset()) * 8),
+                &fragment[usize(FRAGN_HEADER_SIZE)..],
+            )
+        } else {
+            return Err(Error::NotAFragment);
+        };
+
+        if key.size == 0 {
+            return Err(Error::Malformed);
+        }
+
+        if HEADROOM + usize(key.size) > M::to_usize() {
+            return Err(Error::TooLarge);
+        }
+
+        self.expire(now);
+
+        let pos = if let Some(pos) = self.slots.iter().position(|slot| slot.key == key) {
+            pos
+        } else {
+            let mut assembly = Assembly::new();
+            assembly.set_data_len(key.size)?;
+
+            self.slots
+                .push(Slot {
+                    key,
+                    start: now,
+                    first: None,
+                    assembly,
+                    done: false,
+                })
+                .map_err(|_| Error::Exhausted)?;
+
+            self.slots.len() - 1
+        };
+
+        let res = if let Some(offset) = offset {
+            let limit = HEADROOM + usize(key.size);
+            // there's no "last fragment" flag; the fragment that reaches 'datagram_size' is the last
+            let last = usize(offset) + data.len() == usize(key.size);
+            self.slots[pos]
+                .assembly
+                .insert(HEADROOM, limit, offset, data, last)
+        } else {
+            Self::update_first(&mut self.slots[pos], data)
+        };
+
+        if let Err(e) = res {
+            self.slots.swap_remove(pos);
+            return Err(e);
+        }
+
+        let slot = &mut self.slots[pos];
+        if let (Some(first), true) = (slot.first, slot.assembly.is_complete()) {
+            slot.done = true;
+
+            let end = HEADROOM + usize(key.size);
+            Ok(Some(&mut slot.assembly.buffer_mut()[usize(first)..end]))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Discards the datagrams that have not been reassembled within the timeout
+    pub fn expire(&mut self, now: Instant) {
+        let timeout = self.timeout;
+
+        let mut i = 0;
+        while i < self.slots.len() {
+            let slot = &self.slots[i];
+
+            if slot.done || now.duration_since(slot.start) >= timeout {
+                self.slots.swap_remove(i);
+            } else {
+                i += 1;
+            }
+        }
+    }
+
+    /// Returns the number of datagrams that are currently being reassembled
+    pub fn len(&self) -> usize {
+        self.slots.iter().filter(|slot| !slot.done).count()
+    }
+
+    /// Returns `true` if no datagram is being reassembled
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Stores the payload of the first fragment
+    fn update_first(slot: &mut Slot<M>, payload: &[u8]) -> Result<(), Error> {
+        let (compressed, uncompressed) = headers_size(payload).ok_or(Error::Malformed)?;
+
+        if compressed > uncompressed + HEADROOM {
+            return Err(Error::Malformed);
+        }
+
+        let start = HEADROOM + uncompressed - compressed;
+        let end = start + payload.len();
+        if end > HEADROOM + usize(slot.key.size) {
+            return Err(Error::Malformed);
+        }
+
+        if let Some(first) = slot.first {
+            if usize(first) == start && slot.assembly.buffer_mut()[start..end] == *payload {
+                // exact retransmission of the first fragment; ignore
+                return Ok(());
+            }
+
+            return Err(Error::Overlap);
+        }
+
+        let len = (end - HEADROOM) as u16;
+        slot.assembly.add(0, len, len == slot.key.size)?;
+        slot.assembly.buffer_mut()[start..end].copy_from_slice(payload);
+        slot.first = Some(start as u16);
+
+        Ok(())
+    }
+}
+
+/// Splits a 6LoWPAN datagram into fragments
+///
+/// The datagram must start with either the uncompressed IPv6 dispatch or a LOWPAN_IPHC header.
+/// The fragments are meant to be used as the payloads of successive 802.15.4 frames.
+pub struct Fragmenter<'a> {
+    datagram: &'a [u8],
+    // size of the compressed headers and the size of their uncompressed form
+    compressed: usize,
+    uncompressed: usize,
+    size: u16,
+    tag: u16,
+    // bytes of `datagram` that have been sent so far
+    sent: usize,
+    done: bool,
+}
+
+impl<'a> Fragmenter<'a> {
+    /// Prepares `datagram` for fragmentation
+    ///
+    /// All the fragments will use the given `tag` as their 'datagram_tag'.
+    ///
+    /// Returns `Error::Malformed` if the headers of `datagram` can't be parsed and
+    /// `Error::TooLarge` if the uncompressed datagram is larger than `MAX_DATAGRAM_SIZE`.
+    pub fn new(datagram: &'a [u8], tag: u16) -> Result<Self, Error> {
+        let (compressed, uncompressed) = headers_size(datagram).ok_or(Error::Malformed)?;
+
+        let size = uncompressed + datagram.len() - compressed;
+        if size > usize(MAX_DATAGRAM_SIZE) {
+            return Err(Error::TooLarge);
+        }
+
+        Ok(Fragmenter {
+            datagram,
+            compressed,
+            uncompressed,
+            size: size as u16,
+            tag,
+            sent: 0,
+            done: false,
+        })
+    }
+
+    /// Returns `true` if all the fragments have been produced
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
+    /// Writes the next fragment into `buffer` and returns the used part of `buffer`
+    ///
+    /// Returns `None` if all the fragments have already been produced. The size of the fragment is
+    /// limited by the size of `buffer`. If the datagram fits in `buffer` it'll be produced as is,
+    /// without fragment header.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if `buffer` can't hold a fragment header plus 8 bytes of data, or if it
+    /// can't hold the whole compressed headers (plus some data) in the case of the first fragment.
+    pub fn next_fragment<'b>(&mut self, buffer: &'b mut [u8]) -> Option<&'b [u8]> {
+        if self.done {
+            return None;
+        }
+
+        let len = if self.sent == 0 {
+            if self.datagram.len() <= buffer.len() {
+                // no need to fragment
+                buffer[..self.datagram.len()].copy_from_slice(self.datagram);
+                self.done = true;
+
+                self.datagram.len()
+            } else {
+                let header_len = usize(FRAG1_HEADER_SIZE);
+                assert!(buffer.len() >= header_len + self.compressed);
+
+                // the data carried by this fragment, once uncompressed, must be a multiple of 8
+                let max_data = buffer.len() - header_len - self.compressed;
+                let covered = (self.uncompressed + max_data) & !0b111;
+                assert!(covered > self.uncompressed);
+                let len = self.compressed + covered - self.uncompressed;
+
+                let mut frag = Frag1::new(&mut buffer[..header_len + len]);
+                frag.set_datagram_size(self.size);
+                frag.set_datagram_tag(self.tag);
+                frag.payload_mut().copy_from_slice(&self.datagram[..len]);
+
+                self.sent = len;
+
+                header_len + len
+            }
+        } else {
+            let header_len = usize(FRAGN_HEADER_SIZE);
+            assert!(buffer.len() >= header_len + 8);
+
+            let remaining = self.datagram.len() - self.sent;
+            let max_data = (buffer.len() - header_len) & !0b111;
+            let len = if remaining <= max_data {
+                self.done = true;
+
+                remaining
+            } else {
+                max_data
+            };
+
+            // offset into the uncompressed datagram
+            let offset = self.uncompressed + self.sent - self.compressed;
+
+            let mut frag = FragN::new(&mut buffer[..header_len + len]);
+            frag.set_datagram_size(self.size);
+            frag.set_datagram_tag(self.tag);
+            frag.set_datagram_offset((offset / 8) as u8);
+            frag.payload_mut()
+                .copy_from_slice(&self.datagram[self.sent..self.sent + len]);
+
+            self.sent += len;
+
+            header_len + len
+        };
+
+        let buffer: &'b [u8] = buffer;
+        Some(&buffer[..len])
+    }
+}
+
+fn set_datagram_size(bytes: &mut [u8], size: u16) {
+    let mut word = NE::read_u16(&bytes[DISPATCH_SIZE]);
+    set!(word, datagram_size, size);
+    NE::write_u16(&mut bytes[DISPATCH_SIZE], word);
+}
+
+// Returns the size of the headers at the start of `datagram` and the size they have once
+// uncompressed
+fn headers_size(datagram: &[u8]) -> Option<(usize, usize)> {
+    if datagram.first() == Some(&IPV6_DISPATCH) {
+        // the uncompressed IPv6 header follows the dispatch byte
+        return Some((1, 0));
+    }
+
+    iphc_headers_size(datagram)
+}
+
+// Like `headers_size` but for a LOWPAN_IPHC compressed IPv6 packet and the LOWPAN_NHC encoded
+// headers that follow it
+fn iphc_headers_size(bytes: &[u8]) -> Option<(usize, usize)> {
+    let packet = iphc::Packet::parse(bytes).ok()?;
+    let mut sizes = (packet.header().len(), usize(ipv6::HEADER_SIZE));
+
+    if !packet.get_nh() {
+        return Some(sizes);
+    }
+
+    let mut rest = &bytes[sizes.0..];
+    loop {
+        if let Ok(eh) = nhc::ExtensionHeader::parse(rest) {
+            let len = rest.len() - eh.payload().len();
+            sizes.0 += len;
+            sizes.1 += eh.uncompressed_size();
+            rest = &rest[len..];
+
+            if eh.get_eid() == nhc::Eid::Ipv6 {
+                // encapsulated IPv6 packet
+                let inner = iphc_headers_size(rest)?;
+                return Some((sizes.0 + inner.0, sizes.1 + inner.1));
+            } else if !eh.get_nh() {
+                return Some(sizes);
+            }
+        } else {
+            let udp = nhc::UdpPacket::parse(rest).ok()?;
+            sizes.0 += udp.bytes().len() - udp.payload().len();
+            sizes.1 += 8;
+
+            return Some(sizes);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use heapless::consts;
+
+    use super::{Error, Frag1, FragN, Fragmenter, Reassembler};
+    use crate::{
+        ieee802154 as ll,
+        time::{Duration, Instant},
+    };
+
+    const SRC: ll::Addr = ll::Addr::Short(ll::ShortAddr(0x1234));
+
+    // LOWPAN_IPHC (addresses elided) + LOWPAN_NHC UDP + `payload_len` bytes of payload
+    fn datagram(buffer: &mut [u8], payload_len: usize) -> &[u8] {
+        let header = [
+            0b011_11_1_11,   // DISPATCH + TF + NH + HLIM
+            0b0_0_11_0_0_11, // CID + SAC + SAM + M + DAC + DAM
+            0b11110_0_11,    // NHC UDP: C + P
+            0xab,            // ports
+            0xbe,            // checksum
+            0xef,
+        ];
+        buffer[..header.len()].copy_from_slice(&header);
+        for (i, byte) in buffer[header.len()..].iter_mut().enumerate() {
+            *byte = i as u8;
+        }
+
+        &buffer[..header.len() + payload_len]
+    }
+
+    #[test]
+    fn headers() {
+        let mut buf = [0; 8];
+        let mut frag = Frag1::new(&mut buf[..]);
+        frag.set_datagram_size(1280);
+        frag.set_datagram_tag(0xbeef);
+
+        assert_eq!(&buf[..4], &[0b11000_101, 0, 0xbe, 0xef][..]);
+        let frag = Frag1::parse(&buf[..]).unwrap();
+        assert_eq!(frag.get_datagram_size(), 1280);
+        assert_eq!(frag.get_datagram_tag(), 0xbeef);
+        assert_eq!(frag.payload().len(), 4);
+        assert!(FragN::parse(&buf[..]).is_err());
+
+        let mut buf = [0; 8];
+        let mut frag = FragN::new(&mut buf[..]);
+        frag.set_datagram_size(2047);
+        frag.set_datagram_tag(0xbeef);
+        frag.set_datagram_offset(17);
+
+        assert_eq!(&buf[..5], &[0b11100_111, 0xff, 0xbe, 0xef, 17][..]);
+        let frag = FragN::parse(&buf[..]).unwrap();
+        assert_eq!(frag.get_datagram_size(), 2047);
+        assert_eq!(frag.get_datagram_tag(), 0xbeef);
+        assert_eq!(frag.get_datagram_offset(), 17);
+        assert_eq!(frag.payload().len(), 3);
+        assert!(Frag1::parse(&buf[..]).is_err());
+    }
+
+    #[test]
+    fn headers_size() {
+        let datagram = [
+            0b011_11_1_11,   // DISPATCH + TF + NH + HLIM
+            0b0_0_11_0_0_11, // CID + SAC + SAM + M + DAC + DAM
+            0b1110_000_1,    // NHC Hop-by-Hop + NH
+            4,               // length
+            5,               // Router Alert
+            2,
+            0,
+            0,
+            0b11110_0_11, // NHC UDP: C + P
+            0xab,         // ports
+            0xbe,         // checksum
+            0xef,
+            1, // payload
+            2,
+        ];
+
+        // IPv6 + Hop-by-Hop + UDP
+        assert_eq!(super::headers_size(&datagram), Some((12, 40 + 8 + 8)));
+        // truncated
+        assert_eq!(super::headers_size(&datagram[..5]), None);
+    }
+
+    #[test]
+    fn roundtrip() {
+        let mut buffer = [0; 320];
+        let datagram = datagram(&mut buffer, 300);
+
+        let mut fragments = [[0; 100]; 4];
+        let mut lens = [0; 4];
+        {
+            let mut fragmenter = Fragmenter::new(datagram, 0xbeef).unwrap();
+            for (i, buf) in fragments.iter_mut().enumerate() {
+                lens[i] = fragmenter.next_fragment(buf).unwrap().len();
+            }
+            assert!(fragmenter.is_done());
+            assert!(fragmenter.next_fragment(&mut [0; 100]).is_none());
+        }
+        assert_eq!(lens, [98, 93, 93, 41]);
+
+        // 48 bytes of uncompressed IPv6 + UDP headers
+        let frag = Frag1::parse(&fragments[0][..]).unwrap();
+        assert_eq!(frag.get_datagram_size(), 48 + 300);
+        for (i, offset) in [17, 28, 39].iter().enumerate() {
+            let frag = FragN::parse(&fragments[i + 1][..]).unwrap();
+            assert_eq!(frag.get_datagram_size(), 48 + 300);
+            assert_eq!(frag.get_datagram_offset(), *offset);
+        }
+
+        let mut reassembler: Reassembler<consts::U2, consts::U512> =
+            Reassembler::new(Duration::from_secs(60));
+
+        // out of order arrival
+        for &i in &[3, 0, 1] {
+            assert!(reassembler
+                .insert(SRC, &fragments[i][..lens[i]], Instant::from_millis(0))
+                .unwrap()
+                .is_none());
+        }
+
+        // same tag but different source
+        let other = ll::Addr::Short(ll::ShortAddr(0x4321));
+        assert!(reassembler
+            .insert(other, &fragments[2][..lens[2]], Instant::from_millis(0))
+            .unwrap()
+            .is_none());
+        assert_eq!(reassembler.len(), 2);
+
+        let reassembled = reassembler
+            .insert(SRC, &fragments[2][..lens[2]], Instant::from_millis(100))
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(reassembled, datagram);
+        assert_eq!(reassembler.len(), 1);
+    }
+
+    #[test]
+    fn overlap() {
+        let mut buffer = [0; 320];
+        let datagram = datagram(&mut buffer, 300);
+
+        let mut first = [0; 100];
+        let len = Fragmenter::new(datagram, 1)
+            .unwrap()
+            .next_fragment(&mut first)
+            .unwrap()
+            .len();
+
+        let mut reassembler: Reassembler<consts::U1, consts::U512> =
+            Reassembler::new(Duration::from_secs(60));
+        assert!(reassembler
+            .insert(SRC, &first[..len], Instant::from_millis(0))
+            .unwrap()
+            .is_none());
+
+        // exact duplicates are ignored
+        assert!(reassembler
+            .insert(SRC, &first[..len], Instant::from_millis(0))
+            .unwrap()
+            .is_none());
+        assert_eq!(reassembler.len(), 1);
+
+        // this fragment overlaps with the data of the first one
+        let mut buf = [0; 32];
+        let mut frag = FragN::new(&mut buf[..]);
+        frag.set_datagram_size(48 + 300);
+        frag.set_datagram_tag(1);
+        frag.set_datagram_offset(16);
+        assert_eq!(
+            reassembler.insert(SRC, &buf, Instant::from_millis(0)).err(),
+            Some(Error::Overlap)
+        );
+
+        // the whole datagram was discarded
+        assert!(reassembler.is_empty());
+    }
+
+    #[test]
+    fn timeout() {
+        let mut buf = [0; 32];
+        let mut frag = FragN::new(&mut buf[..]);
+        frag.set_datagram_size(200);
+        frag.set_datagram_offset(4);
+
+        let mut reassembler: Reassembler<consts::U1, consts::U256> =
+            Reassembler::new(Duration::from_secs(60));
+        assert!(reassembler
+            .insert(SRC, &buf, Instant::from_millis(0))
+            .unwrap()
+            .is_none());
+
+        // too large for the reassembly buffer
+        let mut frag = FragN::new(&mut buf[..]);
+        frag.set_datagram_size(256);
+        assert_eq!(
+            reassembler.insert(SRC, &buf, Instant::from_millis(0)).err(),
+            Some(Error::TooLarge)
+        );
+
+        reassembler.expire(Instant::from_millis(60_000));
+        assert!(reassembler.is_empty());
+
+        // not a fragment
+        let mut buffer = [0; 16];
+        assert_eq!(
+            reassembler
+                .insert(SRC, datagram(&mut buffer, 8), Instant::from_millis(60_000))
+                .err(),
+            Some(Error::NotAFragment)
+        );
+    }
+}
@@ -6,7 +6,8 @@ use as_slice::{AsMutSlice, AsSlice};
 use byteorder::{ByteOrder, NetworkEndian as NE};
 use owning_slice::Truncate;
 
-use crate::{fmt::Quoted, ieee802154 as ll, ipv6, traits::UncheckedIndex};
+use super::nhc;
+use crate::{fmt::Quoted, ieee802154 as ll, ipv6, traits::UncheckedIndex, udp};
 
 /* Header format */
 const IPHC0: usize = 0;
@@ -74,6 +75,25 @@ mod dam {
     pub const SIZE: usize = 2;
 }
 
+// Context Identifier Extension; only present if CID = 1
+const CIE: usize = 2;
+
+/// Compression or decompression error
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Error {
+    /// The output buffer is too small to hold the (de)compressed packet
+    BufferTooSmall,
+
+    /// The packet is truncated or its LOWPAN_NHC encoded headers are malformed
+    Malformed,
+
+    /// One of the compressed addresses can't be recovered using the context
+    UnknownAddress,
+
+    /// The decompressed payload doesn't fit in the IPv6 'Payload Length' field
+    TooLarge,
+}
+
 /// LOWPAN_IPHC compressed IPv6 packet
 #[derive(Clone, Copy)]
 pub struct Packet<BUFFER>
@@ -94,13 +114,7 @@ where
     ///
     /// # Notes
     ///
-    /// The following field values are currently not supported and treated as errors
-    ///
-    /// - CID = 1
-    /// - SAC = 1 && SAM != 0
-    /// - DAC = 1
-    ///
-    /// Likewise, extension headers are not supported and their presence are treated as errors
+    /// Extension headers are not supported and their presence are treated as errors
     pub fn parse(bytes: B) -> Result<Self, B> {
         // validation
         if let Ok(len) = (|| {
@@ -121,14 +135,6 @@ where
                 return Err(());
             }
 
-            // unsupported modes currently treated as errors
-            if header.get_cid()
-                || (header.get_sac() && header.get_sam() != 0b00)
-                || header.get_dac()
-            {
-                return Err(());
-            }
-
             // end of IPHC encoding
             len += header.cid_size();
 
@@ -157,6 +163,40 @@ where
     }
 
     /* Accessors */
+    /// Reads the (potentially elided) 'Traffic Class' field
+    ///
+    /// The value is returned in the IPv6 format: DSCP in the upper 6 bits and ECN in the lower 2
+    /// bits
+    pub fn get_traffic_class(&self) -> u8 {
+        let start = usize::from(self.ip_fields_start());
+
+        match self.get_tf() {
+            // ECN + DSCP
+            0b00 | 0b10 => unsafe { self.as_slice().gu(start).rotate_left(2) },
+            // ECN; DSCP elided
+            0b01 => unsafe { *self.as_slice().gu(start) >> 6 },
+            // elided
+            0b11 => 0,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Reads the (potentially elided) 'Flow Label' field
+    pub fn get_flow_label(&self) -> u32 {
+        let start = usize::from(self.ip_fields_start());
+
+        let start = match self.get_tf() {
+            // ECN + DSCP + 4-bit padding + Flow Label
+            0b00 => start + 1,
+            // ECN + 2-bit padding + Flow Label
+            0b01 => start,
+            // elided
+            _ => return 0,
+        };
+
+        NE::read_u24(unsafe { self.as_slice().r(start..start + 3) }) & ((1 << 20) - 1)
+    }
+
     /// Reads the 'Next header' field
     ///
     /// **NOTE**: This returns `None` if the next header is encoded using the LOWPAN_NHC format. In
@@ -236,9 +276,18 @@ where
             (false, 0b11) => Addr::Elided(ElidedAddr { _0: () }),
 
             (true, 0b00) => Addr::Complete(ipv6::Addr::UNSPECIFIED),
-
-            // reserved combination -- we reject these in `parse`
-            (true, _) => unsafe { debug_unreachable!() },
+            (true, 0b01) => Addr::Contextual(ContextualAddr {
+                context: self.get_sci(),
+                kind: Kind::Iid(unsafe { *(self.as_slice().as_ptr().add(start) as *const _) }),
+            }),
+            (true, 0b10) => Addr::Contextual(ContextualAddr {
+                context: self.get_sci(),
+                kind: Kind::Iid(short_iid(unsafe { self.as_slice().r(start..start + 2) })),
+            }),
+            (true, 0b11) => Addr::Contextual(ContextualAddr {
+                context: self.get_sci(),
+                kind: Kind::Elided,
+            }),
 
             _ => unreachable!(),
         }
@@ -331,8 +380,30 @@ where
                 Addr::Complete(ipv6::Addr(bytes))
             }
 
-            // reserved combination -- we reject these in `parse`
-            (_, true, _) => unsafe { debug_unreachable!() },
+            (false, true, 0b01) => Addr::Contextual(ContextualAddr {
+                context: self.get_dci(),
+                kind: Kind::Iid(unsafe { *(self.as_slice().as_ptr().add(start) as *const _) }),
+            }),
+
+            (false, true, 0b10) => Addr::Contextual(ContextualAddr {
+                context: self.get_dci(),
+                kind: Kind::Iid(short_iid(unsafe { self.as_slice().r(start..start + 2) })),
+            }),
+
+            (false, true, 0b11) => Addr::Contextual(ContextualAddr {
+                context: self.get_dci(),
+                kind: Kind::Elided,
+            }),
+
+            (true, true, 0b00) => Addr::Contextual(ContextualAddr {
+                context: self.get_dci(),
+                kind: Kind::Multicast(unsafe {
+                    *(self.as_slice().as_ptr().add(start) as *const _)
+                }),
+            }),
+
+            // reserved combinations -- we reject these in `parse`
+            (false, true, 0b00) | (true, true, _) => unsafe { debug_unreachable!() },
 
             _ => unreachable!(),
         }
@@ -373,6 +444,28 @@ where
         get!(self.header_()[IPHC1], cid) != 0
     }
 
+    /// Reads the 'Source Context Identifier' field
+    ///
+    /// This returns `0` (the default context) if the CID field is not set
+    pub fn get_sci(&self) -> u8 {
+        if self.get_cid() {
+            unsafe { *self.as_slice().gu(CIE) >> 4 }
+        } else {
+            0
+        }
+    }
+
+    /// Reads the 'Destination Context Identifier' field
+    ///
+    /// This returns `0` (the default context) if the CID field is not set
+    pub fn get_dci(&self) -> u8 {
+        if self.get_cid() {
+            unsafe { *self.as_slice().gu(CIE) & 0x0f }
+        } else {
+            0
+        }
+    }
+
     /// Reads the 'Source Address Compression' field
     pub fn get_sac(&self) -> bool {
         get!(self.header_()[IPHC1], sac) != 0
@@ -505,7 +598,7 @@ where
         hop_limit: u8,
         src: ipv6::Addr,
         dest: ipv6::Addr,
-        ctxt: &Context,
+        ctxt: &Context<'_>,
     ) -> Self {
         let blen = buffer.as_slice().len();
 
@@ -518,6 +611,37 @@ where
 
         let mut packet = Packet { buffer, payload: 0 };
 
+        // stateful (context based) compression is only used when stateless compression doesn't
+        // apply
+        let src_ctxt = if src.is_unspecified() || src.is_link_local() {
+            None
+        } else {
+            ctxt.table.and_then(|t| t.compress(src, ctxt.source))
+        };
+        let dest_ctxt = if dest.is_multicast() {
+            if is_stateless_multicast(dest) {
+                None
+            } else {
+                ctxt.table
+                    .and_then(|t| t.compress_multicast(dest))
+                    .map(|cid| Stateful { cid, mode: 0b00 })
+            }
+        } else if dest.is_link_local() {
+            None
+        } else {
+            ctxt.table.and_then(|t| t.compress(dest, ctxt.destination))
+        };
+
+        let sci = src_ctxt.map(|c| c.cid).unwrap_or(0);
+        let dci = dest_ctxt.map(|c| c.cid).unwrap_or(0);
+        if sci != 0 || dci != 0 {
+            packet.set_cid(1);
+
+            idx += 1;
+            assert!(blen >= idx);
+            packet.as_mut_slice()[CIE] = (sci << 4) | dci;
+        }
+
         if let Some(next_header) = next_header {
             idx += 1;
             assert!(blen >= idx);
@@ -542,6 +666,14 @@ where
 
         if src.is_unspecified() {
             packet.set_sac(1);
+        } else if let Some(c) = src_ctxt {
+            packet.set_sac(1);
+            packet.set_sam(c.mode);
+
+            let n = c.inline_size();
+            idx += n;
+            assert!(blen >= idx);
+            packet.as_mut_slice()[idx - n..idx].copy_from_slice(&src.0[16 - n..]);
         } else if src.is_link_local() {
             debug_assert!(!packet.get_sac());
 
@@ -589,7 +721,16 @@ where
         if dest.is_multicast() {
             packet.set_m(1);
 
-            if dest.0[1] == 0x02 && dest.0[2..15] == [0; 13] {
+            if dest_ctxt.is_some() {
+                // ffXX:XXLL:PPPP:PPPP:PPPP:PPPP:XXXX:XXXX
+                packet.set_dac(1);
+                packet.set_dam(0b00);
+
+                idx += 6;
+                assert!(blen >= idx);
+                packet.as_mut_slice()[idx - 6..idx - 4].copy_from_slice(&dest.0[1..3]);
+                packet.as_mut_slice()[idx - 4..idx].copy_from_slice(&dest.0[12..]);
+            } else if dest.0[1] == 0x02 && dest.0[2..15] == [0; 13] {
                 packet.set_dam(0b11);
 
                 idx += 1;
@@ -610,12 +751,20 @@ where
                 packet.as_mut_slice()[idx - 6] = dest.0[1];
                 packet.as_mut_slice()[idx - 5..idx].copy_from_slice(&dest.0[11..]);
             } else {
-                packet.set_dam(0b11);
+                packet.set_dam(0b00);
 
                 idx += 16;
                 assert!(blen >= idx);
                 packet.as_mut_slice()[idx - 16..idx].copy_from_slice(&dest.0);
             }
+        } else if let Some(c) = dest_ctxt {
+            packet.set_dac(1);
+            packet.set_dam(c.mode);
+
+            let n = c.inline_size();
+            idx += n;
+            assert!(blen >= idx);
+            packet.as_mut_slice()[idx - n..idx].copy_from_slice(&dest.0[16 - n..]);
         } else {
             debug_assert!(!packet.get_m());
 
@@ -671,6 +820,10 @@ where
     }
 
     /* Private */
+    fn set_tf(&mut self, tf: u8) {
+        set!(self.header_mut_()[IPHC0], tf, tf);
+    }
+
     fn set_nh(&mut self, nh: u8) {
         set!(self.header_mut_()[IPHC0], nh, nh);
     }
@@ -679,6 +832,10 @@ where
         set!(self.header_mut_()[IPHC0], hlim, hlim);
     }
 
+    fn set_cid(&mut self, cid: u8) {
+        set!(self.header_mut_()[IPHC1], cid, cid);
+    }
+
     fn set_sac(&mut self, sac: u8) {
         set!(self.header_mut_()[IPHC1], sac, sac);
     }
@@ -691,6 +848,10 @@ where
         set!(self.header_mut_()[IPHC1], m, m);
     }
 
+    fn set_dac(&mut self, dac: u8) {
+        set!(self.header_mut_()[IPHC1], dac, dac);
+    }
+
     fn set_dam(&mut self, dam: u8) {
         set!(self.header_mut_()[IPHC1], dam, dam);
     }
@@ -760,6 +921,9 @@ where
             Addr::Elided(ea) => {
                 s.field("source", &Quoted(ea));
             }
+            Addr::Contextual(ca) => {
+                s.field("source", &Quoted(ca));
+            }
         }
 
         match self.get_destination() {
@@ -769,6 +933,9 @@ where
             Addr::Elided(ea) => {
                 s.field("destination", &Quoted(ea));
             }
+            Addr::Contextual(ca) => {
+                s.field("destination", &Quoted(ca));
+            }
         }
 
         // s.field("payload", &self.payload());
@@ -776,12 +943,508 @@ where
     }
 }
 
+// IPHC + CID + NH + HLIM + Source Address + Destination Address (TF not included)
+const MAX_HEADER_SIZE: usize = 2 + 1 + 1 + 1 + 16 + 16;
+
+// NHC ID + ports + checksum
+const MAX_NHC_UDP_SIZE: usize = 1 + 4 + 2;
+
+// Maximum number of encapsulated IPv6 headers (IPv6-in-IPv6) that are compressed or decompressed;
+// this bounds the recursion between the IPHC and NHC (de)compressors
+const MAX_DEPTH: u8 = 4;
+
+/// Compresses the IPv6 `packet` into `buffer` using the LOWPAN_IPHC encoding
+///
+/// The most compact encoding of each IPv6 header field is picked. The extension headers, UDP
+/// header and encapsulated IPv6 packet (IPv6-in-IPv6) that follow the IPv6 header are compressed
+/// using the LOWPAN_NHC encoding. The UDP checksum is always carried in-line; the padding at the
+/// end of Hop-by-Hop and Destination Options headers is elided.
+///
+/// `ctxt` must contain the link-layer addresses of the frame that will carry the compressed
+/// packet. An encapsulated IPv6 packet is compressed without link-layer information; past 4
+/// levels of IPv6-in-IPv6 encapsulation the innermost packets are carried in-line.
+///
+/// This function returns an error if
+///
+/// - `buffer` is too small to hold the compressed packet (`Error::BufferTooSmall`)
+/// - the payload of `packet` is shorter than its 'Payload Length' field indicates
+///   (`Error::Malformed`)
+pub fn compress<'b, B>(
+    packet: &ipv6::Packet<B>,
+    ctxt: &Context<'_>,
+    buffer: &'b mut [u8],
+) -> Result<Packet<&'b mut [u8]>, Error>
+where
+    B: AsSlice<Element = u8>,
+{
+    compress_(packet, ctxt, buffer, 0)
+}
+
+// `depth` is the number of IPv6 headers that encapsulate `packet`
+fn compress_<'b, B>(
+    packet: &ipv6::Packet<B>,
+    ctxt: &Context<'_>,
+    buffer: &'b mut [u8],
+    depth: u8,
+) -> Result<Packet<&'b mut [u8]>, Error>
+where
+    B: AsSlice<Element = u8>,
+{
+    let next_header = packet.get_next_header();
+    let payload = packet
+        .payload()
+        .get(..usize::from(packet.get_length()))
+        .ok_or(Error::Malformed)?;
+
+    // Traffic Class (DSCP + ECN) and Flow Label
+    let tc = packet.get_traffic_class();
+    let fl = packet.get_flow_label();
+    let (dscp, ecn) = (tc >> 2, tc & 0b11);
+    let mut tf_inline = [0; 4];
+    let (tf, tf_size) = match (dscp, fl) {
+        (0, 0) if ecn == 0 => (0b11, 0),
+        (_, 0) => {
+            tf_inline[0] = tc.rotate_right(2);
+            (0b10, 1)
+        }
+        (0, _) => {
+            NE::write_u24(&mut tf_inline[..3], fl);
+            tf_inline[0] |= ecn << 6;
+            (0b01, 3)
+        }
+        _ => {
+            tf_inline[0] = tc.rotate_right(2);
+            NE::write_u24(&mut tf_inline[1..], fl);
+            (0b00, 4)
+        }
+    };
+
+    let mut header = [0; MAX_HEADER_SIZE];
+    let header_len = usize::from(
+        Packet::new(
+            &mut header[..],
+            if is_nhc_compressible(next_header, payload, depth) {
+                None
+            } else {
+                Some(next_header)
+            },
+            packet.get_hop_limit(),
+            packet.get_source(),
+            packet.get_destination(),
+            ctxt,
+        )
+        .payload,
+    );
+
+    let start = header_len + tf_size;
+    if buffer.len() < start {
+        return Err(Error::BufferTooSmall);
+    }
+
+    // the in-line TF fields go between the CID extension and the in-line NH field
+    let cie = usize::from(get!(header[IPHC1], cid)) + 2;
+    buffer[..cie].copy_from_slice(&header[..cie]);
+    buffer[cie..cie + tf_size].copy_from_slice(&tf_inline[..tf_size]);
+    buffer[cie + tf_size..start].copy_from_slice(&header[cie..header_len]);
+
+    let len = start
+        + if get!(header[IPHC0], nh) != 0 {
+            compress_nhc(
+                next_header,
+                payload,
+                ctxt.table,
+                &mut buffer[start..],
+                depth,
+            )?
+        } else {
+            copy(payload, &mut buffer[start..])?
+        };
+
+    let mut packet = Packet {
+        buffer: &mut buffer[..len],
+        payload: start as u8,
+    };
+    packet.set_tf(tf);
+
+    Ok(packet)
+}
+
+/// Decompresses the LOWPAN_IPHC `packet` into `buffer`, restoring the original IPv6 packet
+///
+/// `ctxt` must contain the link-layer addresses of the frame that carried `packet`; these are
+/// used to recover elided addresses. Addresses compressed against a shared context are recovered
+/// using `ctxt.table`.
+///
+/// This function returns an error if
+///
+/// - `buffer` is too small to hold the IPv6 packet (`Error::BufferTooSmall`)
+/// - one of the addresses can't be recovered using `ctxt` (`Error::UnknownAddress`)
+/// - the LOWPAN_NHC encoded headers are malformed, or nest more than 4 encapsulated IPv6 headers
+///   (`Error::Malformed`)
+/// - the decompressed payload doesn't fit in the IPv6 'Payload Length' field (`Error::TooLarge`)
+pub fn decompress<'b, B>(
+    packet: &Packet<B>,
+    ctxt: &Context<'_>,
+    buffer: &'b mut [u8],
+) -> Result<ipv6::Packet<&'b mut [u8]>, Error>
+where
+    B: AsSlice<Element = u8>,
+{
+    decompress_(packet, ctxt, buffer, 0)
+}
+
+// `depth` is the number of IPv6 headers that encapsulate `packet`
+fn decompress_<'b, B>(
+    packet: &Packet<B>,
+    ctxt: &Context<'_>,
+    buffer: &'b mut [u8],
+    depth: u8,
+) -> Result<ipv6::Packet<&'b mut [u8]>, Error>
+where
+    B: AsSlice<Element = u8>,
+{
+    let src = packet
+        .get_source()
+        .complete_(ctxt.table, ctxt.source)
+        .ok_or(Error::UnknownAddress)?;
+    let dest = packet
+        .get_destination()
+        .complete_(ctxt.table, ctxt.destination)
+        .ok_or(Error::UnknownAddress)?;
+
+    let start = usize::from(ipv6::HEADER_SIZE);
+    if buffer.len() < start {
+        return Err(Error::BufferTooSmall);
+    }
+
+    let (next_header, payload_len) = if let Some(next_header) = packet.get_next_header() {
+        (next_header, copy(packet.payload(), &mut buffer[start..])?)
+    } else {
+        decompress_nhc(
+            packet.payload(),
+            src,
+            dest,
+            ctxt.table,
+            &mut buffer[start..],
+            depth,
+        )?
+    };
+
+    if payload_len > usize::from(u16::MAX) {
+        return Err(Error::TooLarge);
+    }
+
+    let mut ip = ipv6::Packet::new(&mut buffer[..start + payload_len]);
+    ip.set_traffic_class(packet.get_traffic_class());
+    ip.set_flow_label(packet.get_flow_label());
+    ip.set_next_header(next_header);
+    ip.set_hop_limit(packet.get_hop_limit());
+    ip.set_source(src);
+    ip.set_destination(dest);
+
+    Ok(ip)
+}
+
+// Can the header identified by `next_header`, which is at the start of `payload`, be encoded using
+// LOWPAN_NHC?
+fn is_nhc_compressible(next_header: ipv6::NextHeader, payload: &[u8], depth: u8) -> bool {
+    match next_header {
+        // the UDP 'Length' field is elided so it must match the size of the payload
+        ipv6::NextHeader::Udp => udp::Packet::parse(payload)
+            .map(|udp| usize::from(udp.get_length()) == payload.len())
+            .unwrap_or(false),
+
+        // past `MAX_DEPTH` the encapsulated packet is carried in-line
+        ipv6::NextHeader::Ipv6 => {
+            depth < MAX_DEPTH
+                && ipv6::Packet::parse(payload)
+                    .map(|ip| ip.payload().len() == usize::from(ip.get_length()))
+                    .unwrap_or(false)
+        }
+
+        ipv6::NextHeader::Ipv6Frag => payload.len() >= usize::from(ipv6::ext::FRAGMENT_HEADER_SIZE),
+
+        _ => {
+            if nhc::Eid::from_next_header(next_header).is_none() || payload.len() < 2 {
+                return false;
+            }
+
+            // the 'Length' field of the compressed header is 8 bits wide
+            let len = (usize::from(payload[1]) + 1) * 8;
+            len <= payload.len() && elide_padding(next_header, &payload[2..len]).len() <= 255
+        }
+    }
+}
+
+// Compresses the chain of headers that starts with the one identified by `next_header` using
+// LOWPAN_NHC; whatever can't be compressed is carried in-line. Returns the number of bytes written
+// into `buffer`
+fn compress_nhc(
+    mut next_header: ipv6::NextHeader,
+    mut payload: &[u8],
+    table: Option<&ContextTable>,
+    buffer: &mut [u8],
+    depth: u8,
+) -> Result<usize, Error> {
+    let mut len = 0;
+
+    loop {
+        let eid = if let Some(eid) = nhc::Eid::from_next_header(next_header) {
+            eid
+        } else {
+            // NOTE `is_nhc_compressible` returned `true` so this must be UDP
+            let udp = udp::Packet::parse(payload).map_err(|_| Error::Malformed)?;
+
+            let mut header = [0; MAX_NHC_UDP_SIZE];
+            let mut up = nhc::UdpPacket::new(
+                &mut header[..],
+                false,
+                udp.get_source(),
+                udp.get_destination(),
+            );
+            // 'Checksum' field
+            unsafe { up.set_checksum(NE::read_u16(&udp.as_bytes()[6..8])) }
+            let header_len = MAX_NHC_UDP_SIZE - up.payload_mut().len();
+
+            len += copy(&header[..header_len], &mut buffer[len..])?;
+            return Ok(len + copy(udp.payload(), &mut buffer[len..])?);
+        };
+
+        if eid == nhc::Eid::Ipv6 {
+            let ip = ipv6::Packet::parse(payload).map_err(|_| Error::Malformed)?;
+            nhc::ExtensionHeader::new(
+                buffer.get_mut(len..len + 1).ok_or(Error::BufferTooSmall)?,
+                eid,
+                None,
+                &[],
+            );
+            len += 1;
+
+            let ctxt = Context {
+                source: None,
+                destination: None,
+                table,
+            };
+            return Ok(len
+                + compress_(&ip, &ctxt, &mut buffer[len..], depth + 1)?
+                    .bytes()
+                    .len());
+        }
+
+        let header_len = if eid == nhc::Eid::Fragment {
+            usize::from(ipv6::ext::FRAGMENT_HEADER_SIZE)
+        } else {
+            (usize::from(payload[1]) + 1) * 8
+        };
+        let (header, rest) = payload.split_at(header_len);
+        let data = elide_padding(next_header, &header[2..]);
+
+        let following = ipv6::NextHeader::from(header[0]);
+        // the data that follows the Fragment header of a non-first fragment is not a header
+        let is_fragment_data = eid == nhc::Eid::Fragment && NE::read_u16(&header[2..4]) >> 3 != 0;
+        let is_nhc = !is_fragment_data && is_nhc_compressible(following, rest, depth);
+
+        let nhc_len = 2 + data.len() + if is_nhc { 0 } else { 1 };
+        nhc::ExtensionHeader::new(
+            buffer
+                .get_mut(len..len + nhc_len)
+                .ok_or(Error::BufferTooSmall)?,
+            eid,
+            if is_nhc { None } else { Some(following) },
+            data,
+        );
+        len += nhc_len;
+
+        if !is_nhc {
+            return Ok(len + copy(rest, &mut buffer[len..])?);
+        }
+
+        next_header = following;
+        payload = rest;
+    }
+}
+
+// Decompresses the chain of LOWPAN_NHC encoded headers in `bytes`, and the payload that follows
+// them, into `buffer`. Returns the 'Next Header' value that identifies the first header and the
+// number of bytes written into `buffer`
+fn decompress_nhc(
+    mut bytes: &[u8],
+    src: ipv6::Addr,
+    dest: ipv6::Addr,
+    table: Option<&ContextTable>,
+    buffer: &mut [u8],
+    depth: u8,
+) -> Result<(ipv6::NextHeader, usize), Error> {
+    let mut first = None;
+    // position of the 'Next Header' field that identifies the header being decompressed
+    let mut nh_pos: Option<usize> = None;
+    let mut len = 0;
+
+    loop {
+        let (next_header, next_nh_pos) = if let Ok(eh) = nhc::ExtensionHeader::parse(bytes) {
+            let eid = eh.get_eid();
+
+            if eid == nhc::Eid::Ipv6 {
+                if depth >= MAX_DEPTH {
+                    return Err(Error::Malformed);
+                }
+
+                let ctxt = Context {
+                    source: None,
+                    destination: None,
+                    table,
+                };
+                let ip = Packet::parse(eh.payload()).map_err(|_| Error::Malformed)?;
+                len += decompress_(&ip, &ctxt, &mut buffer[len..], depth + 1)?
+                    .as_bytes()
+                    .len();
+
+                (eid.next_header(), None)
+            } else {
+                let header_len = eh.uncompressed_size();
+                let data = eh.data();
+                let header = buffer
+                    .get_mut(len..len + header_len)
+                    .ok_or(Error::BufferTooSmall)?;
+
+                header[0] = eh.get_next_header().map(|nh| nh.into()).unwrap_or(0);
+                header[1] = if eid == nhc::Eid::Fragment {
+                    // 'Reserved' field
+                    0
+                } else {
+                    (header_len / 8 - 1) as u8
+                };
+                header[2..2 + data.len()].copy_from_slice(data);
+
+                // restore the padding elided by the compressor; a zero byte is a Pad1 option
+                let padding = &mut header[2 + data.len()..];
+                for byte in padding.iter_mut() {
+                    *byte = 0;
+                }
+
+                let n = padding.len();
+                if n > 1 && (eid == nhc::Eid::HopByHop || eid == nhc::Eid::DestinationOptions) {
+                    // PadN option
+                    padding[0] = 1;
+                    padding[1] = (n - 2) as u8;
+                }
+
+                let pos = len;
+                len += header_len;
+
+                if eh.get_nh() {
+                    bytes = &bytes[bytes.len() - eh.payload().len()..];
+
+                    (eid.next_header(), Some(pos))
+                } else {
+                    len += copy(eh.payload(), &mut buffer[len..])?;
+
+                    (eid.next_header(), None)
+                }
+            }
+        } else {
+            let nhc = nhc::UdpPacket::parse(bytes).map_err(|_| Error::Malformed)?;
+
+            let udp_len = usize::from(udp::HEADER_SIZE) + nhc.payload().len();
+            let mut udp = udp::Packet::new(
+                buffer
+                    .get_mut(len..len + udp_len)
+                    .ok_or(Error::BufferTooSmall)?,
+            );
+            udp.set_source(nhc.get_source());
+            udp.set_destination(nhc.get_destination());
+            udp.payload_mut().copy_from_slice(nhc.payload());
+
+            if let Some(cksum) = nhc.get_checksum() {
+                // 'Checksum' field
+                NE::write_u16(&mut buffer[len + 6..len + 8], cksum);
+            } else {
+                // the checksum was elided so we must compute it (see section 4.3.2 of RFC 6282)
+                udp.update_ipv6_checksum(src, dest);
+            }
+            len += udp_len;
+
+            (ipv6::NextHeader::Udp, None)
+        };
+
+        // fill the 'Next Header' field of the previous header
+        if let Some(pos) = nh_pos {
+            buffer[pos] = next_header.into();
+        } else {
+            first = Some(next_header);
+        }
+
+        nh_pos = next_nh_pos;
+        if nh_pos.is_none() {
+            return Ok((
+                first.unwrap_or_else(|| unsafe { debug_unreachable!() }),
+                len,
+            ));
+        }
+    }
+}
+
+// Removes the padding options at the end of the options area of a Hop-by-Hop or Destination
+// Options header; the `data` of other extension headers is returned as it is
+fn elide_padding(next_header: ipv6::NextHeader, data: &[u8]) -> &[u8] {
+    if next_header != ipv6::NextHeader::Hopopt && next_header != ipv6::NextHeader::Ipv6Opts {
+        return data;
+    }
+
+    let mut end = 0;
+    let mut i = 0;
+    while i < data.len() {
+        let (ty, len) = match data[i] {
+            // Pad1
+            0 => (0, 1),
+            ty => (ty, 2 + usize::from(*data.get(i + 1).unwrap_or(&0))),
+        };
+
+        i += len;
+
+        // not a PadN option
+        if ty != 0 && ty != 1 {
+            end = i.min(data.len());
+        }
+    }
+
+    &data[..end]
+}
+
+// Copies `src` into the start of `dst`; returns the number of bytes copied
+fn copy(src: &[u8], dst: &mut [u8]) -> Result<usize, Error> {
+    dst.get_mut(..src.len())
+        .ok_or(Error::BufferTooSmall)?
+        .copy_from_slice(src);
+    Ok(src.len())
+}
+
 /// Maybe IPHC compressed address
+///
+/// NOTE new variants may be added as more compression modes are supported
+#[non_exhaustive]
 pub enum Addr {
     /// Complete address
     Complete(ipv6::Addr),
     /// Elided address
     Elided(ElidedAddr),
+    /// Address compressed against a shared context (stateful compression)
+    Contextual(ContextualAddr),
+}
+
+impl Addr {
+    // Completes this address using whatever information is available
+    fn complete_(
+        self,
+        table: Option<&ContextTable>,
+        ll_addr: Option<ll::Addr>,
+    ) -> Option<ipv6::Addr> {
+        match self {
+            Addr::Complete(addr) => Some(addr),
+            Addr::Elided(ea) => ll_addr.map(|ll_addr| ea.complete_(ll_addr)),
+            Addr::Contextual(ca) => ca.complete_(table?, ll_addr),
+        }
+    }
 }
 
 /// Fully elided IPv6 address
@@ -811,48 +1474,327 @@ impl ElidedAddr {
         bytes[0] = 0xfe;
         bytes[1] = 0x80;
 
-        match ll_addr {
-            ll::Addr::Short(sa) => {
-                // map into an EUI-64 address
-                bytes[11] = 0xff;
-                bytes[12] = 0xfe;
+        bytes[8..].copy_from_slice(&ll_iid(ll_addr));
+
+        ipv6::Addr(bytes)
+    }
+}
+
+/// IPv6 address compressed against one of the contexts of a `ContextTable`
+pub struct ContextualAddr {
+    context: u8,
+    kind: Kind,
+}
 
-                NE::write_u16(&mut bytes[14..], sa.0);
+enum Kind {
+    // Interface Identifier carried in-line (fully or partially)
+    Iid([u8; 8]),
+    // Interface Identifier derived from the link-layer address
+    Elided,
+    // bytes carried in-line of a ffXX:XXLL:PPPP:PPPP:PPPP:PPPP:XXXX:XXXX multicast address
+    Multicast([u8; 6]),
+}
+
+impl fmt::Display for ContextualAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            Kind::Iid(iid) => {
+                write!(f, "ctx{}:", self.context)?;
+                for chunk in iid.chunks(2) {
+                    write!(f, ":{:x}", NE::read_u16(chunk))?;
+                }
+
+                Ok(())
             }
-            ll::Addr::Extended(ea) => bytes[8..].copy_from_slice(&ea.eui_64()),
+            Kind::Elided => write!(f, "ctx{}::X:X:X:X", self.context),
+            Kind::Multicast(bytes) => write!(
+                f,
+                "ff{:02x}:{:02x}LL:ctx{}:{:x}:{:x}",
+                bytes[0],
+                bytes[1],
+                self.context,
+                NE::read_u16(&bytes[2..4]),
+                NE::read_u16(&bytes[4..]),
+            ),
         }
+    }
+}
 
-        ipv6::Addr(bytes)
+impl ContextualAddr {
+    /// Returns the identifier of the context this address was compressed against
+    pub fn get_context(&self) -> u8 {
+        self.context
+    }
+
+    /// Complete this address using the shared context and Link-layer information
+    ///
+    /// Returns `None` if the context is not in the `table` (the packet should be discarded in
+    /// that case)
+    pub fn complete<A>(self, table: &ContextTable, ll_addr: A) -> Option<ipv6::Addr>
+    where
+        A: Into<ll::Addr>,
+    {
+        self.complete_(table, Some(ll_addr.into()))
+    }
+
+    fn complete_(self, table: &ContextTable, ll_addr: Option<ll::Addr>) -> Option<ipv6::Addr> {
+        let prefix = table.get(self.context)?;
+
+        Some(match self.kind {
+            Kind::Iid(iid) => prefix.expand(iid),
+            Kind::Elided => prefix.expand(ll_iid(ll_addr?)),
+            Kind::Multicast(inline) => {
+                if prefix.len > 64 {
+                    // doesn't fit in the multicast address
+                    return None;
+                }
+
+                let mut bytes = [0; 16];
+                bytes[0] = 0xff;
+                bytes[1..3].copy_from_slice(&inline[..2]);
+                bytes[3] = prefix.len;
+                bytes[4..12].copy_from_slice(&prefix.addr.0[..8]);
+                bytes[12..].copy_from_slice(&inline[2..]);
+
+                ipv6::Addr(bytes)
+            }
+        })
     }
 }
 
 /// IPHC encoding context
-pub struct Context {
+pub struct Context<'a> {
     /// Source link-layer address
     pub source: Option<ll::Addr>,
 
     /// Destination link-layer address
     pub destination: Option<ll::Addr>,
+
+    /// Contexts shared by all the nodes of the network; used for stateful address compression
+    pub table: Option<&'a ContextTable>,
 }
 
-impl Context {
+impl Context<'static> {
     /// No context
     pub fn empty() -> Self {
         Context {
             source: None,
             destination: None,
+            table: None,
+        }
+    }
+}
+
+/// An IPv6 prefix shared by the nodes of the network
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Prefix {
+    addr: ipv6::Addr,
+    len: u8,
+}
+
+impl Prefix {
+    /// Creates a new prefix from the first `len` bits of `addr`
+    ///
+    /// # Panics
+    ///
+    /// This constructor panics if `len` is greater than 128
+    pub fn new(addr: ipv6::Addr, len: u8) -> Self {
+        assert!(len <= 128);
+
+        let mut bytes = [0; 16];
+        overlay(&mut bytes, &addr.0, len);
+
+        Prefix {
+            addr: ipv6::Addr(bytes),
+            len,
+        }
+    }
+
+    /// Returns the prefix, padded with zeros
+    pub fn get_addr(&self) -> ipv6::Addr {
+        self.addr
+    }
+
+    /// Returns the length of the prefix, in bits
+    pub fn get_len(&self) -> u8 {
+        self.len
+    }
+
+    /// Does `addr` start with this prefix?
+    pub fn contains(&self, addr: ipv6::Addr) -> bool {
+        let mut bytes = addr.0;
+        overlay(&mut bytes, &self.addr.0, self.len);
+        bytes == addr.0
+    }
+
+    // Builds an address from this prefix and the given Interface Identifier; the bits covered by
+    // the prefix take precedence over the IID bits
+    fn expand(&self, iid: [u8; 8]) -> ipv6::Addr {
+        let mut bytes = [0; 16];
+        bytes[8..].copy_from_slice(&iid);
+        overlay(&mut bytes, &self.addr.0, self.len);
+
+        ipv6::Addr(bytes)
+    }
+}
+
+/// Table of the (up to 16) contexts used for stateful address compression
+///
+/// The table is indexed by Context IDentifier (CID). Context 0 is the default context; it's used
+/// when a packet doesn't carry the CID extension.
+#[derive(Clone, Debug, Default)]
+pub struct ContextTable {
+    prefixes: [Option<Prefix>; 16],
+}
+
+impl ContextTable {
+    /// Creates an empty table
+    pub fn new() -> Self {
+        ContextTable {
+            prefixes: [None; 16],
+        }
+    }
+
+    /// Returns the context with the given `cid`
+    pub fn get(&self, cid: u8) -> Option<&Prefix> {
+        self.prefixes.get(usize::from(cid))?.as_ref()
+    }
+
+    /// Sets the context `cid` to `prefix`
+    ///
+    /// # Panics
+    ///
+    /// This method panics if `cid` is greater than 15
+    pub fn set(&mut self, cid: u8, prefix: Prefix) {
+        self.prefixes[usize::from(cid)] = Some(prefix);
+    }
+
+    /// Removes the context `cid` from the table
+    pub fn remove(&mut self, cid: u8) -> Option<Prefix> {
+        self.prefixes.get_mut(usize::from(cid))?.take()
+    }
+
+    // Picks the context, and address mode, that compresses the unicast `addr` the most
+    fn compress(&self, addr: ipv6::Addr, ll_addr: Option<ll::Addr>) -> Option<Stateful> {
+        let mut best: Option<Stateful> = None;
+
+        for (cid, prefix) in self.prefixes.iter().enumerate() {
+            let prefix = if let Some(prefix) = prefix {
+                prefix
+            } else {
+                continue;
+            };
+
+            if !prefix.contains(addr) {
+                continue;
+            }
+
+            let mut iid = [0; 8];
+            iid.copy_from_slice(&addr.0[8..]);
+
+            let mode = if ll_addr.map(|ll| prefix.expand(ll_iid(ll)) == addr) == Some(true) {
+                0b11
+            } else if prefix.expand(short_iid(&addr.0[14..])) == addr {
+                0b10
+            } else if prefix.expand(iid) == addr {
+                0b01
+            } else {
+                // bits not covered by the prefix are not zero
+                continue;
+            };
+
+            let candidate = Stateful {
+                cid: cid as u8,
+                mode,
+            };
+            if best
+                .map(|b| candidate.inline_size() < b.inline_size())
+                .unwrap_or(true)
+            {
+                best = Some(candidate);
+            }
+        }
+
+        best
+    }
+
+    // Finds a context that matches the prefix embedded in the unicast-prefix-based multicast
+    // `addr` (see RFC 3306)
+    fn compress_multicast(&self, addr: ipv6::Addr) -> Option<u8> {
+        self.prefixes
+            .iter()
+            .position(|prefix| {
+                prefix
+                    .map(|p| p.len <= 64 && addr.0[3] == p.len && addr.0[4..12] == p.addr.0[..8])
+                    .unwrap_or(false)
+            })
+            .map(|cid| cid as u8)
+    }
+}
+
+// Stateful encoding of an address
+#[derive(Clone, Copy)]
+struct Stateful {
+    cid: u8,
+    // SAM / DAM
+    mode: u8,
+}
+
+impl Stateful {
+    fn inline_size(&self) -> usize {
+        match self.mode {
+            0b01 => 8,
+            0b10 => 2,
+            _ => 0,
         }
     }
 }
 
+// Can the multicast `addr` be compressed without using a context?
+fn is_stateless_multicast(addr: ipv6::Addr) -> bool {
+    (addr.0[1] == 0x02 && addr.0[2..15] == [0; 13]) || addr.0[2..11] == [0; 9]
+}
+
+// Interface Identifier derived from a link-layer address
+fn ll_iid(ll_addr: ll::Addr) -> [u8; 8] {
+    match ll_addr {
+        // map into an EUI-64 address
+        ll::Addr::Short(sa) => {
+            let mut iid = short_iid(&[0, 0]);
+            NE::write_u16(&mut iid[6..], sa.0);
+            iid
+        }
+        ll::Addr::Extended(ea) => ea.eui_64(),
+    }
+}
+
+// 0000:00ff:fe00:XXXX
+fn short_iid(bytes: &[u8]) -> [u8; 8] {
+    let mut iid = [0, 0, 0, 0xff, 0xfe, 0, 0, 0];
+    iid[6..].copy_from_slice(bytes);
+    iid
+}
+
+// Overwrites the first `len` bits of `bytes` with the bits of `prefix`
+fn overlay(bytes: &mut [u8; 16], prefix: &[u8; 16], len: u8) {
+    let full = usize::from(len / 8);
+    bytes[..full].copy_from_slice(&prefix[..full]);
+
+    let rem = len % 8;
+    if rem != 0 {
+        let mask = !0u8 << (8 - rem);
+        bytes[full] = (prefix[full] & mask) | (bytes[full] & !mask);
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use as_slice::AsSlice;
     use rand::RngCore;
 
-    use super::{Addr, Context, ElidedAddr, Packet};
+    use super::{Addr, Context, ContextTable, ElidedAddr, Packet, Prefix};
 
-    use crate::{ieee802154 as ll, ipv6};
+    use crate::{ieee802154 as ll, ipv6, udp};
 
     #[test]
     fn offsets() {
@@ -909,6 +1851,17 @@ mod tests {
         );
     }
 
+    fn complete(addr: Addr, ctxt: &Context<'_>, ll_addr: Option<ll::Addr>) -> ipv6::Addr {
+        // NOTE the link-layer address is only used if the address is (partially) elided
+        let ll_addr = ll_addr.unwrap_or(ll::ShortAddr(0xffff).into());
+
+        match addr {
+            Addr::Complete(addr) => addr,
+            Addr::Elided(ea) => ea.complete(ll_addr),
+            Addr::Contextual(ca) => ca.complete(ctxt.table.unwrap(), ll_addr).unwrap(),
+        }
+    }
+
     #[test]
     fn new() {
         let mut bytes = [0; 128];
@@ -938,18 +1891,9 @@ mod tests {
                     let packet = Packet::parse(bytes).unwrap();
 
                     assert_eq!(packet.get_hop_limit(), 255);
+                    assert_eq!(complete(packet.get_source(), &ctxt, ctxt.source), src);
                     assert_eq!(
-                        match packet.get_source() {
-                            Addr::Complete(addr) => addr,
-                            Addr::Elided(addr) => addr.complete(ctxt.source.unwrap()),
-                        },
-                        src
-                    );
-                    assert_eq!(
-                        match packet.get_destination() {
-                            Addr::Complete(addr) => addr,
-                            Addr::Elided(ea) => ea.complete(ctxt.destination.unwrap()),
-                        },
+                        complete(packet.get_destination(), &ctxt, ctxt.destination),
                         dest
                     );
                     assert_eq!(packet.payload(), &[]);
@@ -1014,6 +1958,7 @@ mod tests {
             Context {
                 source: None,
                 destination: Some(ll::ShortAddr(0xdead).into()),
+                table: None,
             },
             ipv6::Addr::UNSPECIFIED,
             ipv6::Addr([0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xff, 0xfe, 0, 0xde, 0xad]),
@@ -1031,6 +1976,7 @@ mod tests {
             Context {
                 source: None,
                 destination: Some(ll::ExtendedAddr(0x20_18_05_21_23_59_59_01).into()),
+                table: None,
             },
             ipv6::Addr::UNSPECIFIED,
             ipv6::Addr([
@@ -1065,6 +2011,7 @@ mod tests {
             Context {
                 source: Some(ll::ShortAddr(0xdead).into()),
                 destination: None,
+                table: None,
             },
             ipv6::Addr([0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xff, 0xfe, 0, 0xde, 0xad]),
             ipv6::Addr([0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15]),
@@ -1082,6 +2029,7 @@ mod tests {
             Context {
                 source: Some(ll::ExtendedAddr(0x20_18_05_21_23_59_59_01).into()),
                 destination: None,
+                table: None,
             },
             ipv6::Addr([
                 0xfe,
@@ -1153,4 +2101,461 @@ mod tests {
             }
         );
     }
+
+    #[test]
+    fn stateful() {
+        let mut table = ContextTable::new();
+        // default context
+        table.set(
+            0,
+            Prefix::new(
+                ipv6::Addr([
+                    0x20, 0x01, 0x0d, 0xb8, 0xaa, 0xaa, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+                ]),
+                48,
+            ),
+        );
+        table.set(
+            3,
+            Prefix::new(
+                ipv6::Addr([0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]),
+                64,
+            ),
+        );
+
+        let ctxt = Context {
+            source: Some(ll::ShortAddr(0xbeef).into()),
+            destination: None,
+            table: Some(&table),
+        };
+
+        let src = ipv6::Addr([
+            0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0xff, 0xfe, 0, 0xbe, 0xef,
+        ]);
+        let dest = ipv6::Addr([
+            0x20, 0x01, 0x0d, 0xb8, 0xaa, 0xaa, 0, 0, 0, 1, 0, 2, 0, 3, 0, 4,
+        ]);
+
+        let mut bytes = [0; 64];
+        let mut packet = Packet::new(
+            &mut bytes[..],
+            Some(ipv6::NextHeader::Udp),
+            64,
+            src,
+            dest,
+            &ctxt,
+        );
+        packet.set_payload(&[]);
+
+        let packet = Packet::parse(packet.bytes()).unwrap();
+        // IPHC + CID + NH + 64-bit destination IID
+        assert_eq!(packet.header().len(), 2 + 1 + 1 + 8);
+        assert!(packet.get_cid());
+        assert_eq!(packet.get_sci(), 3);
+        assert_eq!(packet.get_dci(), 0);
+        assert!(packet.get_sac());
+        assert_eq!(packet.get_sam(), 0b11);
+        assert!(packet.get_dac());
+        assert!(!packet.get_m());
+        assert_eq!(packet.get_dam(), 0b01);
+        assert_eq!(complete(packet.get_source(), &ctxt, ctxt.source), src);
+        assert_eq!(complete(packet.get_destination(), &ctxt, None), dest);
+
+        // unknown context
+        if let Addr::Contextual(ca) = packet.get_source() {
+            assert!(ca
+                .complete(&ContextTable::new(), ctxt.source.unwrap())
+                .is_none());
+        } else {
+            panic!("expected a contextual address");
+        }
+
+        // unicast-prefix-based multicast address: ff3e:40:2001:db8::1234:5678
+        let dest = ipv6::Addr([
+            0xff, 0x3e, 0, 0x40, 0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0x12, 0x34, 0x56, 0x78,
+        ]);
+
+        let mut bytes = [0; 64];
+        let mut packet = Packet::new(
+            &mut bytes[..],
+            Some(ipv6::NextHeader::Udp),
+            64,
+            ipv6::Addr::UNSPECIFIED,
+            dest,
+            &ctxt,
+        );
+        packet.set_payload(&[]);
+
+        let packet = Packet::parse(packet.bytes()).unwrap();
+        // IPHC + CID + NH + 48 bits of destination address
+        assert_eq!(packet.header().len(), 2 + 1 + 1 + 6);
+        assert_eq!(packet.get_dci(), 3);
+        assert!(packet.get_m());
+        assert!(packet.get_dac());
+        assert_eq!(packet.get_dam(), 0b00);
+        assert_eq!(complete(packet.get_destination(), &ctxt, None), dest);
+
+        // global address not covered by any context
+        let dest = ipv6::Addr([0x20, 0x01, 0x0d, 0xb9, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+
+        let mut bytes = [0; 64];
+        let mut packet = Packet::new(
+            &mut bytes[..],
+            Some(ipv6::NextHeader::Udp),
+            64,
+            ipv6::Addr::UNSPECIFIED,
+            dest,
+            &ctxt,
+        );
+        packet.set_payload(&[]);
+
+        let packet = Packet::parse(packet.bytes()).unwrap();
+        assert!(!packet.get_cid());
+        assert!(!packet.get_dac());
+        assert_eq!(packet.get_dam(), 0b00);
+        assert_eq!(complete(packet.get_destination(), &ctxt, None), dest);
+    }
+
+    // Builds an IPv6 packet, compresses it and then decompresses it; returns the compressed packet
+    fn roundtrip<'b>(
+        ctxt: &Context<'_>,
+        next_header: ipv6::NextHeader,
+        (traffic_class, flow_label): (u8, u32),
+        hop_limit: u8,
+        src: ipv6::Addr,
+        dest: ipv6::Addr,
+        compressed: &'b mut [u8],
+    ) -> Packet<&'b mut [u8]> {
+        const PAYLOAD: &[u8] = b"Hello, world!";
+
+        let mut bytes = [0; 128];
+        let len = usize::from(ipv6::HEADER_SIZE + udp::HEADER_SIZE) + PAYLOAD.len();
+        let mut ip = ipv6::Packet::new(&mut bytes[..len]);
+        ip.set_traffic_class(traffic_class);
+        ip.set_flow_label(flow_label);
+        ip.set_next_header(next_header);
+        ip.set_hop_limit(hop_limit);
+        ip.set_source(src);
+        ip.set_destination(dest);
+
+        let mut udp = udp::Packet::new(ip.payload_mut());
+        udp.set_source(0xf0b1);
+        udp.set_destination(0xf0b2);
+        udp.payload_mut().copy_from_slice(PAYLOAD);
+        udp.update_ipv6_checksum(src, dest);
+
+        let packet = super::compress(&ip, ctxt, compressed).unwrap();
+
+        let mut bytes = [0; 128];
+        let ip2 = super::decompress(&packet, ctxt, &mut bytes).unwrap();
+        assert_eq!(ip2.as_bytes(), ip.as_bytes());
+
+        // buffer too small
+        let mut small = [0; 128];
+        let n = packet.bytes().len() - 1;
+        assert_eq!(
+            super::compress(&ip, ctxt, &mut small[..n]).err(),
+            Some(super::Error::BufferTooSmall)
+        );
+
+        packet
+    }
+
+    #[test]
+    fn compress() {
+        let ctxt = Context {
+            source: Some(ll::ShortAddr(0xdead).into()),
+            destination: Some(ll::ShortAddr(0xbeef).into()),
+            table: None,
+        };
+
+        // UDP between link-local addresses derived from the link-layer addresses
+        let mut bytes = [0; 128];
+        let packet = roundtrip(
+            &ctxt,
+            ipv6::NextHeader::Udp,
+            (0, 0),
+            64,
+            ipv6::Addr([
+                0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xff, 0xfe, 0, 0xde, 0xad,
+            ]),
+            ipv6::Addr([
+                0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xff, 0xfe, 0, 0xbe, 0xef,
+            ]),
+            &mut bytes,
+        );
+        // IPHC
+        assert_eq!(packet.header().len(), 2);
+        assert_eq!(packet.get_tf(), 0b11);
+        assert!(packet.get_nh());
+        assert_eq!(packet.get_hlim(), 0b10);
+        assert_eq!(packet.get_sam(), 0b11);
+        assert_eq!(packet.get_dam(), 0b11);
+        // NHC ID + 4-bit ports + checksum
+        assert_eq!(packet.payload().len(), 1 + 1 + 2 + 13);
+
+        // traffic class (DSCP + ECN) and flow label
+        let mut bytes = [0; 128];
+        let packet = roundtrip(
+            &ctxt,
+            ipv6::NextHeader::Udp,
+            (0b101110_01, 0xabcde),
+            42,
+            ipv6::Addr([0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]),
+            ipv6::Addr([0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2]),
+            &mut bytes,
+        );
+        assert_eq!(packet.get_tf(), 0b00);
+        assert_eq!(packet.get_traffic_class(), 0b101110_01);
+        assert_eq!(packet.get_flow_label(), 0xabcde);
+        assert_eq!(packet.get_hop_limit(), 42);
+        // IPHC + TF + HLIM + addresses
+        assert_eq!(packet.header().len(), 2 + 4 + 1 + 16 + 16);
+
+        // ECN + flow label
+        let mut bytes = [0; 128];
+        let packet = roundtrip(
+            &ctxt,
+            ipv6::NextHeader::Udp,
+            (0b10, 1),
+            1,
+            ipv6::Addr::UNSPECIFIED,
+            ipv6::Addr([0xff, 0x02, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]),
+            &mut bytes,
+        );
+        assert_eq!(packet.get_tf(), 0b01);
+        assert_eq!(packet.get_traffic_class(), 0b10);
+        assert_eq!(packet.get_flow_label(), 1);
+        // IPHC + TF + 8-bit multicast address
+        assert_eq!(packet.header().len(), 2 + 3 + 1);
+
+        // traffic class only; uncompressed multicast address
+        let dest = ipv6::Addr([
+            0xff, 0x0e, 0xde, 0xad, 0xbe, 0xef, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1,
+        ]);
+        let mut bytes = [0; 128];
+        let packet = roundtrip(
+            &ctxt,
+            ipv6::NextHeader::Udp,
+            (0b000001_00, 0),
+            255,
+            ipv6::Addr::UNSPECIFIED,
+            dest,
+            &mut bytes,
+        );
+        assert_eq!(packet.get_tf(), 0b10);
+        assert_eq!(packet.get_traffic_class(), 0b000001_00);
+        assert!(packet.get_m());
+        assert_eq!(packet.get_dam(), 0b00);
+        // IPHC + TF + destination address
+        assert_eq!(packet.header().len(), 2 + 1 + 16);
+
+        // the payload is not compressed if the next header is not UDP
+        let mut bytes = [0; 128];
+        let packet = roundtrip(
+            &ctxt,
+            ipv6::NextHeader::Ipv6Icmp,
+            (0, 0),
+            255,
+            ipv6::Addr::UNSPECIFIED,
+            dest,
+            &mut bytes,
+        );
+        assert_eq!(packet.get_next_header(), Some(ipv6::NextHeader::Ipv6Icmp));
+        assert_eq!(packet.payload().len(), 8 + 13);
+
+        // stateful compression
+        let mut table = ContextTable::new();
+        table.set(
+            0,
+            Prefix::new(
+                ipv6::Addr([0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]),
+                64,
+            ),
+        );
+        let stateful = Context {
+            table: Some(&table),
+            ..ctxt
+        };
+
+        let mut bytes = [0; 128];
+        let packet = roundtrip(
+            &stateful,
+            ipv6::NextHeader::Udp,
+            (0, 0),
+            64,
+            ipv6::Addr([
+                0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0xff, 0xfe, 0, 0xde, 0xad,
+            ]),
+            ipv6::Addr([
+                0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0xff, 0xfe, 0, 0, 2,
+            ]),
+            &mut bytes,
+        );
+        // IPHC + 16-bit destination IID
+        assert_eq!(packet.header().len(), 2 + 2);
+
+        // the packet can't be decompressed without the context table
+        let mut buffer = [0; 128];
+        assert_eq!(
+            super::decompress(&packet, &ctxt, &mut buffer).err(),
+            Some(super::Error::UnknownAddress)
+        );
+    }
+
+    fn ipv6_packet<'a>(
+        buffer: &'a mut [u8],
+        next_header: ipv6::NextHeader,
+        src: ipv6::Addr,
+        dest: ipv6::Addr,
+        payload: &[u8],
+    ) -> ipv6::Packet<&'a mut [u8]> {
+        let len = usize::from(ipv6::HEADER_SIZE) + payload.len();
+        let mut ip = ipv6::Packet::new(&mut buffer[..len]);
+        ip.set_next_header(next_header);
+        ip.set_source(src);
+        ip.set_destination(dest);
+        ip.payload_mut().copy_from_slice(payload);
+        ip
+    }
+
+    #[test]
+    fn extension_headers() {
+        let ctxt = Context {
+            source: Some(ll::ShortAddr(0xdead).into()),
+            destination: Some(ll::ShortAddr(0xbeef).into()),
+            table: None,
+        };
+        let src = ipv6::Addr([
+            0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xff, 0xfe, 0, 0xde, 0xad,
+        ]);
+        let dest = ipv6::Addr([
+            0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xff, 0xfe, 0, 0xbe, 0xef,
+        ]);
+
+        // Hop-by-Hop Options + Fragment + UDP
+        #[rustfmt::skip]
+        let payload = [
+            44, 0, // hbh: next header = Fragment
+            5, 2, 0, 0, // hbh: Router Alert
+            1, 0, // hbh: PadN
+            17, 0, // frag: next header = UDP
+            0, 0, // frag: offset = 0, M = 0
+            0, 0, 0, 42, // frag: identification
+            0xf0, 0xb1, 0xf0, 0xb2, 0, 12, 0xbe, 0xef, // UDP header
+            1, 2, 3, 4, // UDP payload
+        ];
+        let mut bytes = [0; 128];
+        let ip = ipv6_packet(&mut bytes, ipv6::NextHeader::Hopopt, src, dest, &payload);
+
+        let mut compressed = [0; 128];
+        let packet = super::compress(&ip, &ctxt, &mut compressed).unwrap();
+        // IPHC + NHC Hop-by-Hop (padding elided) + NHC Fragment + NHC UDP + payload
+        assert_eq!(
+            packet.bytes().len(),
+            2 + (2 + 4) + (2 + 6) + (1 + 1 + 2) + 4
+        );
+        assert!(packet.get_nh());
+
+        let mut bytes = [0; 128];
+        let ip2 = super::decompress(&packet, &ctxt, &mut bytes).unwrap();
+        assert_eq!(ip2.as_bytes(), ip.as_bytes());
+
+        // the data that follows the Fragment header of a non-first fragment is not compressed
+        #[rustfmt::skip]
+        let payload = [
+            17, 0, // frag: next header = UDP
+            0, 0b1000, // frag: offset = 1, M = 0
+            0, 0, 0, 42, // frag: identification
+            0xf0, 0xb1, 0xf0, 0xb2, 0, 12, 0xbe, 0xef, // fragment data
+        ];
+        let mut bytes = [0; 128];
+        let ip = ipv6_packet(&mut bytes, ipv6::NextHeader::Ipv6Frag, src, dest, &payload);
+
+        let mut compressed = [0; 128];
+        let packet = super::compress(&ip, &ctxt, &mut compressed).unwrap();
+        // IPHC + NHC Fragment (in-line next header) + fragment data
+        assert_eq!(packet.bytes().len(), 2 + (3 + 6) + 8);
+
+        let mut bytes = [0; 128];
+        let ip2 = super::decompress(&packet, &ctxt, &mut bytes).unwrap();
+        assert_eq!(ip2.as_bytes(), ip.as_bytes());
+
+        // IPv6-in-IPv6
+        let inner_src = ipv6::Addr([0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+        let inner_dest = ipv6::Addr([0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2]);
+        let mut inner = [0; 128];
+        let inner = ipv6_packet(
+            &mut inner,
+            ipv6::NextHeader::Udp,
+            inner_src,
+            inner_dest,
+            &[0xf0, 0xb1, 0xf0, 0xb2, 0, 12, 0xbe, 0xef, 1, 2, 3, 4],
+        );
+        let mut bytes = [0; 128];
+        let ip = ipv6_packet(
+            &mut bytes,
+            ipv6::NextHeader::Ipv6,
+            src,
+            dest,
+            inner.as_bytes(),
+        );
+
+        let mut compressed = [0; 128];
+        let packet = super::compress(&ip, &ctxt, &mut compressed).unwrap();
+        // IPHC + NHC IPv6 + IPHC (uncompressed addresses) + NHC UDP + payload
+        assert_eq!(
+            packet.bytes().len(),
+            2 + 1 + (2 + 16 + 16) + (1 + 1 + 2) + 4
+        );
+
+        let mut bytes = [0; 128];
+        let ip2 = super::decompress(&packet, &ctxt, &mut bytes).unwrap();
+        assert_eq!(ip2.as_bytes(), ip.as_bytes());
+    }
+
+    #[test]
+    fn nesting() {
+        // `n` encapsulated IPv6 headers, each compressed as IPHC (16-bit link-local addresses) +
+        // NHC IPv6, followed by an IPHC header with an in-line 'No Next Header'
+        fn nested(n: usize, buffer: &mut [u8]) -> Packet<&[u8]> {
+            let mut len = 0;
+            for _ in 0..n {
+                buffer[len..len + 7].copy_from_slice(&[0x7f, 0x22, 0, 1, 0, 2, 0b1110_111_0]);
+                len += 7;
+            }
+            buffer[len..len + 7].copy_from_slice(&[0x7b, 0x22, 59, 0, 1, 0, 2]);
+            len += 7;
+
+            Packet::parse(&buffer[..len]).unwrap()
+        }
+
+        let ctxt = Context::empty();
+
+        let mut compressed = [0; 64];
+        let mut bytes = [0; 256];
+        let ip = super::decompress(
+            &nested(usize::from(super::MAX_DEPTH), &mut compressed),
+            &ctxt,
+            &mut bytes,
+        )
+        .unwrap();
+        assert_eq!(
+            ip.as_bytes().len(),
+            40 * (usize::from(super::MAX_DEPTH) + 1)
+        );
+
+        // too deep
+        let mut compressed = [0; 64];
+        let mut bytes = [0; 256];
+        assert_eq!(
+            super::decompress(
+                &nested(usize::from(super::MAX_DEPTH) + 1, &mut compressed),
+                &ctxt,
+                &mut bytes
+            )
+            .err(),
+            Some(super::Error::Malformed)
+        );
+    }
 }
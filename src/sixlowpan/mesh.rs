@@ -0,0 +1,419 @@
+//! 6LoWPAN Mesh Addressing and Broadcast headers
+//!
+//! # References
+//!
+//! - [RFC 4944: Transmission of IPv6 Packets over IEEE 802.15.4 Networks][0], section 5.2 "Mesh
+//!   Addressing Type and Header" and section 11.1 "LoWPAN Broadcast"
+//!
+//! [0]: https://tools.ietf.org/html/rfc4944
+
+use core::fmt;
+
+use as_slice::{AsMutSlice, AsSlice};
+use byteorder::{ByteOrder, NetworkEndian as NE};
+use cast::usize;
+
+use crate::{ieee802154 as ll, traits::UncheckedIndex};
+
+/* Mesh header format */
+const MESH: usize = 0;
+
+mod dispatch {
+    pub const MASK: u8 = (1 << SIZE) - 1;
+    pub const OFFSET: usize = super::v::OFFSET + super::v::SIZE;
+    pub const SIZE: usize = 2;
+    pub const VALUE: u8 = 0b10;
+}
+
+mod v {
+    pub const MASK: u8 = (1 << SIZE) - 1;
+    pub const OFFSET: usize = super::f::OFFSET + super::f::SIZE;
+    pub const SIZE: usize = 1;
+}
+
+mod f {
+    pub const MASK: u8 = (1 << SIZE) - 1;
+    pub const OFFSET: usize = super::hops_left::OFFSET + super::hops_left::SIZE;
+    pub const SIZE: usize = 1;
+}
+
+mod hops_left {
+    pub const MASK: u8 = (1 << SIZE) - 1;
+    pub const OFFSET: usize = 0;
+    pub const SIZE: usize = 4;
+}
+
+const ORIGINATOR: usize = 1;
+
+/// Largest 'Hops Left' value that can be encoded in a Mesh header
+pub const MAX_HOPS_LEFT: u8 = hops_left::MASK;
+
+/* BC0 header format */
+const BC0_DISPATCH: usize = 0;
+const SEQUENCE_NUMBER: usize = 1;
+
+/// Size of the BC0 header
+pub const BC0_HEADER_SIZE: u8 = 2;
+
+// Dispatch value of the BC0 header
+pub(crate) const BC0: u8 = 0b0101_0000;
+
+/// Mesh Addressing header
+pub struct Header<BUFFER>
+where
+    BUFFER: AsSlice<Element = u8>,
+{
+    buffer: BUFFER,
+}
+
+impl<B> Header<B>
+where
+    B: AsSlice<Element = u8>,
+{
+    /* Constructors */
+    /// Parses the bytes as a Mesh Addressing header plus its payload
+    pub fn parse(bytes: B) -> Result<Self, B> {
+        if bytes.as_slice().is_empty() {
+            return Err(bytes);
+        }
+
+        let h = Header { buffer: bytes };
+
+        if get!(h.as_slice()[MESH], dispatch) != dispatch::VALUE
+            || h.as_slice().len() < h.header_len()
+        {
+            return Err(h.buffer);
+        }
+
+        Ok(h)
+    }
+
+    /* Getters */
+    /// Reads the 'Very first' (V) field
+    ///
+    /// `true` means that the originator address is a short address
+    pub fn get_v(&self) -> bool {
+        get!(self.header_(), v) != 0
+    }
+
+    /// Reads the 'Final' (F) field
+    ///
+    /// `true` means that the final destination address is a short address
+    pub fn get_f(&self) -> bool {
+        get!(self.header_(), f) != 0
+    }
+
+    /// Reads the 'Hops Left' field
+    pub fn get_hops_left(&self) -> u8 {
+        get!(self.header_(), hops_left)
+    }
+
+    /// Reads the 'Originator Address' field
+    pub fn get_originator(&self) -> ll::Addr {
+        read_addr(self.get_v(), unsafe { self.as_slice().rf(ORIGINATOR..) })
+    }
+
+    /// Reads the 'Final Destination Address' field
+    pub fn get_final_destination(&self) -> ll::Addr {
+        let start = ORIGINATOR + addr_size(self.get_v());
+        read_addr(self.get_f(), unsafe { self.as_slice().rf(start..) })
+    }
+
+    /// Immutable view into the header
+    pub fn header(&self) -> &[u8] {
+        unsafe { self.as_slice().rt(..self.header_len()) }
+    }
+
+    /// Immutable view into the payload
+    ///
+    /// The payload starts with another 6LoWPAN dispatch (e.g. a fragment header)
+    pub fn payload(&self) -> &[u8] {
+        unsafe { self.as_slice().rf(self.header_len()..) }
+    }
+
+    /// Byte representation of this header plus its payload
+    pub fn bytes(&self) -> &[u8] {
+        self.as_slice()
+    }
+
+    /* Private */
+    fn as_slice(&self) -> &[u8] {
+        self.buffer.as_slice()
+    }
+
+    fn header_(&self) -> u8 {
+        unsafe { *self.as_slice().gu(MESH) }
+    }
+
+    fn header_len(&self) -> usize {
+        ORIGINATOR + addr_size(self.get_v()) + addr_size(self.get_f())
+    }
+}
+
+impl<B> Header<B>
+where
+    B: AsMutSlice<Element = u8>,
+{
+    /* Constructors */
+    /// Transforms the given buffer into a Mesh Addressing header
+    ///
+    /// The 'Hops Left' field is set to `MAX_HOPS_LEFT`
+    ///
+    /// # Panics
+    ///
+    /// This constructor panics if `buffer` is too small to contain the header
+    pub fn new(buffer: B, originator: ll::Addr, final_destination: ll::Addr) -> Self {
+        let v = is_short(originator);
+        let f = is_short(final_destination);
+
+        let mut h = Header { buffer };
+        assert!(h.as_slice().len() >= ORIGINATOR + addr_size(v) + addr_size(f));
+
+        h.as_mut_slice()[MESH] = (dispatch::VALUE << dispatch::OFFSET)
+            | (u8::from(v) << v::OFFSET)
+            | (u8::from(f) << f::OFFSET)
+            | MAX_HOPS_LEFT;
+
+        let start = ORIGINATOR + addr_size(v);
+        write_addr(&mut h.as_mut_slice()[ORIGINATOR..start], originator);
+        write_addr(
+            &mut h.as_mut_slice()[start..start + addr_size(f)],
+            final_destination,
+        );
+
+        h
+    }
+
+    /* Setters */
+    /// Sets the 'Hops Left' field
+    ///
+    /// # Panics
+    ///
+    /// This method panics if `hops_left` is greater than `MAX_HOPS_LEFT`
+    pub fn set_hops_left(&mut self, hops_left: u8) {
+        assert!(hops_left <= MAX_HOPS_LEFT);
+
+        set!(self.as_mut_slice()[MESH], hops_left, hops_left);
+    }
+
+    /// Mutable view into the payload
+    pub fn payload_mut(&mut self) -> &mut [u8] {
+        let start = self.header_len();
+        unsafe { self.as_mut_slice().rfm(start..) }
+    }
+
+    /* Private */
+    fn as_mut_slice(&mut self) -> &mut [u8] {
+        self.buffer.as_mut_slice()
+    }
+}
+
+impl<B> fmt::Debug for Header<B>
+where
+    B: AsSlice<Element = u8>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("mesh::Header")
+            .field("hops_left", &self.get_hops_left())
+            .field("originator", &self.get_originator())
+            .field("final_destination", &self.get_final_destination())
+            // .field("payload", &self.payload())
+            .finish()
+    }
+}
+
+/// Broadcast header (LOWPAN_BC0)
+pub struct Bc0<BUFFER>
+where
+    BUFFER: AsSlice<Element = u8>,
+{
+    buffer: BUFFER,
+}
+
+impl<B> Bc0<B>
+where
+    B: AsSlice<Element = u8>,
+{
+    /* Constructors */
+    /// Parses the bytes as a BC0 header plus its payload
+    pub fn parse(bytes: B) -> Result<Self, B> {
+        if bytes.as_slice().len() < usize(BC0_HEADER_SIZE) || bytes.as_slice()[BC0_DISPATCH] != BC0
+        {
+            return Err(bytes);
+        }
+
+        Ok(Bc0 { buffer: bytes })
+    }
+
+    /* Getters */
+    /// Reads the 'Sequence Number' field
+    pub fn get_sequence_number(&self) -> u8 {
+        unsafe { *self.as_slice().gu(SEQUENCE_NUMBER) }
+    }
+
+    /// Immutable view into the payload
+    pub fn payload(&self) -> &[u8] {
+        unsafe { self.as_slice().rf(usize(BC0_HEADER_SIZE)..) }
+    }
+
+    /// Byte representation of this header plus its payload
+    pub fn bytes(&self) -> &[u8] {
+        self.as_slice()
+    }
+
+    /* Private */
+    fn as_slice(&self) -> &[u8] {
+        self.buffer.as_slice()
+    }
+}
+
+impl<B> Bc0<B>
+where
+    B: AsMutSlice<Element = u8>,
+{
+    /* Constructors */
+    /// Transforms the given buffer into a BC0 header
+    ///
+    /// The 'Sequence Number' field is set to zero
+    ///
+    /// # Panics
+    ///
+    /// This constructor panics if `buffer` is smaller than `BC0_HEADER_SIZE`
+    pub fn new(buffer: B) -> Self {
+        assert!(buffer.as_slice().len() >= usize(BC0_HEADER_SIZE));
+
+        let mut h = Bc0 { buffer };
+        h.as_mut_slice()[BC0_DISPATCH] = BC0;
+        h.set_sequence_number(0);
+
+        h
+    }
+
+    /* Setters */
+    /// Sets the 'Sequence Number' field
+    pub fn set_sequence_number(&mut self, seq: u8) {
+        self.as_mut_slice()[SEQUENCE_NUMBER] = seq;
+    }
+
+    /// Mutable view into the payload
+    pub fn payload_mut(&mut self) -> &mut [u8] {
+        unsafe { self.as_mut_slice().rfm(usize(BC0_HEADER_SIZE)..) }
+    }
+
+    /* Private */
+    fn as_mut_slice(&mut self) -> &mut [u8] {
+        self.buffer.as_mut_slice()
+    }
+}
+
+impl<B> fmt::Debug for Bc0<B>
+where
+    B: AsSlice<Element = u8>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("mesh::Bc0")
+            .field("sequence_number", &self.get_sequence_number())
+            // .field("payload", &self.payload())
+            .finish()
+    }
+}
+
+fn is_short(addr: ll::Addr) -> bool {
+    match addr {
+        ll::Addr::Short(_) => true,
+        ll::Addr::Extended(_) => false,
+    }
+}
+
+fn addr_size(short: bool) -> usize {
+    if short {
+        2
+    } else {
+        8
+    }
+}
+
+// NOTE the caller must ensure that `bytes` is large enough
+fn read_addr(short: bool, bytes: &[u8]) -> ll::Addr {
+    unsafe {
+        if short {
+            ll::ShortAddr(NE::read_u16(bytes.rt(..2))).into()
+        } else {
+            ll::ExtendedAddr(NE::read_u64(bytes.rt(..8))).into()
+        }
+    }
+}
+
+fn write_addr(bytes: &mut [u8], addr: ll::Addr) {
+    match addr {
+        ll::Addr::Short(sa) => NE::write_u16(bytes, sa.0),
+        ll::Addr::Extended(ea) => NE::write_u64(bytes, ea.0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Bc0, Header};
+    use crate::ieee802154 as ll;
+
+    #[test]
+    fn mesh() {
+        let mut buf = [0; 16];
+        let mut h = Header::new(
+            &mut buf[..],
+            ll::ShortAddr(0x1234).into(),
+            ll::ExtendedAddr(0x0011_2233_4455_6677).into(),
+        );
+        h.set_hops_left(3);
+
+        assert_eq!(
+            &buf[..11],
+            &[
+                0b10_1_0_0011,
+                0x12,
+                0x34,
+                0,
+                0x11,
+                0x22,
+                0x33,
+                0x44,
+                0x55,
+                0x66,
+                0x77
+            ][..]
+        );
+
+        let h = Header::parse(&buf[..]).unwrap();
+        assert!(h.get_v());
+        assert!(!h.get_f());
+        assert_eq!(h.get_hops_left(), 3);
+        assert_eq!(h.get_originator(), ll::ShortAddr(0x1234).into());
+        assert_eq!(
+            h.get_final_destination(),
+            ll::ExtendedAddr(0x0011_2233_4455_6677).into()
+        );
+        assert_eq!(h.header().len(), 11);
+        assert_eq!(h.payload().len(), 5);
+
+        // truncated
+        assert!(Header::parse(&buf[..10]).is_err());
+        // not a Mesh header
+        assert!(Header::parse(&[0b01_000001, 0, 0, 0, 0][..]).is_err());
+    }
+
+    #[test]
+    fn bc0() {
+        let mut buf = [0; 4];
+        let mut h = Bc0::new(&mut buf[..]);
+        h.set_sequence_number(42);
+
+        assert_eq!(&buf[..2], &[0x50, 42][..]);
+
+        let h = Bc0::parse(&buf[..]).unwrap();
+        assert_eq!(h.get_sequence_number(), 42);
+        assert_eq!(h.payload().len(), 2);
+
+        assert!(Bc0::parse(&buf[..1]).is_err());
+        assert!(Bc0::parse(&[0x41, 42][..]).is_err());
+    }
+}
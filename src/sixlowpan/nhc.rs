@@ -34,6 +34,26 @@ mod p {
     pub const SIZE: usize = 2;
 }
 
+/* Extension header format */
+mod eh_id {
+    pub const MASK: u8 = (1 << SIZE) - 1;
+    pub const OFFSET: usize = super::eid::OFFSET + super::eid::SIZE;
+    pub const SIZE: usize = 4;
+    pub const VALUE: u8 = 0b1110;
+}
+
+mod eid {
+    pub const MASK: u8 = (1 << SIZE) - 1;
+    pub const OFFSET: usize = super::nh::OFFSET + super::nh::SIZE;
+    pub const SIZE: usize = 3;
+}
+
+mod nh {
+    pub const MASK: u8 = (1 << SIZE) - 1;
+    pub const OFFSET: usize = 0;
+    pub const SIZE: usize = 1;
+}
+
 /// LOWPAN_NHC compressed UDP packet
 pub struct UdpPacket<BUFFER>
 where
@@ -305,7 +325,7 @@ where
     }
 
     /* Private */
-    unsafe fn set_checksum(&mut self, cksum: u16) {
+    pub(crate) unsafe fn set_checksum(&mut self, cksum: u16) {
         debug_assert!(!self.get_c());
 
         let start = 1 + usize::from(self.ports_size());
@@ -359,11 +379,274 @@ where
     }
 }
 
+/// IPv6 Extension Header ID
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Eid {
+    /// IPv6 Hop-by-Hop Options header
+    HopByHop,
+    /// IPv6 Routing header
+    Routing,
+    /// IPv6 Fragment header
+    Fragment,
+    /// IPv6 Destination Options header
+    DestinationOptions,
+    /// IPv6 Mobility header
+    Mobility,
+    /// Encapsulated IPv6 header
+    Ipv6,
+}
+
+impl Eid {
+    /// Maps an IPv6 'Next Header' value into an EID
+    pub fn from_next_header(nh: ipv6::NextHeader) -> Option<Self> {
+        Some(match nh {
+            ipv6::NextHeader::Hopopt => Eid::HopByHop,
+            ipv6::NextHeader::Ipv6Route => Eid::Routing,
+            ipv6::NextHeader::Ipv6Frag => Eid::Fragment,
+            ipv6::NextHeader::Ipv6Opts => Eid::DestinationOptions,
+            ipv6::NextHeader::MobilityHeader => Eid::Mobility,
+            ipv6::NextHeader::Ipv6 => Eid::Ipv6,
+            _ => return None,
+        })
+    }
+
+    /// Returns the IPv6 'Next Header' value that identifies this header
+    pub fn next_header(self) -> ipv6::NextHeader {
+        match self {
+            Eid::HopByHop => ipv6::NextHeader::Hopopt,
+            Eid::Routing => ipv6::NextHeader::Ipv6Route,
+            Eid::Fragment => ipv6::NextHeader::Ipv6Frag,
+            Eid::DestinationOptions => ipv6::NextHeader::Ipv6Opts,
+            Eid::Mobility => ipv6::NextHeader::MobilityHeader,
+            Eid::Ipv6 => ipv6::NextHeader::Ipv6,
+        }
+    }
+
+    fn from_u8(eid: u8) -> Option<Self> {
+        Some(match eid {
+            0 => Eid::HopByHop,
+            1 => Eid::Routing,
+            2 => Eid::Fragment,
+            3 => Eid::DestinationOptions,
+            4 => Eid::Mobility,
+            7 => Eid::Ipv6,
+            // reserved
+            _ => return None,
+        })
+    }
+
+    fn into_u8(self) -> u8 {
+        match self {
+            Eid::HopByHop => 0,
+            Eid::Routing => 1,
+            Eid::Fragment => 2,
+            Eid::DestinationOptions => 3,
+            Eid::Mobility => 4,
+            Eid::Ipv6 => 7,
+        }
+    }
+}
+
+/// LOWPAN_NHC compressed IPv6 extension header
+///
+/// The 'Length' field of the compressed header is expressed in octets and doesn't include the
+/// 'Next Header' and 'Length' fields. The padding at the end of Hop-by-Hop and Destination Options
+/// headers may have been elided by the compressor.
+///
+/// If the EID is `Ipv6` the header has no 'Next Header' and 'Length' fields and `payload` is an
+/// encapsulated LOWPAN_IPHC compressed IPv6 packet.
+pub struct ExtensionHeader<BUFFER>
+where
+    BUFFER: AsSlice<Element = u8>,
+{
+    buffer: BUFFER,
+    /// Index at which the payload (the next header) starts; this can be past index 255 when the
+    /// 'Next Header' field is carried in-line
+    payload: u16,
+}
+
+impl<B> ExtensionHeader<B>
+where
+    B: AsSlice<Element = u8>,
+{
+    /* Constructors */
+    /// Parses the bytes as a LOWPAN_NHC compressed IPv6 extension header
+    pub fn parse(buffer: B) -> Result<Self, B> {
+        let mut start = 1; // NHC
+
+        if buffer.as_slice().len() < start {
+            return Err(buffer);
+        }
+
+        let mut eh = ExtensionHeader { buffer, payload: 0 };
+
+        // check NHC ID and EID
+        if get!(eh.header_(), eh_id) != eh_id::VALUE
+            || Eid::from_u8(get!(eh.header_(), eid)).is_none()
+        {
+            return Err(eh.buffer);
+        }
+
+        if eh.get_eid() != Eid::Ipv6 {
+            if !eh.get_nh() {
+                start += 1; // next header
+            }
+
+            // length
+            start += 1;
+            if eh.as_slice().len() < start {
+                return Err(eh.buffer);
+            }
+
+            start += usize::from(unsafe { *eh.as_slice().gu(start - 1) });
+        }
+
+        if eh.as_slice().len() < start {
+            Err(eh.buffer)
+        } else {
+            // NOTE `start` is at most `3 + 255`
+            eh.payload = start as u16;
+            Ok(eh)
+        }
+    }
+
+    /* Getters */
+    /// Reads the 'Extension Header ID' field
+    pub fn get_eid(&self) -> Eid {
+        Eid::from_u8(get!(self.header_(), eid)).unwrap_or_else(|| unsafe { debug_unreachable!() })
+    }
+
+    /// Reads the 'Next Header' NHC field
+    pub fn get_nh(&self) -> bool {
+        get!(self.header_(), nh) != 0
+    }
+
+    /// Reads the in-line 'Next Header' field
+    ///
+    /// **NOTE**: This returns `None` if the next header is encoded using the LOWPAN_NHC format or if
+    /// the EID is `Ipv6`
+    pub fn get_next_header(&self) -> Option<ipv6::NextHeader> {
+        if self.get_nh() || self.get_eid() == Eid::Ipv6 {
+            None
+        } else {
+            Some(unsafe { *self.as_slice().gu(1) }.into())
+        }
+    }
+
+    /// Immutable view into the contents of the extension header; these are the bytes that follow
+    /// the 'Length' field
+    pub fn data(&self) -> &[u8] {
+        if self.get_eid() == Eid::Ipv6 {
+            &[]
+        } else {
+            let start = if self.get_nh() { 2 } else { 3 };
+            unsafe { self.as_slice().r(start..usize::from(self.payload)) }
+        }
+    }
+
+    /// Immutable view into the payload (the next header)
+    pub fn payload(&self) -> &[u8] {
+        unsafe { self.as_slice().rf(usize::from(self.payload)..) }
+    }
+
+    /// Byte representation of this header
+    pub fn bytes(&self) -> &[u8] {
+        self.as_slice()
+    }
+
+    // Size of the header once uncompressed; the padding elided by the compressor is included
+    pub(crate) fn uncompressed_size(&self) -> usize {
+        if self.get_eid() == Eid::Ipv6 {
+            // the size of the IPv6 header depends on the LOWPAN_IPHC encoding that follows
+            0
+        } else {
+            // the size of an extension header is a multiple of 8 octets
+            (2 + self.data().len() + 7) & !7
+        }
+    }
+
+    /* Private */
+    fn header_(&self) -> u8 {
+        unsafe { *self.as_slice().gu(NHC) }
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        self.buffer.as_slice()
+    }
+}
+
+impl<B> ExtensionHeader<B>
+where
+    B: AsMutSlice<Element = u8>,
+{
+    /* Constructors */
+    // `next_header = None` means that the next header will be encoded using LOWPAN_NHC
+    //
+    // NOTE `next_header` and `data` are ignored if `eid` is `Ipv6`
+    pub(crate) fn new(
+        mut buffer: B,
+        eid: Eid,
+        next_header: Option<ipv6::NextHeader>,
+        data: &[u8],
+    ) -> Self {
+        let (nh, mut start) = if eid == Eid::Ipv6 {
+            (0, 1)
+        } else if next_header.is_some() {
+            (0, 3 + data.len())
+        } else {
+            (1, 2 + data.len())
+        };
+
+        assert!(buffer.as_slice().len() >= start && data.len() <= usize::from(u8::MAX));
+
+        let bytes = buffer.as_mut_slice();
+        bytes[NHC] = (eh_id::VALUE << eh_id::OFFSET) | (eid.into_u8() << eid::OFFSET) | nh;
+
+        if eid != Eid::Ipv6 {
+            if let Some(next_header) = next_header {
+                bytes[1] = next_header.into();
+            }
+
+            start -= data.len();
+            bytes[start - 1] = data.len() as u8;
+            bytes[start..start + data.len()].copy_from_slice(data);
+            start += data.len();
+        }
+
+        ExtensionHeader {
+            buffer,
+            // NOTE `start` is at most `3 + 255`
+            payload: start as u16,
+        }
+    }
+
+    /// Mutable view into the payload (the next header)
+    pub fn payload_mut(&mut self) -> &mut [u8] {
+        let start = usize::from(self.payload);
+        unsafe { self.buffer.as_mut_slice().rfm(start..) }
+    }
+}
+
+impl<B> fmt::Debug for ExtensionHeader<B>
+where
+    B: AsSlice<Element = u8>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("nhc::ExtensionHeader")
+            .field("eid", &self.get_eid())
+            .field("next_header", &self.get_next_header())
+            .field("data", &self.data())
+            // .field("payload", &self.payload())
+            .finish()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use rand::RngCore;
 
-    use super::UdpPacket;
+    use super::{Eid, ExtensionHeader, UdpPacket};
+    use crate::ipv6;
 
     #[test]
     fn new() {
@@ -402,4 +685,67 @@ mod tests {
             test!(*elide, 1337, 1337);
         }
     }
+
+    #[test]
+    fn extension_header() {
+        // in-line next header
+        let mut bytes = [0; 16];
+        ExtensionHeader::new(
+            &mut bytes[..],
+            Eid::HopByHop,
+            Some(ipv6::NextHeader::Ipv6Icmp),
+            &[5, 2, 0, 0],
+        );
+        assert_eq!(&bytes[..7], &[0b1110_000_0, 58, 4, 5, 2, 0, 0][..]);
+
+        let eh = ExtensionHeader::parse(&bytes[..]).unwrap();
+        assert_eq!(eh.get_eid(), Eid::HopByHop);
+        assert!(!eh.get_nh());
+        assert_eq!(eh.get_next_header(), Some(ipv6::NextHeader::Ipv6Icmp));
+        assert_eq!(eh.data(), &[5, 2, 0, 0]);
+        assert_eq!(eh.payload().len(), 16 - 7);
+        assert_eq!(eh.uncompressed_size(), 8);
+
+        // next header encoded using LOWPAN_NHC
+        let mut bytes = [0; 16];
+        ExtensionHeader::new(&mut bytes[..], Eid::Routing, None, &[3; 10]);
+        let eh = ExtensionHeader::parse(&bytes[..]).unwrap();
+        assert_eq!(eh.get_eid(), Eid::Routing);
+        assert!(eh.get_nh());
+        assert_eq!(eh.get_next_header(), None);
+        assert_eq!(eh.data(), &[3; 10]);
+        assert_eq!(eh.uncompressed_size(), 16);
+
+        // encapsulated IPv6 header
+        let mut bytes = [0; 4];
+        ExtensionHeader::new(&mut bytes[..], Eid::Ipv6, None, &[]);
+        assert_eq!(bytes[0], 0b1110_111_0);
+        let eh = ExtensionHeader::parse(&bytes[..]).unwrap();
+        assert_eq!(eh.get_eid(), Eid::Ipv6);
+        assert_eq!(eh.data(), &[]);
+        assert_eq!(eh.payload().len(), 3);
+
+        // reserved EID
+        assert!(ExtensionHeader::parse(&[0b1110_101_0, 58, 0][..]).is_err());
+        // truncated
+        assert!(ExtensionHeader::parse(&[0b1110_000_0, 58, 4, 5][..]).is_err());
+        // NHC UDP
+        assert!(ExtensionHeader::parse(&[0b11110_0_11, 0xab, 0, 0][..]).is_err());
+
+        // in-line next header and the largest 'Length'; the payload starts past index 255
+        let mut bytes = [0; 3 + 255 + 2];
+        ExtensionHeader::new(
+            &mut bytes[..],
+            Eid::DestinationOptions,
+            Some(ipv6::NextHeader::Udp),
+            &[7; 255],
+        );
+        assert_eq!(&bytes[..3], &[0b1110_011_0, 17, 255][..]);
+
+        let eh = ExtensionHeader::parse(&bytes[..]).unwrap();
+        assert_eq!(eh.get_next_header(), Some(ipv6::NextHeader::Udp));
+        assert_eq!(eh.data(), &[7; 255][..]);
+        assert_eq!(eh.payload().len(), 2);
+        assert_eq!(eh.uncompressed_size(), 264);
+    }
 }
@@ -0,0 +1,1193 @@
+//! TCP: Transmission Control Protocol
+//!
+//! # References
+//!
+//! - [RFC 793: Transmission Control Protocol][0]
+//!
+//! [0]: https://tools.ietf.org/html/rfc793
+//!
+//! - [RFC 2018: TCP Selective Acknowledgment Options][1]
+//!
+//! [1]: https://tools.ietf.org/html/rfc2018
+//!
+//! - [RFC 7323: TCP Extensions for High Performance][2]
+//!
+//! [2]: https://tools.ietf.org/html/rfc7323
+
+use core::{
+    fmt,
+    marker::PhantomData,
+    ops::{Range, RangeFrom},
+    option::Option as CoreOption,
+};
+
+use as_slice::{AsMutSlice, AsSlice};
+use byteorder::{ByteOrder, NetworkEndian as NE};
+use cast::{u16, usize};
+use owning_slice::Truncate;
+
+use crate::{fmt::Hex, ipv4, ipv6, traits::UncheckedIndex, Invalid, Unknown, Valid};
+
+/* Segment structure */
+const SOURCE: Range<usize> = 0..2;
+const DESTINATION: Range<usize> = 2..4;
+const SEQ_NO: Range<usize> = 4..8;
+const ACK_NO: Range<usize> = 8..12;
+
+const DATA_OFFSET_NS: usize = 12;
+mod ns {
+    pub const MASK: u8 = (1 << SIZE) - 1;
+    pub const OFFSET: usize = 0;
+    pub const SIZE: usize = 1;
+}
+mod reserved {
+    pub const MASK: u8 = (1 << SIZE) - 1;
+    pub const OFFSET: usize = super::ns::OFFSET + super::ns::SIZE;
+    pub const SIZE: usize = 3;
+}
+mod data_offset {
+    pub const MASK: u8 = (1 << SIZE) - 1;
+    pub const OFFSET: usize = super::reserved::OFFSET + super::reserved::SIZE;
+    pub const SIZE: usize = 4;
+}
+
+const FLAGS: usize = 13;
+mod fin {
+    pub const MASK: u8 = (1 << SIZE) - 1;
+    pub const OFFSET: usize = 0;
+    pub const SIZE: usize = 1;
+}
+mod syn {
+    pub const MASK: u8 = (1 << SIZE) - 1;
+    pub const OFFSET: usize = super::fin::OFFSET + super::fin::SIZE;
+    pub const SIZE: usize = 1;
+}
+mod rst {
+    pub const MASK: u8 = (1 << SIZE) - 1;
+    pub const OFFSET: usize = super::syn::OFFSET + super::syn::SIZE;
+    pub const SIZE: usize = 1;
+}
+mod psh {
+    pub const MASK: u8 = (1 << SIZE) - 1;
+    pub const OFFSET: usize = super::rst::OFFSET + super::rst::SIZE;
+    pub const SIZE: usize = 1;
+}
+mod ack {
+    pub const MASK: u8 = (1 << SIZE) - 1;
+    pub const OFFSET: usize = super::psh::OFFSET + super::psh::SIZE;
+    pub const SIZE: usize = 1;
+}
+mod urg {
+    pub const MASK: u8 = (1 << SIZE) - 1;
+    pub const OFFSET: usize = super::ack::OFFSET + super::ack::SIZE;
+    pub const SIZE: usize = 1;
+}
+mod ece {
+    pub const MASK: u8 = (1 << SIZE) - 1;
+    pub const OFFSET: usize = super::urg::OFFSET + super::urg::SIZE;
+    pub const SIZE: usize = 1;
+}
+mod cwr {
+    pub const MASK: u8 = (1 << SIZE) - 1;
+    pub const OFFSET: usize = super::ece::OFFSET + super::ece::SIZE;
+    pub const SIZE: usize = 1;
+}
+
+const WINDOW: Range<usize> = 14..16;
+const CHECKSUM: Range<usize> = 16..18;
+const URGENT_POINTER: Range<usize> = 18..20;
+const OPTIONS: RangeFrom<usize> = 20..;
+
+/// Minimum size of the TCP header
+pub const MIN_HEADER_SIZE: u8 = OPTIONS.start as u8;
+
+/// Maximum size of the TCP header (header + options)
+pub const MAX_HEADER_SIZE: u8 = 60;
+
+/// TCP segment
+pub struct Segment<BUFFER, CHECKSUM>
+where
+    BUFFER: AsSlice<Element = u8>,
+{
+    buffer: BUFFER,
+    _checksum: PhantomData<CHECKSUM>,
+}
+
+/* Unknown */
+impl<B> Segment<B, Unknown>
+where
+    B: AsSlice<Element = u8>,
+{
+    /* Constructors */
+    /// Parses the bytes as a TCP segment
+    ///
+    /// NOTE the checksum is *not* verified by this constructor; use `verify_ipv4_checksum` or
+    /// `verify_ipv6_checksum` for that
+    pub fn parse(bytes: B) -> Result<Self, B> {
+        let nbytes = bytes.as_slice().len();
+        if nbytes < usize(MIN_HEADER_SIZE) {
+            // too small; header doesn't fit
+            return Err(bytes);
+        }
+
+        let segment: Self = unsafe { Segment::unchecked(bytes) };
+        let header_len = segment.header_len();
+
+        if header_len < MIN_HEADER_SIZE || usize(header_len) > nbytes {
+            // invalid Data Offset
+            return Err(segment.buffer);
+        }
+
+        if Options::are_valid(unsafe { segment.as_slice().r(OPTIONS.start..usize(header_len)) }) {
+            Ok(segment)
+        } else {
+            Err(segment.buffer)
+        }
+    }
+
+    /* Miscellaneous */
+    /// Verifies the 'Checksum' field using the IPv4 pseudo-header
+    pub fn verify_ipv4_checksum(
+        self,
+        src: ipv4::Addr,
+        dest: ipv4::Addr,
+    ) -> Result<Segment<B, Valid>, Self> {
+        if self.compute_ipv4_checksum(src, dest) == self.get_checksum() {
+            Ok(unsafe { Segment::unchecked(self.buffer) })
+        } else {
+            Err(self)
+        }
+    }
+
+    /// Verifies the 'Checksum' field using the IPv6 pseudo-header
+    pub fn verify_ipv6_checksum(
+        self,
+        src: ipv6::Addr,
+        dest: ipv6::Addr,
+    ) -> Result<Segment<B, Valid>, Self> {
+        if self.compute_ipv6_checksum(src, dest) == self.get_checksum() {
+            Ok(unsafe { Segment::unchecked(self.buffer) })
+        } else {
+            Err(self)
+        }
+    }
+}
+
+/* CHECKSUM */
+impl<B, C> Segment<B, C>
+where
+    B: AsSlice<Element = u8>,
+{
+    /* Constructors */
+    unsafe fn unchecked(buffer: B) -> Self {
+        Segment {
+            buffer,
+            _checksum: PhantomData,
+        }
+    }
+
+    /* Getters */
+    /// Returns the Source (port) field of the header
+    pub fn get_source(&self) -> u16 {
+        NE::read_u16(&self.header_()[SOURCE])
+    }
+
+    /// Returns the Destination (port) field of the header
+    pub fn get_destination(&self) -> u16 {
+        NE::read_u16(&self.header_()[DESTINATION])
+    }
+
+    /// Returns the Sequence Number field of the header
+    pub fn get_sequence_number(&self) -> u32 {
+        NE::read_u32(&self.header_()[SEQ_NO])
+    }
+
+    /// Returns the Acknowledgment Number field of the header
+    pub fn get_acknowledgment_number(&self) -> u32 {
+        NE::read_u32(&self.header_()[ACK_NO])
+    }
+
+    /// Returns the Data Offset field of the header
+    ///
+    /// This is the size of the TCP header in units of 32-bit words
+    pub fn get_data_offset(&self) -> u8 {
+        get!(self.header_()[DATA_OFFSET_NS], data_offset)
+    }
+
+    /// Returns the NS (ECN-nonce concealment protection) flag
+    pub fn get_ns(&self) -> bool {
+        get!(self.header_()[DATA_OFFSET_NS], ns) == 1
+    }
+
+    /// Returns the CWR (Congestion Window Reduced) flag
+    pub fn get_cwr(&self) -> bool {
+        get!(self.header_()[FLAGS], cwr) == 1
+    }
+
+    /// Returns the ECE (ECN-Echo) flag
+    pub fn get_ece(&self) -> bool {
+        get!(self.header_()[FLAGS], ece) == 1
+    }
+
+    /// Returns the URG (Urgent pointer field is significant) flag
+    pub fn get_urg(&self) -> bool {
+        get!(self.header_()[FLAGS], urg) == 1
+    }
+
+    /// Returns the ACK (Acknowledgment field is significant) flag
+    pub fn get_ack(&self) -> bool {
+        get!(self.header_()[FLAGS], ack) == 1
+    }
+
+    /// Returns the PSH (Push function) flag
+    pub fn get_psh(&self) -> bool {
+        get!(self.header_()[FLAGS], psh) == 1
+    }
+
+    /// Returns the RST (Reset the connection) flag
+    pub fn get_rst(&self) -> bool {
+        get!(self.header_()[FLAGS], rst) == 1
+    }
+
+    /// Returns the SYN (Synchronize sequence numbers) flag
+    pub fn get_syn(&self) -> bool {
+        get!(self.header_()[FLAGS], syn) == 1
+    }
+
+    /// Returns the FIN (No more data from sender) flag
+    pub fn get_fin(&self) -> bool {
+        get!(self.header_()[FLAGS], fin) == 1
+    }
+
+    /// Returns the Window field of the header
+    pub fn get_window(&self) -> u16 {
+        NE::read_u16(&self.header_()[WINDOW])
+    }
+
+    /// Returns the Urgent Pointer field of the header
+    pub fn get_urgent_pointer(&self) -> u16 {
+        NE::read_u16(&self.header_()[URGENT_POINTER])
+    }
+
+    /// Returns an iterator over the options of this segment
+    pub fn options(&self) -> Options<'_> {
+        let end = usize(self.header_len());
+        unsafe { Options::new(self.as_slice().r(OPTIONS.start..end)) }
+    }
+
+    /// Returns the length (header + data) of this segment
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> u16 {
+        self.as_slice().len() as u16
+    }
+
+    /* Miscellaneous */
+    /// Immutable view into the header (including options)
+    pub fn header(&self) -> &[u8] {
+        let end = usize(self.header_len());
+        unsafe { self.as_slice().rt(..end) }
+    }
+
+    /// Immutable view into the payload
+    pub fn payload(&self) -> &[u8] {
+        let start = usize(self.header_len());
+        unsafe { self.as_slice().rf(start..) }
+    }
+
+    /// Returns the byte representation of this segment
+    pub fn as_bytes(&self) -> &[u8] {
+        self.as_slice()
+    }
+
+    /// Frees the underlying buffer
+    pub fn free(self) -> B {
+        self.buffer
+    }
+
+    /* Private */
+    fn as_slice(&self) -> &[u8] {
+        self.buffer.as_slice()
+    }
+
+    fn header_(&self) -> &[u8; MIN_HEADER_SIZE as usize] {
+        debug_assert!(self.as_slice().len() >= MIN_HEADER_SIZE as usize);
+
+        unsafe { &*(self.as_slice().as_ptr() as *const _) }
+    }
+
+    fn get_checksum(&self) -> u16 {
+        NE::read_u16(&self.header_()[CHECKSUM])
+    }
+
+    fn header_len(&self) -> u8 {
+        self.get_data_offset() * 4
+    }
+
+    fn compute_ipv4_checksum(&self, src: ipv4::Addr, dest: ipv4::Addr) -> u16 {
+        let len = self.len();
+        self.compute_checksum(ipv4::pseudo_header_sum(src, dest, ipv4::Protocol::Tcp, len))
+    }
+
+    fn compute_ipv6_checksum(&self, src: ipv6::Addr, dest: ipv6::Addr) -> u16 {
+        let len = u32::from(self.len());
+        self.compute_checksum(ipv6::pseudo_header_sum(
+            src,
+            dest,
+            ipv6::NextHeader::Tcp,
+            len,
+        ))
+    }
+
+    fn compute_checksum(&self, pseudo_header: u32) -> u16 {
+        let mut sum = pseudo_header;
+
+        for (i, chunk) in self.as_slice().chunks(2).enumerate() {
+            if i == CHECKSUM.start / 2 {
+                // this is the checksum field, skip
+                continue;
+            }
+
+            if chunk.len() == 1 {
+                sum += u32::from(chunk[0]) << 8;
+            } else {
+                sum += u32::from(NE::read_u16(chunk));
+            }
+        }
+
+        // fold carry-over
+        while sum >> 16 != 0 {
+            sum = (sum & 0xffff) + (sum >> 16);
+        }
+
+        !(sum as u16)
+    }
+}
+
+impl<B, C> Segment<B, C>
+where
+    B: AsSlice<Element = u8> + AsMutSlice<Element = u8>,
+{
+    /* Private */
+    fn as_mut_slice(&mut self) -> &mut [u8] {
+        self.buffer.as_mut_slice()
+    }
+
+    fn header_mut_(&mut self) -> &mut [u8; MIN_HEADER_SIZE as usize] {
+        debug_assert!(self.as_slice().len() >= MIN_HEADER_SIZE as usize);
+
+        unsafe { &mut *(self.as_mut_slice().as_mut_ptr() as *mut _) }
+    }
+}
+
+/* Invalid */
+impl<B> Segment<B, Invalid>
+where
+    B: AsSlice<Element = u8> + AsMutSlice<Element = u8>,
+{
+    /* Setters */
+    /// Sets the Source (port) field of the header
+    pub fn set_source(&mut self, port: u16) {
+        NE::write_u16(&mut self.header_mut_()[SOURCE], port)
+    }
+
+    /// Sets the Destination (port) field of the header
+    pub fn set_destination(&mut self, port: u16) {
+        NE::write_u16(&mut self.header_mut_()[DESTINATION], port)
+    }
+
+    /// Sets the Sequence Number field of the header
+    pub fn set_sequence_number(&mut self, seq_no: u32) {
+        NE::write_u32(&mut self.header_mut_()[SEQ_NO], seq_no)
+    }
+
+    /// Sets the Acknowledgment Number field of the header
+    pub fn set_acknowledgment_number(&mut self, ack_no: u32) {
+        NE::write_u32(&mut self.header_mut_()[ACK_NO], ack_no)
+    }
+
+    /// Sets the NS (ECN-nonce concealment protection) flag
+    pub fn set_ns(&mut self, ns: bool) {
+        set!(
+            self.header_mut_()[DATA_OFFSET_NS],
+            ns,
+            if ns { 1 } else { 0 }
+        );
+    }
+
+    /// Sets the CWR (Congestion Window Reduced) flag
+    pub fn set_cwr(&mut self, cwr: bool) {
+        set!(self.header_mut_()[FLAGS], cwr, if cwr { 1 } else { 0 });
+    }
+
+    /// Sets the ECE (ECN-Echo) flag
+    pub fn set_ece(&mut self, ece: bool) {
+        set!(self.header_mut_()[FLAGS], ece, if ece { 1 } else { 0 });
+    }
+
+    /// Sets the URG (Urgent pointer field is significant) flag
+    pub fn set_urg(&mut self, urg: bool) {
+        set!(self.header_mut_()[FLAGS], urg, if urg { 1 } else { 0 });
+    }
+
+    /// Sets the ACK (Acknowledgment field is significant) flag
+    pub fn set_ack(&mut self, ack: bool) {
+        set!(self.header_mut_()[FLAGS], ack, if ack { 1 } else { 0 });
+    }
+
+    /// Sets the PSH (Push function) flag
+    pub fn set_psh(&mut self, psh: bool) {
+        set!(self.header_mut_()[FLAGS], psh, if psh { 1 } else { 0 });
+    }
+
+    /// Sets the RST (Reset the connection) flag
+    pub fn set_rst(&mut self, rst: bool) {
+        set!(self.header_mut_()[FLAGS], rst, if rst { 1 } else { 0 });
+    }
+
+    /// Sets the SYN (Synchronize sequence numbers) flag
+    pub fn set_syn(&mut self, syn: bool) {
+        set!(self.header_mut_()[FLAGS], syn, if syn { 1 } else { 0 });
+    }
+
+    /// Sets the FIN (No more data from sender) flag
+    pub fn set_fin(&mut self, fin: bool) {
+        set!(self.header_mut_()[FLAGS], fin, if fin { 1 } else { 0 });
+    }
+
+    /// Sets the Window field of the header
+    pub fn set_window(&mut self, window: u16) {
+        NE::write_u16(&mut self.header_mut_()[WINDOW], window)
+    }
+
+    /// Sets the Urgent Pointer field of the header
+    pub fn set_urgent_pointer(&mut self, urgent_pointer: u16) {
+        NE::write_u16(&mut self.header_mut_()[URGENT_POINTER], urgent_pointer)
+    }
+
+    // NOTE(unsafe) this doesn't check that the header still fits in the buffer
+    unsafe fn set_data_offset(&mut self, data_offset: u8) {
+        set!(self.header_mut_()[DATA_OFFSET_NS], data_offset, data_offset);
+    }
+
+    fn clear_reserved(&mut self) {
+        set!(self.header_mut_()[DATA_OFFSET_NS], reserved, 0);
+    }
+
+    fn set_checksum(&mut self, checksum: u16) {
+        NE::write_u16(&mut self.header_mut_()[CHECKSUM], checksum)
+    }
+
+    /* Miscellaneous */
+    /// Mutable view into the payload
+    pub fn payload_mut(&mut self) -> &mut [u8] {
+        let start = usize(self.header_len());
+        unsafe { self.as_mut_slice().rfm(start..) }
+    }
+
+    /// Recomputes and updates the 'Checksum' field using the IPv4 pseudo-header
+    pub fn update_ipv4_checksum(mut self, src: ipv4::Addr, dest: ipv4::Addr) -> Segment<B, Valid> {
+        let cksum = self.compute_ipv4_checksum(src, dest);
+        self.set_checksum(cksum);
+
+        unsafe { Segment::unchecked(self.buffer) }
+    }
+
+    /// Recomputes and updates the 'Checksum' field using the IPv6 pseudo-header
+    pub fn update_ipv6_checksum(mut self, src: ipv6::Addr, dest: ipv6::Addr) -> Segment<B, Valid> {
+        let cksum = self.compute_ipv6_checksum(src, dest);
+        self.set_checksum(cksum);
+
+        unsafe { Segment::unchecked(self.buffer) }
+    }
+
+    /* Options */
+    /// Appends a Maximum Segment Size option to the header
+    ///
+    /// NOTE options must be added *before* the payload is filled as the payload will be shifted
+    /// (and its contents lost) to make room for the option
+    ///
+    /// # Panics
+    ///
+    /// This method panics if the option doesn't fit in the header (see `MAX_HEADER_SIZE`) or in
+    /// the buffer
+    pub fn add_mss(&mut self, mss: u16) {
+        let mut value = [0; 2];
+        NE::write_u16(&mut value, mss);
+        self.add_option(OptionKind::MaximumSegmentSize, &value);
+    }
+
+    /// Appends a Window Scale option to the header
+    ///
+    /// See `add_mss` for notes and panicking conditions
+    pub fn add_window_scale(&mut self, shift_count: u8) {
+        self.add_option(OptionKind::WindowScale, &[shift_count]);
+    }
+
+    /// Appends a SACK-Permitted option to the header
+    ///
+    /// See `add_mss` for notes and panicking conditions
+    pub fn add_sack_permitted(&mut self) {
+        self.add_option(OptionKind::SackPermitted, &[]);
+    }
+
+    /// Appends a SACK option, containing the given `(left_edge, right_edge)` blocks, to the
+    /// header
+    ///
+    /// See `add_mss` for notes and panicking conditions. This method will also panic if more than
+    /// 4 blocks are given.
+    pub fn add_sack(&mut self, blocks: &[(u32, u32)]) {
+        assert!(!blocks.is_empty() && blocks.len() <= 4);
+
+        let mut value = [0; 32];
+        for (chunk, &(left, right)) in value.chunks_exact_mut(8).zip(blocks) {
+            NE::write_u32(&mut chunk[..4], left);
+            NE::write_u32(&mut chunk[4..], right);
+        }
+
+        self.add_option(OptionKind::Sack, &value[..8 * blocks.len()]);
+    }
+
+    /// Appends a Timestamps option to the header
+    ///
+    /// See `add_mss` for notes and panicking conditions
+    pub fn add_timestamps(&mut self, value: u32, echo_reply: u32) {
+        let mut bytes = [0; 8];
+        NE::write_u32(&mut bytes[..4], value);
+        NE::write_u32(&mut bytes[4..], echo_reply);
+        self.add_option(OptionKind::Timestamps, &bytes);
+    }
+
+    fn add_option(&mut self, kind: OptionKind, value: &[u8]) {
+        let header_len = usize(self.header_len());
+
+        // find the end of the option list; this overwrites any previous End of Option List padding
+        let mut start = OPTIONS.start;
+        {
+            let mut opts = unsafe { self.as_slice().r(OPTIONS.start..header_len) };
+            while let Some(&kind) = opts.first() {
+                let len = match OptionKind::from(kind) {
+                    OptionKind::EndOfOptionList => break,
+                    OptionKind::NoOperation => 1,
+                    _ => usize::from(opts[1]),
+                };
+
+                start += len;
+                opts = &opts[len..];
+            }
+        }
+
+        let end = start + 2 + value.len();
+        // round up to a multiple of 4
+        let new_header_len = (end + 3) & !3;
+        assert!(new_header_len <= usize(MAX_HEADER_SIZE));
+        assert!(self.as_slice().len() >= new_header_len);
+
+        let opt = &mut self.as_mut_slice()[start..new_header_len];
+        opt[0] = kind.into();
+        opt[1] = (2 + value.len()) as u8;
+        opt[2..2 + value.len()].copy_from_slice(value);
+        // End of Option List padding
+        for byte in &mut opt[2 + value.len()..] {
+            *byte = 0;
+        }
+
+        unsafe { self.set_data_offset((new_header_len / 4) as u8) }
+    }
+}
+
+impl<B> Segment<B, Invalid>
+where
+    B: AsSlice<Element = u8> + AsMutSlice<Element = u8> + Truncate<u16>,
+{
+    /* Constructors */
+    /// Transforms the given buffer into a TCP segment
+    ///
+    /// The header will contain no options (Data Offset = 5) and all the flags, the Urgent
+    /// Pointer and the Checksum fields will be zeroed. The segment will span the whole buffer.
+    ///
+    /// # Panics
+    ///
+    /// This constructor panics if the given `buffer` is not large enough to contain the TCP
+    /// header.
+    pub fn new(mut buffer: B) -> Self {
+        assert!(buffer.as_slice().len() >= usize(MIN_HEADER_SIZE));
+
+        let len = u16(buffer.as_slice().len()).unwrap_or(u16::MAX);
+        buffer.truncate(len);
+
+        let mut segment: Self = unsafe { Segment::unchecked(buffer) };
+
+        unsafe { segment.set_data_offset(MIN_HEADER_SIZE / 4) }
+        segment.clear_reserved();
+        segment.set_ns(false);
+        segment.header_mut_()[FLAGS] = 0;
+        segment.set_checksum(0);
+        segment.set_urgent_pointer(0);
+
+        segment
+    }
+
+    /* Setters */
+    /// Fills the payload with the given data and adjusts the length of the TCP segment
+    pub fn set_payload(&mut self, data: &[u8]) {
+        let len = u16(data.len()).unwrap();
+        assert!(self.payload_len() >= len);
+
+        self.truncate(len);
+        self.payload_mut().copy_from_slice(data);
+    }
+
+    /// Truncates the *payload* to the specified length
+    pub fn truncate(&mut self, len: u16) {
+        if len < self.payload_len() {
+            let total_len = len + u16(self.header_len());
+            self.buffer.truncate(total_len);
+        }
+    }
+
+    /* Private */
+    fn payload_len(&self) -> u16 {
+        self.len() - u16(self.header_len())
+    }
+}
+
+/* Valid */
+impl<B> Segment<B, Valid>
+where
+    B: AsSlice<Element = u8> + AsMutSlice<Element = u8>,
+{
+    /* Setters */
+    /// Sets the Source (port) field of the header
+    pub fn set_source(self, port: u16) -> Segment<B, Invalid> {
+        let mut segment = self.invalidate_checksum();
+        segment.set_source(port);
+        segment
+    }
+
+    /// Sets the Destination (port) field of the header
+    pub fn set_destination(self, port: u16) -> Segment<B, Invalid> {
+        let mut segment = self.invalidate_checksum();
+        segment.set_destination(port);
+        segment
+    }
+
+    /// Sets the Sequence Number field of the header
+    pub fn set_sequence_number(self, seq_no: u32) -> Segment<B, Invalid> {
+        let mut segment = self.invalidate_checksum();
+        segment.set_sequence_number(seq_no);
+        segment
+    }
+
+    /// Sets the Acknowledgment Number field of the header
+    pub fn set_acknowledgment_number(self, ack_no: u32) -> Segment<B, Invalid> {
+        let mut segment = self.invalidate_checksum();
+        segment.set_acknowledgment_number(ack_no);
+        segment
+    }
+
+    /// Sets the NS (ECN-nonce concealment protection) flag
+    pub fn set_ns(self, ns: bool) -> Segment<B, Invalid> {
+        let mut segment = self.invalidate_checksum();
+        segment.set_ns(ns);
+        segment
+    }
+
+    /// Sets the CWR (Congestion Window Reduced) flag
+    pub fn set_cwr(self, cwr: bool) -> Segment<B, Invalid> {
+        let mut segment = self.invalidate_checksum();
+        segment.set_cwr(cwr);
+        segment
+    }
+
+    /// Sets the ECE (ECN-Echo) flag
+    pub fn set_ece(self, ece: bool) -> Segment<B, Invalid> {
+        let mut segment = self.invalidate_checksum();
+        segment.set_ece(ece);
+        segment
+    }
+
+    /// Sets the URG (Urgent pointer field is significant) flag
+    pub fn set_urg(self, urg: bool) -> Segment<B, Invalid> {
+        let mut segment = self.invalidate_checksum();
+        segment.set_urg(urg);
+        segment
+    }
+
+    /// Sets the ACK (Acknowledgment field is significant) flag
+    pub fn set_ack(self, ack: bool) -> Segment<B, Invalid> {
+        let mut segment = self.invalidate_checksum();
+        segment.set_ack(ack);
+        segment
+    }
+
+    /// Sets the PSH (Push function) flag
+    pub fn set_psh(self, psh: bool) -> Segment<B, Invalid> {
+        let mut segment = self.invalidate_checksum();
+        segment.set_psh(psh);
+        segment
+    }
+
+    /// Sets the RST (Reset the connection) flag
+    pub fn set_rst(self, rst: bool) -> Segment<B, Invalid> {
+        let mut segment = self.invalidate_checksum();
+        segment.set_rst(rst);
+        segment
+    }
+
+    /// Sets the SYN (Synchronize sequence numbers) flag
+    pub fn set_syn(self, syn: bool) -> Segment<B, Invalid> {
+        let mut segment = self.invalidate_checksum();
+        segment.set_syn(syn);
+        segment
+    }
+
+    /// Sets the FIN (No more data from sender) flag
+    pub fn set_fin(self, fin: bool) -> Segment<B, Invalid> {
+        let mut segment = self.invalidate_checksum();
+        segment.set_fin(fin);
+        segment
+    }
+
+    /// Sets the Window field of the header
+    pub fn set_window(self, window: u16) -> Segment<B, Invalid> {
+        let mut segment = self.invalidate_checksum();
+        segment.set_window(window);
+        segment
+    }
+
+    /// Sets the Urgent Pointer field of the header
+    pub fn set_urgent_pointer(self, urgent_pointer: u16) -> Segment<B, Invalid> {
+        let mut segment = self.invalidate_checksum();
+        segment.set_urgent_pointer(urgent_pointer);
+        segment
+    }
+
+    /* Private */
+    fn invalidate_checksum(self) -> Segment<B, Invalid> {
+        unsafe { Segment::unchecked(self.buffer) }
+    }
+}
+
+impl<B, C> Clone for Segment<B, C>
+where
+    B: AsSlice<Element = u8> + Clone,
+{
+    fn clone(&self) -> Self {
+        Segment {
+            buffer: self.buffer.clone(),
+            _checksum: PhantomData,
+        }
+    }
+}
+
+/// NOTE excludes the payload
+impl<B, C> fmt::Debug for Segment<B, C>
+where
+    B: AsSlice<Element = u8>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        struct Options<'a>(super::tcp::Options<'a>);
+
+        impl<'a> fmt::Debug for Options<'a> {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.debug_list().entries(self.0.clone()).finish()
+            }
+        }
+
+        f.debug_struct("tcp::Segment")
+            .field("source", &self.get_source())
+            .field("destination", &self.get_destination())
+            .field("sequence_number", &self.get_sequence_number())
+            .field("acknowledgment_number", &self.get_acknowledgment_number())
+            .field("data_offset", &self.get_data_offset())
+            .field("ns", &self.get_ns())
+            .field("cwr", &self.get_cwr())
+            .field("ece", &self.get_ece())
+            .field("urg", &self.get_urg())
+            .field("ack", &self.get_ack())
+            .field("psh", &self.get_psh())
+            .field("rst", &self.get_rst())
+            .field("syn", &self.get_syn())
+            .field("fin", &self.get_fin())
+            .field("window", &self.get_window())
+            .field("checksum", &Hex(self.get_checksum()))
+            .field("urgent_pointer", &self.get_urgent_pointer())
+            .field("options", &Options(self.options()))
+            // .field("payload", &self.payload())
+            .finish()
+    }
+}
+
+/// A TCP option
+///
+/// NOTE known options whose length doesn't match the one mandated by their specification are
+/// reported as `Unknown`
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Option<'a> {
+    /// Maximum Segment Size
+    MaximumSegmentSize(u16),
+    /// Window Scale
+    WindowScale(u8),
+    /// SACK-Permitted
+    SackPermitted,
+    /// Selective Acknowledgment
+    Sack(SackBlocks<'a>),
+    /// Timestamps
+    Timestamps {
+        /// Timestamp Value (TSval)
+        value: u32,
+        /// Timestamp Echo Reply (TSecr)
+        echo_reply: u32,
+    },
+    /// Unknown option
+    Unknown {
+        /// Option kind
+        kind: u8,
+        /// Option data, excluding the Kind and Length octets
+        data: &'a [u8],
+    },
+}
+
+/// Iterator over the `(left_edge, right_edge)` blocks of a SACK option
+#[derive(Clone, Copy, Eq, PartialEq)]
+pub struct SackBlocks<'a> {
+    // NOTE(invariant) `blocks.len() % 8 == 0`
+    blocks: &'a [u8],
+}
+
+impl<'a> Iterator for SackBlocks<'a> {
+    type Item = (u32, u32);
+
+    fn next(&mut self) -> CoreOption<(u32, u32)> {
+        if self.blocks.is_empty() {
+            None
+        } else {
+            unsafe {
+                let left = NE::read_u32(self.blocks.r(0..4));
+                let right = NE::read_u32(self.blocks.r(4..8));
+                self.blocks = self.blocks.rf(8..);
+                Some((left, right))
+            }
+        }
+    }
+}
+
+impl<'a> fmt::Debug for SackBlocks<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(*self).finish()
+    }
+}
+
+/// Iterator over the options of a TCP segment
+#[derive(Clone)]
+pub struct Options<'a> {
+    opts: &'a [u8],
+}
+
+impl<'a> Options<'a> {
+    // NOTE: Caller must ensure that `are_valid` returns `true` before using this as an iterator
+    unsafe fn new(opts: &'a [u8]) -> Self {
+        Options { opts }
+    }
+
+    fn are_valid(mut opts: &[u8]) -> bool {
+        while let Some(&kind) = opts.first() {
+            match OptionKind::from(kind) {
+                OptionKind::EndOfOptionList => return true,
+                OptionKind::NoOperation => opts = &opts[1..],
+                _ => {
+                    if opts.len() < 2 {
+                        // not big enough to contain the Kind and Length
+                        return false;
+                    }
+
+                    let length = usize::from(opts[1]);
+
+                    if length < 2 || length > opts.len() {
+                        return false;
+                    }
+
+                    opts = &opts[length..];
+                }
+            }
+        }
+
+        true
+    }
+}
+
+impl<'a> Iterator for Options<'a> {
+    type Item = Option<'a>;
+
+    fn next(&mut self) -> CoreOption<Option<'a>> {
+        unsafe {
+            loop {
+                if self.opts.is_empty() {
+                    return None;
+                }
+
+                let kind = OptionKind::from(*self.opts.gu(0));
+                match kind {
+                    OptionKind::EndOfOptionList => {
+                        self.opts = &[];
+                        return None;
+                    }
+                    OptionKind::NoOperation => {
+                        self.opts = self.opts.rf(1..);
+                        continue;
+                    }
+                    _ => {}
+                }
+
+                let len = usize::from(*self.opts.gu(1));
+                let data = self.opts.r(2..len);
+                self.opts = self.opts.rf(len..);
+
+                return Some(match (kind, data.len()) {
+                    (OptionKind::MaximumSegmentSize, 2) => {
+                        Option::MaximumSegmentSize(NE::read_u16(data))
+                    }
+                    (OptionKind::WindowScale, 1) => Option::WindowScale(*data.gu(0)),
+                    (OptionKind::SackPermitted, 0) => Option::SackPermitted,
+                    (OptionKind::Sack, n) if n != 0 && n % 8 == 0 => {
+                        Option::Sack(SackBlocks { blocks: data })
+                    }
+                    (OptionKind::Timestamps, 8) => Option::Timestamps {
+                        value: NE::read_u32(data.r(0..4)),
+                        echo_reply: NE::read_u32(data.r(4..8)),
+                    },
+                    (kind, _) => Option::Unknown {
+                        kind: kind.into(),
+                        data,
+                    },
+                });
+            }
+        }
+    }
+}
+
+full_range!(
+    u8,
+    /// Option kind
+    #[derive(Clone, Copy, Debug, Eq, PartialEq)]
+    enum OptionKind {
+        EndOfOptionList = 0,
+        NoOperation = 1,
+        MaximumSegmentSize = 2,
+        WindowScale = 3,
+        SackPermitted = 4,
+        Sack = 5,
+        Timestamps = 8,
+    }
+);
+
+#[cfg(test)]
+mod tests {
+    use rand::{self, RngCore};
+
+    use crate::{ether, ipv4, ipv6, mac, tcp, Unknown};
+
+    const SIZE: usize = 74;
+
+    const BYTES: &[u8; SIZE] = &[
+        255, 255, 255, 255, 255, 255, // ether: destination
+        1, 1, 1, 1, 1, 1, // ether: source
+        8, 0,  // ether: type
+        69, // ipv4: version & IHL
+        0,  // ipv4: DSCP & ECN
+        0, 60, // ipv4: total length
+        0, 0, // ipv4: identification
+        64, 0,  // ipv4: fragment
+        64, // ipv4: ttl
+        6,  // ipv4: protocol
+        185, 73, // ipv4: checksum
+        192, 168, 0, 33, // ipv4: source
+        192, 168, 0, 1, // ipv4: destination
+        192, 0, // tcp: source
+        0, 80, // tcp: destination
+        1, 2, 3, 4, // tcp: sequence number
+        0, 0, 0, 0,   // tcp: acknowledgment number
+        160, // tcp: data offset & NS
+        2,   // tcp: flags
+        114, 16, // tcp: window
+        138, 3, // tcp: checksum
+        0, 0, // tcp: urgent pointer
+        2, 4, 5, 180, // tcp: MSS option
+        4, 2, // tcp: SACK-Permitted option
+        8, 10, 0, 0, 0, 42, 0, 0, 0, 0, // tcp: Timestamps option
+        3, 3, 7, // tcp: Window Scale option
+        0, // tcp: End of Option List
+    ];
+
+    const MAC_SRC: mac::Addr = mac::Addr([0x01; 6]);
+    const MAC_DST: mac::Addr = mac::Addr([0xff; 6]);
+
+    const IP_SRC: ipv4::Addr = ipv4::Addr([192, 168, 0, 33]);
+    const IP_DST: ipv4::Addr = ipv4::Addr([192, 168, 0, 1]);
+
+    #[test]
+    fn construct() {
+        // NOTE start with randomized array to make sure we set *everything* correctly
+        let mut array: [u8; SIZE] = [0; SIZE];
+        rand::thread_rng().fill_bytes(&mut array);
+
+        let mut eth = ether::Frame::new(&mut array[..]);
+
+        eth.set_destination(MAC_DST);
+        eth.set_source(MAC_SRC);
+
+        eth.ipv4(|ip| {
+            ip.set_destination(IP_DST);
+            ip.set_source(IP_SRC);
+
+            ip.tcp(|tcp| {
+                tcp.set_source(49152);
+                tcp.set_destination(80);
+                tcp.set_sequence_number(0x01_02_03_04);
+                tcp.set_acknowledgment_number(0);
+                tcp.set_syn(true);
+                tcp.set_window(29200);
+                tcp.add_mss(1460);
+                tcp.add_sack_permitted();
+                tcp.add_timestamps(42, 0);
+                tcp.add_window_scale(7);
+                tcp.set_payload(&[]);
+            });
+        });
+
+        assert_eq!(eth.as_bytes(), &BYTES[..]);
+    }
+
+    #[test]
+    fn parse() {
+        let eth = ether::Frame::parse(&BYTES[..]).unwrap();
+        let ip = ipv4::Packet::parse(eth.payload()).unwrap();
+        assert_eq!(ip.get_protocol(), ipv4::Protocol::Tcp);
+
+        let tcp = tcp::Segment::parse(ip.payload())
+            .unwrap()
+            .verify_ipv4_checksum(IP_SRC, IP_DST)
+            .unwrap();
+
+        assert_eq!(tcp.get_source(), 49152);
+        assert_eq!(tcp.get_destination(), 80);
+        assert_eq!(tcp.get_sequence_number(), 0x01_02_03_04);
+        assert_eq!(tcp.get_acknowledgment_number(), 0);
+        assert_eq!(tcp.get_data_offset(), 10);
+        assert!(tcp.get_syn());
+        assert!(!tcp.get_ack());
+        assert!(!tcp.get_fin());
+        assert_eq!(tcp.get_window(), 29200);
+        assert_eq!(tcp.get_urgent_pointer(), 0);
+        assert_eq!(tcp.payload(), &[]);
+
+        let mut opts = tcp.options();
+        assert_eq!(opts.next(), Some(tcp::Option::MaximumSegmentSize(1460)));
+        assert_eq!(opts.next(), Some(tcp::Option::SackPermitted));
+        assert_eq!(
+            opts.next(),
+            Some(tcp::Option::Timestamps {
+                value: 42,
+                echo_reply: 0
+            })
+        );
+        assert_eq!(opts.next(), Some(tcp::Option::WindowScale(7)));
+        assert_eq!(opts.next(), None);
+
+        // wrong pseudo-header
+        assert!(tcp::Segment::parse(ip.payload())
+            .unwrap()
+            .verify_ipv4_checksum(ipv4::Addr([192, 168, 0, 34]), IP_DST)
+            .is_err());
+    }
+
+    #[test]
+    fn parse_invalid() {
+        // Data Offset < 5
+        let mut bytes = [0; 20];
+        bytes[12] = 4 << 4;
+        assert!(tcp::Segment::parse(&bytes[..]).is_err());
+
+        // options don't fit in the header
+        let mut bytes = [0; 24];
+        bytes[12] = 6 << 4;
+        bytes[20..].copy_from_slice(&[2, 6, 5, 180]);
+        assert!(tcp::Segment::parse(&bytes[..]).is_err());
+
+        // header doesn't fit in the buffer
+        let mut bytes = [0; 20];
+        bytes[12] = 6 << 4;
+        assert!(tcp::Segment::parse(&bytes[..]).is_err());
+    }
+
+    #[test]
+    fn sack() {
+        let mut bytes = [0; 64];
+        let mut tcp = tcp::Segment::new(&mut bytes[..]);
+        tcp.set_ack(true);
+        tcp.add_sack(&[(1, 2), (3, 4)]);
+        tcp.set_payload(b"hi");
+
+        let tcp = tcp.update_ipv4_checksum(IP_SRC, IP_DST);
+        assert_eq!(usize::from(tcp.len()), 20 + 20 + 2);
+
+        let tcp = tcp::Segment::parse(tcp.as_bytes())
+            .unwrap()
+            .verify_ipv4_checksum(IP_SRC, IP_DST)
+            .unwrap();
+
+        let mut opts = tcp.options();
+        match opts.next() {
+            Some(tcp::Option::Sack(blocks)) => {
+                let mut blocks = blocks;
+                assert_eq!(blocks.next(), Some((1, 2)));
+                assert_eq!(blocks.next(), Some((3, 4)));
+                assert_eq!(blocks.next(), None);
+            }
+            _ => panic!(),
+        }
+        assert_eq!(opts.next(), None);
+        assert_eq!(tcp.payload(), b"hi");
+    }
+
+    #[test]
+    fn ipv6() {
+        const SRC: ipv6::Addr = ipv6::Addr([0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+        const DEST: ipv6::Addr = ipv6::Addr([0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2]);
+
+        let mut bytes = [0; 128];
+        let mut ip = ipv6::Packet::new(&mut bytes[..]);
+        ip.set_source(SRC);
+        ip.set_destination(DEST);
+        ip.tcp(|tcp| {
+            tcp.set_source(1337);
+            tcp.set_destination(80);
+            tcp.set_ack(true);
+            tcp.set_psh(true);
+            tcp.set_payload(b"Hello, world!\n");
+        });
+
+        let ip = ipv6::Packet::parse(ip.as_bytes()).unwrap();
+        assert_eq!(ip.get_next_header(), ipv6::NextHeader::Tcp);
+
+        let tcp: tcp::Segment<_, Unknown> = tcp::Segment::parse(ip.payload()).unwrap();
+        let tcp = tcp.verify_ipv6_checksum(SRC, DEST).unwrap();
+        assert!(tcp.get_ack());
+        assert!(tcp.get_psh());
+        assert_eq!(tcp.payload(), b"Hello, world!\n");
+
+        // modifying a valid segment invalidates its checksum
+        let mut bytes = [0; 34];
+        bytes.copy_from_slice(tcp.as_bytes());
+        let tcp = tcp::Segment::parse(&mut bytes[..])
+            .unwrap()
+            .verify_ipv6_checksum(SRC, DEST)
+            .unwrap()
+            .set_window(1024)
+            .update_ipv6_checksum(SRC, DEST);
+        assert!(tcp::Segment::parse(tcp.as_bytes())
+            .unwrap()
+            .verify_ipv6_checksum(SRC, DEST)
+            .is_ok());
+    }
+}
@@ -0,0 +1,114 @@
+//! Time keeping for the stateful parts of this crate (e.g. reassembly timeouts)
+//!
+//! This crate doesn't read any hardware timer. Instead the caller passes the current `Instant` to
+//! the methods that need to know the time. An `Instant` is a millisecond count that's expected to
+//! wrap around (every ~49.7 days); all the arithmetic done on it is wrapping arithmetic.
+
+use core::{fmt, ops};
+
+/// A measurement of a monotonically nondecreasing clock, in milliseconds
+#[derive(Clone, Copy, Eq, PartialEq)]
+pub struct Instant {
+    millis: u32,
+}
+
+impl Instant {
+    /// Creates an `Instant` from a millisecond count
+    pub const fn from_millis(millis: u32) -> Self {
+        Instant { millis }
+    }
+
+    /// Returns the millisecond count of this `Instant`
+    pub fn as_millis(&self) -> u32 {
+        self.millis
+    }
+
+    /// Returns the amount of time elapsed from `earlier` to this instant
+    ///
+    /// NOTE `earlier` must be no older than ~49.7 days or the result will be wrong
+    pub fn duration_since(&self, earlier: Instant) -> Duration {
+        Duration::from_millis(self.millis.wrapping_sub(earlier.millis))
+    }
+}
+
+impl ops::Add<Duration> for Instant {
+    type Output = Instant;
+
+    fn add(self, rhs: Duration) -> Instant {
+        Instant::from_millis(self.millis.wrapping_add(rhs.millis))
+    }
+}
+
+impl fmt::Debug for Instant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Instant({} ms)", self.millis)
+    }
+}
+
+/// A span of time, in milliseconds
+#[derive(Clone, Copy, Eq, Ord, PartialEq, PartialOrd)]
+pub struct Duration {
+    millis: u32,
+}
+
+impl Duration {
+    /// Creates a `Duration` from a millisecond count
+    pub const fn from_millis(millis: u32) -> Self {
+        Duration { millis }
+    }
+
+    /// Creates a `Duration` from a second count
+    ///
+    /// NOTE durations longer than `u32::MAX` milliseconds (~49.7 days) saturate
+    pub fn from_secs(secs: u32) -> Self {
+        Duration {
+            millis: secs.saturating_mul(1_000),
+        }
+    }
+
+    /// Returns the millisecond count of this `Duration`
+    pub fn as_millis(&self) -> u32 {
+        self.millis
+    }
+
+    /// Returns the number of *whole* seconds in this `Duration`
+    pub fn as_secs(&self) -> u32 {
+        self.millis / 1_000
+    }
+}
+
+impl ops::Add for Duration {
+    type Output = Duration;
+
+    fn add(self, rhs: Duration) -> Duration {
+        Duration::from_millis(self.millis.saturating_add(rhs.millis))
+    }
+}
+
+impl ops::Sub for Duration {
+    type Output = Duration;
+
+    fn sub(self, rhs: Duration) -> Duration {
+        Duration::from_millis(self.millis.saturating_sub(rhs.millis))
+    }
+}
+
+impl fmt::Debug for Duration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ms", self.millis)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Duration, Instant};
+
+    #[test]
+    fn wrap_around() {
+        let before = Instant::from_millis(u32::MAX - 499);
+        let after = before + Duration::from_millis(1_000);
+
+        assert_eq!(after.as_millis(), 500);
+        assert_eq!(after.duration_since(before), Duration::from_millis(1_000));
+    }
+}
@@ -1,17 +1,25 @@
 //! UDP: User Datagram Protocol
-
+//!
+//! # References
+//!
+//! - [RFC 768: User Datagram Protocol][rfc]
+//!
+//! [rfc]: https://tools.ietf.org/html/rfc768
+
+use core::marker::PhantomData;
 use core::ops::{Range, RangeFrom};
 use core::{fmt, u16};
 
 use as_slice::{AsMutSlice, AsSlice};
 use byteorder::{ByteOrder, NetworkEndian as NE};
-use cast::{u16, usize};
+use cast::{u16, u32, usize};
 use owning_slice::Truncate;
 
 use crate::{
     coap::{self, Unset},
-    ipv6,
+    dhcp, ipv4, ipv6,
     traits::UncheckedIndex,
+    Invalid, Unknown, Valid,
 };
 
 /* Packet structure */
@@ -25,14 +33,23 @@ const PAYLOAD: RangeFrom<usize> = 8..;
 pub const HEADER_SIZE: u8 = PAYLOAD.start as u8;
 
 /// UDP packet
-pub struct Packet<BUFFER>
+///
+/// The `CHECKSUM` type state tracks whether the Checksum field is known to be correct:
+///
+/// - `Unknown`: the checksum has not been verified (e.g. the packet was just parsed) or is not
+///   being tracked
+/// - `Invalid`: the packet has been modified and its checksum needs to be recomputed
+/// - `Valid`: the checksum was recomputed and the packet can no longer be modified
+pub struct Packet<BUFFER, CHECKSUM = Unknown>
 where
     BUFFER: AsSlice<Element = u8>,
 {
     buffer: BUFFER,
+    _checksum: PhantomData<CHECKSUM>,
 }
 
-impl<B> Packet<B>
+/* Unknown */
+impl<B> Packet<B, Unknown>
 where
     B: AsSlice<Element = u8>,
 {
@@ -44,7 +61,7 @@ where
             return Err(bytes);
         }
 
-        let packet = Packet { buffer: bytes };
+        let packet: Self = unsafe { Packet::unchecked(bytes) };
         let len = packet.get_length();
 
         if len < u16(HEADER_SIZE) || usize(len) > nbytes {
@@ -54,6 +71,51 @@ where
         }
     }
 
+    /* Miscellaneous */
+    /// Verifies the 'Checksum' field using the IPv4 pseudo-header
+    ///
+    /// NOTE as per RFC 768 a Checksum field of zero means that the sender didn't compute the
+    /// checksum; the packet is accepted in that case
+    pub fn verify_ipv4_checksum(
+        self,
+        src: ipv4::Addr,
+        dest: ipv4::Addr,
+    ) -> Result<Packet<B, Valid>, Self> {
+        let cksum = self.get_checksum();
+
+        if cksum == 0 || self.compute_ipv4_checksum(src, dest) == cksum {
+            Ok(unsafe { Packet::unchecked(self.buffer) })
+        } else {
+            Err(self)
+        }
+    }
+
+    /// Verifies the 'Checksum' field using the IPv6 pseudo-header
+    pub fn verify_ipv6_checksum(
+        self,
+        src: ipv6::Addr,
+        dest: ipv6::Addr,
+    ) -> Result<Packet<B, Valid>, Self> {
+        if self.compute_ipv6_checksum(src, dest) == self.get_checksum() {
+            Ok(unsafe { Packet::unchecked(self.buffer) })
+        } else {
+            Err(self)
+        }
+    }
+}
+
+impl<B, C> Packet<B, C>
+where
+    B: AsSlice<Element = u8>,
+{
+    /* Constructors */
+    unsafe fn unchecked(buffer: B) -> Self {
+        Packet {
+            buffer,
+            _checksum: PhantomData,
+        }
+    }
+
     /* Getters */
     /// Returns the Source (port) field of the header
     pub fn get_source(&self) -> u16 {
@@ -90,27 +152,43 @@ where
         self.as_slice()
     }
 
-    /* Miscellaneous */
-    pub(crate) fn compute_checksum(&self, src: ipv6::Addr, dest: ipv6::Addr) -> u16 {
-        const NEXT_HEADER: u8 = 17;
+    /* Private */
+    fn as_slice(&self) -> &[u8] {
+        self.buffer.as_slice()
+    }
 
-        let mut sum: u32 = 0;
+    fn header_(&self) -> &[u8; HEADER_SIZE as usize] {
+        debug_assert!(self.as_slice().len() >= HEADER_SIZE as usize);
 
-        // Pseudo-header
-        for chunk in src.0.chunks_exact(2).chain(dest.0.chunks_exact(2)) {
-            sum += u32::from(NE::read_u16(chunk));
-        }
+        unsafe { &*(self.as_slice().as_ptr() as *const _) }
+    }
+
+    fn payload_len(&self) -> u16 {
+        self.get_length() - u16(HEADER_SIZE)
+    }
 
-        // XXX should this be just `as u16`?
-        let len = self.as_slice().len() as u32;
-        sum += len >> 16;
-        sum += len & 0xffff;
+    fn compute_ipv4_checksum(&self, src: ipv4::Addr, dest: ipv4::Addr) -> u16 {
+        let len = self.get_length();
+        self.compute_checksum(ipv4::pseudo_header_sum(src, dest, ipv4::Protocol::Udp, len))
+    }
+
+    fn compute_ipv6_checksum(&self, src: ipv6::Addr, dest: ipv6::Addr) -> u16 {
+        let len = u32(self.get_length());
+        self.compute_checksum(ipv6::pseudo_header_sum(
+            src,
+            dest,
+            ipv6::NextHeader::Udp,
+            len,
+        ))
+    }
 
-        sum += u32::from(NEXT_HEADER);
+    fn compute_checksum(&self, pseudo_header: u32) -> u16 {
+        let mut sum = pseudo_header;
 
         // UDP message
-        for (i, chunk) in self.as_slice().chunks(2).enumerate() {
-            if i == 3 {
+        let len = usize(self.get_length());
+        for (i, chunk) in unsafe { self.as_slice().rt(..len) }.chunks(2).enumerate() {
+            if i == CHECKSUM.start / 2 {
                 // this is the checksum field, skip
                 continue;
             }
@@ -127,31 +205,36 @@ where
             sum = (sum & 0xffff) + (sum >> 16);
         }
 
-        !(sum as u16)
-    }
-
-    /// Verifies the 'Checksum' field
-    pub fn verify_ipv6_checksum(&self, src: ipv6::Addr, dest: ipv6::Addr) -> bool {
-        self.compute_checksum(src, dest) == self.get_checksum()
+        match !(sum as u16) {
+            // a computed checksum of zero is transmitted as all ones (see RFC 768)
+            0 => 0xffff,
+            cksum => cksum,
+        }
     }
+}
 
+impl<B, C> Packet<B, C>
+where
+    B: AsSlice<Element = u8> + AsMutSlice<Element = u8>,
+{
     /* Private */
-    fn as_slice(&self) -> &[u8] {
-        self.buffer.as_slice()
+    fn as_mut_slice(&mut self) -> &mut [u8] {
+        self.buffer.as_mut_slice()
     }
 
-    fn header_(&self) -> &[u8; HEADER_SIZE as usize] {
+    fn header_mut_(&mut self) -> &mut [u8; HEADER_SIZE as usize] {
         debug_assert!(self.as_slice().len() >= HEADER_SIZE as usize);
 
-        unsafe { &*(self.as_slice().as_ptr() as *const _) }
+        unsafe { &mut *(self.as_mut_slice().as_mut_ptr() as *mut _) }
     }
 
-    fn payload_len(&self) -> u16 {
-        self.get_length() - u16(HEADER_SIZE)
+    fn set_checksum(&mut self, checksum: u16) {
+        NE::write_u16(&mut self.header_mut_()[CHECKSUM], checksum)
     }
 }
 
-impl<B> Packet<B>
+/* Invalid */
+impl<B> Packet<B, Invalid>
 where
     B: AsSlice<Element = u8> + AsMutSlice<Element = u8>,
 {
@@ -171,40 +254,63 @@ where
     }
 
     /// Zeroes the Checksum field of the header
+    ///
+    /// NOTE this is only allowed when the packet is transmitted over IPv4 and indicates that the
+    /// checksum was not computed
     pub fn zero_checksum(&mut self) {
         self.set_checksum(0);
     }
 
-    /// Sets the Destination (port) field of the header
-    fn set_checksum(&mut self, checksum: u16) {
-        NE::write_u16(&mut self.header_mut_()[CHECKSUM], checksum)
-    }
-
     /* Miscellaneous */
     /// Mutable view into the payload
     pub fn payload_mut(&mut self) -> &mut [u8] {
         &mut self.as_mut_slice()[PAYLOAD]
     }
 
-    /// Recomputes and updates the 'Checksum' field
-    pub fn update_ipv6_checksum(&mut self, src: ipv6::Addr, dest: ipv6::Addr) {
-        let cksum = self.compute_checksum(src, dest);
-        self.set_checksum(cksum)
+    /// Recomputes and updates the 'Checksum' field using the IPv4 pseudo-header
+    pub fn update_ipv4_checksum(mut self, src: ipv4::Addr, dest: ipv4::Addr) -> Packet<B, Valid> {
+        let cksum = self.compute_ipv4_checksum(src, dest);
+        self.set_checksum(cksum);
+
+        unsafe { Packet::unchecked(self.buffer) }
     }
 
-    /* Private */
-    fn as_mut_slice(&mut self) -> &mut [u8] {
-        self.buffer.as_mut_slice()
+    /// Recomputes and updates the 'Checksum' field using the IPv6 pseudo-header
+    pub fn update_ipv6_checksum(mut self, src: ipv6::Addr, dest: ipv6::Addr) -> Packet<B, Valid> {
+        let cksum = self.compute_ipv6_checksum(src, dest);
+        self.set_checksum(cksum);
+
+        unsafe { Packet::unchecked(self.buffer) }
     }
+}
 
-    fn header_mut_(&mut self) -> &mut [u8; HEADER_SIZE as usize] {
-        debug_assert!(self.as_slice().len() >= HEADER_SIZE as usize);
+/* Valid */
+impl<B> Packet<B, Valid>
+where
+    B: AsSlice<Element = u8> + AsMutSlice<Element = u8>,
+{
+    /* Setters */
+    /// Sets the Source (port) field of the header
+    pub fn set_source(self, port: u16) -> Packet<B, Invalid> {
+        let mut packet = self.invalidate_checksum();
+        packet.set_source(port);
+        packet
+    }
 
-        unsafe { &mut *(self.as_mut_slice().as_mut_ptr() as *mut _) }
+    /// Sets the Destination (port) field of the header
+    pub fn set_destination(self, port: u16) -> Packet<B, Invalid> {
+        let mut packet = self.invalidate_checksum();
+        packet.set_destination(port);
+        packet
+    }
+
+    /* Private */
+    fn invalidate_checksum(self) -> Packet<B, Invalid> {
+        unsafe { Packet::unchecked(self.buffer) }
     }
 }
 
-impl<B> Packet<B>
+impl<B> Packet<B, Invalid>
 where
     B: AsSlice<Element = u8> + AsMutSlice<Element = u8> + Truncate<u16>,
 {
@@ -221,7 +327,7 @@ where
 
         let len = u16(buffer.as_slice().len()).unwrap_or(u16::MAX);
         buffer.truncate(len);
-        let mut packet = Packet { buffer };
+        let mut packet: Self = unsafe { Packet::unchecked(buffer) };
 
         packet.set_checksum(0);
         unsafe { packet.set_length(len) }
@@ -252,6 +358,19 @@ where
         self.truncate(len);
     }
 
+    /// Fills the payload with a DHCP message
+    pub fn dhcp<F>(&mut self, f: F)
+    where
+        F: FnOnce(&mut dhcp::Message<&mut [u8]>),
+    {
+        let len = {
+            let mut m = dhcp::Message::new(self.payload_mut());
+            f(&mut m);
+            m.len()
+        };
+        self.truncate(len);
+    }
+
     /// Truncates the *payload* to the specified length
     pub fn truncate(&mut self, len: u16) {
         if len < self.payload_len() {
@@ -263,7 +382,7 @@ where
 }
 
 /// NOTE excludes the payload
-impl<B> fmt::Debug for Packet<B>
+impl<B, C> fmt::Debug for Packet<B, C>
 where
     B: AsSlice<Element = u8>,
 {
@@ -304,7 +423,7 @@ mod tests {
         0, 0, // udp: source
         5, 57, // udp: destination
         0, 22, // udp: length
-        0, 0, // udp: checksum
+        55, 192, // udp: checksum
         72, 101, 108, 108, 111, 44, 32, 119, 111, 114, 108, 100, 33, 10, // udp: payload
     ];
 
@@ -374,5 +493,39 @@ mod tests {
             MESSAGE.len() as u16 + u16(udp::HEADER_SIZE)
         );
         assert_eq!(udp.payload(), MESSAGE);
+
+        // wrong pseudo-header
+        assert!(udp::Packet::parse(ip.payload())
+            .unwrap()
+            .verify_ipv4_checksum(IP_DST, ipv4::Addr([192, 168, 0, 2]))
+            .is_err());
+
+        let udp = udp.verify_ipv4_checksum(IP_SRC, IP_DST).unwrap();
+        assert_eq!(udp.payload(), MESSAGE);
+    }
+
+    #[test]
+    fn ipv4_checksum() {
+        let mut bytes = [0; 22];
+        bytes.copy_from_slice(&BYTES[34..]);
+
+        let udp = udp::Packet::parse(&mut bytes[..]).unwrap();
+        let udp = udp.verify_ipv4_checksum(IP_SRC, IP_DST).unwrap();
+
+        // modifying the packet invalidates the checksum
+        let mut udp = udp.set_source(1);
+        udp.payload_mut()[0] = b'h';
+        let udp = udp.update_ipv4_checksum(IP_SRC, IP_DST);
+        assert_eq!(udp.get_source(), 1);
+
+        // corrupted payload
+        bytes[8] = b'H';
+        let udp = udp::Packet::parse(&mut bytes[..]).unwrap();
+        assert!(udp.verify_ipv4_checksum(IP_SRC, IP_DST).is_err());
+
+        // zero means "no checksum"
+        bytes[6..8].copy_from_slice(&[0, 0]);
+        let udp = udp::Packet::parse(&mut bytes[..]).unwrap();
+        assert!(udp.verify_ipv4_checksum(IP_SRC, IP_DST).is_ok());
     }
 }